@@ -0,0 +1,77 @@
+/*
+
+time evaluating a couple of small recursive programs, run with cargo bench
+
+fib makes a lot of calls and sum goes deep, both spend most of their time looking up variables
+sum and fact are run again with the strictness analysis (strict.rs), their arguments don't need thunks then
+
+*/
+
+use bagl::ast::Expr;
+use bagl::env::Env;
+use bagl::eval::Budget;
+use bagl::eval::Machine;
+use bagl::front::compile;
+use bagl::gram;
+use bagl::strict::analyze;
+use bagl::strict::Strictness;
+use std::rc::Rc;
+use std::time::Instant;
+
+const FIB: &str = "Bool = True | False;
+or x = case x {True -> (\\_ . True); False -> (\\y . y)};
+fib n = if (or (eq n 1) (eq n 0)) then 1 else + (fib (- n 1)) (fib (- n 2));
+main = fib 20";
+
+const SUM: &str = "Bool = True | False;
+sum n = if (eq n 0) then 0 else + n (sum (- n 1));
+main = sum 1000";
+
+const FACT: &str = "Bool = True | False;
+fact n = let m = - n 1 in if (eq n 0) then 1 else * n (fact m);
+main = fact 500";
+
+// eq is a method, so the dictionaries have to be worked out before evaluating
+// that only happens once, the time is just for eval
+fn prepare(source: &str) -> Rc<Expr> {
+    compile(&gram::TopParser::new().parse(source).unwrap(), "main").unwrap()
+}
+
+// the number of Expr nodes allocated
+fn run(expr: &Rc<Expr>, strictness: Option<&Strictness>) -> u64 {
+    let mut machine = Machine::new(Budget::default());
+    if let Some(strictness) = strictness {
+        machine = machine.with_strictness(strictness);
+    }
+    machine
+        .eval(Rc::clone(expr), Rc::new(Env::new()), Vec::new())
+        .unwrap();
+    machine.stats().allocations
+}
+
+fn bench(name: &str, source: &str, iterations: u32, strict: bool) {
+    let expr = prepare(source);
+    let strictness = analyze(&expr);
+    let strictness = if strict { Some(&strictness) } else { None };
+    let allocations = run(&expr, strictness);
+    let start = Instant::now();
+    for _ in 0..iterations {
+        run(&expr, strictness);
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{:<13} {:>10.3?} per run ({} runs), {} allocations",
+        name,
+        elapsed / iterations,
+        iterations,
+        allocations
+    );
+}
+
+fn main() {
+    bench("fib", FIB, 20, false);
+    bench("sum", SUM, 100, false);
+    bench("sum strict", SUM, 100, true);
+    bench("fact", FACT, 100, false);
+    bench("fact strict", FACT, 100, true);
+}
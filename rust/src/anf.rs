@@ -0,0 +1,403 @@
+/*
+
+a-normal form, a smaller language for passes that come after checking and rearranging the lets
+
+Expr is both the syntax and what eval works on, builtins carry the arguments they got and constructors count theirs
+a Term only has what a program is before it runs
+    every result in between gets a name with a let, $1 $2 ..., names the source can't use
+    applications only take atoms, a variable or a literal, both for what's applied and for the arguments
+    case looks at a variable and if at an atom
+    lambdas right inside of each other are one lambda with all of the parameters
+    constructors and builtins are atoms too, they don't have any fields yet so there's nothing to compute
+
+a let is still lazy, the definition is a thunk that's evaluated the first time it's needed
+so naming the argument of an application doesn't change when it's evaluated, the lambda forces it like it would have
+and a definition can be any term, let x = case y { ... } can't be moved out of the let without evaluating it too early
+
+lower makes a Term out of the program from change_lets, to_expr makes it an Expr again for eval
+printed it's one thing on each line, with the lets first and what they're for under them
+
+*/
+
+use crate::ast::Expr;
+use crate::ast::Pattern;
+use crate::ast::Span;
+use crate::classes::app;
+use crate::classes::var;
+use num::BigInt;
+use std::fmt::Display;
+use std::rc::Rc;
+
+#[derive(Clone)]
+pub enum Atom {
+    Var(String),
+    Int(BigInt),
+    Float(f64),
+    Str(String),
+    Con(usize, String, String), // arguments, type, constructor
+    Builtin(usize, String, fn(Vec<Rc<Expr>>) -> Rc<Expr>), // arguments, representation, function
+}
+
+#[derive(Clone)]
+pub enum Term {
+    Atom(Atom),
+    App(Atom, Vec<Atom>),
+    Lam(Vec<String>, Rc<Term>),
+    Let(String, Rc<Term>, Rc<Term>),
+    LetRec(Vec<(String, Rc<Term>)>, Rc<Term>),
+    Case(String, Vec<(Pattern, Rc<Term>)>),
+    If(Atom, Rc<Term>, Rc<Term>),
+    Error(String),
+    Bottom,
+}
+
+// the program in a-normal form
+pub fn lower(expr: &Rc<Expr>) -> Term {
+    Lower { fresh: 0 }.term(expr)
+}
+
+struct Lower {
+    fresh: usize,
+}
+
+impl Lower {
+    fn name(&mut self) -> String {
+        self.fresh += 1;
+        format!("${}", self.fresh)
+    }
+
+    fn term(&mut self, expr: &Rc<Expr>) -> Term {
+        match &**expr {
+            Expr::Var(_, _, _)
+            | Expr::Int(_, _)
+            | Expr::Float(_, _)
+            | Expr::Str(_, _)
+            | Expr::Data(_, _, _, _)
+            | Expr::Builtin(_, _, _, _, _)
+                if atom(expr).is_some() =>
+            {
+                Term::Atom(atom(expr).unwrap())
+            }
+            Expr::App(_, _) => {
+                let mut args = Vec::new();
+                let mut func = expr;
+                while let Expr::App(left, right) = &**func {
+                    args.push(right);
+                    func = left;
+                }
+                args.reverse();
+                let mut bound = Vec::new();
+                let func = self.atom(func, &mut bound);
+                let args = args.iter().map(|a| self.atom(a, &mut bound)).collect();
+                wrap(bound, Term::App(func, args))
+            }
+            Expr::Lam(_, _) => {
+                let mut params = Vec::new();
+                let mut body = expr;
+                while let Expr::Lam(head, inner) = &**body {
+                    params.push(head.to_string());
+                    body = inner;
+                }
+                Term::Lam(params, Rc::new(self.term(body)))
+            }
+            Expr::Let(vars, defs, body) => {
+                let defs: Vec<Term> = defs.iter().map(|d| self.term(d)).collect();
+                let mut term = self.term(body);
+                for (v, d) in vars.iter().zip(defs).rev() {
+                    term = Term::Let(v.to_string(), Rc::new(d), Rc::new(term));
+                }
+                term
+            }
+            Expr::LetRec(vars, defs, body) => {
+                let defs = vars
+                    .iter()
+                    .zip(defs)
+                    .map(|(v, d)| (v.to_string(), Rc::new(self.term(d))))
+                    .collect();
+                Term::LetRec(defs, Rc::new(self.term(body)))
+            }
+            Expr::Case(cond, pats, branches, _) => {
+                let mut bound = Vec::new();
+                let name = match &**cond {
+                    Expr::Var(name, _, _) => name.to_string(),
+                    _ => {
+                        let name = self.name();
+                        bound.push((name.to_string(), self.term(cond)));
+                        name
+                    }
+                };
+                let alts = pats
+                    .iter()
+                    .zip(branches)
+                    .map(|(p, b)| (p.clone(), Rc::new(self.term(b))))
+                    .collect();
+                wrap(bound, Term::Case(name, alts))
+            }
+            Expr::If(cond, b1, b2) => {
+                let mut bound = Vec::new();
+                let cond = self.atom(cond, &mut bound);
+                let term = Term::If(cond, Rc::new(self.term(b1)), Rc::new(self.term(b2)));
+                wrap(bound, term)
+            }
+            Expr::Error(s) => Term::Error(s.to_string()),
+            Expr::Bottom => Term::Bottom,
+            Expr::Annot(inner, _) => self.term(inner),
+            _ => panic!("Can't lower {} to a-normal form.", expr),
+        }
+    }
+
+    // anything that isn't an atom already gets a name
+    fn atom(&mut self, expr: &Rc<Expr>, bound: &mut Vec<(String, Term)>) -> Atom {
+        match atom(expr) {
+            Some(atom) => atom,
+            None => {
+                let name = self.name();
+                bound.push((name.to_string(), self.term(expr)));
+                Atom::Var(name)
+            }
+        }
+    }
+}
+
+fn atom(expr: &Rc<Expr>) -> Option<Atom> {
+    match &**expr {
+        Expr::Var(name, _, _) => Some(Atom::Var(name.to_string())),
+        Expr::Int(n, _) => Some(Atom::Int(n.clone())),
+        Expr::Float(n, _) => Some(Atom::Float(*n)),
+        Expr::Str(s, _) => Some(Atom::Str(s.to_string())),
+        Expr::Data(arity, typ, name, fields) if fields.is_empty() => {
+            Some(Atom::Con(*arity, typ.to_string(), name.to_string()))
+        }
+        Expr::Builtin(arity, name, func, fields, _) if fields.is_empty() => {
+            Some(Atom::Builtin(*arity, name.to_string(), *func))
+        }
+        Expr::Annot(inner, _) => atom(inner),
+        _ => None,
+    }
+}
+
+// the names are fresh so they can't be used by each other's definitions
+fn wrap(bound: Vec<(String, Term)>, term: Term) -> Term {
+    let mut term = term;
+    for (name, def) in bound.into_iter().rev() {
+        term = Term::Let(name, Rc::new(def), Rc::new(term));
+    }
+    term
+}
+
+impl Atom {
+    pub fn to_expr(&self) -> Rc<Expr> {
+        match self {
+            Atom::Var(name) => var(name),
+            Atom::Int(n) => Rc::new(Expr::Int(n.clone(), Span::default())),
+            Atom::Float(n) => Rc::new(Expr::Float(*n, Span::default())),
+            Atom::Str(s) => Rc::new(Expr::Str(s.to_string(), Span::default())),
+            Atom::Con(arity, typ, name) => Rc::new(Expr::Data(
+                *arity,
+                typ.to_string(),
+                name.to_string(),
+                Vec::new(),
+            )),
+            Atom::Builtin(arity, name, func) => Rc::new(Expr::Builtin(
+                *arity,
+                name.to_string(),
+                *func,
+                Vec::new(),
+                Span::default(),
+            )),
+        }
+    }
+}
+
+impl Term {
+    // the variables the term uses that it doesn't define, in the order they're first used
+    pub fn free_vars(&self) -> Vec<String> {
+        let mut found = Vec::new();
+        self.collect(&mut Vec::new(), &mut found);
+        found
+    }
+
+    fn collect(&self, bound: &mut Vec<String>, found: &mut Vec<String>) {
+        let mark = bound.len();
+        let mut atom = |a: &Atom, bound: &Vec<String>| {
+            if let Atom::Var(name) = a {
+                if !bound.contains(name) && !found.contains(name) {
+                    found.push(name.to_string());
+                }
+            }
+        };
+        match self {
+            Term::Atom(a) => atom(a, bound),
+            Term::App(func, args) => {
+                atom(func, bound);
+                for arg in args {
+                    atom(arg, bound);
+                }
+            }
+            Term::Lam(params, body) => {
+                bound.extend(params.iter().cloned());
+                body.collect(bound, found);
+            }
+            Term::Let(name, def, body) => {
+                def.collect(bound, found);
+                bound.push(name.to_string());
+                body.collect(bound, found);
+            }
+            Term::LetRec(defs, body) => {
+                bound.extend(defs.iter().map(|(name, _)| name.to_string()));
+                for (_, def) in defs {
+                    def.collect(bound, found);
+                }
+                body.collect(bound, found);
+            }
+            Term::Case(name, alts) => {
+                atom(&Atom::Var(name.to_string()), bound);
+                for (pat, branch) in alts {
+                    bound.extend(pat.vars().iter().map(|v| v.to_string()));
+                    branch.collect(bound, found);
+                    bound.truncate(mark);
+                }
+            }
+            Term::If(cond, b1, b2) => {
+                atom(cond, bound);
+                b1.collect(bound, found);
+                b2.collect(bound, found);
+            }
+            Term::Error(_) | Term::Bottom => (),
+        }
+        bound.truncate(mark);
+    }
+
+    // back to an Expr that eval can run, it has to be resolved first
+    pub fn to_expr(&self) -> Rc<Expr> {
+        match self {
+            Term::Atom(atom) => atom.to_expr(),
+            Term::App(func, args) => app(func.to_expr(), args.iter().map(Atom::to_expr).collect()),
+            Term::Lam(params, body) => {
+                let mut expr = body.to_expr();
+                for param in params.iter().rev() {
+                    expr = Rc::new(Expr::Lam(var(param), expr));
+                }
+                expr
+            }
+            Term::Let(name, def, body) => Rc::new(Expr::Let(
+                vec![var(name)],
+                vec![def.to_expr()],
+                body.to_expr(),
+            )),
+            Term::LetRec(defs, body) => Rc::new(Expr::LetRec(
+                defs.iter().map(|(name, _)| var(name)).collect(),
+                defs.iter().map(|(_, def)| def.to_expr()).collect(),
+                body.to_expr(),
+            )),
+            Term::Case(name, alts) => Rc::new(Expr::Case(
+                var(name),
+                alts.iter().map(|(p, _)| p.clone()).collect(),
+                alts.iter().map(|(_, b)| b.to_expr()).collect(),
+                vec![Span::default(); alts.len()],
+            )),
+            Term::If(cond, b1, b2) => Rc::new(Expr::If(cond.to_expr(), b1.to_expr(), b2.to_expr())),
+            Term::Error(s) => Rc::new(Expr::Error(s.to_string())),
+            Term::Bottom => Rc::new(Expr::Bottom),
+        }
+    }
+
+    // fits on the line it starts on
+    fn simple(&self) -> bool {
+        matches!(
+            self,
+            Term::Atom(_) | Term::App(_, _) | Term::Error(_) | Term::Bottom
+        )
+    }
+
+    // the rest of the line it starts on and the lines after it
+    fn show(&self, indent: usize, out: &mut String) {
+        let pad = "    ".repeat(indent);
+        match self {
+            Term::Atom(atom) => out.push_str(&atom.to_string()),
+            Term::App(func, args) => {
+                out.push_str(&func.to_string());
+                for arg in args {
+                    out.push(' ');
+                    out.push_str(&arg.to_string());
+                }
+            }
+            Term::Lam(params, body) => {
+                out.push_str(&format!("\\ {} .", params.join(" ")));
+                body.after(indent, out);
+            }
+            Term::Let(name, def, body) => {
+                out.push_str(&format!("let {} =", name));
+                def.after(indent, out);
+                if def.simple() {
+                    out.push_str(&format!(" in\n{}", pad));
+                } else {
+                    out.push_str(&format!("\n{}in\n{}", pad, pad));
+                }
+                body.show(indent, out);
+            }
+            Term::LetRec(defs, body) => {
+                for (i, (name, def)) in defs.iter().enumerate() {
+                    let keyword = if i == 0 { "letrec" } else { "and" };
+                    out.push_str(&format!("{} {} =", keyword, name));
+                    def.after(indent, out);
+                    out.push_str(&format!("\n{}", pad));
+                }
+                out.push_str(&format!("in\n{}", pad));
+                body.show(indent, out);
+            }
+            Term::Case(name, alts) => {
+                out.push_str(&format!("case {} {{", name));
+                for (pat, branch) in alts {
+                    out.push_str(&format!("\n{}    {} ->", pad, pat));
+                    branch.after(indent + 1, out);
+                }
+                out.push_str(&format!("\n{}}}", pad));
+            }
+            Term::If(cond, b1, b2) => {
+                out.push_str(&format!("if {} then", cond));
+                b1.after(indent, out);
+                out.push_str(&format!("\n{}else", pad));
+                b2.after(indent, out);
+            }
+            Term::Error(s) => out.push_str(&format!("error {:?}", s)),
+            Term::Bottom => out.push_str("undefined"),
+        }
+    }
+
+    // after something that ends with = or ->, on the same line if it fits and under it if it doesn't
+    fn after(&self, indent: usize, out: &mut String) {
+        if self.simple() {
+            out.push(' ');
+            self.show(indent, out);
+        } else {
+            out.push_str(&format!("\n{}", "    ".repeat(indent + 1)));
+            self.show(indent + 1, out);
+        }
+    }
+}
+
+impl Display for Atom {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Atom::Var(name) => write!(f, "{}", name),
+            Atom::Int(n) => write!(f, "{}", n),
+            Atom::Float(n) => write!(f, "{:?}", n),
+            Atom::Str(s) => write!(f, "{:?}", s),
+            // like a constructor that hasn't gotten its fields in Expr
+            Atom::Con(arity, _, name) if *arity > 0 => {
+                write!(f, "<{}{}>", name, " _".repeat(*arity))
+            }
+            Atom::Con(_, _, name) => write!(f, "{}", name),
+            Atom::Builtin(_, name, _) => write!(f, "({})", name),
+        }
+    }
+}
+
+impl Display for Term {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut out = String::new();
+        self.show(0, &mut out);
+        writeln!(f, "{}", out)
+    }
+}
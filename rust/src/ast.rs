@@ -1,46 +1,111 @@
+use crate::classes::ClassInfo;
+use crate::classes::InstanceInfo;
+use crate::diagnostic::Diagnostic;
+use crate::env::Binder;
 use crate::env::Env;
+use crate::info::DataInfo;
+use crate::info::TypeInfo;
+use crate::records::desugar;
 use num::bigint::BigInt;
+use num::Signed;
 
 use std::cell::RefCell;
-use std::collections::HashMap;
 use std::fmt::Display;
 use std::rc::Rc;
 
 #[derive(Debug)]
 pub enum Expr {
-    Var(String, RefCell<usize>), // store the environment depth
-    Int(BigInt),
-    Float(f64),
-    Str(String),
+    Var(String, RefCell<(usize, usize)>, Span), // store the environment depth and slot
+    Int(BigInt, Span), // literals know where they are for type errors, the ones made while evaluating don't
+    Float(f64, Span),
+    Str(String, Span),
     Lam(Rc<Expr>, Rc<Expr>),
     App(Rc<Expr>, Rc<Expr>),
     Let(Vec<Rc<Expr>>, Vec<Rc<Expr>>, Rc<Expr>), //vars, defs, body
     LetRec(Vec<Rc<Expr>>, Vec<Rc<Expr>>, Rc<Expr>), // vars, defs, body
     Data(usize, String, String, Vec<Rc<Expr>>),  // arguments, type, constructor, fields
-    Case(Rc<Expr>, Vec<Pattern>, Vec<Rc<Expr>>), // expression, patterns, branches
-    If(Rc<Expr>, Rc<Expr>, Rc<Expr>),            // condition, branch 1, branch 2
-    Builtin(usize, String, fn(Vec<Rc<Expr>>) -> Rc<Expr>, Vec<Rc<Expr>>), //arguments, representation, list of args to result, fields
-    Error(String), // halt program and print error
+    Case(Rc<Expr>, Vec<Pattern>, Vec<Rc<Expr>>, Vec<Span>), // expression, patterns, branches, pattern locations
+    If(Rc<Expr>, Rc<Expr>, Rc<Expr>),                       // condition, branch 1, branch 2
+    Builtin(
+        usize,
+        String,
+        fn(Vec<Rc<Expr>>) -> Rc<Expr>,
+        Vec<Rc<Expr>>,
+        Span,
+    ), //arguments, representation, list of args to result, fields, where the operator is
+    Error(String),                                          // halt program and print error
     Bottom,
+    Closure(Rc<Expr>, Rc<Env>), // an expression along with the environment it has to be evaluated in
+    Annot(Rc<Expr>, TypeInfo),  // expression with a declared type, also how signatures are attached
+    Record(String, Vec<(String, Rc<Expr>)>, Span), // constructor and named fields, desugared after parsing
+    Update(Rc<Expr>, Vec<(String, Rc<Expr>)>, Span), // record with some fields replaced, desugared after parsing
 }
 
 use crate::ast::Expr::*;
 
+// location in the source as byte offsets, the default is used for things that aren't in the source
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    // generated variables don't have a location
+    pub fn is_known(&self) -> bool {
+        self.end > 0
+    }
+
+    // line and column of the start, both counting from 1
+    pub fn line_col(&self, source: &str) -> (usize, usize) {
+        let mut line = 1;
+        let mut col = 1;
+        for (i, c) in source.char_indices() {
+            if i >= self.start {
+                break;
+            }
+            if c == '\n' {
+                line += 1;
+                col = 1;
+            } else {
+                col += 1;
+            }
+        }
+        (line, col)
+    }
+}
+
 impl Display for Expr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Var(s, _) => write!(f, "{}", s),
+            Var(s, _, _) => write!(f, "{}", s),
             Lam(head, body) => write!(f, "(\\ {} . {})", head, body),
             App(left, right) => write!(f, "({} {})", left, right),
-            Int(n) => write!(f, "{}", n),
-            Float(n) => write!(f, "{}", n),
-            Str(s) => write!(f, "{}", s),
-            Data(_args, _typ, str, fields) => {
-                write!(f, "({}", str)?;
+            Int(n, _) => write!(f, "{}", n),
+            Float(n, _) => write!(f, "{}", n),
+            Str(s, _) => write!(f, "{}", s),
+            // written like it would be in the source, a partially applied constructor has holes for what's missing
+            Data(args, _typ, str, fields) => {
+                let partial = fields.len() < *args;
+                if partial {
+                    write!(f, "<")?;
+                }
+                write!(f, "{}", str)?;
                 for field in fields {
-                    write!(f, " {}", field)?;
+                    write!(f, " ")?;
+                    write_field(f, field)?;
                 }
-                write!(f, ")")
+                if partial {
+                    for _ in fields.len()..*args {
+                        write!(f, " _")?;
+                    }
+                    write!(f, ">")?;
+                }
+                Ok(())
             }
             Let(vars, defs, body) => {
                 write!(f, "let ")?;
@@ -56,7 +121,7 @@ impl Display for Expr {
                 }
                 write!(f, "in {}", body)
             }
-            Case(expr, pats, branches) => {
+            Case(expr, pats, branches, _) => {
                 write!(f, "case {} of [", expr)?;
                 for i in 0..pats.len() {
                     write!(f, "{} -> {}; ", pats[i], branches[i])?;
@@ -64,7 +129,7 @@ impl Display for Expr {
                 write!(f, "]")
             }
             If(expr, b1, b2) => write!(f, "if {} {} {}", expr, b1, b2),
-            Builtin(_, str, _, fields) => {
+            Builtin(_, str, _, fields, _) => {
                 write!(f, "({}", str)?;
                 for field in fields {
                     write!(f, " {}", field)?;
@@ -73,24 +138,77 @@ impl Display for Expr {
             }
             Error(s) => write!(f, "Error {}", s),
             Bottom => write!(f, "_|_"),
+            Closure(expr, _) => write!(f, "{}", expr),
+            Annot(expr, typ) => write!(f, "({} :: {})", expr, typ),
+            Record(cons, fields, _) => {
+                write!(f, "{} {{", cons)?;
+                write_fields(f, fields)?;
+                write!(f, "}}")
+            }
+            Update(expr, fields, _) => {
+                write!(f, "({} {{", expr)?;
+                write_fields(f, fields)?;
+                write!(f, "}})")
+            }
+        }
+    }
+}
+
+// nested constructors and negative numbers need parentheses, strings get quoted
+fn write_field(f: &mut std::fmt::Formatter<'_>, field: &Rc<Expr>) -> std::fmt::Result {
+    match &**field {
+        Data(args, _, _, fields) if !fields.is_empty() && fields.len() == *args => {
+            write!(f, "({})", field)
+        }
+        Int(n, _) if n.is_negative() => write!(f, "({})", n),
+        Float(n, _) if *n < 0.0 => write!(f, "({})", n),
+        Str(s, _) => write!(f, "{:?}", s),
+        _ => write!(f, "{}", field),
+    }
+}
+
+fn write_fields(
+    f: &mut std::fmt::Formatter<'_>,
+    fields: &[(String, Rc<Expr>)],
+) -> std::fmt::Result {
+    for (i, (name, expr)) in fields.iter().enumerate() {
+        if i > 0 {
+            write!(f, ",")?;
         }
+        write!(f, " {} = {}", name, expr)?;
     }
+    write!(f, " ")
 }
 
 impl PartialEq for Expr {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
-            (Var(s1, _), Var(s2, _)) => s1 == s2,
+            (Var(s1, _, _), Var(s2, _, _)) => s1 == s2,
             _ => false,
         }
     }
 }
 
 // more convenient to hold the top level definitions rather than trying to copmress it into a single function to evaluate
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct Toplevel {
     pub data: Vec<Definition>,
     pub defs: Vec<Definition>,
+    pub types: Vec<DataInfo>,
+    pub sigs: Vec<Signature>,
+    pub classes: Vec<ClassInfo>,
+    pub instances: Vec<InstanceInfo>,
+    pub tests: Vec<(String, Span)>, // definitions marked with {-# TEST name #-}
+}
+
+// the different things that can be at the toplevel
+pub enum Item {
+    Definition(Rc<Expr>, Rc<Expr>),
+    Data(DataInfo),
+    Signature(Signature),
+    Class(ClassInfo),
+    Instance(InstanceInfo),
+    Test(String, Span),
 }
 
 impl Display for Toplevel {
@@ -108,8 +226,28 @@ impl Display for Toplevel {
 }
 
 impl Toplevel {
+    pub fn add(&mut self, item: Item) {
+        match item {
+            Item::Definition(var, def) => self.defs.push(Definition::new(var, def)),
+            Item::Data(info) => {
+                self.data.extend(info.to_definitions());
+                self.data.extend(info.to_accessors());
+                self.types.push(info);
+            }
+            Item::Signature(sig) => self.sigs.push(sig),
+            Item::Class(class) => self.classes.push(class),
+            Item::Instance(instance) => self.instances.push(instance),
+            Item::Test(name, span) => self.tests.push((name, span)),
+        }
+    }
+
     // convert the toplevel definition to a single let expression
     pub fn to_let(&self) -> Rc<Expr> {
+        self.to_let_entry("main")
+    }
+
+    // same as to_let, but evaluate something other than main
+    pub fn to_let_entry(&self, entry: &str) -> Rc<Expr> {
         let mut vars = Vec::new();
         let mut defs = Vec::new();
         for d in &self.data {
@@ -123,25 +261,136 @@ impl Toplevel {
         Rc::new(LetRec(
             vars,
             defs,
-            Rc::new(Var("main".to_string(), RefCell::new(0))),
+            Rc::new(Var(
+                entry.to_string(),
+                RefCell::new((0, 0)),
+                Span::default(),
+            )),
         ))
     }
 
-    // convert toplevel to environment definitions
+    // put the signatures onto their definitions, has to be done once everything is parsed
+    pub fn attach_signatures(self) -> Result<Toplevel, Diagnostic> {
+        let mut used = vec![false; self.sigs.len()];
+        let mut defs = Vec::new();
+        for d in &self.defs {
+            let def = annotate(&d.assign, Rc::clone(&d.def), &self.sigs, &mut used)?;
+            defs.push(Definition::new(Rc::clone(&d.assign), def));
+        }
+        unused_signature(&self.sigs, &used)?;
+        Ok(Toplevel { defs, ..self })
+    }
+
+    // replace the record syntax in every definition now that all the data declarations are known
+    pub fn desugar_records(self) -> Result<Toplevel, Diagnostic> {
+        let mut defs = Vec::new();
+        for d in &self.defs {
+            let def = desugar(&d.def, &self.types)?;
+            defs.push(Definition::new(Rc::clone(&d.assign), def));
+        }
+        Ok(Toplevel { defs, ..self })
+    }
+
+    // convert toplevel to environment definitions, the slots are in the same order as the variables of to_let
     pub fn to_env(&self) -> Env {
-        let env = Env::Empty;
-        let mut defs = HashMap::new();
+        let mut defs = Vec::new();
         for d in &self.data {
-            if let Expr::Var(s, _) = &*d.assign {
-                defs.insert(s.to_string(), Rc::clone(&d.def));
-            }
+            defs.push(Rc::clone(&d.def));
         }
         for d in &self.defs {
-            if let Expr::Var(s, _) = &*d.assign {
-                defs.insert(s.to_string(), Rc::clone(&d.def));
+            defs.push(Rc::clone(&d.def));
+        }
+        Env::Context(RefCell::new(defs), Rc::new(Env::Empty), Binder::Unnamed)
+    }
+}
+
+// a type signature for a variable
+#[derive(Debug, Clone)]
+pub struct Signature {
+    pub name: String,
+    pub span: Span,
+    pub typ: TypeInfo,
+}
+
+// the escapes in a string literal, "a \"b\"" is the string a "b" so show doesn't escape it a second time
+pub fn unescape(literal: &str) -> String {
+    let mut out = String::new();
+    let mut chars = literal.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('0') => out.push('\0'),
+            Some(c) => out.push(c),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+// \ a b . body for the given arguments
+pub fn lambdas(args: Vec<(String, Span)>, body: Rc<Expr>) -> Rc<Expr> {
+    let mut expr = body;
+    for (arg, span) in args.into_iter().rev() {
+        expr = Rc::new(Lam(Rc::new(Var(arg, RefCell::new((0, 0)), span)), expr));
+    }
+    expr
+}
+
+// variables and their definitions
+pub type Bindings = Vec<(Rc<Expr>, Rc<Expr>)>;
+
+// the definitions of a let, signatures get attached the same way as for the toplevel
+pub fn attach_signatures(defs: Bindings, sigs: Vec<Signature>) -> Result<Bindings, Diagnostic> {
+    let mut used = vec![false; sigs.len()];
+    let mut annotated = Vec::new();
+    for (var, def) in defs {
+        let def = annotate(&var, def, &sigs, &mut used)?;
+        annotated.push((var, def));
+    }
+    unused_signature(&sigs, &used)?;
+    Ok(annotated)
+}
+
+fn annotate(
+    var: &Rc<Expr>,
+    def: Rc<Expr>,
+    sigs: &[Signature],
+    used: &mut [bool],
+) -> Result<Rc<Expr>, Diagnostic> {
+    if let Var(name, _, _) = &**var {
+        let mut found = None;
+        for (i, sig) in sigs.iter().enumerate() {
+            if &sig.name == name {
+                if found.is_some() {
+                    return Err(Diagnostic::error(
+                        sig.span,
+                        format!("duplicate type signature for `{}`", name),
+                    ));
+                }
+                used[i] = true;
+                found = Some(sig);
             }
         }
-        Env::Context(defs, Rc::new(env))
+        if let Some(sig) = found {
+            return Ok(Rc::new(Annot(def, sig.typ.clone())));
+        }
+    }
+    Ok(def)
+}
+
+fn unused_signature(sigs: &[Signature], used: &[bool]) -> Result<(), Diagnostic> {
+    match sigs.iter().zip(used).find(|(_, used)| !**used) {
+        Some((sig, _)) => Err(Diagnostic::error(
+            sig.span,
+            format!("type signature for `{}` has no definition", sig.name),
+        )),
+        None => Ok(()),
     }
 }
 
@@ -155,6 +404,24 @@ impl Definition {
     pub fn new(assign: Rc<Expr>, def: Rc<Expr>) -> Definition {
         Definition { assign, def }
     }
+
+    pub fn name(&self) -> &str {
+        match &*self.assign {
+            Var(s, _, _) => s,
+            _ => panic!("Can only define variables."),
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match &*self.assign {
+            Var(_, _, span) => *span,
+            _ => Span::default(),
+        }
+    }
+
+    pub fn def(&self) -> &Rc<Expr> {
+        &self.def
+    }
 }
 
 impl Display for Definition {
@@ -171,10 +438,23 @@ pub enum Pattern {
     Int(BigInt),                    // literal patterns
     Float(f64),
     Str(String),
+    Record(String, Vec<(String, String)>), // constructor and (field, variable) pairs, desugared after parsing
 }
 
 // use crate::ast::Pattern;
 
+impl Pattern {
+    // the variables defined by matching the pattern
+    pub fn vars(&self) -> Vec<&str> {
+        match self {
+            Pattern::Irrefutable(s) => vec![s],
+            Pattern::Construct(_, vars) => vars.iter().map(|v| v.as_str()).collect(),
+            Pattern::Record(_, fields) => fields.iter().map(|(_, v)| v.as_str()).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
 impl Display for Pattern {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -190,6 +470,16 @@ impl Display for Pattern {
             Pattern::Int(i) => write!(f, "{}", i),
             Pattern::Float(n) => write!(f, "{}", n),
             Pattern::Str(s) => write!(f, "{}", s),
+            Pattern::Record(cons, fields) => {
+                write!(f, "{} {{", cons)?;
+                for (i, (field, var)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, " {} = {}", field, var)?;
+                }
+                write!(f, " }}")
+            }
         }
     }
 }
@@ -2,17 +2,25 @@
 
 definitions for some builtin functions
 
+the assertions for tests give back True, or an Error that stops evaluation with what went wrong
+    assertEq compares the values themselves, constructors and their fields all the way down, no Eq instance needed
+    constructors are strict so by the time the builtin sees a value it has been completely evaluated
+    a failure shows both values and where they first differ, the rest of the value is left out as _
+        first difference: Cons _ (Cons [2] _) vs Cons _ (Cons [3] _)
+
 */
 
 use crate::ast::Expr;
+use crate::ast::Span;
+use std::cmp::Ordering;
 use std::ops::Deref;
 use std::rc::Rc;
 
 pub fn add(args: Vec<Rc<Expr>>) -> Rc<Expr> {
     match Rc::deref(&args[0]) {
-        Expr::Int(a) => {
-            if let Expr::Int(b) = Rc::deref(&args[1]) {
-                Rc::new(Expr::Int(a + b))
+        Expr::Int(a, _) => {
+            if let Expr::Int(b, _) = Rc::deref(&args[1]) {
+                Rc::new(Expr::Int(a + b, Span::default()))
             } else {
                 panic!(
                     "Can only add numbers of the same type. {} {}",
@@ -20,9 +28,9 @@ pub fn add(args: Vec<Rc<Expr>>) -> Rc<Expr> {
                 );
             }
         }
-        Expr::Float(a) => {
-            if let Expr::Float(b) = Rc::deref(&args[1]) {
-                Rc::new(Expr::Float(a + b))
+        Expr::Float(a, _) => {
+            if let Expr::Float(b, _) = Rc::deref(&args[1]) {
+                Rc::new(Expr::Float(a + b, Span::default()))
             } else {
                 panic!("Can only add numbers of the same type.")
             }
@@ -33,16 +41,16 @@ pub fn add(args: Vec<Rc<Expr>>) -> Rc<Expr> {
 
 pub fn sub(args: Vec<Rc<Expr>>) -> Rc<Expr> {
     match Rc::deref(&args[0]) {
-        Expr::Int(a) => {
-            if let Expr::Int(b) = Rc::deref(&args[1]) {
-                Rc::new(Expr::Int(a - b))
+        Expr::Int(a, _) => {
+            if let Expr::Int(b, _) = Rc::deref(&args[1]) {
+                Rc::new(Expr::Int(a - b, Span::default()))
             } else {
                 panic!("Can only subtract numbers of the same type.");
             }
         }
-        Expr::Float(a) => {
-            if let Expr::Float(b) = Rc::deref(&args[1]) {
-                Rc::new(Expr::Float(a - b))
+        Expr::Float(a, _) => {
+            if let Expr::Float(b, _) = Rc::deref(&args[1]) {
+                Rc::new(Expr::Float(a - b, Span::default()))
             } else {
                 panic!("Can only subtract numbers of the same type.")
             }
@@ -53,16 +61,16 @@ pub fn sub(args: Vec<Rc<Expr>>) -> Rc<Expr> {
 
 pub fn mult(args: Vec<Rc<Expr>>) -> Rc<Expr> {
     match Rc::deref(&args[0]) {
-        Expr::Int(a) => {
-            if let Expr::Int(b) = Rc::deref(&args[1]) {
-                Rc::new(Expr::Int(a * b))
+        Expr::Int(a, _) => {
+            if let Expr::Int(b, _) = Rc::deref(&args[1]) {
+                Rc::new(Expr::Int(a * b, Span::default()))
             } else {
                 panic!("Can only multiply numbers of the same type.");
             }
         }
-        Expr::Float(a) => {
-            if let Expr::Float(b) = Rc::deref(&args[1]) {
-                Rc::new(Expr::Float(a * b))
+        Expr::Float(a, _) => {
+            if let Expr::Float(b, _) = Rc::deref(&args[1]) {
+                Rc::new(Expr::Float(a * b, Span::default()))
             } else {
                 panic!("Can only multiply numbers of the same type.")
             }
@@ -73,16 +81,16 @@ pub fn mult(args: Vec<Rc<Expr>>) -> Rc<Expr> {
 
 pub fn div(args: Vec<Rc<Expr>>) -> Rc<Expr> {
     match Rc::deref(&args[0]) {
-        Expr::Int(a) => {
-            if let Expr::Int(b) = Rc::deref(&args[1]) {
-                Rc::new(Expr::Int(a / b))
+        Expr::Int(a, _) => {
+            if let Expr::Int(b, _) = Rc::deref(&args[1]) {
+                Rc::new(Expr::Int(a / b, Span::default()))
             } else {
                 panic!("Can only divide numbers of the same type.");
             }
         }
-        Expr::Float(a) => {
-            if let Expr::Float(b) = Rc::deref(&args[1]) {
-                Rc::new(Expr::Float(a / b))
+        Expr::Float(a, _) => {
+            if let Expr::Float(b, _) = Rc::deref(&args[1]) {
+                Rc::new(Expr::Float(a / b, Span::default()))
             } else {
                 panic!("Can only divide numbers of the same type.")
             }
@@ -93,8 +101,8 @@ pub fn div(args: Vec<Rc<Expr>>) -> Rc<Expr> {
 
 pub fn eq(args: Vec<Rc<Expr>>) -> Rc<Expr> {
     match Rc::deref(&args[0]) {
-        Expr::Int(a) => {
-            if let Expr::Int(b) = Rc::deref(&args[1]) {
+        Expr::Int(a, _) => {
+            if let Expr::Int(b, _) = Rc::deref(&args[1]) {
                 if a == b {
                     Rc::new(Expr::Data(
                         0,
@@ -114,8 +122,8 @@ pub fn eq(args: Vec<Rc<Expr>>) -> Rc<Expr> {
                 panic!("Can only equate numbers of the same type.");
             }
         }
-        Expr::Float(a) => {
-            if let Expr::Float(b) = Rc::deref(&args[1]) {
+        Expr::Float(a, _) => {
+            if let Expr::Float(b, _) = Rc::deref(&args[1]) {
                 if a == b {
                     Rc::new(Expr::Data(
                         0,
@@ -135,6 +143,186 @@ pub fn eq(args: Vec<Rc<Expr>>) -> Rc<Expr> {
                 panic!("Can only equate numbers of the same type.")
             }
         }
-        _ => panic!("Can only dequate numbers."),
+        Expr::Str(a, _) => {
+            if let Expr::Str(b, _) = Rc::deref(&args[1]) {
+                boolean(a == b)
+            } else {
+                panic!("Can only equate strings with strings.")
+            }
+        }
+        _ => panic!("Can only equate numbers and strings."),
+    }
+}
+
+fn boolean(b: bool) -> Rc<Expr> {
+    let cons = if b { "True" } else { "False" };
+    Rc::new(Expr::Data(
+        0,
+        "Bool".to_string(),
+        cons.to_string(),
+        Vec::new(),
+    ))
+}
+
+// Less, Equal, or Greater
+pub fn compare(args: Vec<Rc<Expr>>) -> Rc<Expr> {
+    let order = match (Rc::deref(&args[0]), Rc::deref(&args[1])) {
+        (Expr::Int(a, _), Expr::Int(b, _)) => a.cmp(b),
+        (Expr::Float(a, _), Expr::Float(b, _)) => match a.partial_cmp(b) {
+            Some(order) => order,
+            None => panic!("Can't compare NaN."),
+        },
+        (Expr::Str(a, _), Expr::Str(b, _)) => a.cmp(b),
+        _ => panic!("Can only compare numbers or strings of the same type."),
+    };
+    let cons = match order {
+        Ordering::Less => "Less",
+        Ordering::Equal => "Equal",
+        Ordering::Greater => "Greater",
+    };
+    Rc::new(Expr::Data(
+        0,
+        "Ordering".to_string(),
+        cons.to_string(),
+        Vec::new(),
+    ))
+}
+
+// strings are quoted so they can be told apart from everything else
+pub fn show(args: Vec<Rc<Expr>>) -> Rc<Expr> {
+    match Rc::deref(&args[0]) {
+        Expr::Int(a, _) => Rc::new(Expr::Str(a.to_string(), Span::default())),
+        Expr::Float(a, _) => Rc::new(Expr::Str(a.to_string(), Span::default())),
+        Expr::Str(a, _) => Rc::new(Expr::Str(format!("{:?}", a), Span::default())),
+        _ => panic!("Can only show numbers and strings."),
+    }
+}
+
+// a shown value that is going to be a field of a constructor, anything with a space or a minus sign needs parentheses
+pub fn parens(args: Vec<Rc<Expr>>) -> Rc<Expr> {
+    match Rc::deref(&args[0]) {
+        Expr::Str(a, _) if needs_parens(a) => {
+            Rc::new(Expr::Str(format!("({})", a), Span::default()))
+        }
+        Expr::Str(_, _) => Rc::clone(&args[0]),
+        _ => panic!("Can only put parentheses around strings."),
+    }
+}
+
+// spaces inside of brackets or strings don't count
+fn needs_parens(shown: &str) -> bool {
+    let mut depth = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+    for c in shown.chars() {
+        if quoted {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => quoted = false,
+                _ => (),
+            }
+            continue;
+        }
+        match c {
+            '"' => quoted = true,
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            ' ' if depth == 0 => return true,
+            _ => (),
+        }
+    }
+    shown.starts_with('-')
+}
+
+pub fn concat(args: Vec<Rc<Expr>>) -> Rc<Expr> {
+    match (Rc::deref(&args[0]), Rc::deref(&args[1])) {
+        (Expr::Str(a, _), Expr::Str(b, _)) => {
+            Rc::new(Expr::Str(format!("{}{}", a, b), Span::default()))
+        }
+        _ => panic!("Can only concatenate strings."),
+    }
+}
+
+pub fn assert(args: Vec<Rc<Expr>>) -> Rc<Expr> {
+    match Rc::deref(&args[0]) {
+        Expr::Data(_, _, cons, _) if cons == "True" => Rc::clone(&args[0]),
+        Expr::Data(_, _, cons, _) if cons == "False" => {
+            Rc::new(Expr::Error("assert failed".to_string()))
+        }
+        _ => panic!("Can only assert booleans."),
+    }
+}
+
+pub fn assert_eq(args: Vec<Rc<Expr>>) -> Rc<Expr> {
+    match difference(&args[0], &args[1]) {
+        Ok(None) => boolean(true),
+        Ok(Some((left, right))) => Rc::new(Expr::Error(format!(
+            "assertEq failed\n    left:  {}\n    right: {}\n    first difference: {} vs {}",
+            shown(&args[0]),
+            shown(&args[1]),
+            left,
+            right
+        ))),
+        Err(message) => Rc::new(Expr::Error(message)),
+    }
+}
+
+// the two values with the first place they differ marked, None if they're the same
+fn difference(a: &Rc<Expr>, b: &Rc<Expr>) -> Result<Option<(String, String)>, String> {
+    let marked = || Ok(Some((format!("[{}]", shown(a)), format!("[{}]", shown(b)))));
+    match (Rc::deref(a), Rc::deref(b)) {
+        (Expr::Int(x, _), Expr::Int(y, _)) if x == y => Ok(None),
+        (Expr::Float(x, _), Expr::Float(y, _)) if x == y => Ok(None),
+        (Expr::Str(x, _), Expr::Str(y, _)) if x == y => Ok(None),
+        (Expr::Int(_, _), Expr::Int(_, _))
+        | (Expr::Float(_, _), Expr::Float(_, _))
+        | (Expr::Str(_, _), Expr::Str(_, _)) => marked(),
+        (Expr::Data(n1, _, c1, f1), Expr::Data(n2, _, c2, f2))
+            if f1.len() == *n1 && f2.len() == *n2 =>
+        {
+            if c1 != c2 {
+                return marked();
+            }
+            for (i, (x, y)) in f1.iter().zip(f2).enumerate() {
+                if let Some((left, right)) = difference(x, y)? {
+                    let around = |inner: String| {
+                        let fields: Vec<String> = (0..f1.len())
+                            .map(|j| {
+                                if j == i {
+                                    field(&inner)
+                                } else {
+                                    "_".to_string()
+                                }
+                            })
+                            .collect();
+                        format!("{} {}", c1, fields.join(" "))
+                    };
+                    return Ok(Some((around(left), around(right))));
+                }
+            }
+            Ok(None)
+        }
+        _ => Err(format!(
+            "assertEq can only compare values, not {} and {}",
+            shown(a),
+            shown(b)
+        )),
+    }
+}
+
+// strings are quoted so "1" and 1 don't look the same
+fn shown(expr: &Rc<Expr>) -> String {
+    match Rc::deref(expr) {
+        Expr::Str(s, _) => format!("{:?}", s),
+        _ => expr.to_string(),
+    }
+}
+
+fn field(inner: &str) -> String {
+    if inner.contains(' ') && !inner.starts_with('[') {
+        format!("({})", inner)
+    } else {
+        inner.to_string()
     }
 }
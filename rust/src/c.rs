@@ -0,0 +1,443 @@
+/*
+
+compile a program to C, one .c file with the runtime (runtime.c) at the top so any C compiler can build it
+    cc -O2 program.c -o program -lm -lpthread
+
+this takes the program after resolve, the lets are rearranged and every variable knows its (depth, slot)
+the generated code builds the same frames eval does, so the coordinates can be used as they are
+
+every expression becomes a C function of its environment that gives back the value
+    a lambda's body, the definition of a let, an argument that isn't simple enough to pass as it is
+    those are the thunks and closures, code and the frame it runs in
+a function keeps what it is working on in its slots on the runtime's stack, fp[0] is the environment it was given
+    the collector can move anything, so nothing is held in C variables across something that allocates
+
+constructors are numbered by info::Constructors, the alternatives of each data declaration in order
+    the dictionaries for classes aren't declared anywhere, they get numbers after all of the types in the order of the classes
+builtins are numbered by the table in the runtime, the names have to stay in the same order as it
+
+values are printed the same way Display does it, a lambda is printed from its source like eval's closures are
+
+*/
+
+use crate::ast::Expr;
+use crate::ast::Pattern;
+use crate::ast::Toplevel;
+use crate::classes::dict_constructor;
+use crate::info::Constructors;
+use num::ToPrimitive;
+use std::fmt::Write;
+use std::rc::Rc;
+
+const RUNTIME: &str = include_str!("runtime.c");
+
+// in the same order as the table in the runtime
+const BUILTINS: [&str; 11] = [
+    "+", "-", "*", "/", "++", "==", "compare", "show", "parens", "assert", "assertEq",
+];
+
+struct Lam {
+    code: usize,
+    lazy: bool,
+    shown: String,
+}
+
+// a C function being written, slots counts how many of fp it needs
+struct Function {
+    lines: Vec<String>,
+    indent: usize,
+    slots: usize,
+}
+
+impl Function {
+    fn new() -> Function {
+        Function {
+            lines: Vec::new(),
+            indent: 1,
+            slots: 1,
+        }
+    }
+
+    fn emit(&mut self, line: String) {
+        self.lines
+            .push(format!("{}{}", "    ".repeat(self.indent), line));
+    }
+
+    // fp[slot] gets the value
+    fn set(&mut self, slot: usize, value: String) {
+        self.uses(slot);
+        self.emit(format!("fp[{}] = {};", slot, value));
+    }
+
+    fn uses(&mut self, slot: usize) {
+        self.slots = self.slots.max(slot + 1);
+    }
+
+    fn open(&mut self, line: String) {
+        self.emit(line);
+        self.indent += 1;
+    }
+
+    fn close(&mut self, line: &str) {
+        self.indent -= 1;
+        self.emit(line.to_string());
+    }
+}
+
+struct Compiler {
+    cons: Constructors,
+    lams: Vec<Lam>,
+    functions: Vec<String>,
+}
+
+// the whole C program for the resolved expression
+pub fn to_c(expr: &Rc<Expr>, top: &Toplevel) -> String {
+    let mut compiler = Compiler::new(top);
+    let entry = compiler.function(expr);
+
+    let mut out = String::from(RUNTIME);
+    out.push_str("\n/* the program */\n\n");
+    for i in 0..compiler.functions.len() {
+        writeln!(out, "static Obj *code_{}(Obj *env);", i).unwrap();
+    }
+    out.push_str("\nstatic const Con con_table[] = {\n");
+    for con in &compiler.cons.tags {
+        writeln!(
+            out,
+            "    {{{}, {}, {}, {}}},",
+            c_string(&con.name),
+            con.arity,
+            con.type_index,
+            con.tag
+        )
+        .unwrap();
+    }
+    out.push_str("};\n\nstatic const Lam lam_table[] = {\n");
+    for lam in &compiler.lams {
+        writeln!(
+            out,
+            "    {{code_{}, {}, {}}},",
+            lam.code,
+            lam.lazy as u8,
+            c_string(&lam.shown)
+        )
+        .unwrap();
+    }
+    // C doesn't allow an empty array
+    out.push_str("    {NULL, 0, NULL}\n};\n");
+    for (i, function) in compiler.functions.iter().enumerate() {
+        write!(
+            out,
+            "\nstatic Obj *code_{}(Obj *env) {{\n{}}}\n",
+            i, function
+        )
+        .unwrap();
+    }
+    let id = |name: &str| compiler.cons.find(name).unwrap_or(0);
+    out.push_str("\nint main(void) {\n");
+    out.push_str("    cons = con_table;\n    lams = lam_table;\n");
+    for (var, name) in &[
+        ("con_true", "True"),
+        ("con_false", "False"),
+        ("con_less", "Less"),
+        ("con_equal", "Equal"),
+        ("con_greater", "Greater"),
+    ] {
+        writeln!(out, "    {} = {};", var, id(name)).unwrap();
+    }
+    writeln!(out, "    return rt_main(code_{});\n}}", entry).unwrap();
+    out
+}
+
+impl Compiler {
+    fn new(top: &Toplevel) -> Compiler {
+        let mut cons = Constructors::new(&top.types);
+        for class in top.classes.iter().filter(|c| c.dictionary) {
+            cons.id(
+                class.methods.len(),
+                &class.name,
+                &dict_constructor(&class.name),
+            );
+        }
+        Compiler {
+            cons,
+            lams: Vec::new(),
+            functions: Vec::new(),
+        }
+    }
+
+    // a C function evaluating the expression in the environment it is given, gives back its number
+    fn function(&mut self, expr: &Rc<Expr>) -> usize {
+        let index = self.functions.len();
+        self.functions.push(String::new());
+        let mut f = Function::new();
+        self.expr(&mut f, expr, 0, 1, 2);
+        let mut body = format!("    Obj **fp = rt_enter({}, env);\n", f.slots);
+        for line in &f.lines {
+            body.push_str(line);
+            body.push('\n');
+        }
+        body.push_str("    return rt_leave(fp, fp[1]);\n");
+        self.functions[index] = body;
+        index
+    }
+
+    fn lam(&mut self, expr: &Rc<Expr>, head: &Rc<Expr>, body: &Rc<Expr>) -> usize {
+        let code = self.function(body);
+        let lazy = matches!(&**head, Expr::Var(s, _, _) if s == "_");
+        self.lams.push(Lam {
+            code,
+            lazy,
+            shown: expr.to_string(),
+        });
+        self.lams.len() - 1
+    }
+
+    // put the value of expr in fp[target], the environment is in fp[env] and everything from free up can be used
+    fn expr(&mut self, f: &mut Function, expr: &Rc<Expr>, env: usize, target: usize, free: usize) {
+        match &**expr {
+            Expr::Var(_, coords, _) => {
+                let (depth, slot) = *coords.borrow();
+                f.set(target, format!("rt_var(fp[{}], {}, {})", env, depth, slot));
+            }
+            Expr::Int(_, _) | Expr::Float(_, _) | Expr::Str(_, _) => f.set(target, literal(expr)),
+            Expr::Lam(head, body) => {
+                let lam = self.lam(expr, head, body);
+                f.set(target, format!("rt_fun({}, fp[{}])", lam, env));
+            }
+            Expr::App(_, _) => self.app(f, expr, env, target, free),
+            Expr::Let(_, defs, body) => {
+                for (i, def) in defs.iter().enumerate() {
+                    self.delay(f, def, env, free + i, false);
+                }
+                let frame = free + defs.len();
+                f.set(
+                    frame,
+                    format!("rt_frame(fp[{}], {}, &fp[{}])", env, defs.len(), free),
+                );
+                self.expr(f, body, frame, target, frame + 1);
+            }
+            Expr::LetRec(_, defs, body) => {
+                f.set(free, format!("rt_frame(fp[{}], {}, NULL)", env, defs.len()));
+                for (i, def) in defs.iter().enumerate() {
+                    self.delay(f, def, free, free + 1, true);
+                    f.emit(format!("rt_set(fp[{}], {}, fp[{}]);", free, i, free + 1));
+                }
+                self.expr(f, body, free, target, free + 1);
+            }
+            Expr::If(cond, b1, b2) => {
+                self.expr(f, cond, env, free, free + 1);
+                f.open(format!("if (rt_is_true(fp[{}])) {{", free));
+                self.expr(f, b1, env, target, free);
+                f.indent -= 1;
+                f.open("} else {".to_string());
+                self.expr(f, b2, env, target, free);
+                f.close("}");
+            }
+            Expr::Case(cond, pats, branches, _) => {
+                self.expr(f, cond, env, free, free + 1);
+                let value = format!("fp[{}]", free);
+                let frame = free + 1;
+                for (i, (pat, branch)) in pats.iter().zip(branches).enumerate() {
+                    let test = self.test(pat, &value);
+                    if i == 0 {
+                        f.open(format!("if ({}) {{", test));
+                    } else {
+                        f.indent -= 1;
+                        f.open(format!("}} else if ({}) {{", test));
+                    }
+                    let slots = match pat {
+                        Pattern::Construct(_, _) => {
+                            format!("rt_frame_fields(fp[{}], {})", env, value)
+                        }
+                        Pattern::Irrefutable(_) => format!("rt_frame(fp[{}], 1, &{})", env, value),
+                        _ => format!("rt_frame(fp[{}], 0, NULL)", env),
+                    };
+                    f.set(frame, slots);
+                    self.expr(f, branch, frame, target, frame + 1);
+                }
+                if !pats.is_empty() {
+                    f.indent -= 1;
+                    f.open("} else {".to_string());
+                }
+                f.set(target, "rt_error(\"No pattern matched.\")".to_string());
+                if !pats.is_empty() {
+                    f.close("}");
+                }
+            }
+            Expr::Data(arity, typ, name, _) => {
+                let con = self.cons.id(*arity, typ, name);
+                f.set(target, format!("rt_con({})", con));
+            }
+            Expr::Builtin(_, name, _, _, _) => {
+                f.set(target, format!("rt_builtin({})", builtin(name)))
+            }
+            Expr::Error(s) => f.set(
+                target,
+                format!("rt_error({})", c_string(&format!("Error: {}", s))),
+            ),
+            Expr::Bottom => f.set(target, "rt_error(\"Ran into undefined.\")".to_string()),
+            Expr::Annot(inner, _) => self.expr(f, inner, env, target, free),
+            _ => panic!("Can't compile {} to C.", expr),
+        }
+    }
+
+    // the condition for a pattern to match the value
+    fn test(&mut self, pat: &Pattern, value: &str) -> String {
+        match pat {
+            Pattern::Wildcard | Pattern::Irrefutable(_) => "1".to_string(),
+            Pattern::Construct(name, vars) => match self.cons.find(name) {
+                Some(con) => format!("rt_is_con({}, {}, {})", value, con, vars.len()),
+                None => "0".to_string(),
+            },
+            // an integer too big for 64 bits can't be a value
+            Pattern::Int(n) => match n.to_i64() {
+                Some(i) => format!("rt_is_int({}, {})", value, int(i)),
+                None => "0".to_string(),
+            },
+            Pattern::Float(n) => format!("rt_is_float({}, {})", value, float(*n)),
+            Pattern::Str(s) => format!("rt_is_str({}, {}, {})", value, c_string(s), s.len()),
+            Pattern::Record(_, _) => panic!("Records should be desugared before compiling."),
+        }
+    }
+
+    // applications go through rt_apply one argument at a time, unless a builtin or constructor gets all of its arguments
+    fn app(&mut self, f: &mut Function, expr: &Rc<Expr>, env: usize, target: usize, free: usize) {
+        let mut args = Vec::new();
+        let mut head = expr;
+        loop {
+            match &**head {
+                Expr::App(left, right) => {
+                    args.push(right);
+                    head = left;
+                }
+                Expr::Annot(inner, _) => head = inner,
+                _ => break,
+            }
+        }
+        args.reverse();
+        for (i, arg) in args.iter().enumerate() {
+            self.delay(f, arg, env, free + i, false);
+        }
+        let saturated = match &**head {
+            Expr::Builtin(arity, name, _, fields, _)
+                if fields.is_empty() && args.len() >= *arity =>
+            {
+                Some((*arity, format!("rt_prim({}, &fp[{}])", builtin(name), free)))
+            }
+            Expr::Data(arity, typ, name, fields)
+                if fields.is_empty() && *arity > 0 && args.len() >= *arity =>
+            {
+                let con = self.cons.id(*arity, typ, name);
+                Some((*arity, format!("rt_data({}, &fp[{}])", con, free)))
+            }
+            _ => None,
+        };
+        let applied = match saturated {
+            Some((arity, call)) => {
+                for i in free..free + arity {
+                    f.set(i, format!("rt_force(fp[{}])", i));
+                }
+                f.set(target, call);
+                arity
+            }
+            None => {
+                let function = free + args.len();
+                self.expr(f, head, env, function, function + 1);
+                f.set(target, format!("rt_apply(fp[{}], fp[{}])", function, free));
+                1
+            }
+        };
+        for i in applied..args.len() {
+            f.set(
+                target,
+                format!("rt_apply(fp[{}], fp[{}])", target, free + i),
+            );
+        }
+    }
+
+    // put something in fp[slot] that gives the value of expr when it is forced
+    // the definitions of a letrec can't look up their neighbours yet, they might not be there
+    fn delay(&mut self, f: &mut Function, expr: &Rc<Expr>, env: usize, slot: usize, rec: bool) {
+        match &**expr {
+            Expr::Int(_, _) | Expr::Float(_, _) | Expr::Str(_, _) => f.set(slot, literal(expr)),
+            Expr::Var(_, coords, _) if !rec => {
+                let (depth, index) = *coords.borrow();
+                f.set(
+                    slot,
+                    format!("rt_lookup(fp[{}], {}, {})", env, depth, index),
+                );
+            }
+            Expr::Lam(head, body) => {
+                let lam = self.lam(expr, head, body);
+                f.set(slot, format!("rt_fun({}, fp[{}])", lam, env));
+            }
+            Expr::Data(_, _, _, fields) | Expr::Builtin(_, _, _, fields, _)
+                if fields.is_empty() =>
+            {
+                self.expr(f, expr, env, slot, slot + 1)
+            }
+            Expr::Annot(inner, _) => self.delay(f, inner, env, slot, rec),
+            _ => {
+                let code = self.function(expr);
+                f.set(slot, format!("rt_thunk(code_{}, fp[{}])", code, env));
+            }
+        }
+    }
+}
+
+fn builtin(name: &str) -> usize {
+    match BUILTINS.iter().position(|b| *b == name) {
+        Some(i) => i,
+        None => panic!("The C runtime doesn't have the builtin {}.", name),
+    }
+}
+
+fn literal(expr: &Expr) -> String {
+    match expr {
+        Expr::Int(n, _) => match n.to_i64() {
+            Some(i) => format!("rt_int({})", int(i)),
+            None => format!("rt_error(\"The integer {} doesn't fit in 64 bits.\")", n),
+        },
+        Expr::Float(n, _) => format!("rt_float({})", float(*n)),
+        Expr::Str(s, _) => format!("rt_str({}, {})", c_string(s), s.len()),
+        _ => panic!("{} isn't a literal.", expr),
+    }
+}
+
+// the smallest integer can't be written as a literal, it is the negation of one that is too big
+fn int(i: i64) -> String {
+    if i == i64::MIN {
+        "INT64_MIN".to_string()
+    } else {
+        format!("INT64_C({})", i)
+    }
+}
+
+// rust writes the shortest exponent form that reads back as the same number
+fn float(n: f64) -> String {
+    if n.is_nan() {
+        "NAN".to_string()
+    } else if n.is_infinite() {
+        if n > 0.0 { "HUGE_VAL" } else { "-HUGE_VAL" }.to_string()
+    } else {
+        format!("{:e}", n)
+    }
+}
+
+// escapes for anything that isn't printable ascii, octal has to have all three digits so a digit after it isn't taken as part of it
+fn c_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for b in s.bytes() {
+        match b {
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            // trigraphs
+            b'?' => out.push_str("\\?"),
+            0x20..=0x7e => out.push(b as char),
+            _ => write!(out, "\\{:03o}", b).unwrap(),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -39,7 +39,7 @@ fn check_total(info: DataInfo, expr: Rc<Expr>) -> bool {
     // only works on case statements
     // either there is a match all pattern or all contructors are found
     // need to provide proper type info to know which constructors to look for
-    if let Expr::Case(_, pats, _) = Rc::deref(&expr) {
+    if let Expr::Case(_, pats, _, _) = Rc::deref(&expr) {
         let mut found = Vec::new();
         for pat in pats {
             match pat {
@@ -73,7 +73,7 @@ fn check_total(info: DataInfo, expr: Rc<Expr>) -> bool {
 pub fn check_cases(expr: Rc<Expr>) -> bool {
     // go through ast and check all cases statements, if any are false it fails
     match &*expr {
-        Expr::Case(_, pats, branches) => {
+        Expr::Case(_, pats, branches, _) => {
             for pat in pats {
                 if check_pattern(pat) {
                     continue;
@@ -115,6 +115,7 @@ pub fn check_cases(expr: Rc<Expr>) -> bool {
         Expr::If(cond, b1, b2) => {
             check_cases(Rc::clone(cond)) && check_cases(Rc::clone(b1)) && check_cases(Rc::clone(b2))
         }
+        Expr::Annot(expr, _) => check_cases(Rc::clone(expr)),
         _ => true,
     }
 }
@@ -167,11 +168,12 @@ pub fn shadowing(expr: Rc<Expr>, defined: Vec<Rc<Expr>>) -> bool {
             }
             shadowing(Rc::clone(expr), defined)
         }
-        Expr::Var(_, _) => defined.contains(&expr),
+        // using a variable doesn't shadow anything, only binding it again does
+        Expr::Var(_, _, _) => false,
         Expr::App(left, right) => {
             shadowing(Rc::clone(left), defined.clone()) || shadowing(Rc::clone(right), defined)
         }
-        Expr::Case(expr, _, branches) => {
+        Expr::Case(expr, _, branches, _) => {
             for branch in branches {
                 if shadowing(Rc::clone(&branch), defined.clone()) {
                     return true;
@@ -186,6 +188,7 @@ pub fn shadowing(expr: Rc<Expr>, defined: Vec<Rc<Expr>>) -> bool {
                 || shadowing(Rc::clone(b1), defined.clone())
                 || shadowing(Rc::clone(b2), defined)
         }
+        Expr::Annot(expr, _) => shadowing(Rc::clone(expr), defined),
         _ => false,
     }
 }
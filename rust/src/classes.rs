@@ -0,0 +1,510 @@
+/*
+
+type classes, done with dictionary passing
+
+    class Show a { show :: a -> Str };
+    instance Show a => Show (List a) { show xs = ... };
+    showAll :: Show a => List a -> Str;
+
+a dictionary holds the methods of a class for one type
+    a function with a constraint takes the dictionary as an extra argument
+    calling it passes the dictionary for the type it is used at
+    showAll xs        becomes        showAll Show.Int xs
+figuring out which dictionary goes where happens during type checking (infer) and the rewriting happens in elaborate
+
+every name made here has a dot in it so it can't clash with anything written in the source
+    Show.dict               the constructor for dictionaries of Show
+    Show.List               the dictionary for Show (List a), a function of the dictionary for Show a
+    Show.List.show          the show method of that instance, a toplevel definition with the method type as its signature
+                                Show.List.show :: Show a => List a -> Str
+    show                    picks the method out of a dictionary
+                                show = \d . case d {Show.dict _0 -> _0}
+before elaborating the method is only a placeholder so that the name is in scope for checking names
+
+builtin classes
+    Eq, Ord, and Show have instances for Int, Float, and Str that use the builtins
+    Num is only there for the arithmetic builtins, it doesn't have a dictionary since the builtins look at the values themselves
+Bool and Ordering get declared if the program doesn't declare them itself, deriving Eq, Ord, and Show
+
+deriving generates the instance methods from the constructors, every type parameter gets the same constraint
+    List a = Cons a (List a) | Nil deriving (Eq)
+    instance Eq a => Eq (List a) {
+        eq x y = case x {
+            Cons a0 a1 -> case y {Cons b0 b1 -> if eq a0 b0 then (if eq a1 b1 then True else False) else False; _ -> False};
+            Nil -> case y {Nil -> True; _ -> False}
+        }
+    }
+compare goes by the order the constructors are declared in and then the fields from left to right
+show writes values the way they would be written in the source
+    Cons 1 (Cons 2 Nil)
+    Person { name = "bob", age = 31 }
+    a field is put in parentheses when it has a space or is negative (builtins::parens), so it works for any instance of Show
+    constructors are always prefix, the grammar doesn't have operators as constructors
+
+*/
+
+use crate::ast::Definition;
+use crate::ast::Expr;
+use crate::ast::Pattern;
+use crate::ast::Span;
+use crate::ast::Toplevel;
+use crate::builtins;
+use crate::diagnostic::Diagnostic;
+use crate::info::create_data_info;
+use crate::info::DataInfo;
+use crate::info::ProdInfo;
+use crate::info::TypeInfo;
+use crate::records::positions;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[derive(Debug, Clone)]
+pub struct ClassInfo {
+    pub name: String,
+    pub var: String,                      // the type the class is about
+    pub methods: Vec<(String, TypeInfo)>, // names and types, the types mention var
+    pub dictionary: bool,                 // false for classes that are handled by builtins
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct InstanceInfo {
+    pub class: String,
+    pub typ: TypeInfo, // a type constructor applied to distinct variables
+    pub context: Vec<(String, String)>, // constraints on the variables of typ
+    pub methods: Vec<(String, Span, Rc<Expr>)>,
+    pub span: Span,
+}
+
+impl ClassInfo {
+    pub fn method(&self, name: &str) -> Option<&TypeInfo> {
+        self.methods.iter().find(|(m, _)| m == name).map(|(_, t)| t)
+    }
+}
+
+impl InstanceInfo {
+    pub fn type_name(&self) -> String {
+        self.typ.get_name()
+    }
+
+    // the dictionary, or a function from the context's dictionaries to the dictionary
+    pub fn dict_name(&self) -> String {
+        format!("{}.{}", self.class, self.type_name())
+    }
+
+    pub fn method_name(&self, method: &str) -> String {
+        format!("{}.{}.{}", self.class, self.type_name(), method)
+    }
+
+    // the type a method has to have in this instance
+    pub fn method_type(&self, class: &ClassInfo, method: &TypeInfo) -> TypeInfo {
+        // variables of the instance can't be captured by the other variables of the method
+        let taken = method.vars();
+        let mut typ = self.typ.clone();
+        let mut context = self.context.clone();
+        for v in self.typ.vars() {
+            if v != class.var && taken.contains(&v) {
+                let renamed = format!("{}'", v);
+                typ = typ.substitute(&v, &TypeInfo::TVar(renamed.to_string()));
+                for (_, var) in context.iter_mut() {
+                    if *var == v {
+                        *var = renamed.to_string();
+                    }
+                }
+            }
+        }
+        let method = method.substitute(&class.var, &typ);
+        if context.is_empty() {
+            method
+        } else {
+            TypeInfo::TConstrained(context, Box::new(method))
+        }
+    }
+}
+
+// constraints are parsed as types, they have to be a class applied to a type variable
+pub fn constraints(
+    context: Vec<TypeInfo>,
+    span: Span,
+) -> Result<Vec<(String, String)>, Diagnostic> {
+    let mut constraints = Vec::new();
+    for typ in context {
+        if let TypeInfo::TApp(class, var) = &typ {
+            if let (TypeInfo::TConstructor(c), TypeInfo::TVar(v)) = (&**class, &**var) {
+                constraints.push((c.to_string(), v.to_string()));
+                continue;
+            }
+        }
+        return Err(Diagnostic::error(
+            span,
+            format!(
+                "`{}` isn't a constraint, constraints look like `Show a`",
+                typ
+            ),
+        ));
+    }
+    Ok(constraints)
+}
+
+// the class and type of an instance, the type has to be a type constructor applied to distinct variables
+pub fn instance_head(head: TypeInfo, span: Span) -> Result<(String, TypeInfo), Diagnostic> {
+    if let TypeInfo::TApp(class, typ) = &head {
+        if let TypeInfo::TConstructor(c) = &**class {
+            let args = typ.args();
+            let mut constructor = &**typ;
+            while let TypeInfo::TApp(left, _) = constructor {
+                constructor = left;
+            }
+            let vars = args.iter().all(|arg| matches!(arg, TypeInfo::TVar(_)));
+            let distinct = vars && typ.vars().len() == args.len();
+            if let (TypeInfo::TConstructor(_), true) = (constructor, distinct) {
+                return Ok((c.to_string(), (**typ).clone()));
+            }
+            return Err(Diagnostic::error(
+                span,
+                format!(
+                    "can't have an instance `{}`, an instance has to be for a type applied to distinct type variables",
+                    head
+                ),
+            ));
+        }
+    }
+    Err(Diagnostic::error(
+        span,
+        format!(
+            "`{}` isn't an instance, instances look like `Show (List a)`",
+            head
+        ),
+    ))
+}
+
+// the constructor that dictionaries of a class are made with
+pub fn dict_constructor(class: &str) -> String {
+    format!("{}.dict", class)
+}
+
+// the builtin classes and instances, plus Bool and Ordering if they aren't declared
+pub fn prelude(top: Toplevel) -> Toplevel {
+    let mut top = top;
+    let a = || TypeInfo::TVar("a".to_string());
+    let con = |name: &str| TypeInfo::TConstructor(name.to_string());
+    let fun = |arg: TypeInfo, res: TypeInfo| TypeInfo::TFun(Box::new(arg), Box::new(res));
+    let class = |name: &str, methods: Vec<(&str, TypeInfo)>, dictionary: bool| ClassInfo {
+        name: name.to_string(),
+        var: "a".to_string(),
+        methods: methods
+            .into_iter()
+            .map(|(m, t)| (m.to_string(), t))
+            .collect(),
+        dictionary,
+        span: Span::default(),
+    };
+    let mut classes = vec![
+        class("Eq", vec![("eq", fun(a(), fun(a(), con("Bool"))))], true),
+        class(
+            "Ord",
+            vec![("compare", fun(a(), fun(a(), con("Ordering"))))],
+            true,
+        ),
+        class("Show", vec![("show", fun(a(), con("Str")))], true),
+        class("Num", Vec::new(), false),
+    ];
+    classes.append(&mut top.classes);
+    top.classes = classes;
+
+    let mut instances = Vec::new();
+    for name in &["Int", "Float", "Str"] {
+        let builtin = |class: &str, method: &str, expr: Expr| InstanceInfo {
+            class: class.to_string(),
+            typ: con(name),
+            context: Vec::new(),
+            methods: vec![(method.to_string(), Span::default(), Rc::new(expr))],
+            span: Span::default(),
+        };
+        let two = |name: &str, func: fn(Vec<Rc<Expr>>) -> Rc<Expr>| {
+            Expr::Builtin(2, name.to_string(), func, Vec::new(), Span::default())
+        };
+        instances.push(builtin("Eq", "eq", two("==", builtins::eq)));
+        instances.push(builtin("Ord", "compare", two("compare", builtins::compare)));
+        instances.push(builtin(
+            "Show",
+            "show",
+            Expr::Builtin(
+                1,
+                "show".to_string(),
+                builtins::show,
+                Vec::new(),
+                Span::default(),
+            ),
+        ));
+        if *name != "Str" {
+            instances.push(InstanceInfo {
+                methods: Vec::new(),
+                ..builtin("Num", "", Expr::Bottom)
+            });
+        }
+    }
+    instances.append(&mut top.instances);
+    top.instances = instances;
+
+    for (name, cons) in &[
+        ("Bool", vec!["True", "False"]),
+        ("Ordering", vec!["Less", "Equal", "Greater"]),
+    ] {
+        if top.types.iter().any(|t| &t.type_info.get_name() == name) {
+            continue;
+        }
+        let alts = cons
+            .iter()
+            .map(|c| ProdInfo::new(c.to_string(), Vec::new(), Span::default()))
+            .collect();
+        let mut info = create_data_info(vec![name.to_string()], alts, Span::default());
+        for class in &["Eq", "Ord", "Show"] {
+            info.deriving.push((class.to_string(), Span::default()));
+        }
+        top.data.extend(info.to_definitions());
+        top.types.push(info);
+    }
+    top
+}
+
+// make the instances asked for with deriving
+pub fn derive(top: Toplevel) -> Result<Toplevel, Diagnostic> {
+    let mut top = top;
+    let mut derived = Vec::new();
+    for info in &top.types {
+        for (class, span) in &info.deriving {
+            let (method, def) = match class.as_str() {
+                "Eq" => ("eq", derive_eq(info)),
+                "Ord" => ("compare", derive_compare(info)),
+                "Show" => ("show", derive_show(info)),
+                _ => {
+                    return Err(Diagnostic::error(
+                        *span,
+                        format!(
+                            "can't derive `{}`, only Eq, Ord, and Show can be derived",
+                            class
+                        ),
+                    ))
+                }
+            };
+            let context = info
+                .type_info
+                .vars()
+                .into_iter()
+                .map(|v| (class.to_string(), v))
+                .collect();
+            derived.push(InstanceInfo {
+                class: class.to_string(),
+                typ: info.type_info.clone(),
+                context,
+                methods: vec![(method.to_string(), *span, def)],
+                span: *span,
+            });
+        }
+    }
+    top.instances.extend(derived);
+    Ok(top)
+}
+
+// placeholders for the methods and a definition for each method of each instance
+pub fn add_class_definitions(top: Toplevel) -> Toplevel {
+    let mut top = top;
+    for class in &top.classes {
+        for (method, _) in &class.methods {
+            top.data
+                .push(Definition::new(var(method), Rc::new(Expr::Bottom)));
+        }
+    }
+    for instance in &top.instances {
+        let class = match top.classes.iter().find(|c| c.name == instance.class) {
+            Some(class) => class,
+            None => continue,
+        };
+        for (method, _, def) in &instance.methods {
+            if let Some(typ) = class.method(method) {
+                let typ = instance.method_type(class, typ);
+                top.defs.push(Definition::new(
+                    var(&instance.method_name(method)),
+                    Rc::new(Expr::Annot(Rc::clone(def), typ)),
+                ));
+            }
+        }
+    }
+    top
+}
+
+fn derive_eq(info: &DataInfo) -> Rc<Expr> {
+    let alts = &info.data_info.alts;
+    let mut branches = Vec::new();
+    for alt in alts {
+        let n = alt.args.len();
+        let mut same = var("True");
+        for k in (0..n).rev() {
+            let test = app(var("eq"), vec![var(&left(k)), var(&right(k))]);
+            same = Rc::new(Expr::If(test, same, var("False")));
+        }
+        let mut pats = vec![Pattern::Construct(alt.name.to_string(), rights(n))];
+        let mut inner = vec![same];
+        if alts.len() > 1 {
+            pats.push(Pattern::Wildcard);
+            inner.push(var("False"));
+        }
+        branches.push(case(var("_y"), pats, inner));
+    }
+    let pats = alts
+        .iter()
+        .map(|alt| Pattern::Construct(alt.name.to_string(), lefts(alt.args.len())))
+        .collect();
+    lams(&["_x", "_y"], case(var("_x"), pats, branches))
+}
+
+fn derive_compare(info: &DataInfo) -> Rc<Expr> {
+    let alts = &info.data_info.alts;
+    let mut branches = Vec::new();
+    for (i, alt) in alts.iter().enumerate() {
+        let mut inner = Vec::new();
+        for j in 0..alts.len() {
+            if j < i {
+                inner.push(var("Greater"));
+            } else if j > i {
+                inner.push(var("Less"));
+            } else {
+                // the first field that isn't equal decides
+                let mut order = var("Equal");
+                for k in (0..alt.args.len()).rev() {
+                    let field = app(var("compare"), vec![var(&left(k)), var(&right(k))]);
+                    order = case(
+                        field,
+                        vec![
+                            Pattern::Construct("Equal".to_string(), Vec::new()),
+                            Pattern::Irrefutable("_o".to_string()),
+                        ],
+                        vec![order, var("_o")],
+                    );
+                }
+                inner.push(order);
+            }
+        }
+        let pats = alts
+            .iter()
+            .map(|alt| Pattern::Construct(alt.name.to_string(), rights(alt.args.len())))
+            .collect();
+        branches.push(case(var("_y"), pats, inner));
+    }
+    let pats = alts
+        .iter()
+        .map(|alt| Pattern::Construct(alt.name.to_string(), lefts(alt.args.len())))
+        .collect();
+    lams(&["_x", "_y"], case(var("_x"), pats, branches))
+}
+
+// Cons x y shows as Cons (show x) (show y), a record as Cons { a = show a, b = show b }
+// there is nothing to show infix, a constructor is always a capitalized name applied to its fields
+fn derive_show(info: &DataInfo) -> Rc<Expr> {
+    let alts = &info.data_info.alts;
+    let mut branches = Vec::new();
+    for alt in alts {
+        let n = alt.args.len();
+        let mut shown = string(&alt.name);
+        if alt.fields.is_empty() {
+            for k in 0..n {
+                let field = app(var("show"), vec![var(&left(k))]);
+                shown = concat(shown, concat(string(" "), parens(field)));
+            }
+        } else {
+            for (k, field) in alt.fields.iter().enumerate() {
+                let sep = if k == 0 { " { " } else { ", " };
+                shown = concat(shown, string(&format!("{}{} = ", sep, field)));
+                shown = concat(shown, app(var("show"), vec![var(&left(k))]));
+            }
+            shown = concat(shown, string(" }"));
+        }
+        branches.push(shown);
+    }
+    let pats = alts
+        .iter()
+        .map(|alt| Pattern::Construct(alt.name.to_string(), lefts(alt.args.len())))
+        .collect();
+    lams(&["_x"], case(var("_x"), pats, branches))
+}
+
+// the fields of the first and second value being looked at
+fn left(k: usize) -> String {
+    format!("_a{}", k)
+}
+
+fn right(k: usize) -> String {
+    format!("_b{}", k)
+}
+
+fn lefts(n: usize) -> Vec<String> {
+    (0..n).map(left).collect()
+}
+
+fn rights(n: usize) -> Vec<String> {
+    (0..n).map(right).collect()
+}
+
+pub fn var(name: &str) -> Rc<Expr> {
+    Rc::new(Expr::Var(
+        name.to_string(),
+        RefCell::new((0, 0)),
+        Span::default(),
+    ))
+}
+
+pub fn app(func: Rc<Expr>, args: Vec<Rc<Expr>>) -> Rc<Expr> {
+    let mut expr = func;
+    for arg in args {
+        expr = Rc::new(Expr::App(expr, arg));
+    }
+    expr
+}
+
+pub fn lams(params: &[&str], body: Rc<Expr>) -> Rc<Expr> {
+    let mut expr = body;
+    for param in params.iter().rev() {
+        expr = Rc::new(Expr::Lam(var(param), expr));
+    }
+    expr
+}
+
+fn case(scrutinee: Rc<Expr>, pats: Vec<Pattern>, branches: Vec<Rc<Expr>>) -> Rc<Expr> {
+    let spans = vec![Span::default(); pats.len()];
+    Rc::new(Expr::Case(scrutinee, pats, branches, spans))
+}
+
+fn string(s: &str) -> Rc<Expr> {
+    Rc::new(Expr::Str(s.to_string(), Span::default()))
+}
+
+fn parens(shown: Rc<Expr>) -> Rc<Expr> {
+    let builtin = Expr::Builtin(
+        1,
+        "parens".to_string(),
+        builtins::parens,
+        Vec::new(),
+        Span::default(),
+    );
+    app(Rc::new(builtin), vec![shown])
+}
+
+fn concat(a: Rc<Expr>, b: Rc<Expr>) -> Rc<Expr> {
+    let builtin = Expr::Builtin(
+        2,
+        "++".to_string(),
+        builtins::concat,
+        Vec::new(),
+        Span::default(),
+    );
+    app(Rc::new(builtin), vec![a, b])
+}
+
+// dictionaries are a constructor holding the methods, selecting a method is a case on that
+pub fn selector(class: &ClassInfo, index: usize) -> Rc<Expr> {
+    let vars = positions(class.methods.len());
+    let method = var(&vars[index]);
+    let pats = vec![Pattern::Construct(dict_constructor(&class.name), vars)];
+    lams(&["_d"], case(var("_d"), pats, vec![method]))
+}
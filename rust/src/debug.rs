@@ -0,0 +1,216 @@
+/*
+
+watching eval work, both of these are hooks (eval::Hook) so eval tells them about every reduction
+
+the tracer writes a line for each reduction
+       12  depth 5  spine 1  env 3  lookup fib
+    depth is how deeply eval is nested, spine is how many arguments are waiting, env is how many frames are in scope
+
+the debugger stops and asks what to do
+    step            stop at the next reduction
+    next            stop at the next reduction that isn't nested deeper than this one, skips over evaluating arguments
+    continue        run until a breakpoint
+    break <name>    stop whenever the definition is looked up
+    delete <name>   remove a breakpoint
+    env [n]         show the frames of the environment, innermost first, only n of them if given
+    where           show the current reduction again
+    quit            stop evaluating
+an empty line steps, the end of the input lets the program run to the end
+
+env shows each slot with the name it was bound to, taken from the lambda, let, or case branch that made the frame
+a frame that wasn't made by eval doesn't know its names, its slots are shown by position
+
+*/
+
+use crate::ast::Expr;
+use crate::env::Env;
+use crate::eval::Event;
+use crate::eval::Hook;
+use crate::eval::State;
+use std::io::BufRead;
+use std::io::Write;
+use std::rc::Rc;
+
+// how much of a value is shown
+const SHORT: usize = 60;
+
+pub struct Tracer<W: Write> {
+    output: W,
+}
+
+impl<W: Write> Tracer<W> {
+    pub fn new(output: W) -> Tracer<W> {
+        Tracer { output }
+    }
+}
+
+impl<W: Write> Hook for Tracer<W> {
+    fn event(&mut self, event: &Event, state: &State) -> bool {
+        // the trace is best effort, a closed output shouldn't stop the program
+        let _ = writeln!(self.output, "{}", describe(event, state));
+        true
+    }
+}
+
+enum Mode {
+    Step,
+    Next(usize), // stop once eval is back at this depth
+    Continue,
+    Detached, // no more input, just run
+}
+
+pub struct Debugger<R: BufRead, W: Write> {
+    input: R,
+    output: W,
+    breakpoints: Vec<String>,
+    mode: Mode,
+}
+
+impl<R: BufRead, W: Write> Debugger<R, W> {
+    // without breakpoints it stops right away, otherwise it runs until the first one
+    pub fn new(input: R, output: W, breakpoints: Vec<String>) -> Debugger<R, W> {
+        let mode = if breakpoints.is_empty() {
+            Mode::Step
+        } else {
+            Mode::Continue
+        };
+        Debugger {
+            input,
+            output,
+            breakpoints,
+            mode,
+        }
+    }
+
+    fn stops(&self, event: &Event, state: &State) -> bool {
+        let breakpoint = match event {
+            Event::Lookup(name) => self.breakpoints.iter().any(|b| b == name),
+            _ => false,
+        };
+        match self.mode {
+            Mode::Step => true,
+            Mode::Next(depth) => breakpoint || state.depth <= depth,
+            Mode::Continue => breakpoint,
+            Mode::Detached => false,
+        }
+    }
+
+    // keep asking until a command resumes evaluation, false if it should stop
+    fn prompt(&mut self, event: &Event, state: &State) -> std::io::Result<bool> {
+        writeln!(self.output, "{}", describe(event, state))?;
+        loop {
+            write!(self.output, "(bagl) ")?;
+            self.output.flush()?;
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                self.mode = Mode::Detached;
+                return Ok(true);
+            }
+            let mut words = line.split_whitespace();
+            let command = words.next().unwrap_or("step");
+            let arg = words.next();
+            match (command, arg) {
+                ("s", _) | ("step", _) => {
+                    self.mode = Mode::Step;
+                    return Ok(true);
+                }
+                ("n", _) | ("next", _) => {
+                    self.mode = Mode::Next(state.depth);
+                    return Ok(true);
+                }
+                ("c", _) | ("continue", _) => {
+                    self.mode = Mode::Continue;
+                    return Ok(true);
+                }
+                ("q", _) | ("quit", _) => return Ok(false),
+                ("b", Some(name)) | ("break", Some(name)) => {
+                    if !self.breakpoints.iter().any(|b| b == name) {
+                        self.breakpoints.push(name.to_string());
+                    }
+                    writeln!(self.output, "breakpoint on {}", name)?;
+                }
+                ("b", None) | ("break", None) => {
+                    if self.breakpoints.is_empty() {
+                        writeln!(self.output, "no breakpoints")?;
+                    }
+                    for b in &self.breakpoints {
+                        writeln!(self.output, "breakpoint on {}", b)?;
+                    }
+                }
+                ("d", Some(name)) | ("delete", Some(name)) => {
+                    let before = self.breakpoints.len();
+                    self.breakpoints.retain(|b| b != name);
+                    if self.breakpoints.len() == before {
+                        writeln!(self.output, "no breakpoint on {}", name)?;
+                    } else {
+                        writeln!(self.output, "deleted breakpoint on {}", name)?;
+                    }
+                }
+                ("e", n) | ("env", n) => {
+                    let limit = n.and_then(|n| n.parse().ok()).unwrap_or(usize::MAX);
+                    self.env(state.env, limit)?;
+                }
+                ("w", _) | ("where", _) => writeln!(self.output, "{}", describe(event, state))?,
+                ("h", _) | ("help", _) => writeln!(
+                    self.output,
+                    "step, next, continue, break [name], delete <name>, env [n], where, quit"
+                )?,
+                _ => writeln!(self.output, "unknown command `{}`, try help", line.trim())?,
+            }
+        }
+    }
+
+    fn env(&mut self, env: &Rc<Env>, limit: usize) -> std::io::Result<()> {
+        let mut env = &**env;
+        let mut frame = 0;
+        while let Env::Context(slots, next, binder) = env {
+            if frame == limit {
+                break;
+            }
+            writeln!(self.output, "frame {}", frame)?;
+            let names = binder.names();
+            for (i, slot) in slots.borrow().iter().enumerate() {
+                match names.get(i) {
+                    Some(name) => writeln!(self.output, "    {}: {}", name, short(slot))?,
+                    None => writeln!(self.output, "    {}: {}", i, short(slot))?,
+                }
+            }
+            env = next;
+            frame += 1;
+        }
+        if frame == 0 {
+            writeln!(self.output, "the environment is empty")?;
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead, W: Write> Hook for Debugger<R, W> {
+    fn event(&mut self, event: &Event, state: &State) -> bool {
+        if !self.stops(event, state) {
+            return true;
+        }
+        // if the terminal goes away there's nobody to ask, so just run
+        self.prompt(event, state).unwrap_or(true)
+    }
+}
+
+fn describe(event: &Event, state: &State) -> String {
+    format!(
+        "{:>8}  depth {}  spine {}  env {}  {}",
+        state.steps,
+        state.depth,
+        state.spine,
+        state.env.depth(),
+        event
+    )
+}
+
+// the start of a value, closures can be whole functions
+fn short(expr: &Rc<Expr>) -> String {
+    let shown = expr.to_string();
+    match shown.char_indices().nth(SHORT) {
+        Some((i, _)) => format!("{}...", &shown[..i]),
+        None => shown,
+    }
+}
@@ -0,0 +1,78 @@
+/*
+
+errors and warnings found before running a program
+
+each one points at a place in the source, generated code doesn't have a location and only the message gets shown
+
+*/
+
+use crate::ast::Span;
+use std::fmt::Display;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub span: Span,
+    pub message: String,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(span: Span, message: String) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            span,
+            message,
+            help: None,
+        }
+    }
+
+    pub fn warning(span: Span, message: String) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Warning,
+            span,
+            message,
+            help: None,
+        }
+    }
+
+    pub fn with_help(self, help: String) -> Diagnostic {
+        Diagnostic {
+            help: Some(help),
+            ..self
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    // message with the location filled in from the source it came from
+    pub fn render(&self, filename: &str, source: &str) -> String {
+        let mut out = if self.span.is_known() {
+            let (line, col) = self.span.line_col(source);
+            format!("{}:{}:{}: {}", filename, line, col, self)
+        } else {
+            format!("{}: {}", filename, self)
+        };
+        if let Some(help) = &self.help {
+            out.push_str(&format!("\n    help: {}", help));
+        }
+        out
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.severity {
+            Severity::Error => write!(f, "error: {}", self.message),
+            Severity::Warning => write!(f, "warning: {}", self.message),
+        }
+    }
+}
@@ -0,0 +1,172 @@
+/*
+
+turn the overloading into plain functions by passing dictionaries around, runs before change_lets
+
+check_types works out which dictionary goes where, this puts them into the tree
+    a definition with constraints gets a lambda for each dictionary
+        showAll = \xs . ...                    showAll = \_Show1 . \xs . ...
+    a use of something with constraints gets the dictionaries as arguments
+        showAll (Cons 1 Nil)                   showAll Show.Int (Cons 1 Nil)
+    a method used at a known instance goes straight to the instance's definition
+        show 1                                 Show.Int.show
+        show (Cons 1 Nil)                      Show.List.show Show.Int
+    otherwise the method picks itself out of the dictionary
+        show x                                 show _Show1 x
+the placeholders for the methods become the selectors and the dictionaries are added to the toplevel
+    Show.List = \_d0 . Show.dict (Show.List.show _d0)
+
+the annotations are dropped since the types don't match anymore
+
+*/
+
+use crate::ast::Expr;
+use crate::ast::Toplevel;
+use crate::classes::app;
+use crate::classes::dict_constructor;
+use crate::classes::lams;
+use crate::classes::selector;
+use crate::classes::var;
+use crate::infer::Dict;
+use crate::infer::Elaboration;
+use std::rc::Rc;
+
+// the program from Toplevel::to_let with the dictionaries passed explicitly
+pub fn elaborate(expr: &Rc<Expr>, elaboration: &Elaboration, top: &Toplevel) -> Rc<Expr> {
+    let expr = rewrite(expr, elaboration, top);
+    match &*expr {
+        Expr::LetRec(vars, defs, body) => {
+            let mut vars = vars.to_vec();
+            let mut defs = defs.to_vec();
+            for (name, def) in dictionaries(top) {
+                vars.push(var(&name));
+                defs.push(def);
+            }
+            Rc::new(Expr::LetRec(vars, defs, Rc::clone(body)))
+        }
+        _ => expr,
+    }
+}
+
+fn rewrite(expr: &Rc<Expr>, elaboration: &Elaboration, top: &Toplevel) -> Rc<Expr> {
+    let go = |e: &Rc<Expr>| rewrite(e, elaboration, top);
+    match &**expr {
+        Expr::Var(name, _, _) => {
+            let key = Rc::as_ptr(expr);
+            if let Some(dict) = elaboration.methods.get(&key) {
+                match dict {
+                    Dict::Instance(instance, subs) => app(
+                        var(&format!("{}.{}", instance, name)),
+                        subs.iter().map(dictionary).collect(),
+                    ),
+                    _ => app(Rc::clone(expr), vec![dictionary(dict)]),
+                }
+            } else if let Some(dicts) = elaboration.args.get(&key) {
+                app(Rc::clone(expr), dicts.iter().map(dictionary).collect())
+            } else {
+                Rc::clone(expr)
+            }
+        }
+        Expr::Lam(head, body) => Rc::new(Expr::Lam(Rc::clone(head), go(body))),
+        Expr::App(left, right) => Rc::new(Expr::App(go(left), go(right))),
+        Expr::If(cond, b1, b2) => Rc::new(Expr::If(go(cond), go(b1), go(b2))),
+        Expr::Case(cond, pats, branches, spans) => Rc::new(Expr::Case(
+            go(cond),
+            pats.to_vec(),
+            branches.iter().map(go).collect(),
+            spans.to_vec(),
+        )),
+        Expr::Let(vars, defs, body) => Rc::new(Expr::Let(
+            vars.to_vec(),
+            definitions(vars, defs, elaboration, top),
+            go(body),
+        )),
+        Expr::LetRec(vars, defs, body) => Rc::new(Expr::LetRec(
+            vars.to_vec(),
+            definitions(vars, defs, elaboration, top),
+            go(body),
+        )),
+        Expr::Annot(inner, _) => go(inner),
+        _ => Rc::clone(expr),
+    }
+}
+
+// definitions take their dictionaries first, the placeholders become selectors
+fn definitions(
+    vars: &[Rc<Expr>],
+    defs: &[Rc<Expr>],
+    elaboration: &Elaboration,
+    top: &Toplevel,
+) -> Vec<Rc<Expr>> {
+    let mut new_defs = Vec::new();
+    for (v, def) in vars.iter().zip(defs) {
+        if let Some(selected) = placeholder(v, def, top) {
+            new_defs.push(selected);
+            continue;
+        }
+        let def = rewrite(def, elaboration, top);
+        let params = match elaboration.params.get(&Rc::as_ptr(v)) {
+            Some(params) => params.iter().map(|p| p.as_str()).collect(),
+            None => Vec::new(),
+        };
+        new_defs.push(lams(&params, def));
+    }
+    new_defs
+}
+
+fn placeholder(v: &Rc<Expr>, def: &Rc<Expr>, top: &Toplevel) -> Option<Rc<Expr>> {
+    match (&**v, &**def) {
+        (Expr::Var(name, _, span), Expr::Bottom) if !span.is_known() => {
+            for class in &top.classes {
+                if let Some(index) = class.methods.iter().position(|(m, _)| m == name) {
+                    return Some(selector(class, index));
+                }
+            }
+            None
+        }
+        _ => None,
+    }
+}
+
+fn dictionary(dict: &Dict) -> Rc<Expr> {
+    match dict {
+        Dict::Instance(name, subs) => app(var(name), subs.iter().map(dictionary).collect()),
+        Dict::Param(name) => var(name),
+        Dict::None => Rc::new(Expr::Error("no dictionary".to_string())),
+    }
+}
+
+// a dictionary for each instance, a function of the dictionaries for its context
+fn dictionaries(top: &Toplevel) -> Vec<(String, Rc<Expr>)> {
+    let mut dicts = Vec::new();
+    for instance in &top.instances {
+        let class = match top.classes.iter().find(|c| c.name == instance.class) {
+            Some(class) if class.dictionary => class,
+            _ => continue,
+        };
+        let params: Vec<String> = instance
+            .context
+            .iter()
+            .filter(|(c, _)| top.classes.iter().any(|k| &k.name == c && k.dictionary))
+            .enumerate()
+            .map(|(i, _)| format!("_d{}", i))
+            .collect();
+        let args: Vec<Rc<Expr>> = params.iter().map(|p| var(p)).collect();
+        let methods = class
+            .methods
+            .iter()
+            .map(|(m, _)| app(var(&instance.method_name(m)), args.clone()))
+            .collect();
+        let constructor = Rc::new(Expr::Data(
+            class.methods.len(),
+            class.name.to_string(),
+            dict_constructor(&class.name),
+            Vec::new(),
+        ));
+        let params: Vec<&str> = params.iter().map(|p| p.as_str()).collect();
+        dicts.push((
+            instance.dict_name(),
+            lams(&params, app(constructor, methods)),
+        ));
+    }
+    dicts
+}
@@ -1,90 +1,122 @@
 /*
 The environment for looking up variables
+
+every binder adds a frame, the variables it defines are slots in the frame
+scan works out how many frames up and which slot a variable is in, so a lookup is just following the chain and indexing
+
+letrecs need the frame to exist before their definitions can be put into it, so the slots can be replaced
+this is also how a let definition gets replaced by its value after being evaluated the first time
+both can leave a frame holding closures over itself, those cycles are freed by the collector in gc.rs
+
+a frame also keeps what made it, the names of the slots are only needed by the debugger so they're worked out from that when asked for
 */
 
 use crate::ast::Expr;
 use crate::env::Env::*;
 use core::fmt::Display;
-use std::collections::HashMap;
+use std::cell::RefCell;
 use std::ops::Deref;
 use std::rc::Rc;
 
-#[derive(Debug, Clone)]
 pub enum Env {
-    Empty,                                       //empty environment
-    Context(HashMap<String, Rc<Expr>>, Rc<Env>), //current defintions and the next environemnt up
+    Empty,                                            //empty environment
+    Context(RefCell<Vec<Rc<Expr>>>, Rc<Env>, Binder), //current defintions, the next environemnt up, and what made it
+}
+
+// what made a frame
+#[derive(Clone)]
+pub enum Binder {
+    Unnamed,                 // made outside of eval, the slots are only known by position
+    Expr(Rc<Expr>),          // a lambda, let, or letrec
+    Branch(Rc<Expr>, usize), // a case and which of its branches was taken
+}
+
+impl Binder {
+    // the names of the slots in order, empty when they aren't known
+    pub fn names(&self) -> Vec<String> {
+        match self {
+            Binder::Unnamed => Vec::new(),
+            Binder::Expr(expr) => match &**expr {
+                Expr::Lam(head, _) => vec![head.to_string()],
+                Expr::Let(vars, _, _) | Expr::LetRec(vars, _, _) => {
+                    vars.iter().map(|v| v.to_string()).collect()
+                }
+                _ => Vec::new(),
+            },
+            Binder::Branch(expr, i) => match &**expr {
+                Expr::Case(_, pats, _, _) => {
+                    pats[*i].vars().iter().map(|v| v.to_string()).collect()
+                }
+                _ => Vec::new(),
+            },
+        }
+    }
 }
 
 impl Display for Env {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
             Empty => write!(f, "Empty"),
-            Context(defs, next) => {
-                write!(f, "Context(")?;
-                for key in defs.keys() {
-                    write!(f, "{}, ", key)?;
-                }
-                write!(f, "{})", next)
-            }
+            Context(defs, next, _) => write!(f, "Context({} slots, {})", defs.borrow().len(), next),
         }
     }
 }
 
+// the slots can refer back to the environment through closures, so don't print them
+impl std::fmt::Debug for Env {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl Default for Env {
+    fn default() -> Env {
+        Env::new()
+    }
+}
+
 impl Env {
     pub fn new() -> Env {
         Empty
     }
-    pub fn lookup(&self, s: &String) -> Option<&Rc<Expr>> {
-        match self {
-            Empty => None,
-            Context(defs, next) => {
-                if let Some(val) = defs.get(s) {
-                    Some(val)
-                } else {
-                    next.lookup(s)
-                }
-            }
-        }
-    }
 
-    pub fn lookup_top(&self, s: &String) -> Option<&Rc<Expr>> {
-        // only look at the top of the environment
-        match self {
-            Empty => None,
-            Context(defs, _) => defs.get(s),
-        }
+    // a new frame on top of next
+    pub fn extend(defs: Vec<Rc<Expr>>, next: Rc<Env>, binder: Binder) -> Rc<Env> {
+        Rc::new(Context(RefCell::new(defs), next, binder))
     }
 
     pub fn depth(&self) -> usize {
         match self {
             Empty => 0,
-            Context(_, next) => 1 + next.depth(),
+            Context(_, next, _) => 1 + next.depth(),
         }
     }
 
     // drop n environments from the top
     pub fn drop(&self, n: usize) -> &Env {
-        if n == 0 {
-            self
-        } else {
-            match self {
-                Empty => self,
-                Context(_, next) => Rc::deref(next).drop(n - 1),
+        let mut env = self;
+        for _ in 0..n {
+            match env {
+                Empty => return env,
+                Context(_, next, _) => env = Rc::deref(next),
             }
         }
+        env
     }
 
-    // get the ith sub environment, treating list as a vector where Empty is 0
-    pub fn sub_env(&self, i: usize) -> &Env {
-        self.drop(self.depth() - i)
-    }
-
-    pub fn lookup_at(&self, s: &String, i: usize) -> Option<&Rc<Expr>> {
-        self.sub_env(i).lookup(s)
+    // the value in a slot of the frame depth levels up
+    pub fn lookup(&self, depth: usize, slot: usize) -> Option<Rc<Expr>> {
+        match self.drop(depth) {
+            Empty => None,
+            Context(defs, _, _) => defs.borrow().get(slot).map(Rc::clone),
+        }
     }
 
-    pub fn lookup_in(&self, s: &String, i: usize) -> Option<&Rc<Expr>> {
-        // drop the top i envs then try to lookup variable
-        self.drop(i).lookup(s)
+    pub fn set(&self, depth: usize, slot: usize, value: Rc<Expr>) {
+        if let Context(defs, _, _) = self.drop(depth) {
+            if let Some(old) = defs.borrow_mut().get_mut(slot) {
+                *old = value;
+            }
+        }
     }
 }
@@ -8,160 +8,589 @@ meaning if we were to do (and x y) even if x is false we wil still evaluate y be
 could deal with this by bolting on laziness via thunks or could make a special function that does not evaluate its argument, but still consumes it
 
 dealing with all lets as letrecs
+
+
+with the environment being frames of slots the evaluation has to become lexically scoped
+    a variable has to be evaluated in the environment it was defined in, not where it gets used
+    so anything put in the environment or on the spine is paired up with its environment in a closure
+
+    arguments are evaluated when a lambda takes them off of the spine, unless the argument is _
+    let definitions are left as closures until they are looked up, then the slot is replaced by the value
+    a lambda with nothing left on the spine is returned as a closure
+
+evaluation can be given a budget so a program that doesn't stop can't take the whole process with it
+    steps       every reduction is a step
+    time        checked every so often, looking at the clock every step is slow
+    heap        a rough count of the bytes allocated for closures, frames, and values, nothing is subtracted when they are freed
+    depth       how deeply eval is nested, running out of stack would abort the process instead of giving an error
+        a tail call (the function of an application, a body, a branch) is reduced in the same call of eval, so a loop doesn't nest
+        a lookup is one too unless there's a hook, which has to be told when the value returns
+running out of any of them stops evaluation with Exhausted, which says how far it got
+
+a Hook gets told about each reduction as it happens, that is how tracing and the debugger (debug.rs) see what eval is doing
+    a lookup is told about before the value is evaluated and again with Return once it has been applied to the spine
+    so everything in between is the cost of using that variable, the profiler (profile.rs) counts costs that way
+
+frames that refer back to themselves (a letrec, a let replaced by a closure over its frame) are never freed by reference counting
+    so the machine has a Heap (gc.rs) that tracks every frame it makes and collects the ones only kept alive by cycles every so often
+    heap() is what the collector has seen, collect() runs it right away, eval and eval_with_budget collect before they return
+
+with the strictness analysis (strict.rs) lets and arguments that are going to be forced anyway are evaluated right away instead of becoming closures
 */
 
 use crate::ast::Expr;
 use crate::ast::Pattern;
+use crate::env::Binder;
 use crate::env::Env;
+use crate::gc::Heap;
+use crate::gc::HeapStats;
+use crate::lift::is_lazy;
+use crate::strict::Strictness;
 
-use std::cell::RefCell;
-use std::collections::HashMap;
-use std::ops::Deref;
+use std::fmt::Display;
+use std::mem::size_of;
 use std::rc::Rc;
+use std::time::Duration;
+use std::time::Instant;
+
+// the limits for a run, None is unlimited
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Budget {
+    pub steps: Option<u64>,
+    pub timeout: Option<Duration>,
+    pub heap: Option<usize>, // bytes
+    pub depth: Option<usize>,
+}
 
+// how far evaluation got
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    pub steps: u64,
+    pub elapsed: Duration,
+    pub allocated: usize,
+    pub allocations: u64, // Expr nodes
+    pub max_depth: usize,
+    pub last: Option<String>, // the last variable that was looked up
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+    Steps,
+    Timeout,
+    Heap,
+    Depth,
+    Stopped, // a hook asked to stop
+}
+
+// evaluation ran out of its budget
+#[derive(Debug, Clone)]
+pub struct Exhausted {
+    pub limit: Limit,
+    pub stats: Stats,
+}
+
+// the reductions a hook gets told about
+pub enum Event<'a> {
+    Lookup(&'a str),           // a variable is about to be evaluated
+    Return(&'a str),           // the variable has been evaluated and applied to the spine
+    Beta(&'a str),             // a lambda took its argument off of the spine
+    Construct(&'a str, usize), // a constructor got all of its fields
+    Builtin(&'a str),          // a builtin got all of its arguments
+    Branch(&'a Pattern),       // the pattern of the case branch that was chosen
+    If(bool),
+}
+
+// where eval is when something happens
+pub struct State<'a> {
+    pub steps: u64,
+    pub allocations: u64,
+    pub expr: &'a Rc<Expr>, // what is being reduced, the variable for Lookup and Return
+    pub depth: usize,       // how deeply eval is nested
+    pub spine: usize,       // arguments waiting to be applied
+    pub env: &'a Rc<Env>,
+}
+
+// what a reduction gives back, tail calls go back around the loop in reduce
+enum Reduced {
+    Value(Rc<Expr>),
+    Tail(Rc<Expr>, Rc<Env>, Vec<Rc<Expr>>),
+}
+
+// false stops evaluation
+pub trait Hook {
+    fn event(&mut self, event: &Event, state: &State) -> bool;
+}
+
+// how often the clock is checked
+const CLOCK_INTERVAL: u64 = 1024;
+
+pub struct Machine<'a> {
+    budget: Budget,
+    stats: Stats,
+    start: Instant,
+    depth: usize,
+    last: Option<Rc<Expr>>,
+    hook: Option<&'a mut dyn Hook>,
+    strictness: Option<&'a Strictness>,
+    heap: Heap,
+}
+
+// evaluate without any limits
 pub fn eval(expr: Rc<Expr>, env: Rc<Env>, spine: Vec<Rc<Expr>>) -> Rc<Expr> {
-    match &*expr {
-        Expr::If(cond, b1, b2) => {
-            let d = eval(Rc::clone(cond), Rc::clone(&env), spine.clone());
-            if let Expr::Data(_, _, s, _) = &*d {
-                if s == "True" {
-                    eval(Rc::clone(b1), env, spine)
-                } else if s == "False" {
-                    eval(Rc::clone(b2), env, spine)
-                } else {
-                    panic!("If expression needs condition to be a boolean.");
-                }
-            } else {
-                panic!("Condition in if needs to be a boolean.");
+    let mut machine = Machine::new(Budget::default());
+    let value = machine.eval(expr, env, spine);
+    machine.collect();
+    match value {
+        Ok(value) => value,
+        Err(exhausted) => panic!("{}", exhausted),
+    }
+}
+
+// evaluate a program within the budget
+pub fn eval_with_budget(expr: Rc<Expr>, budget: Budget) -> Result<Rc<Expr>, Exhausted> {
+    let mut machine = Machine::new(budget);
+    let value = machine.eval(expr, Rc::new(Env::new()), Vec::new());
+    machine.collect();
+    value
+}
+
+impl<'a> Machine<'a> {
+    pub fn new(budget: Budget) -> Machine<'a> {
+        Machine {
+            budget,
+            stats: Stats::default(),
+            start: Instant::now(),
+            depth: 0,
+            last: None,
+            hook: None,
+            strictness: None,
+            heap: Heap::new(),
+        }
+    }
+
+    pub fn with_hook(budget: Budget, hook: &'a mut dyn Hook) -> Machine<'a> {
+        Machine {
+            hook: Some(hook),
+            ..Machine::new(budget)
+        }
+    }
+
+    // evaluate what the analysis (strict.rs) found is going to be forced anyway right away
+    pub fn with_strictness(self, strictness: &'a Strictness) -> Machine<'a> {
+        Machine {
+            strictness: Some(strictness),
+            ..self
+        }
+    }
+
+    fn emit(
+        &mut self,
+        event: Event,
+        expr: &Rc<Expr>,
+        env: &Rc<Env>,
+        spine: usize,
+    ) -> Result<(), Exhausted> {
+        if let Some(hook) = self.hook.as_mut() {
+            let state = State {
+                steps: self.stats.steps,
+                allocations: self.stats.allocations,
+                expr,
+                depth: self.depth,
+                spine,
+                env,
+            };
+            if !hook.event(&event, &state) {
+                return Err(self.exhausted(Limit::Stopped));
             }
         }
-        Expr::Var(s, depth) => {
-            // println!("Variable looking up {:?}", expr);
-            if let Some(val) = env.lookup_in(s, *depth.borrow()) {
-                eval(Rc::clone(val), env, spine)
-            } else {
-                panic!("Variable not found, {:?}, in {}", expr, env)
+        Ok(())
+    }
+
+    pub fn heap(&self) -> HeapStats {
+        self.heap.stats()
+    }
+
+    // free the frames only kept alive by cycles now instead of waiting, how many there were
+    pub fn collect(&mut self) -> usize {
+        self.heap.collect()
+    }
+
+    pub fn stats(&self) -> Stats {
+        let mut stats = self.stats.clone();
+        stats.elapsed = self.start.elapsed();
+        stats.last = match self.last.as_deref() {
+            Some(Expr::Var(s, _, _)) => Some(s.to_string()),
+            _ => None,
+        };
+        stats
+    }
+
+    fn exhausted(&self, limit: Limit) -> Exhausted {
+        Exhausted {
+            limit,
+            stats: self.stats(),
+        }
+    }
+
+    // a nested step, checking the budget on the way in
+    fn enter(&mut self) -> Result<(), Exhausted> {
+        self.depth += 1;
+        if self.depth > self.stats.max_depth {
+            self.stats.max_depth = self.depth;
+        }
+        if self.budget.depth.is_some_and(|max| self.depth > max) {
+            return Err(self.exhausted(Limit::Depth));
+        }
+        self.step()
+    }
+
+    // a step at the same depth, what every reduction costs
+    fn step(&mut self) -> Result<(), Exhausted> {
+        self.stats.steps += 1;
+        if self.budget.steps.is_some_and(|max| self.stats.steps > max) {
+            return Err(self.exhausted(Limit::Steps));
+        }
+        if self.stats.steps.is_multiple_of(CLOCK_INTERVAL) {
+            if let Some(max) = self.budget.timeout {
+                if self.start.elapsed() > max {
+                    return Err(self.exhausted(Limit::Timeout));
+                }
             }
         }
-        Expr::App(left, right) => {
-            let mut spine = spine;
-            spine.push(Rc::clone(right));
-            eval(Rc::clone(left), env, spine)
+        Ok(())
+    }
+
+    fn allocate(&mut self, bytes: usize) -> Result<(), Exhausted> {
+        self.stats.allocated += bytes;
+        match self.budget.heap {
+            Some(max) if self.stats.allocated > max => Err(self.exhausted(Limit::Heap)),
+            _ => Ok(()),
+        }
+    }
+
+    // a new frame holding the slots
+    fn extend(
+        &mut self,
+        slots: Vec<Rc<Expr>>,
+        env: Rc<Env>,
+        binder: Binder,
+    ) -> Result<Rc<Env>, Exhausted> {
+        self.allocate(size_of::<Env>() + slots.len() * size_of::<Rc<Expr>>())?;
+        if self.heap.due() {
+            self.heap.collect();
         }
-        Expr::Lam(head, body) => {
-            // pop from spine and treat like let
-            if Rc::deref(head) == &Expr::Var("_".to_string(), RefCell::new(0)) {
-                // skip
+        let env = Env::extend(slots, env, binder);
+        self.heap.track(&env);
+        Ok(env)
+    }
+
+    fn value(&mut self, expr: Expr) -> Result<Rc<Expr>, Exhausted> {
+        self.stats.allocations += 1;
+        self.allocate(size_of::<Expr>())?;
+        Ok(Rc::new(expr))
+    }
+
+    pub fn eval(
+        &mut self,
+        expr: Rc<Expr>,
+        env: Rc<Env>,
+        spine: Vec<Rc<Expr>>,
+    ) -> Result<Rc<Expr>, Exhausted> {
+        // the depth goes back down even when the budget ran out, the machine can be used again
+        let value = self.enter().and_then(|()| self.reduce(expr, env, spine));
+        self.depth -= 1;
+        value
+    }
+
+    // what an expression in tail position reduces to is what this one does, so that's a step of the loop instead of a nested eval
+    // a loop runs until it is out of steps or time instead of nesting deeper and deeper
+    fn reduce(
+        &mut self,
+        expr: Rc<Expr>,
+        env: Rc<Env>,
+        spine: Vec<Rc<Expr>>,
+    ) -> Result<Rc<Expr>, Exhausted> {
+        let (mut expr, mut env, mut spine) = (expr, env, spine);
+        loop {
+            (expr, env, spine) = match self.tail(expr, env, spine)? {
+                Reduced::Value(value) => return Ok(value),
+                Reduced::Tail(expr, env, spine) => (expr, env, spine),
+            };
+            self.step()?;
+        }
+    }
+
+    // one reduction, either the value or what to reduce next in the same environment of eval
+    fn tail(
+        &mut self,
+        expr: Rc<Expr>,
+        env: Rc<Env>,
+        spine: Vec<Rc<Expr>>,
+    ) -> Result<Reduced, Exhausted> {
+        let value = match &*expr {
+            Expr::If(cond, b1, b2) => {
+                let d = self.eval(Rc::clone(cond), Rc::clone(&env), Vec::new())?;
+                if let Expr::Data(_, _, s, _) = &*d {
+                    if s == "True" {
+                        self.emit(Event::If(true), &expr, &env, spine.len())?;
+                        return Ok(Reduced::Tail(Rc::clone(b1), env, spine));
+                    } else if s == "False" {
+                        self.emit(Event::If(false), &expr, &env, spine.len())?;
+                        return Ok(Reduced::Tail(Rc::clone(b2), env, spine));
+                    } else {
+                        panic!("If expression needs condition to be a boolean.");
+                    }
+                } else {
+                    panic!("Condition in if needs to be a boolean.");
+                }
+            }
+            Expr::Var(s, coords, _) => {
+                let (depth, slot) = *coords.borrow();
+                self.last = Some(Rc::clone(&expr));
+                if let Some(val) = env.lookup(depth, slot) {
+                    self.emit(Event::Lookup(s), &expr, &env, spine.len())?;
+                    let val = if is_thunk(&val) {
+                        let val = self.force(val)?;
+                        env.set(depth, slot, Rc::clone(&val));
+                        val
+                    } else {
+                        val
+                    };
+                    // a hook is told when the variable returns, so then it has to wait for the value
+                    if self.hook.is_none() {
+                        return Ok(Reduced::Tail(val, env, spine));
+                    }
+                    let spine_len = spine.len();
+                    let value = self.eval(val, Rc::clone(&env), spine)?;
+                    self.emit(Event::Return(s), &expr, &env, spine_len)?;
+                    Ok(value)
+                } else {
+                    panic!("Variable not found, {} at {:?}, in {}", s, coords, env)
+                }
+            }
+            Expr::App(left, right) => {
+                if let Some(strict) = self.strictness.and_then(|s| s.strict_args(&expr)) {
+                    return self.call(&expr, strict, env, spine);
+                }
                 let mut spine = spine;
-                spine.pop();
-                eval(Rc::clone(body), env, spine)
-            } else {
+                spine.push(self.delay(right, &env)?);
+                return Ok(Reduced::Tail(Rc::clone(left), env, spine));
+            }
+            Expr::Lam(head, body) => {
+                // pop from spine and put it in a new frame
                 let mut spine = spine;
                 if let Some(def) = spine.pop() {
-                    let res = eval(def, Rc::clone(&env), spine.clone());
-                    eval(
-                        Rc::new(Expr::Let(vec![Rc::clone(head)], vec![res], Rc::clone(body))),
-                        env,
-                        spine,
-                    )
+                    let arg = if is_wildcard(head) {
+                        def
+                    } else {
+                        self.force(def)?
+                    };
+                    let env = self.extend(vec![arg], env, Binder::Expr(Rc::clone(&expr)))?;
+                    if let Expr::Var(name, _, _) = &**head {
+                        self.emit(Event::Beta(name), &expr, &env, spine.len())?;
+                    }
+                    return Ok(Reduced::Tail(Rc::clone(body), env, spine));
                 } else {
-                    panic!("Not enough arguments supplied")
+                    self.value(Expr::Closure(expr, env))
                 }
             }
-        }
-        Expr::Data(args, t, s, fields) => {
-            if fields.len() < *args {
+            Expr::Closure(inner, closed) => {
+                if spine.is_empty() {
+                    if let Expr::Lam(_, _) = &**inner {
+                        return Ok(Reduced::Value(expr));
+                    }
+                }
+                return Ok(Reduced::Tail(Rc::clone(inner), Rc::clone(closed), spine));
+            }
+            Expr::Data(args, t, s, fields) => {
                 let mut spine = spine;
-                if let Some(def) = spine.pop() {
-                    let mut new_fields = fields.to_vec();
-                    new_fields.push(eval(def, Rc::clone(&env), spine.clone()));
-                    eval(
-                        Rc::new(Expr::Data(
-                            *args,
-                            t.to_string(),
-                            s.to_string(),
-                            new_fields.to_vec(),
-                        )),
-                        env,
-                        spine,
-                    )
+                let mut new_fields = fields.to_vec();
+                while new_fields.len() < *args {
+                    if let Some(def) = spine.pop() {
+                        new_fields.push(self.force(def)?);
+                    } else {
+                        break;
+                    }
+                }
+                if new_fields.len() == fields.len() {
+                    Ok(expr)
                 } else {
-                    panic!("Not enough arguments to constructor")
+                    if new_fields.len() == *args {
+                        self.emit(Event::Construct(s, *args), &expr, &env, spine.len())?;
+                    }
+                    self.value(Expr::Data(*args, t.to_string(), s.to_string(), new_fields))
                 }
-            } else {
-                expr
             }
-        }
-        Expr::Builtin(args, s, func, fields) => {
-            if *args == fields.len() {
-                func(fields.to_vec())
-            } else if fields.len() < *args {
+            Expr::Builtin(args, s, func, fields, span) => {
                 let mut spine = spine;
-                if let Some(def) = spine.pop() {
-                    let mut fields = fields.to_vec();
-                    fields.push(eval(def, Rc::clone(&env), spine.clone()));
-                    eval(
-                        Rc::new(Expr::Builtin(*args, s.to_string(), *func, fields.to_vec())),
-                        env,
-                        spine,
-                    )
+                let mut new_fields = fields.to_vec();
+                while new_fields.len() < *args {
+                    if let Some(def) = spine.pop() {
+                        new_fields.push(self.force(def)?);
+                    } else {
+                        break;
+                    }
+                }
+                if new_fields.len() == *args {
+                    self.emit(Event::Builtin(s), &expr, &env, spine.len())?;
+                    self.stats.allocations += 1;
+                    self.allocate(size_of::<Expr>())?;
+                    return Ok(Reduced::Tail(func(new_fields), env, spine));
+                } else if new_fields.len() == fields.len() {
+                    Ok(expr)
                 } else {
-                    panic!("Not enough arguments supplied to builtin")
+                    self.value(Expr::Builtin(
+                        *args,
+                        s.to_string(),
+                        *func,
+                        new_fields,
+                        *span,
+                    ))
                 }
-            } else {
-                expr
             }
-        }
-        Expr::Let(vars, defs, body) => {
-            // define a new layer for env
-            let mut new_defs = HashMap::new();
-            for i in 0..vars.len() {
-                if let Expr::Var(s, _) = &*vars[i] {
-                    new_defs.insert(s.to_string(), Rc::clone(&defs[i]));
-                } else {
-                    panic!("Can only define variables.");
+            Expr::Let(_, defs, body) => {
+                // define a new frame, the definitions can only see the old environment
+                let mut slots = Vec::new();
+                for def in defs {
+                    if self.strictness.is_some_and(|s| s.strict_let(def)) {
+                        slots.push(self.eval(Rc::clone(def), Rc::clone(&env), Vec::new())?);
+                    } else {
+                        slots.push(self.delay(def, &env)?);
+                    }
                 }
+                let env = self.extend(slots, env, Binder::Expr(Rc::clone(&expr)))?;
+                return Ok(Reduced::Tail(Rc::clone(body), env, spine));
             }
-            let new_env = Rc::new(Env::Context(new_defs, Rc::clone(&env)));
-            eval(Rc::clone(body), new_env, spine)
-        }
-        Expr::LetRec(vars, defs, body) => {
-            // add a new layer to the environment
-            let mut new_defs = HashMap::new();
-            for i in 0..vars.len() {
-                if let Expr::Var(s, _) = &*vars[i] {
-                    new_defs.insert(s.to_string(), Rc::clone(&defs[i]));
-                } else {
-                    panic!("Can only define variables.");
+            Expr::LetRec(_, defs, body) => {
+                // add a new frame first so the definitions can refer to it
+                let slots = defs.iter().map(|_| Rc::new(Expr::Bottom)).collect();
+                let new_env = self.extend(slots, env, Binder::Expr(Rc::clone(&expr)))?;
+                for (i, def) in defs.iter().enumerate() {
+                    let closure = self.value(Expr::Closure(Rc::clone(def), Rc::clone(&new_env)))?;
+                    new_env.set(0, i, closure);
                 }
+                return Ok(Reduced::Tail(Rc::clone(body), new_env, spine));
             }
-            let new_env = Rc::new(Env::Context(new_defs, Rc::clone(&env)));
-            eval(Rc::clone(body), new_env, spine)
-        }
-        Expr::Case(expr, pats, branches) => {
-            // expr should be a data constructor
-            let expr = eval(Rc::clone(expr), Rc::clone(&env), spine.clone());
-            // replace branch with let using the assigned variables
-            let mut i = 0;
-            loop {
-                if i >= pats.len() {
-                    break;
-                } else if pat_match(
-                    eval(Rc::clone(&expr), Rc::clone(&env), spine.clone()),
-                    &pats[i],
-                ) {
-                    let (vars, defs) = assign(Rc::clone(&expr), &pats[i]);
-                    let expr_new = Rc::new(Expr::Let(vars, defs, Rc::clone(&branches[i])));
-                    return eval(expr_new, env, spine);
-                } else {
-                    i += 1;
+            Expr::Case(cond, pats, branches, _) => {
+                // cond should be a data constructor or a literal
+                let data = self.eval(Rc::clone(cond), Rc::clone(&env), Vec::new())?;
+                // the branch gets a frame with the variables from the pattern
+                for (i, (pat, branch)) in pats.iter().zip(branches).enumerate() {
+                    if pat_match(Rc::clone(&data), pat) {
+                        let slots = assign(Rc::clone(&data), pat);
+                        let env = self.extend(slots, env, Binder::Branch(Rc::clone(&expr), i))?;
+                        self.emit(Event::Branch(pat), &expr, &env, spine.len())?;
+                        return Ok(Reduced::Tail(Rc::clone(branch), env, spine));
+                    }
                 }
+                panic!("No pattern matched.");
+            }
+            Expr::Error(s) => panic!("Error: {}", s),
+            Expr::Bottom => panic!("Ran into undefined."),
+            Expr::Annot(inner, _) => return Ok(Reduced::Tail(Rc::clone(inner), env, spine)),
+            _ => Ok(expr),
+        };
+        value.map(Reduced::Value)
+    }
+
+    // an application whose strict arguments are evaluated first, in the order they'd be forced
+    fn call(
+        &mut self,
+        expr: &Rc<Expr>,
+        strict: &[bool],
+        env: Rc<Env>,
+        spine: Vec<Rc<Expr>>,
+    ) -> Result<Reduced, Exhausted> {
+        let mut args = Vec::new();
+        let mut head = expr;
+        while let Expr::App(left, right) = &**head {
+            args.push(right);
+            head = left;
+        }
+        args.reverse();
+        let mut values = Vec::new();
+        for (arg, strict) in args.iter().zip(strict) {
+            if *strict {
+                values.push(self.eval(Rc::clone(arg), Rc::clone(&env), Vec::new())?);
+            } else {
+                values.push(self.delay(arg, &env)?);
             }
-            panic!("No pattern matched.");
         }
-        Expr::Error(s) => panic!("Error: {}", s),
-        Expr::Bottom => panic!("Ran into undefined."),
-        _ => expr,
+        let mut spine = spine;
+        spine.extend(values.into_iter().rev());
+        Ok(Reduced::Tail(Rc::clone(head), env, spine))
+    }
+
+    // pair an expression up with the environment it should be evaluated in, literals don't need one
+    fn delay(&mut self, expr: &Rc<Expr>, env: &Rc<Env>) -> Result<Rc<Expr>, Exhausted> {
+        match &**expr {
+            Expr::Int(_, _) | Expr::Float(_, _) | Expr::Str(_, _) => Ok(Rc::clone(expr)),
+            _ => self.value(Expr::Closure(Rc::clone(expr), Rc::clone(env))),
+        }
+    }
+
+    fn force(&mut self, expr: Rc<Expr>) -> Result<Rc<Expr>, Exhausted> {
+        if is_thunk(&expr) {
+            self.eval(expr, Rc::new(Env::new()), Vec::new())
+        } else {
+            Ok(expr)
+        }
+    }
+}
+
+impl Display for Event<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Event::Lookup(name) => write!(f, "lookup {}", name),
+            Event::Return(name) => write!(f, "return {}", name),
+            Event::Beta(name) => write!(f, "beta \\{}", name),
+            Event::Construct(cons, n) => write!(f, "construct {} with {} fields", cons, n),
+            Event::Builtin(name) => write!(f, "builtin {}", name),
+            Event::Branch(pat) => write!(f, "branch {}", pat),
+            Event::If(b) => write!(f, "branch {}", if *b { "then" } else { "else" }),
+        }
+    }
+}
+
+impl Display for Limit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Limit::Steps => write!(f, "ran out of steps"),
+            Limit::Timeout => write!(f, "timed out"),
+            Limit::Heap => write!(f, "ran out of memory"),
+            Limit::Depth => write!(f, "nested too deeply"),
+            Limit::Stopped => write!(f, "was stopped"),
+        }
+    }
+}
+
+impl Display for Exhausted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let stats = &self.stats;
+        write!(
+            f,
+            "evaluation {} after {} steps, {:.3?}, about {} bytes allocated, nested {} deep",
+            self.limit, stats.steps, stats.elapsed, stats.allocated, stats.max_depth
+        )?;
+        if let Some(name) = &stats.last {
+            write!(f, ", last looked up `{}`", name)?;
+        }
+        Ok(())
+    }
+}
+
+// a closure that isn't a function still needs to be evaluated
+fn is_thunk(expr: &Rc<Expr>) -> bool {
+    match &**expr {
+        Expr::Closure(inner, _) => !matches!(&**inner, Expr::Lam(_, _)),
+        _ => false,
+    }
+}
+
+fn is_wildcard(head: &Rc<Expr>) -> bool {
+    match &**head {
+        Expr::Var(s, _, _) => is_lazy(s),
+        _ => false,
     }
 }
 
@@ -179,7 +608,7 @@ fn pat_match(data: Rc<Expr>, pat: &Pattern) -> bool {
             }
             _ => false,
         },
-        Expr::Int(n) => match pat {
+        Expr::Int(n, _) => match pat {
             Pattern::Wildcard => true,
             Pattern::Irrefutable(_) => true,
             Pattern::Int(i) => {
@@ -191,7 +620,7 @@ fn pat_match(data: Rc<Expr>, pat: &Pattern) -> bool {
             }
             _ => false,
         },
-        Expr::Float(n) => match pat {
+        Expr::Float(n, _) => match pat {
             Pattern::Wildcard => true,
             Pattern::Irrefutable(_) => true,
             Pattern::Float(i) => {
@@ -203,7 +632,7 @@ fn pat_match(data: Rc<Expr>, pat: &Pattern) -> bool {
             }
             _ => false,
         },
-        Expr::Str(n) => match pat {
+        Expr::Str(n, _) => match pat {
             Pattern::Wildcard => true,
             Pattern::Irrefutable(_) => true,
             Pattern::Str(i) => {
@@ -241,53 +670,18 @@ fn pat_match(data: Rc<Expr>, pat: &Pattern) -> bool {
     // }
 }
 
-fn assign(data: Rc<Expr>, pat: &Pattern) -> (Vec<Rc<Expr>>, Vec<Rc<Expr>>) {
-    match &*data {
-        Expr::Data(_, _, _, fields) => match pat {
-            Pattern::Construct(_, pat_vars) => {
-                let mut vars: Vec<Rc<Expr>> = Vec::new();
-                let mut defs = Vec::new();
-                for i in 0..fields.len() {
-                    vars.push(Rc::new(Expr::Var(pat_vars[i].to_string(), RefCell::new(0))));
-                    defs.push(Rc::clone(&fields[i]));
-                }
-                (vars, defs)
+// the values to put in the frame for the branch, in the same order as the pattern variables
+fn assign(data: Rc<Expr>, pat: &Pattern) -> Vec<Rc<Expr>> {
+    match pat {
+        Pattern::Construct(_, _) => {
+            if let Expr::Data(_, _, _, fields) = &*data {
+                fields.to_vec()
+            } else {
+                panic!("Matched pattern, but assignment didn't work.")
             }
-            Pattern::Wildcard => (Vec::new(), Vec::new()),
-            Pattern::Irrefutable(x) => (
-                vec![Rc::new(Expr::Var(x.to_string(), RefCell::new(0)))],
-                vec![data],
-            ),
-            _ => panic!("Matched pattern, but assignment didn't work."),
-        },
-        Expr::Int(_) => match pat {
-            Pattern::Int(_) => (Vec::new(), Vec::new()),
-            Pattern::Wildcard => (Vec::new(), Vec::new()),
-            Pattern::Irrefutable(x) => (
-                vec![Rc::new(Expr::Var(x.to_string(), RefCell::new(0)))],
-                vec![data],
-            ),
-            _ => panic!("Matched pattern, but assignment didn't work."),
-        },
-        Expr::Float(_) => match pat {
-            Pattern::Float(_) => (Vec::new(), Vec::new()),
-            Pattern::Wildcard => (Vec::new(), Vec::new()),
-            Pattern::Irrefutable(x) => (
-                vec![Rc::new(Expr::Var(x.to_string(), RefCell::new(0)))],
-                vec![data],
-            ),
-            _ => panic!("Matched pattern, but assignment didn't work."),
-        },
-        Expr::Str(_) => match pat {
-            Pattern::Str(_) => (Vec::new(), Vec::new()),
-            Pattern::Wildcard => (Vec::new(), Vec::new()),
-            Pattern::Irrefutable(x) => (
-                vec![Rc::new(Expr::Var(x.to_string(), RefCell::new(0)))],
-                vec![data],
-            ),
-            _ => panic!("Matched pattern, but assignment didn't work."),
-        },
-        _ => panic!("Pattern matched, but assignment didn't work."),
+        }
+        Pattern::Irrefutable(_) => vec![data],
+        _ => Vec::new(),
     }
     // if let Expr::Data(_, _, _, fields) = Rc::deref(&data) {
     //     match pat {
@@ -0,0 +1,51 @@
+/*
+
+everything between parsing and evaluating, the one place the passes are put in order
+the interpreter, bagl test, the tests of the passes, and the benchmarks all go through here
+
+    check       the names, then the types on the split up lets
+    finish      the dictionaries go into the original letrec, then it is split up, what isn't used is dropped, and the variables are resolved
+    compile     both, after checking the declarations, for a program with the given entry
+
+check and finish are separate so a caller can look at the types in between
+bagl test makes sure a test is a Bool, the interpreter wraps main in show
+
+*/
+
+use crate::ast::Expr;
+use crate::ast::Toplevel;
+use crate::diagnostic::Diagnostic;
+use crate::elaborate::elaborate;
+use crate::infer::check_types;
+use crate::infer::Checked;
+use crate::kinds::check_declarations;
+use crate::names::check_names;
+use crate::rearrange::change_lets;
+use crate::scan::resolve;
+use crate::unused::eliminate;
+use std::rc::Rc;
+
+// the types of a program that is still one letrec of every definition
+pub fn check(top: &Toplevel, expr: &Rc<Expr>) -> Result<Checked, Vec<Diagnostic>> {
+    let errors = check_names(expr);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    check_types(&change_lets(Rc::clone(expr)), top)
+}
+
+// the checked program ready to evaluate
+pub fn finish(top: &Toplevel, expr: &Rc<Expr>, checked: &Checked) -> Rc<Expr> {
+    let expr = elaborate(expr, &checked.elaboration, top);
+    let expr = eliminate(change_lets(expr));
+    resolve(Rc::clone(&expr));
+    expr
+}
+
+// the program that evaluates the entry
+pub fn compile(top: &Toplevel, entry: &str) -> Result<Rc<Expr>, Vec<Diagnostic>> {
+    check_declarations(top)?;
+    let expr = top.to_let_entry(entry);
+    let checked = check(top, &expr)?;
+    Ok(finish(top, &expr, &checked))
+}
@@ -0,0 +1,224 @@
+/*
+collecting the environments that only keep each other alive
+
+everything at runtime is reference counted, which frees most of it as soon as it isn't used
+but a letrec puts closures over its own frame into the frame's slots, and a let slot gets replaced by its value which can be a closure over the same frame
+those frames are in a cycle so their counts never get to zero, a long running program or an embedding that evaluates over and over keeps all of them around
+
+the machine keeps a weak reference to every frame it makes and every so often traces them
+    everything that can be reached from the frames is found
+        frames through their slots and the frame above, closures through their frame, data and builtins through their fields
+    the references found along the way are counted, something with more strong references than that is held from outside the heap
+        the stack of eval, the spine, a caller holding on to a value, a hook
+    those are the roots and everything reachable from them is marked
+    a frame that isn't marked is only alive because of a cycle, emptying its slots breaks the cycle and reference counting frees the rest
+so it is mark and sweep over the frames, with the roots worked out from the reference counts instead of scanning the stack
+
+only frames are tracked, thunks and data are traced through but never collected on their own
+    a thunk is an unevaluated expression in a frame slot, so it lives in the frame and is freed with it
+    data, closures and builtins are built once and never changed, they can only point at things made before them
+    the slots of a frame are the only thing that is written after it is made, so every cycle goes through at least one frame
+emptying the slots of the unmarked frames is enough to free everything the cycles held, an arena for thunks and data wouldn't find more
+
+a weak reference keeps the memory of a freed frame (not what was in it) until the next collection drops it
+a collection happens once the number of frames has doubled since the last one, so tracing costs about as much as making the frames did
+*/
+
+use crate::ast::Expr;
+use crate::env::Env;
+use crate::strict::Addresses;
+
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::rc::Weak;
+
+// frames made before the first collection
+const FIRST_COLLECTION: usize = 4096;
+
+// what the collector has seen
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HeapStats {
+    pub frames: usize, // frames alive after the last collection
+    pub peak: usize,   // the most frames alive at a collection
+    pub made: u64,     // frames made
+    pub collections: u64,
+    pub collected: u64, // frames freed by breaking their cycles
+}
+
+pub struct Heap {
+    frames: Vec<Weak<Env>>,
+    next: usize, // collect when there are this many frames
+    stats: HeapStats,
+}
+
+impl Default for Heap {
+    fn default() -> Heap {
+        Heap::new()
+    }
+}
+
+impl Heap {
+    pub fn new() -> Heap {
+        Heap {
+            frames: Vec::new(),
+            next: FIRST_COLLECTION,
+            stats: HeapStats::default(),
+        }
+    }
+
+    pub fn track(&mut self, env: &Rc<Env>) {
+        self.frames.push(Rc::downgrade(env));
+        self.stats.made += 1;
+    }
+
+    pub fn due(&self) -> bool {
+        self.frames.len() >= self.next
+    }
+
+    pub fn stats(&self) -> HeapStats {
+        self.stats
+    }
+
+    // free the frames that are only kept alive by cycles, how many there were
+    pub fn collect(&mut self) -> usize {
+        let frames: Vec<Rc<Env>> = self.frames.iter().filter_map(Weak::upgrade).collect();
+        let mut graph = Graph::default();
+        for frame in &frames {
+            graph.frame(frame, 1);
+        }
+        graph.trace();
+        let marked = graph.mark();
+        let mut collected = 0;
+        for (node, marked) in graph.nodes.iter().zip(marked) {
+            if let (Node::Frame(env), false) = (node, marked) {
+                if let Env::Context(slots, _, _) = &**env {
+                    slots.borrow_mut().clear();
+                }
+                collected += 1;
+            }
+        }
+        // the graph is holding on to everything, the garbage goes when it does
+        drop(graph);
+        drop(frames);
+        self.frames.retain(|frame| frame.strong_count() > 0);
+        self.next = FIRST_COLLECTION.max(2 * self.frames.len());
+        self.stats.frames = self.frames.len();
+        self.stats.peak = self.stats.peak.max(self.frames.len());
+        self.stats.collections += 1;
+        self.stats.collected += collected as u64;
+        collected
+    }
+}
+
+// something that can keep a frame alive
+enum Node {
+    Frame(Rc<Env>),
+    Value(Rc<Expr>),
+}
+
+// the frames and values reachable from the tracked frames, a node is an index
+#[derive(Default)]
+struct Graph {
+    nodes: Vec<Node>,
+    counts: Vec<usize>, // strong references from anywhere, not counting the collector's own
+    edges: Vec<Vec<usize>>,
+    index: HashMap<*const (), usize, Addresses>,
+}
+
+impl Graph {
+    // the count is read before the graph holds on to it, held is how many the caller has
+    fn frame(&mut self, env: &Rc<Env>, held: usize) -> usize {
+        let key = Rc::as_ptr(env) as *const ();
+        if let Some(&i) = self.index.get(&key) {
+            return i;
+        }
+        self.add(
+            key,
+            Rc::strong_count(env) - held,
+            Node::Frame(Rc::clone(env)),
+        )
+    }
+
+    // only values that can lead to a frame are nodes, the program itself can't
+    fn value(&mut self, expr: &Rc<Expr>) -> Option<usize> {
+        match &**expr {
+            Expr::Closure(_, _) => {}
+            Expr::Data(_, _, _, fields) | Expr::Builtin(_, _, _, fields, _)
+                if !fields.is_empty() => {}
+            _ => return None,
+        }
+        let key = Rc::as_ptr(expr) as *const ();
+        if let Some(&i) = self.index.get(&key) {
+            return Some(i);
+        }
+        Some(self.add(key, Rc::strong_count(expr), Node::Value(Rc::clone(expr))))
+    }
+
+    fn add(&mut self, key: *const (), count: usize, node: Node) -> usize {
+        let i = self.nodes.len();
+        self.index.insert(key, i);
+        self.nodes.push(node);
+        self.counts.push(count);
+        self.edges.push(Vec::new());
+        i
+    }
+
+    // find everything reachable, a list instead of recursion since data can be long
+    fn trace(&mut self) {
+        let mut i = 0;
+        while i < self.nodes.len() {
+            let mut edges = Vec::new();
+            match &self.nodes[i] {
+                Node::Frame(env) => {
+                    let env = Rc::clone(env);
+                    if let Env::Context(slots, next, _) = &*env {
+                        for slot in slots.borrow().iter() {
+                            edges.extend(self.value(slot));
+                        }
+                        if let Env::Context(_, _, _) = &**next {
+                            edges.push(self.frame(next, 0));
+                        }
+                    }
+                }
+                Node::Value(expr) => {
+                    let expr = Rc::clone(expr);
+                    match &*expr {
+                        Expr::Closure(inner, env) => {
+                            edges.extend(self.value(inner));
+                            edges.push(self.frame(env, 0));
+                        }
+                        Expr::Data(_, _, _, fields) | Expr::Builtin(_, _, _, fields, _) => {
+                            for field in fields {
+                                edges.extend(self.value(field));
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            self.edges[i] = edges;
+            i += 1;
+        }
+    }
+
+    // what the roots reach, a root has references the tracing didn't find
+    fn mark(&self) -> Vec<bool> {
+        let mut found = vec![0; self.nodes.len()];
+        for edges in &self.edges {
+            for &j in edges {
+                found[j] += 1;
+            }
+        }
+        let mut marked = vec![false; self.nodes.len()];
+        let mut stack: Vec<usize> = (0..self.nodes.len())
+            .filter(|&i| self.counts[i] > found[i])
+            .collect();
+        while let Some(i) = stack.pop() {
+            if !marked[i] {
+                marked[i] = true;
+                stack.extend(self.edges[i].iter().filter(|&&j| !marked[j]));
+            }
+        }
+        marked
+    }
+}
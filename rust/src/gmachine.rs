@@ -0,0 +1,1050 @@
+/*
+
+another way to run a program, every lambda is lifted out to a supercombinator and the supercombinators are compiled to code for a G-machine
+
+lambda lifting
+    a lambda becomes a supercombinator whose first parameters are the variables it uses from around it
+    \x . \y . + x z, with z from outside, is the supercombinator f z x y = + x z and where the lambda was is now f z
+    lambdas right inside of each other are one supercombinator, applied to fewer arguments it prints as the inner lambda
+    a case or an if that isn't evaluated right away is lifted the same way, so building graph never has to branch
+    the top level definitions are supercombinators already, the ones that aren't functions have no parameters
+    constructors and builtins are supercombinators too, they force their arguments and then PACK them or do the builtin
+
+the code of a supercombinator builds its body as graph on the stack and updates the root of the redex with it
+    PUSH n copies the nth address from the top of the stack, MKAP makes an application out of the top two
+    UPDATE n points the root at the result, so everything that shares the application sees the value
+    EVAL evaluates the top of the stack, what was on the stack and the code waits on the dump until it's done
+    CASEJUMP picks the alternative for the constructor or literal on top, SPLIT puts the fields on the stack
+    ALLOC makes holes for the definitions of a letrec, they're filled in with UPDATE
+    UNWIND goes down the applications to what is being applied, this is the spine from eval
+definitions without parameters are updated the same way the first time they're evaluated, so they're shared like everything else
+
+lambdas force their arguments unless it's _, and constructors and builtins force theirs, all when they're applied like in eval
+anything that isn't a function ignores what it's applied to, same as eval
+values come back out as Expr, so they print the same and the builtins are the same functions
+
+*/
+
+use crate::ast::Expr;
+use crate::ast::Pattern;
+use crate::ast::Span;
+use crate::ast::Toplevel;
+use crate::classes::dict_constructor;
+use crate::info::Constructors;
+use crate::lift::free_vars;
+use crate::lift::is_lazy;
+use num::BigInt;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::rc::Rc;
+
+type Addr = usize;
+type Code = Rc<[Instr]>;
+
+#[derive(Debug, Clone)]
+pub enum Instr {
+    Pushglobal(usize),
+    Pushint(BigInt),
+    Pushfloat(f64),
+    Pushstr(String),
+    Pusherror(String), // stops the program with the message when it's evaluated
+    Push(usize),
+    Pop(usize),
+    Slide(usize),
+    Update(usize),
+    Alloc(usize),
+    Mkap,
+    Eval,
+    Unwind,
+    Pack(usize, usize), // constructor and arity
+    Split(usize),
+    Casejump(Vec<(Test, Code)>),
+    Cond(Code, Code),
+    Prim(usize),    // the builtin of a global on the arguments on top
+    Partial(usize), // force the arguments of a partial application from the nth on, only unwinding makes these
+}
+
+#[derive(Debug, Clone)]
+pub enum Test {
+    Con(usize, usize), // constructor and the number of variables in the pattern
+    Int(BigInt),
+    Float(f64),
+    Str(String),
+    Any,
+}
+
+#[derive(Clone)]
+enum Kind {
+    Con(usize),
+    Builtin(fn(Vec<Rc<Expr>>) -> Rc<Expr>),
+    Lam(usize, Vec<Rc<Expr>>), // free variables and the lambdas from the outside in
+    Thunk,
+}
+
+pub struct Global {
+    pub name: String,
+    pub arity: usize,
+    pub code: Code,
+    strict: Vec<bool>,
+    kind: Kind,
+}
+
+pub struct Program {
+    pub globals: Vec<Global>,
+    pub entry: usize,
+    cons: Constructors,
+}
+
+struct Compiler {
+    cons: Constructors,
+    globals: Vec<Global>,
+    names: HashMap<String, usize>,
+    builtins: HashMap<String, usize>,
+    constructors: HashMap<usize, usize>,
+    // lifted supercombinators are named after the top level definition they're from
+    parent: String,
+    lifted: usize,
+}
+
+// the variables in scope and where they are on the stack, counting up from the root of the redex
+#[derive(Clone)]
+struct Frame {
+    vars: Vec<(String, usize)>,
+    depth: usize,
+}
+
+// the program after change_lets, the lets around the body are the top level definitions
+pub fn compile(expr: &Rc<Expr>, top: &Toplevel) -> Program {
+    let mut compiler = Compiler::new(top);
+    let mut defs = Vec::new();
+    let mut body = expr;
+    while let Expr::Let(vars, ds, rest) | Expr::LetRec(vars, ds, rest) = &**body {
+        for (var, def) in vars.iter().zip(ds) {
+            defs.push((var_name(var).to_string(), def));
+        }
+        body = rest;
+    }
+    let globals: Vec<usize> = defs
+        .iter()
+        .map(|(name, _)| {
+            let g = compiler.declare(name);
+            compiler.names.insert(name.to_string(), g);
+            g
+        })
+        .collect();
+    for (g, (name, def)) in globals.into_iter().zip(&defs) {
+        compiler.parent = name.to_string();
+        compiler.lifted = 0;
+        compiler.supercombinator(g, &[], def);
+    }
+    compiler.parent = "$main".to_string();
+    compiler.lifted = 0;
+    let entry = compiler.declare("$main");
+    compiler.supercombinator(entry, &[], body);
+    Program {
+        globals: compiler.globals,
+        entry,
+        cons: compiler.cons,
+    }
+}
+
+fn var_name(var: &Rc<Expr>) -> &str {
+    match &**var {
+        Expr::Var(name, _, _) => name,
+        _ => panic!("Only variables can be defined."),
+    }
+}
+
+impl Frame {
+    // the parameters of a supercombinator, the first one is on top
+    fn new(params: &[String]) -> Frame {
+        let n = params.len();
+        Frame {
+            vars: params
+                .iter()
+                .enumerate()
+                .map(|(i, p)| (p.to_string(), n - 1 - i))
+                .collect(),
+            depth: n,
+        }
+    }
+
+    // how far from the top of the stack the variable is
+    fn find(&self, name: &str) -> Option<usize> {
+        self.vars
+            .iter()
+            .rev()
+            .find(|(n, _)| n == name)
+            .map(|(_, pos)| self.depth - 1 - pos)
+    }
+
+    fn free(&self, expr: &Rc<Expr>) -> Vec<String> {
+        let mut found = free_vars(expr);
+        found.retain(|v| self.find(v).is_some());
+        found
+    }
+}
+
+impl Compiler {
+    fn new(top: &Toplevel) -> Compiler {
+        let mut cons = Constructors::new(&top.types);
+        for class in top.classes.iter().filter(|c| c.dictionary) {
+            cons.id(
+                class.methods.len(),
+                &class.name,
+                &dict_constructor(&class.name),
+            );
+        }
+        Compiler {
+            cons,
+            globals: Vec::new(),
+            names: HashMap::new(),
+            builtins: HashMap::new(),
+            constructors: HashMap::new(),
+            parent: String::new(),
+            lifted: 0,
+        }
+    }
+
+    fn add(&mut self, global: Global) -> usize {
+        self.globals.push(global);
+        self.globals.len() - 1
+    }
+
+    // a global that gets its code later
+    fn declare(&mut self, name: &str) -> usize {
+        self.add(Global {
+            name: name.to_string(),
+            arity: 0,
+            code: Rc::from(Vec::new()),
+            strict: Vec::new(),
+            kind: Kind::Thunk,
+        })
+    }
+
+    // the parameters are the free variables and then the lambdas at the top of expr
+    fn supercombinator(&mut self, g: usize, free: &[String], expr: &Rc<Expr>) {
+        let mut params = free.to_vec();
+        let mut strict = vec![false; free.len()];
+        let mut lams = Vec::new();
+        let mut body = expr;
+        loop {
+            match &**body {
+                Expr::Lam(head, inner) => {
+                    let name = var_name(head);
+                    params.push(name.to_string());
+                    strict.push(!is_lazy(name));
+                    lams.push(Rc::clone(body));
+                    body = inner;
+                }
+                Expr::Annot(inner, _) => body = inner,
+                _ => break,
+            }
+        }
+        let mut frame = Frame::new(&params);
+        let mut code = Vec::new();
+        for (i, _) in strict.iter().enumerate().filter(|(_, s)| **s) {
+            code.extend([Instr::Push(i), Instr::Eval, Instr::Pop(1)]);
+        }
+        self.r(body, &mut frame, &mut code);
+        let global = &mut self.globals[g];
+        global.arity = params.len();
+        global.code = code.into();
+        global.strict = strict;
+        global.kind = if lams.is_empty() {
+            Kind::Thunk
+        } else {
+            Kind::Lam(free.len(), lams)
+        };
+    }
+
+    // a new supercombinator for expr, applied to the variables it uses
+    fn lift(&mut self, expr: &Rc<Expr>, frame: &mut Frame, out: &mut Vec<Instr>) {
+        let free = frame.free(expr);
+        self.lifted += 1;
+        let name = format!("{}${}", self.parent, self.lifted);
+        let g = self.declare(&name);
+        self.supercombinator(g, &free, expr);
+        for var in free.iter().rev() {
+            self.var(var, frame, out);
+        }
+        out.push(Instr::Pushglobal(g));
+        frame.depth += 1;
+        for _ in &free {
+            out.push(Instr::Mkap);
+            frame.depth -= 1;
+        }
+    }
+
+    // constructors force their fields and then pack them
+    fn constructor(&mut self, arity: usize, typ: &str, name: &str) -> usize {
+        let con = self.cons.id(arity, typ, name);
+        if let Some(g) = self.constructors.get(&con) {
+            return *g;
+        }
+        let mut code = Vec::new();
+        for i in 0..arity {
+            code.extend([Instr::Push(i), Instr::Eval, Instr::Pop(1)]);
+        }
+        code.extend([Instr::Pack(con, arity), Instr::Update(0), Instr::Unwind]);
+        let g = self.add(Global {
+            name: name.to_string(),
+            arity,
+            code: code.into(),
+            strict: vec![true; arity],
+            kind: Kind::Con(con),
+        });
+        self.constructors.insert(con, g);
+        g
+    }
+
+    fn builtin(&mut self, arity: usize, name: &str, func: fn(Vec<Rc<Expr>>) -> Rc<Expr>) -> usize {
+        if let Some(g) = self.builtins.get(name) {
+            return *g;
+        }
+        let g = self.globals.len();
+        let mut code = Vec::new();
+        for i in 0..arity {
+            code.extend([Instr::Push(i), Instr::Eval, Instr::Pop(1)]);
+        }
+        code.extend([Instr::Prim(g), Instr::Update(0), Instr::Unwind]);
+        self.add(Global {
+            name: name.to_string(),
+            arity,
+            code: code.into(),
+            strict: vec![true; arity],
+            kind: Kind::Builtin(func),
+        });
+        self.builtins.insert(name.to_string(), g);
+        g
+    }
+
+    fn var(&mut self, name: &str, frame: &mut Frame, out: &mut Vec<Instr>) {
+        match frame.find(name) {
+            Some(n) => out.push(Instr::Push(n)),
+            None => match self.names.get(name) {
+                Some(g) => out.push(Instr::Pushglobal(*g)),
+                None => panic!("{} isn't defined.", name),
+            },
+        }
+        frame.depth += 1;
+    }
+
+    // the definitions of a let go on the stack, gives back how many
+    fn bind(&mut self, expr: &Rc<Expr>, frame: &mut Frame, out: &mut Vec<Instr>) -> usize {
+        let base = frame.depth;
+        match &**expr {
+            Expr::Let(vars, defs, _) => {
+                for def in defs {
+                    self.c(def, frame, out);
+                }
+                for (i, var) in vars.iter().enumerate() {
+                    frame.vars.push((var_name(var).to_string(), base + i));
+                }
+                vars.len()
+            }
+            Expr::LetRec(vars, defs, _) => {
+                let n = vars.len();
+                out.push(Instr::Alloc(n));
+                frame.depth += n;
+                for (i, var) in vars.iter().enumerate() {
+                    frame.vars.push((var_name(var).to_string(), base + i));
+                }
+                for (i, def) in defs.iter().enumerate() {
+                    self.c(def, frame, out);
+                    out.push(Instr::Update(n - 1 - i));
+                    frame.depth -= 1;
+                }
+                n
+            }
+            _ => 0,
+        }
+    }
+
+    // code that builds the graph for expr on top of the stack without evaluating it
+    fn c(&mut self, expr: &Rc<Expr>, frame: &mut Frame, out: &mut Vec<Instr>) {
+        match &**expr {
+            Expr::Var(name, _, _) => self.var(name, frame, out),
+            Expr::Int(n, _) => {
+                out.push(Instr::Pushint(n.clone()));
+                frame.depth += 1;
+            }
+            Expr::Float(n, _) => {
+                out.push(Instr::Pushfloat(*n));
+                frame.depth += 1;
+            }
+            Expr::Str(s, _) => {
+                out.push(Instr::Pushstr(s.to_string()));
+                frame.depth += 1;
+            }
+            Expr::App(left, right) => {
+                self.c(right, frame, out);
+                self.c(left, frame, out);
+                out.push(Instr::Mkap);
+                frame.depth -= 1;
+            }
+            Expr::Lam(_, _) | Expr::Case(_, _, _, _) | Expr::If(_, _, _) => {
+                self.lift(expr, frame, out)
+            }
+            Expr::Let(_, _, body) | Expr::LetRec(_, _, body) => {
+                let mark = frame.vars.len();
+                let n = self.bind(expr, frame, out);
+                self.c(body, frame, out);
+                out.push(Instr::Slide(n));
+                frame.depth -= n;
+                frame.vars.truncate(mark);
+            }
+            Expr::Data(arity, typ, name, fields) if fields.is_empty() => {
+                let g = self.constructor(*arity, typ, name);
+                out.push(Instr::Pushglobal(g));
+                frame.depth += 1;
+            }
+            Expr::Builtin(arity, name, func, fields, _) if fields.is_empty() => {
+                let g = self.builtin(*arity, name, *func);
+                out.push(Instr::Pushglobal(g));
+                frame.depth += 1;
+            }
+            Expr::Error(s) => {
+                out.push(Instr::Pusherror(format!("Error: {}", s)));
+                frame.depth += 1;
+            }
+            Expr::Bottom => {
+                out.push(Instr::Pusherror("Ran into undefined.".to_string()));
+                frame.depth += 1;
+            }
+            Expr::Annot(inner, _) => self.c(inner, frame, out),
+            _ => panic!("Can't compile {} for the G-machine.", expr),
+        }
+    }
+
+    // code that leaves the value of expr on top of the stack
+    fn e(&mut self, expr: &Rc<Expr>, frame: &mut Frame, out: &mut Vec<Instr>) {
+        match &**expr {
+            Expr::Int(_, _) | Expr::Float(_, _) | Expr::Str(_, _) => self.c(expr, frame, out),
+            Expr::Let(_, _, body) | Expr::LetRec(_, _, body) => {
+                let mark = frame.vars.len();
+                let n = self.bind(expr, frame, out);
+                self.e(body, frame, out);
+                out.push(Instr::Slide(n));
+                frame.depth -= n;
+                frame.vars.truncate(mark);
+            }
+            Expr::Case(cond, pats, branches, _) => {
+                self.case(cond, pats, branches, frame, out, false)
+            }
+            Expr::If(cond, b1, b2) => self.cond(cond, b1, b2, frame, out, false),
+            Expr::Annot(inner, _) => self.e(inner, frame, out),
+            _ => {
+                self.c(expr, frame, out);
+                out.push(Instr::Eval);
+            }
+        }
+    }
+
+    // code for the body of a supercombinator, it updates the root with expr and carries on unwinding
+    fn r(&mut self, expr: &Rc<Expr>, frame: &mut Frame, out: &mut Vec<Instr>) {
+        match &**expr {
+            Expr::Let(_, _, body) | Expr::LetRec(_, _, body) => {
+                let mark = frame.vars.len();
+                self.bind(expr, frame, out);
+                self.r(body, frame, out);
+                frame.vars.truncate(mark);
+            }
+            Expr::Case(cond, pats, branches, _) => {
+                self.case(cond, pats, branches, frame, out, true)
+            }
+            Expr::If(cond, b1, b2) => self.cond(cond, b1, b2, frame, out, true),
+            Expr::Annot(inner, _) => self.r(inner, frame, out),
+            _ => {
+                self.c(expr, frame, out);
+                let n = frame.depth - 1;
+                out.push(Instr::Update(n));
+                if n > 0 {
+                    out.push(Instr::Pop(n));
+                }
+                out.push(Instr::Unwind);
+            }
+        }
+    }
+
+    // the alternatives either leave their value on the stack or finish the supercombinator
+    fn case(
+        &mut self,
+        cond: &Rc<Expr>,
+        pats: &[Pattern],
+        branches: &[Rc<Expr>],
+        frame: &mut Frame,
+        out: &mut Vec<Instr>,
+        tail: bool,
+    ) {
+        self.e(cond, frame, out);
+        let base = frame.depth - 1;
+        let mut alts = Vec::new();
+        for (pat, branch) in pats.iter().zip(branches) {
+            let mut inner = frame.clone();
+            let mut code = Vec::new();
+            let test = match pat {
+                Pattern::Construct(name, vars) => {
+                    // a constructor that doesn't exist can't match anything
+                    let con = match self.cons.find(name) {
+                        Some(con) => con,
+                        None => continue,
+                    };
+                    code.push(Instr::Split(vars.len()));
+                    inner.depth = base + vars.len();
+                    for (i, var) in vars.iter().enumerate() {
+                        inner.vars.push((var.to_string(), base + i));
+                    }
+                    Test::Con(con, vars.len())
+                }
+                Pattern::Irrefutable(var) => {
+                    inner.vars.push((var.to_string(), base));
+                    Test::Any
+                }
+                Pattern::Wildcard => Test::Any,
+                Pattern::Int(n) => Test::Int(n.clone()),
+                Pattern::Float(n) => Test::Float(*n),
+                Pattern::Str(s) => Test::Str(s.to_string()),
+                Pattern::Record(_, _) => panic!("Records should be desugared before compiling."),
+            };
+            if matches!(
+                pat,
+                Pattern::Wildcard | Pattern::Int(_) | Pattern::Float(_) | Pattern::Str(_)
+            ) {
+                code.push(Instr::Pop(1));
+                inner.depth = base;
+            }
+            let n = inner.depth - base;
+            if tail {
+                self.r(branch, &mut inner, &mut code);
+            } else {
+                self.e(branch, &mut inner, &mut code);
+                if n > 0 {
+                    code.push(Instr::Slide(n));
+                }
+            }
+            alts.push((test, code.into()));
+        }
+        out.push(Instr::Casejump(alts));
+        frame.depth = base + 1;
+    }
+
+    fn cond(
+        &mut self,
+        cond: &Rc<Expr>,
+        b1: &Rc<Expr>,
+        b2: &Rc<Expr>,
+        frame: &mut Frame,
+        out: &mut Vec<Instr>,
+        tail: bool,
+    ) {
+        self.e(cond, frame, out);
+        frame.depth -= 1;
+        let mut branches = Vec::new();
+        for branch in [b1, b2] {
+            let mut inner = frame.clone();
+            let mut code = Vec::new();
+            if tail {
+                self.r(branch, &mut inner, &mut code);
+            } else {
+                self.e(branch, &mut inner, &mut code);
+            }
+            branches.push(Code::from(code));
+        }
+        let b2 = branches.pop().unwrap();
+        let b1 = branches.pop().unwrap();
+        out.push(Instr::Cond(b1, b2));
+        frame.depth += 1;
+    }
+}
+
+enum Node {
+    Int(BigInt),
+    Float(f64),
+    Str(String),
+    Data(usize, Vec<Addr>),
+    App(Addr, Addr),
+    Global(usize),
+    Ind(Addr),
+    Hole,
+    Error(String),
+}
+
+// what is being evaluated, and the stack and code waiting on it
+struct Waiting {
+    evaluated: Addr,
+    stack: Vec<Addr>,
+    code: Vec<(Code, usize)>,
+}
+
+pub struct Machine<'a> {
+    program: &'a Program,
+    heap: Vec<Node>,
+    stack: Vec<Addr>,
+    dump: Vec<Waiting>,
+    // the same addresses, evaluating one of them again would never finish
+    evaluating: HashSet<Addr>,
+    // the code being run and where it's up to, an alternative of a case goes on top of the code it's in
+    code: Vec<(Code, usize)>,
+    unwind: Code,
+    steps: u64,
+}
+
+// compile and run a program, gives back its value
+pub fn run(expr: &Rc<Expr>, top: &Toplevel) -> Rc<Expr> {
+    let program = compile(expr, top);
+    Machine::new(&program).run()
+}
+
+impl<'a> Machine<'a> {
+    // every global has a node at the address of its number
+    pub fn new(program: &'a Program) -> Machine<'a> {
+        Machine {
+            program,
+            heap: (0..program.globals.len()).map(Node::Global).collect(),
+            stack: Vec::new(),
+            dump: Vec::new(),
+            evaluating: HashSet::new(),
+            code: Vec::new(),
+            unwind: Rc::from(vec![Instr::Unwind]),
+            steps: 0,
+        }
+    }
+
+    // instructions run
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    // nodes in the heap, nothing is collected
+    pub fn allocations(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn run(&mut self) -> Rc<Expr> {
+        self.stack = vec![self.program.entry];
+        self.evaluating.insert(self.program.entry);
+        self.code = vec![(Rc::clone(&self.unwind), 0)];
+        loop {
+            if let Some(result) = self.step() {
+                return self.to_expr(result);
+            }
+        }
+    }
+
+    fn alloc(&mut self, node: Node) -> Addr {
+        self.heap.push(node);
+        self.heap.len() - 1
+    }
+
+    fn top(&self) -> Addr {
+        *self.stack.last().expect("The stack is empty.")
+    }
+
+    fn pop(&mut self) -> Addr {
+        self.stack.pop().expect("The stack is empty.")
+    }
+
+    fn resolve(&self, addr: Addr) -> Addr {
+        let mut addr = addr;
+        while let Node::Ind(next) = self.heap[addr] {
+            addr = next;
+        }
+        addr
+    }
+
+    fn is_value(&self, addr: Addr) -> bool {
+        matches!(
+            self.heap[self.resolve(addr)],
+            Node::Int(_) | Node::Float(_) | Node::Str(_) | Node::Data(_, _)
+        )
+    }
+
+    // what an application on the spine is applied to
+    fn arg(&self, addr: Addr) -> Addr {
+        match self.heap[addr] {
+            Node::App(_, arg) => arg,
+            _ => panic!("The spine should only have applications."),
+        }
+    }
+
+    // a step, the address of the result when the program is done
+    fn step(&mut self) -> Option<Addr> {
+        let (code, pc) = match self.code.last_mut() {
+            Some((code, pc)) if *pc < code.len() => {
+                *pc += 1;
+                (Rc::clone(code), *pc - 1)
+            }
+            Some(_) => {
+                self.code.pop();
+                return None;
+            }
+            None => panic!("The G-machine ran out of code."),
+        };
+        self.steps += 1;
+        match &code[pc] {
+            Instr::Pushglobal(g) => self.stack.push(*g),
+            Instr::Pushint(n) => {
+                let addr = self.alloc(Node::Int(n.clone()));
+                self.stack.push(addr);
+            }
+            Instr::Pushfloat(n) => {
+                let addr = self.alloc(Node::Float(*n));
+                self.stack.push(addr);
+            }
+            Instr::Pushstr(s) => {
+                let addr = self.alloc(Node::Str(s.to_string()));
+                self.stack.push(addr);
+            }
+            Instr::Pusherror(s) => {
+                let addr = self.alloc(Node::Error(s.to_string()));
+                self.stack.push(addr);
+            }
+            Instr::Push(n) => {
+                let addr = self.stack[self.stack.len() - 1 - n];
+                self.stack.push(addr);
+            }
+            Instr::Pop(n) => self.stack.truncate(self.stack.len() - n),
+            Instr::Slide(n) => {
+                let addr = self.pop();
+                self.stack.truncate(self.stack.len() - n);
+                self.stack.push(addr);
+            }
+            Instr::Update(n) => {
+                let addr = self.pop();
+                let root = self.stack[self.stack.len() - 1 - n];
+                // a definition that is only itself stays a hole
+                if self.resolve(addr) != root {
+                    self.heap[root] = Node::Ind(addr);
+                }
+            }
+            Instr::Alloc(n) => {
+                for _ in 0..*n {
+                    let addr = self.alloc(Node::Hole);
+                    self.stack.push(addr);
+                }
+            }
+            Instr::Mkap => {
+                let f = self.pop();
+                let x = self.pop();
+                let addr = self.alloc(Node::App(f, x));
+                self.stack.push(addr);
+            }
+            Instr::Eval => {
+                let addr = self.pop();
+                let addr = self.resolve(addr);
+                if self.is_value(addr) {
+                    self.stack.push(addr);
+                } else {
+                    let code = std::mem::take(&mut self.code);
+                    self.evaluate(addr, code);
+                }
+            }
+            Instr::Unwind => return self.unwind(),
+            Instr::Pack(con, n) => {
+                let len = self.stack.len();
+                let fields = (0..*n).map(|i| self.stack[len - 1 - i]).collect();
+                self.stack.truncate(len - n);
+                let addr = self.alloc(Node::Data(*con, fields));
+                self.stack.push(addr);
+            }
+            Instr::Split(_) => {
+                let addr = self.pop();
+                let addr = self.resolve(addr);
+                match &self.heap[addr] {
+                    Node::Data(_, fields) => self.stack.extend(fields),
+                    _ => panic!("Only constructors can be split."),
+                }
+            }
+            Instr::Casejump(alts) => {
+                let addr = self.resolve(self.top());
+                for (test, alt) in alts {
+                    if self.matches(addr, test) {
+                        self.code.push((Rc::clone(alt), 0));
+                        return None;
+                    }
+                }
+                panic!("No pattern matched.");
+            }
+            Instr::Cond(b1, b2) => {
+                let addr = self.pop();
+                let addr = self.resolve(addr);
+                let branch = match &self.heap[addr] {
+                    Node::Data(con, _) if self.program.cons.tags[*con].name == "True" => b1,
+                    Node::Data(con, _) if self.program.cons.tags[*con].name == "False" => b2,
+                    _ => panic!("If expression needs condition to be a boolean."),
+                };
+                self.code.push((Rc::clone(branch), 0));
+            }
+            Instr::Prim(g) => {
+                let program = self.program;
+                let global = &program.globals[*g];
+                let func = match global.kind {
+                    Kind::Builtin(func) => func,
+                    _ => panic!("{} isn't a builtin.", global.name),
+                };
+                let len = self.stack.len();
+                let args = (0..global.arity)
+                    .map(|i| self.to_expr(self.stack[len - 1 - i]))
+                    .collect();
+                self.stack.truncate(len - global.arity);
+                let addr = self.alloc_expr(&func(args));
+                self.stack.push(addr);
+            }
+            Instr::Partial(from) => {
+                // the argument that was evaluated
+                self.pop();
+                return self.partial(*from);
+            }
+        }
+        None
+    }
+
+    // down the spine to a supercombinator with enough arguments, or a value
+    fn unwind(&mut self) -> Option<Addr> {
+        let program = self.program;
+        loop {
+            let top = self.top();
+            match self.heap[top] {
+                Node::Ind(addr) => *self.stack.last_mut().unwrap() = addr,
+                Node::App(f, _) => self.stack.push(f),
+                Node::Global(g) => {
+                    let global = &program.globals[g];
+                    let n = global.arity;
+                    if self.stack.len() - 1 < n {
+                        return self.partial(0);
+                    }
+                    // the arguments replace the applications, the last application is the root that gets updated
+                    if n > 0 {
+                        self.stack.pop();
+                        let len = self.stack.len();
+                        let root = self.stack[len - n];
+                        let args: Vec<Addr> =
+                            (1..=n).map(|i| self.arg(self.stack[len - i])).collect();
+                        self.stack.truncate(len - n);
+                        self.stack.push(root);
+                        self.stack.extend(args.iter().rev());
+                    }
+                    self.code = vec![(Rc::clone(&global.code), 0)];
+                    return None;
+                }
+                Node::Hole => panic!("Ran into undefined."),
+                Node::Error(ref s) => panic!("{}", s),
+                // extra arguments are ignored
+                _ => return self.ret(top),
+            }
+        }
+    }
+
+    // a supercombinator without all of its arguments is done once the ones it forces are evaluated
+    fn partial(&mut self, from: usize) -> Option<Addr> {
+        let program = self.program;
+        let global = match self.heap[self.top()] {
+            Node::Global(g) => &program.globals[g],
+            _ => panic!("A partial application should have a supercombinator on top."),
+        };
+        let len = self.stack.len();
+        for i in from..len - 1 {
+            if !global.strict[i] {
+                continue;
+            }
+            let arg = self.resolve(self.arg(self.stack[len - 2 - i]));
+            if !self.is_value(arg) {
+                let rest: Code = Rc::from(vec![Instr::Partial(i + 1)]);
+                self.evaluate(arg, vec![(rest, 0)]);
+                return None;
+            }
+        }
+        self.ret(self.stack[0])
+    }
+
+    // the stack and the code go on the dump until addr has a value
+    fn evaluate(&mut self, addr: Addr, code: Vec<(Code, usize)>) {
+        if !self.evaluating.insert(addr) {
+            panic!("A definition depends on its own value.");
+        }
+        let stack = std::mem::replace(&mut self.stack, vec![addr]);
+        self.dump.push(Waiting {
+            evaluated: addr,
+            stack,
+            code,
+        });
+        self.code = vec![(Rc::clone(&self.unwind), 0)];
+    }
+
+    // back to what was waiting on the value
+    fn ret(&mut self, addr: Addr) -> Option<Addr> {
+        match self.dump.pop() {
+            Some(waiting) => {
+                self.evaluating.remove(&waiting.evaluated);
+                self.stack = waiting.stack;
+                self.stack.push(addr);
+                self.code = waiting.code;
+                None
+            }
+            None => Some(addr),
+        }
+    }
+
+    fn matches(&self, addr: Addr, test: &Test) -> bool {
+        match (&self.heap[addr], test) {
+            (_, Test::Any) => true,
+            (Node::Data(con, fields), Test::Con(c, n)) => con == c && fields.len() == *n,
+            (Node::Int(a), Test::Int(b)) => a == b,
+            (Node::Float(a), Test::Float(b)) => a == b,
+            (Node::Str(a), Test::Str(b)) => a == b,
+            (Node::Data(_, _) | Node::Int(_) | Node::Float(_) | Node::Str(_), _) => false,
+            _ => panic!(
+                "Can only pattern match on constructors and literals. Received: {}",
+                self.to_expr(addr)
+            ),
+        }
+    }
+
+    // a value in the heap as an expression, applications that aren't done are written like eval writes them
+    fn to_expr(&self, addr: Addr) -> Rc<Expr> {
+        let addr = self.resolve(addr);
+        match &self.heap[addr] {
+            Node::Int(n) => Rc::new(Expr::Int(n.clone(), Span::default())),
+            Node::Float(n) => Rc::new(Expr::Float(*n, Span::default())),
+            Node::Str(s) => Rc::new(Expr::Str(s.to_string(), Span::default())),
+            Node::Data(con, fields) => {
+                let tag = &self.program.cons.tags[*con];
+                let fields = fields.iter().map(|f| self.to_expr(*f)).collect();
+                Rc::new(Expr::Data(
+                    tag.arity,
+                    tag.typ.to_string(),
+                    tag.name.to_string(),
+                    fields,
+                ))
+            }
+            Node::App(_, _) | Node::Global(_) => {
+                let mut args = Vec::new();
+                let mut head = addr;
+                while let Node::App(f, x) = self.heap[head] {
+                    args.push(x);
+                    head = self.resolve(f);
+                }
+                args.reverse();
+                let global = match self.heap[head] {
+                    Node::Global(g) => &self.program.globals[g],
+                    _ => return Rc::new(Expr::Bottom),
+                };
+                let fields = || args.iter().map(|a| self.to_expr(*a)).collect();
+                match &global.kind {
+                    Kind::Con(con) => {
+                        let tag = &self.program.cons.tags[*con];
+                        Rc::new(Expr::Data(
+                            tag.arity,
+                            tag.typ.to_string(),
+                            tag.name.to_string(),
+                            fields(),
+                        ))
+                    }
+                    Kind::Builtin(func) => Rc::new(Expr::Builtin(
+                        global.arity,
+                        global.name.to_string(),
+                        *func,
+                        fields(),
+                        Span::default(),
+                    )),
+                    Kind::Lam(free, lams) => {
+                        let level = args.len().saturating_sub(*free).min(lams.len() - 1);
+                        Rc::clone(&lams[level])
+                    }
+                    Kind::Thunk => Rc::new(Expr::Bottom),
+                }
+            }
+            _ => Rc::new(Expr::Bottom),
+        }
+    }
+
+    // what a builtin gave back
+    fn alloc_expr(&mut self, expr: &Rc<Expr>) -> Addr {
+        let node = match &**expr {
+            Expr::Int(n, _) => Node::Int(n.clone()),
+            Expr::Float(n, _) => Node::Float(*n),
+            Expr::Str(s, _) => Node::Str(s.to_string()),
+            Expr::Data(_, _, name, fields) => {
+                let con = match self.program.cons.find(name) {
+                    Some(con) => con,
+                    None => panic!("{} isn't a constructor.", name),
+                };
+                let fields = fields.iter().map(|f| self.alloc_expr(f)).collect();
+                Node::Data(con, fields)
+            }
+            Expr::Error(s) => panic!("Error: {}", s),
+            _ => panic!("A builtin gave back {}, which isn't a value.", expr),
+        };
+        self.alloc(node)
+    }
+}
+
+// the supercombinators and their code
+impl Display for Program {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for global in &self.globals {
+            writeln!(f, "{} {}", global.name, global.arity)?;
+            self.write_code(f, &global.code, 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl Program {
+    fn write_code(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        code: &[Instr],
+        indent: usize,
+    ) -> std::fmt::Result {
+        let pad = "    ".repeat(indent);
+        for instr in code {
+            match instr {
+                Instr::Pushglobal(g) => writeln!(f, "{}PUSHGLOBAL {}", pad, self.globals[*g].name)?,
+                Instr::Pushint(n) => writeln!(f, "{}PUSHINT {}", pad, n)?,
+                Instr::Pushfloat(n) => writeln!(f, "{}PUSHFLOAT {}", pad, n)?,
+                Instr::Pushstr(s) => writeln!(f, "{}PUSHSTR {:?}", pad, s)?,
+                Instr::Pusherror(s) => writeln!(f, "{}PUSHERROR {:?}", pad, s)?,
+                Instr::Push(n) => writeln!(f, "{}PUSH {}", pad, n)?,
+                Instr::Pop(n) => writeln!(f, "{}POP {}", pad, n)?,
+                Instr::Slide(n) => writeln!(f, "{}SLIDE {}", pad, n)?,
+                Instr::Update(n) => writeln!(f, "{}UPDATE {}", pad, n)?,
+                Instr::Alloc(n) => writeln!(f, "{}ALLOC {}", pad, n)?,
+                Instr::Mkap => writeln!(f, "{}MKAP", pad)?,
+                Instr::Eval => writeln!(f, "{}EVAL", pad)?,
+                Instr::Unwind => writeln!(f, "{}UNWIND", pad)?,
+                Instr::Pack(con, n) => {
+                    writeln!(f, "{}PACK {} {}", pad, self.cons.tags[*con].name, n)?
+                }
+                Instr::Split(n) => writeln!(f, "{}SPLIT {}", pad, n)?,
+                Instr::Casejump(alts) => {
+                    writeln!(f, "{}CASEJUMP", pad)?;
+                    for (test, alt) in alts {
+                        match test {
+                            Test::Con(con, n) => {
+                                writeln!(f, "{}    {} {} ->", pad, self.cons.tags[*con].name, n)?
+                            }
+                            Test::Int(n) => writeln!(f, "{}    {} ->", pad, n)?,
+                            Test::Float(n) => writeln!(f, "{}    {} ->", pad, n)?,
+                            Test::Str(s) => writeln!(f, "{}    {:?} ->", pad, s)?,
+                            Test::Any => writeln!(f, "{}    _ ->", pad)?,
+                        }
+                        self.write_code(f, alt, indent + 2)?;
+                    }
+                }
+                Instr::Cond(b1, b2) => {
+                    writeln!(f, "{}COND", pad)?;
+                    writeln!(f, "{}    True ->", pad)?;
+                    self.write_code(f, b1, indent + 2)?;
+                    writeln!(f, "{}    False ->", pad)?;
+                    self.write_code(f, b2, indent + 2)?;
+                }
+                Instr::Prim(g) => writeln!(f, "{}PRIM {}", pad, self.globals[*g].name)?,
+                Instr::Partial(n) => writeln!(f, "{}PARTIAL {}", pad, n)?,
+            }
+        }
+        Ok(())
+    }
+}
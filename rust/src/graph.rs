@@ -0,0 +1,295 @@
+/*
+
+export the dependency graph that rearrange builds for the letrecs
+
+rearrange only needs the graph to reorder the definitions, but it is also useful for looking at how a program is put together
+
+each definition becomes a node and an edge points from a definition to the definitions it uses
+the strongly connected components are the groups that end up in the same letrec
+
+data constructors are also defined in the toplevel letrec, but they can't depend on anything and would clutter the graph so they get left out
+the same goes for the other generated definitions (field accessors, methods, instances), they are the ones without a location
+
+nested definitions are optional, they get named after the definition they are found in (outer.inner)
+    edges can go from a nested definition to anything it can see
+    the enclosing definition gets an edge to the nested definitions used by the let body
+
+
+output is either graphviz dot or json
+
+*/
+
+use crate::ast::Expr;
+use crate::rearrange::dependencies;
+use crate::rearrange::dependency_graph;
+use std::fmt::Write;
+use std::rc::Rc;
+
+pub struct Node {
+    pub name: String,
+    pub parent: Option<usize>, // the definition this one is nested in
+    pub self_rec: bool,
+}
+
+pub struct CallGraph {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<(usize, usize)>,
+    pub components: Vec<Vec<usize>>,
+}
+
+// build the graph from the letrec created by Toplevel::to_let
+pub fn call_graph(expr: &Rc<Expr>, nested: bool) -> CallGraph {
+    let mut graph = CallGraph {
+        nodes: Vec::new(),
+        edges: Vec::new(),
+        components: Vec::new(),
+    };
+    if let Expr::LetRec(vars, defs, _) = &**expr {
+        graph.add_letrec(vars, defs, None, &[], nested);
+    }
+    graph.components.sort();
+    graph
+}
+
+impl CallGraph {
+    // add the definitions of a letrec, scope holds the definitions visible from outside of it
+    fn add_letrec(
+        &mut self,
+        vars: &[Rc<Expr>],
+        defs: &[Rc<Expr>],
+        parent: Option<usize>,
+        scope: &[(String, usize)],
+        nested: bool,
+    ) -> Vec<(String, usize)> {
+        let deps = dependency_graph(vars, defs);
+
+        // constructors and generated definitions don't get a node
+        let mut ids = Vec::new();
+        let mut local = Vec::new();
+        for (i, def) in defs.iter().enumerate().take(deps.names.len()) {
+            if generated(&vars[i], def) {
+                ids.push(None);
+            } else {
+                let name = match parent {
+                    Some(p) => format!("{}.{}", self.nodes[p].name, deps.names[i]),
+                    None => deps.names[i].to_string(),
+                };
+                self.nodes.push(Node {
+                    name,
+                    parent,
+                    self_rec: deps.self_rec.contains(&i),
+                });
+                ids.push(Some(self.nodes.len() - 1));
+                local.push((deps.names[i].to_string(), self.nodes.len() - 1));
+            }
+        }
+
+        for i in 0..ids.len() {
+            if let (Some(from), Some(targets)) = (ids[i], deps.edges.get(&i)) {
+                for target in targets {
+                    if let Some(to) = ids[*target] {
+                        self.add_edge(from, to);
+                    }
+                }
+            }
+        }
+
+        for component in deps.components() {
+            let mut component: Vec<usize> = component.iter().filter_map(|i| ids[*i]).collect();
+            if !component.is_empty() {
+                component.sort();
+                self.components.push(component);
+            }
+        }
+
+        // uses of the enclosing definitions, a local name hides an outer one
+        let mut outer_names = Vec::new();
+        let mut outer_ids = Vec::new();
+        for (name, id) in scope.iter().rev() {
+            if !deps.names.contains(name) && !outer_names.contains(name) {
+                outer_names.push(name.to_string());
+                outer_ids.push(*id);
+            }
+        }
+        for i in 0..ids.len() {
+            if let Some(from) = ids[i] {
+                for d in dependencies(&outer_names, Rc::clone(&defs[i]), Vec::new()) {
+                    if let Some(pos) = outer_names.iter().position(|n| n == &d) {
+                        self.add_edge(from, outer_ids[pos]);
+                    }
+                }
+            }
+        }
+
+        if nested {
+            let mut inner_scope = scope.to_vec();
+            inner_scope.extend(local.iter().cloned());
+            for i in 0..ids.len() {
+                if let Some(owner) = ids[i] {
+                    let mut found = Vec::new();
+                    nested_letrecs(&defs[i], &mut found);
+                    for letrec in found {
+                        if let Expr::LetRec(nvars, ndefs, nbody) = &*letrec {
+                            let inner =
+                                self.add_letrec(nvars, ndefs, Some(owner), &inner_scope, nested);
+                            let names: Vec<String> =
+                                inner.iter().map(|n| n.0.to_string()).collect();
+                            for d in dependencies(&names, Rc::clone(nbody), Vec::new()) {
+                                if let Some(pos) = names.iter().position(|n| n == &d) {
+                                    self.add_edge(owner, inner[pos].1);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        local
+    }
+
+    fn add_edge(&mut self, from: usize, to: usize) {
+        if !self.edges.contains(&(from, to)) {
+            self.edges.push((from, to));
+        }
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "digraph bagl {{").unwrap();
+        let mut cluster = 0;
+        for component in &self.components {
+            if component.len() > 1 {
+                writeln!(out, "    subgraph cluster_{} {{", cluster).unwrap();
+                writeln!(out, "        style=dashed;").unwrap();
+                for id in component {
+                    writeln!(out, "        n{};", id).unwrap();
+                }
+                writeln!(out, "    }}").unwrap();
+                cluster += 1;
+            }
+        }
+        for (id, node) in self.nodes.iter().enumerate() {
+            if node.self_rec {
+                writeln!(
+                    out,
+                    "    n{} [label=\"{}\", peripheries=2];",
+                    id,
+                    escape(&node.name)
+                )
+                .unwrap();
+            } else {
+                writeln!(out, "    n{} [label=\"{}\"];", id, escape(&node.name)).unwrap();
+            }
+        }
+        for (from, to) in &self.edges {
+            writeln!(out, "    n{} -> n{};", from, to).unwrap();
+        }
+        writeln!(out, "}}").unwrap();
+        out
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "{{").unwrap();
+        writeln!(out, "  \"nodes\": [").unwrap();
+        for (id, node) in self.nodes.iter().enumerate() {
+            let parent = match node.parent {
+                Some(p) => p.to_string(),
+                None => "null".to_string(),
+            };
+            let component = self
+                .components
+                .iter()
+                .position(|c| c.contains(&id))
+                .unwrap_or(0);
+            write!(
+                out,
+                "    {{\"id\": {}, \"name\": \"{}\", \"parent\": {}, \"self_recursive\": {}, \"component\": {}}}",
+                id,
+                escape(&node.name),
+                parent,
+                node.self_rec,
+                component
+            )
+            .unwrap();
+            writeln!(out, "{}", if id + 1 < self.nodes.len() { "," } else { "" }).unwrap();
+        }
+        writeln!(out, "  ],").unwrap();
+        writeln!(out, "  \"edges\": [").unwrap();
+        for (i, (from, to)) in self.edges.iter().enumerate() {
+            write!(out, "    {{\"from\": {}, \"to\": {}}}", from, to).unwrap();
+            writeln!(out, "{}", if i + 1 < self.edges.len() { "," } else { "" }).unwrap();
+        }
+        writeln!(out, "  ],").unwrap();
+        writeln!(out, "  \"components\": [").unwrap();
+        for (i, component) in self.components.iter().enumerate() {
+            let members: Vec<String> = component.iter().map(|id| id.to_string()).collect();
+            write!(out, "    [{}]", members.join(", ")).unwrap();
+            writeln!(
+                out,
+                "{}",
+                if i + 1 < self.components.len() {
+                    ","
+                } else {
+                    ""
+                }
+            )
+            .unwrap();
+        }
+        writeln!(out, "  ]").unwrap();
+        writeln!(out, "}}").unwrap();
+        out
+    }
+}
+
+// the letrecs that belong directly to a definition, the ones inside of their definitions are found when adding them
+fn nested_letrecs(expr: &Rc<Expr>, found: &mut Vec<Rc<Expr>>) {
+    match &**expr {
+        Expr::LetRec(_, _, body) => {
+            found.push(Rc::clone(expr));
+            nested_letrecs(body, found);
+        }
+        Expr::Let(_, _, body) => nested_letrecs(body, found),
+        Expr::Lam(_, body) => nested_letrecs(body, found),
+        Expr::App(left, right) => {
+            nested_letrecs(left, found);
+            nested_letrecs(right, found);
+        }
+        Expr::If(cond, b1, b2) => {
+            nested_letrecs(cond, found);
+            nested_letrecs(b1, found);
+            nested_letrecs(b2, found);
+        }
+        Expr::Case(expr, _, branches, _) => {
+            nested_letrecs(expr, found);
+            for branch in branches {
+                nested_letrecs(branch, found);
+            }
+        }
+        Expr::Annot(expr, _) => nested_letrecs(expr, found),
+        _ => (),
+    }
+}
+
+// escaping is the same for dot and json strings
+fn escape(s: &str) -> String {
+    let mut out = String::new();
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn generated(var: &Rc<Expr>, def: &Rc<Expr>) -> bool {
+    match (&**var, &**def) {
+        (_, Expr::Data(..)) => true,
+        (Expr::Var(_, _, span), _) => !span.is_known(),
+        _ => false,
+    }
+}
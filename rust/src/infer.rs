@@ -0,0 +1,1154 @@
+/*
+
+type checking, hindley milner style inference
+
+runs after change_lets so that the lets are already split up into the smallest recursive groups
+    a let gets generalized right away
+    a letrec group is monomorphic inside of the group and generalized afterwards
+
+the types of constructors come from the data declarations
+    List a = Cons a (List a) | Nil
+    Cons : a -> List a -> List a
+    Nil : List a
+
+builtins are typed by name, the arithmetic is Num a => a -> a -> a since it works on ints and floats
+
+signatures are attached to the definitions as annotations
+    a name with a signature is put in scope with the declared type before its group is checked, that way it can be used at other types in its own definition (polymorphic recursion)
+    the definition is checked against the declared type with the type variables made rigid, so the inferred type has to be at least as general as the declared one
+        every signature gets its own rigid variables, the a in one signature isn't the a in another
+        a rigid variable can't end up as the type of something from outside, f x = let g :: a -> a; g _y = x in g would make a the type of x
+    an annotation on an expression (e :: t) is checked the same way
+
+errors are collected per definition, a broken definition gets a fresh type so the rest can still be checked
+
+type classes (see classes.rs) add constraints to the types
+    using something with a constrained type wants an instance for the type it is used at, show 1 wants Show Int
+    the wanted constraints are solved whenever a group gets generalized
+        a concrete type gets its instance, and the constraints from the instance's context are solved the same way
+        a variable that gets generalized turns into a constraint of the new type and a dictionary parameter of the definitions
+        a variable that is still in the environment has to wait for an outer definition
+        a rigid variable from a signature has to be in the signature's context
+    whatever is left at the end is ambiguous
+Num has no dictionary, solving it only checks that there is an instance
+
+the solutions are kept by the address of the variable they are for, elaborate uses them to add the dictionaries
+the tree from change_lets keeps the same variable nodes as the one it came from so the addresses work for both
+
+*/
+
+use crate::ast::Expr;
+use crate::ast::Pattern;
+use crate::ast::Span;
+use crate::ast::Toplevel;
+use crate::diagnostic::Diagnostic;
+use crate::info::DataInfo;
+use crate::info::TypeInfo;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Var(usize),           // unknown type, gets solved by unification
+    Rigid(String, usize), // type variable from a signature, only matches itself, numbered so each signature has its own
+    Con(String),
+    App(Box<Type>, Box<Type>),
+    Fun(Box<Type>, Box<Type>),
+}
+
+// a type with some variables that can be anything, as long as there are instances for the constraints
+#[derive(Debug, Clone)]
+pub struct Scheme {
+    pub vars: Vec<usize>,
+    pub preds: Vec<(String, usize)>, // class and variable
+    pub typ: Type,
+}
+
+// a variable defined by a let or letrec along with its type
+#[derive(Debug, Clone)]
+pub struct Binding {
+    pub name: String,
+    pub span: Span,
+    pub scheme: Scheme,
+    pub toplevel: bool,
+}
+
+// which dictionary to pass
+#[derive(Debug, Clone, PartialEq)]
+pub enum Dict {
+    Instance(String, Vec<Dict>), // the instance's dictionary applied to the dictionaries for its context
+    Param(String),               // a dictionary parameter of an enclosing definition
+    None,                        // for classes without dictionaries
+}
+
+// what elaborate needs, keyed by the address of the variable nodes
+#[derive(Debug, Default)]
+pub struct Elaboration {
+    pub args: HashMap<*const Expr, Vec<Dict>>, // dictionaries to pass where a variable is used
+    pub methods: HashMap<*const Expr, Dict>,   // the dictionary a method is taken from
+    pub params: HashMap<*const Expr, Vec<String>>, // dictionary parameters of a definition
+}
+
+pub struct Checked {
+    pub bindings: Vec<Binding>,
+    pub elaboration: Elaboration,
+    infer: Infer, // the instances, for finding dictionaries afterwards
+}
+
+impl Checked {
+    // the dictionary for an instance at a type from the checked program, None if there isn't one
+    pub fn dictionary(&mut self, class: &str, typ: &Type) -> Option<Dict> {
+        let errors = self.infer.errors.len();
+        let found = self
+            .infer
+            .entail(class, typ, Span::default(), &[], &[], &mut Vec::new());
+        self.infer.errors.truncate(errors);
+        found.ok().flatten()
+    }
+}
+
+// check the program after change_lets, giving back the types of everything defined by a let
+pub fn check_types(expr: &Rc<Expr>, top: &Toplevel) -> Result<Checked, Vec<Diagnostic>> {
+    let mut infer = Infer::new();
+    for info in &top.types {
+        infer.add_data(info);
+    }
+    infer.add_classes(top);
+    let from = infer.wanted.len();
+    if let Err(error) = infer.infer(expr) {
+        infer.errors.push(error);
+    }
+    infer.solve(from, &[], &[]);
+    if infer.errors.is_empty() {
+        let mut bindings = Vec::new();
+        for binding in &infer.bindings {
+            let mut binding = binding.clone();
+            binding.scheme.typ = infer.apply(&binding.scheme.typ);
+            bindings.push(binding);
+        }
+        let elaboration = infer.elaboration();
+        Ok(Checked {
+            bindings,
+            elaboration,
+            infer,
+        })
+    } else {
+        Err(infer.errors)
+    }
+}
+
+// where a variable in the environment came from
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Bound {
+    Plain,
+    Group(usize), // a definition of a group that is still being checked, it takes the group's dictionaries
+    Method,       // picks its method out of the dictionary for the first constraint
+}
+
+// how a variable was used
+enum Use {
+    Args(Vec<usize>), // the constraints from instantiating it
+    Group(usize),
+    Method(usize),
+}
+
+// type parameters, context, and dictionary of an instance, by class and type
+type Instance = (Vec<String>, Vec<(String, String)>, String);
+
+struct Pred {
+    class: String,
+    typ: Type,
+    span: Span,
+}
+
+struct Infer {
+    subst: Vec<Option<Type>>, // what each variable has been solved to
+    env: Vec<(String, Scheme, Bound)>,
+    constructors: HashMap<String, Scheme>,
+    bindings: Vec<Binding>,
+    errors: Vec<Diagnostic>,
+    level: usize,                   // how many definitions deep, 0 is the toplevel
+    classes: HashMap<String, bool>, // whether the class has a dictionary
+    methods: HashMap<String, Scheme>,
+    instances: HashMap<(String, String), Instance>,
+    preds: Vec<Pred>,
+    wanted: Vec<usize>, // constraints that aren't solved yet
+    solved: HashMap<usize, Dict>,
+    givens: Vec<(String, usize, String)>, // class, rigid variable, dictionary parameter
+    uses: HashMap<*const Expr, Use>,
+    params: HashMap<*const Expr, Vec<String>>,
+    group_params: HashMap<usize, Vec<String>>,
+    groups: usize,
+    names: usize,
+    rigids: usize,
+}
+
+impl Infer {
+    fn new() -> Infer {
+        Infer {
+            subst: Vec::new(),
+            env: Vec::new(),
+            constructors: HashMap::new(),
+            bindings: Vec::new(),
+            errors: Vec::new(),
+            level: 0,
+            classes: HashMap::new(),
+            methods: HashMap::new(),
+            instances: HashMap::new(),
+            preds: Vec::new(),
+            wanted: Vec::new(),
+            solved: HashMap::new(),
+            givens: Vec::new(),
+            uses: HashMap::new(),
+            params: HashMap::new(),
+            group_params: HashMap::new(),
+            groups: 0,
+            names: 0,
+            rigids: 0,
+        }
+    }
+
+    fn fresh(&mut self) -> Type {
+        self.subst.push(None);
+        Type::Var(self.subst.len() - 1)
+    }
+
+    // the type of each constructor is a function from its arguments to the declared type
+    fn add_data(&mut self, info: &DataInfo) {
+        let mut vars = HashMap::new();
+        let result = self.convert(&info.type_info, &mut vars);
+        for alt in &info.data_info.alts {
+            let mut vars = vars.clone();
+            let mut typ = result.clone();
+            for arg in alt.args.iter().rev() {
+                typ = Type::Fun(Box::new(self.convert(arg, &mut vars)), Box::new(typ));
+            }
+            let vars = vars.values().filter_map(var_id).collect();
+            self.constructors.insert(
+                alt.name.to_string(),
+                Scheme {
+                    vars,
+                    preds: Vec::new(),
+                    typ,
+                },
+            );
+        }
+    }
+
+    // a method has the type from its class, with the class as the first constraint
+    fn add_classes(&mut self, top: &Toplevel) {
+        for class in &top.classes {
+            self.classes
+                .insert(class.name.to_string(), class.dictionary);
+            for (method, info) in &class.methods {
+                let mut vars = HashMap::new();
+                let var = self.fresh();
+                vars.insert(class.var.to_string(), var.clone());
+                let typ = self.convert(info, &mut vars);
+                let scheme = Scheme {
+                    vars: vars.values().filter_map(var_id).collect(),
+                    preds: vec![(class.name.to_string(), var_id(&var).unwrap())],
+                    typ,
+                };
+                self.methods.insert(method.to_string(), scheme);
+            }
+        }
+        for instance in &top.instances {
+            self.instances.insert(
+                (instance.class.to_string(), instance.type_name()),
+                (
+                    instance.typ.vars(),
+                    instance.context.clone(),
+                    instance.dict_name(),
+                ),
+            );
+        }
+    }
+
+    // type variables with the same name become the same variable
+    fn convert(&mut self, info: &TypeInfo, vars: &mut HashMap<String, Type>) -> Type {
+        match info {
+            TypeInfo::TConstructor(s) => Type::Con(s.to_string()),
+            TypeInfo::TVar(s) => {
+                if let Some(t) = vars.get(s) {
+                    t.clone()
+                } else {
+                    let t = self.fresh();
+                    vars.insert(s.to_string(), t.clone());
+                    t
+                }
+            }
+            TypeInfo::TApp(left, right) => Type::App(
+                Box::new(self.convert(left, vars)),
+                Box::new(self.convert(right, vars)),
+            ),
+            TypeInfo::TFun(arg, res) => Type::Fun(
+                Box::new(self.convert(arg, vars)),
+                Box::new(self.convert(res, vars)),
+            ),
+            TypeInfo::TConstrained(_, typ) => self.convert(typ, vars),
+        }
+    }
+
+    fn declared(&mut self, info: &TypeInfo) -> Scheme {
+        let mut vars = HashMap::new();
+        let typ = self.convert(info, &mut vars);
+        let mut preds = Vec::new();
+        if let TypeInfo::TConstrained(context, _) = info {
+            for (class, var) in context {
+                if let Some(v) = vars.get(var).and_then(var_id) {
+                    preds.push((class.to_string(), v));
+                }
+            }
+        }
+        Scheme {
+            vars: vars.values().filter_map(var_id).collect(),
+            preds,
+            typ,
+        }
+    }
+
+    // follow the substitution all the way through
+    fn apply(&self, t: &Type) -> Type {
+        match t {
+            Type::Var(i) => match &self.subst[*i] {
+                Some(solved) => self.apply(solved),
+                None => t.clone(),
+            },
+            Type::App(left, right) => {
+                Type::App(Box::new(self.apply(left)), Box::new(self.apply(right)))
+            }
+            Type::Fun(arg, res) => Type::Fun(Box::new(self.apply(arg)), Box::new(self.apply(res))),
+            _ => t.clone(),
+        }
+    }
+
+    fn unify(&mut self, a: &Type, b: &Type) -> Result<(), String> {
+        let a = self.apply(a);
+        let b = self.apply(b);
+        match (&a, &b) {
+            (Type::Var(i), Type::Var(j)) if i == j => Ok(()),
+            (Type::Var(i), t) | (t, Type::Var(i)) => {
+                if free_vars(t).contains(i) {
+                    Err(format!(
+                        "infinite type, `{}` would have to contain itself",
+                        show(&[&a, &b])[0]
+                    ))
+                } else {
+                    self.subst[*i] = Some(t.clone());
+                    Ok(())
+                }
+            }
+            (Type::Rigid(_, x), Type::Rigid(_, y)) if x == y => Ok(()),
+            (Type::Con(x), Type::Con(y)) if x == y => Ok(()),
+            (Type::App(l1, r1), Type::App(l2, r2)) | (Type::Fun(l1, r1), Type::Fun(l2, r2)) => {
+                self.unify(l1, l2)?;
+                self.unify(r1, r2)
+            }
+            _ => {
+                let shown = show(&[&a, &b]);
+                Err(format!("cannot match `{}` with `{}`", shown[0], shown[1]))
+            }
+        }
+    }
+
+    // fresh variables for the scheme, the constraints become wanted at the span
+    fn instantiate(&mut self, scheme: &Scheme, span: Span) -> (Type, Vec<usize>) {
+        let mut mapping = HashMap::new();
+        for v in &scheme.vars {
+            let fresh = self.fresh();
+            mapping.insert(*v, fresh);
+        }
+        let mut preds = Vec::new();
+        for (class, v) in &scheme.preds {
+            let typ = substitute(&Type::Var(*v), &mapping);
+            preds.push(self.want(class, typ, span));
+        }
+        (substitute(&scheme.typ, &mapping), preds)
+    }
+
+    fn want(&mut self, class: &str, typ: Type, span: Span) -> usize {
+        self.preds.push(Pred {
+            class: class.to_string(),
+            typ,
+            span,
+        });
+        self.wanted.push(self.preds.len() - 1);
+        self.preds.len() - 1
+    }
+
+    // the variables that something in the environment still depends on
+    fn env_vars(&self) -> Vec<usize> {
+        self.vars_before(self.env.len())
+    }
+
+    // the variables of the first len entries of the environment
+    fn vars_before(&self, len: usize) -> Vec<usize> {
+        let mut in_env = Vec::new();
+        for (_, scheme, _) in &self.env[..len] {
+            for v in free_vars(&self.apply(&scheme.typ)) {
+                if !scheme.vars.contains(&v) && !in_env.contains(&v) {
+                    in_env.push(v);
+                }
+            }
+        }
+        in_env
+    }
+
+    fn lookup(&self, name: &str) -> Option<(&Scheme, Bound)> {
+        self.env
+            .iter()
+            .rev()
+            .find(|(n, _, _)| n == name)
+            .map(|(_, s, b)| (s, *b))
+    }
+
+    fn has_dictionary(&self, class: &str) -> bool {
+        self.classes.get(class).copied().unwrap_or(true)
+    }
+
+    // solve the constraints wanted since from, the variables in generalized become constraints of the group
+    // gives back the new constraints along with the names of their dictionary parameters
+    fn solve(
+        &mut self,
+        from: usize,
+        generalized: &[usize],
+        env: &[usize],
+    ) -> Vec<(String, usize, String)> {
+        let mut params = Vec::new();
+        let wanted = self.wanted.split_off(from);
+        for id in wanted {
+            let class = self.preds[id].class.to_string();
+            let typ = self.preds[id].typ.clone();
+            let span = self.preds[id].span;
+            match self.entail(&class, &typ, span, generalized, env, &mut params) {
+                Ok(Some(dict)) => {
+                    self.solved.insert(id, dict);
+                }
+                Ok(None) => self.wanted.push(id),
+                Err(e) => {
+                    self.errors.push(e);
+                    self.solved.insert(id, Dict::None);
+                }
+            }
+        }
+        params
+    }
+
+    // the dictionary for an instance of the class, None if it has to wait for an outer definition
+    fn entail(
+        &mut self,
+        class: &str,
+        typ: &Type,
+        span: Span,
+        generalized: &[usize],
+        env: &[usize],
+        params: &mut Vec<(String, usize, String)>,
+    ) -> Result<Option<Dict>, Diagnostic> {
+        let typ = self.apply(typ);
+        let dictionary = self.has_dictionary(class);
+        let constraint = || format!("{} {}", class, pretty(&typ, &mut Vec::new(), true));
+        match &typ {
+            Type::Var(v) if generalized.contains(v) => {
+                if let Some((_, _, name)) = params.iter().find(|(c, w, _)| c == class && w == v) {
+                    return Ok(Some(param(name)));
+                }
+                let name = if dictionary {
+                    self.names += 1;
+                    format!("_{}{}", class, self.names)
+                } else {
+                    String::new()
+                };
+                params.push((class.to_string(), *v, name.to_string()));
+                Ok(Some(param(&name)))
+            }
+            Type::Var(v) if env.contains(v) => Ok(None),
+            // nothing gets passed so any type will do
+            Type::Var(_) if !dictionary => Ok(Some(Dict::None)),
+            Type::Var(_) => Err(Diagnostic::error(
+                span,
+                format!(
+                    "ambiguous type, can't tell which instance of `{}` to use",
+                    class
+                ),
+            )),
+            Type::Rigid(_, r) => match self.givens.iter().find(|(c, v, _)| c == class && v == r) {
+                Some((_, _, name)) => Ok(Some(param(name))),
+                None => Err(
+                    Diagnostic::error(span, format!("no instance `{}`", constraint()))
+                        .with_help(format!("add `{}` to the signature", constraint())),
+                ),
+            },
+            _ => {
+                let mut args = Vec::new();
+                let mut head = &typ;
+                while let Type::App(left, right) = head {
+                    args.push((**right).clone());
+                    head = left;
+                }
+                args.reverse();
+                let instance = match head {
+                    Type::Con(name) => self.instances.get(&(class.to_string(), name.to_string())),
+                    _ => None,
+                };
+                let (vars, context, dict) = match instance {
+                    Some(instance) => instance.clone(),
+                    None => {
+                        return Err(Diagnostic::error(
+                            span,
+                            format!("no instance `{}`", constraint()),
+                        ))
+                    }
+                };
+                if !dictionary {
+                    return Ok(Some(Dict::None));
+                }
+                let mut subs = Vec::new();
+                for (c, var) in &context {
+                    let arg = match vars.iter().position(|v| v == var) {
+                        Some(i) if i < args.len() => args[i].clone(),
+                        _ => continue,
+                    };
+                    match self.entail(c, &arg, span, generalized, env, params)? {
+                        Some(Dict::None) => (),
+                        Some(sub) => subs.push(sub),
+                        None => return Ok(None),
+                    }
+                }
+                Ok(Some(Dict::Instance(dict, subs)))
+            }
+        }
+    }
+
+    // the solved dictionaries for each use of a variable
+    fn elaboration(&self) -> Elaboration {
+        let dict = |id: &usize| self.solved.get(id).cloned().unwrap_or(Dict::None);
+        let mut elaboration = Elaboration::default();
+        for (var, u) in &self.uses {
+            match u {
+                Use::Args(ids) => {
+                    let dicts: Vec<Dict> =
+                        ids.iter().map(dict).filter(|d| *d != Dict::None).collect();
+                    if !dicts.is_empty() {
+                        elaboration.args.insert(*var, dicts);
+                    }
+                }
+                Use::Group(group) => {
+                    let names = self.group_params.get(group).cloned().unwrap_or_default();
+                    if !names.is_empty() {
+                        elaboration
+                            .args
+                            .insert(*var, names.iter().map(|n| param(n)).collect());
+                    }
+                }
+                Use::Method(id) => {
+                    elaboration.methods.insert(*var, dict(id));
+                }
+            }
+        }
+        elaboration.params = self.params.clone();
+        elaboration
+    }
+
+    fn infer(&mut self, expr: &Rc<Expr>) -> Result<Type, Diagnostic> {
+        match &**expr {
+            Expr::Int(_, _) => Ok(Type::Con("Int".to_string())),
+            Expr::Float(_, _) => Ok(Type::Con("Float".to_string())),
+            Expr::Str(_, _) => Ok(Type::Con("Str".to_string())),
+            Expr::Var(s, _, span) => match self.lookup(s) {
+                Some((scheme, bound)) => {
+                    let scheme = scheme.clone();
+                    let (t, preds) = self.instantiate(&scheme, *span);
+                    let u = match bound {
+                        Bound::Group(group) => Use::Group(group),
+                        Bound::Method => Use::Method(preds[0]),
+                        Bound::Plain => Use::Args(preds),
+                    };
+                    self.uses.insert(Rc::as_ptr(expr), u);
+                    Ok(t)
+                }
+                None => Err(Diagnostic::error(
+                    *span,
+                    format!("unbound variable `{}`", s),
+                )),
+            },
+            Expr::Lam(head, body) => {
+                let arg = self.fresh();
+                self.env.push((name(head), mono(arg.clone()), Bound::Plain));
+                let res = self.infer(body);
+                self.env.pop();
+                Ok(Type::Fun(Box::new(arg), Box::new(res?)))
+            }
+            Expr::App(left, right) => {
+                let f = self.infer(left)?;
+                let x = self.infer(right)?;
+                let res = self.fresh();
+                let expected = Type::Fun(Box::new(x), Box::new(res.clone()));
+                self.unify(&f, &expected).map_err(|e| mismatch(expr, e))?;
+                Ok(res)
+            }
+            Expr::If(cond, b1, b2) => {
+                let c = self.infer(cond)?;
+                self.unify(&c, &Type::Con("Bool".to_string()))
+                    .map_err(|e| mismatch(cond, e))?;
+                let t1 = self.infer(b1)?;
+                let t2 = self.infer(b2)?;
+                self.unify(&t1, &t2).map_err(|e| mismatch(expr, e))?;
+                Ok(t1)
+            }
+            Expr::Case(cond, pats, branches, spans) => {
+                let scrutinee = self.infer(cond)?;
+                let res = self.fresh();
+                for (i, (pat, branch)) in pats.iter().zip(branches).enumerate() {
+                    let span = spans.get(i).copied().unwrap_or_default();
+                    let bound = self.pattern(pat, &scrutinee, span)?;
+                    let t = self.infer(branch);
+                    self.env.truncate(self.env.len() - bound);
+                    let t = t?;
+                    self.unify(&res, &t).map_err(|e| mismatch(branch, e))?;
+                }
+                Ok(res)
+            }
+            Expr::Let(vars, defs, body) => {
+                self.group(vars, defs, false);
+                let t = self.infer(body);
+                self.env.truncate(self.env.len() - vars.len());
+                t
+            }
+            Expr::LetRec(vars, defs, body) => {
+                self.group(vars, defs, true);
+                let t = self.infer(body);
+                self.env.truncate(self.env.len() - vars.len());
+                t
+            }
+            Expr::Data(_, _, cons, _) => match self.constructors.get(cons).cloned() {
+                Some(scheme) => Ok(self.instantiate(&scheme, Span::default()).0),
+                None => Err(Diagnostic::error(
+                    Span::default(),
+                    format!("no data declaration for constructor `{}`", cons),
+                )),
+            },
+            Expr::Builtin(_, s, _, _, span) => {
+                let a = self.fresh();
+                let con = |name: &str| Type::Con(name.to_string());
+                let fun = |arg: Type, res: Type| Type::Fun(Box::new(arg), Box::new(res));
+                Ok(match s.as_str() {
+                    "==" => fun(a.clone(), fun(a, con("Bool"))),
+                    "compare" => fun(a.clone(), fun(a, con("Ordering"))),
+                    "show" => fun(a, con("Str")),
+                    "++" => fun(con("Str"), fun(con("Str"), con("Str"))),
+                    "parens" => fun(con("Str"), con("Str")),
+                    "assertEq" => fun(a.clone(), fun(a, con("Bool"))),
+                    "assert" => fun(con("Bool"), con("Bool")),
+                    _ => {
+                        self.want("Num", a.clone(), *span);
+                        fun(a.clone(), fun(a.clone(), a))
+                    }
+                })
+            }
+            Expr::Annot(inner, info) => {
+                let t = self.infer(inner)?;
+                let mut vars = HashMap::new();
+                let rigid = self.rigid(info, &mut vars);
+                let outer = self.env_vars();
+                self.unify(&t, &rigid)
+                    .and_then(|()| self.escaped(&outer, &vars))
+                    .map_err(|e| {
+                        self.forget(&vars);
+                        Diagnostic::error(
+                            locate(inner),
+                            format!("expression doesn't match its annotation `{}`: {}", info, e),
+                        )
+                    })?;
+                let scheme = self.declared(info);
+                Ok(self.instantiate(&scheme, locate(inner)).0)
+            }
+            Expr::Error(_) | Expr::Bottom | Expr::Closure(..) => Ok(self.fresh()),
+            Expr::Record(..) | Expr::Update(..) => {
+                panic!("Records should be desugared while parsing.")
+            }
+        }
+    }
+
+    // infer a definition, an error is recorded and the definition can be anything
+    fn definition(&mut self, def: &Rc<Expr>) -> Type {
+        self.level += 1;
+        let t = self.infer(def);
+        self.level -= 1;
+        match t {
+            Ok(t) => t,
+            Err(e) => {
+                self.errors.push(e);
+                self.fresh()
+            }
+        }
+    }
+
+    // check a group of definitions that can see each other if they are recursive, and put them in scope
+    // the ones without signatures are monomorphic inside of the group and generalized together
+    fn group(&mut self, vars: &[Rc<Expr>], defs: &[Rc<Expr>], recursive: bool) {
+        let from = self.wanted.len();
+        let group = self.groups;
+        self.groups += 1;
+        let start = self.env.len();
+        let mut monos = Vec::new();
+        for (var, def) in vars.iter().zip(defs) {
+            let (scheme, bound) = match (&**def, self.placeholder(var, def)) {
+                (_, Some(scheme)) => (scheme, Bound::Method),
+                (Expr::Annot(_, info), _) => (self.declared(info), Bound::Plain),
+                _ => (mono(self.fresh()), Bound::Group(group)),
+            };
+            monos.push(scheme.typ.clone());
+            if recursive {
+                self.env.push((name(var), scheme, bound));
+            }
+        }
+        let mut schemes = Vec::new();
+        for (i, (var, def)) in vars.iter().zip(defs).enumerate() {
+            let scheme = match (&**def, self.placeholder(var, def)) {
+                (_, Some(scheme)) => Some(scheme),
+                (Expr::Annot(inner, info), _) => Some(self.signature(var, inner, info, start)),
+                _ => {
+                    let t = self.definition(def);
+                    if let Err(e) = self.unify(&monos[i], &t) {
+                        self.errors.push(mismatch(var, e));
+                    }
+                    None
+                }
+            };
+            schemes.push(scheme);
+        }
+        self.env.truncate(start);
+
+        let env = self.env_vars();
+        let mut generalized = Vec::new();
+        for (mono, scheme) in monos.iter().zip(&schemes) {
+            if scheme.is_none() {
+                for v in free_vars(&self.apply(mono)) {
+                    if !env.contains(&v) && !generalized.contains(&v) {
+                        generalized.push(v);
+                    }
+                }
+            }
+        }
+        let params = self.solve(from, &generalized, &env);
+        let names: Vec<String> = params
+            .iter()
+            .filter(|(_, _, n)| !n.is_empty())
+            .map(|(_, _, n)| n.to_string())
+            .collect();
+        self.group_params.insert(group, names.clone());
+        let preds: Vec<(String, usize)> = params.into_iter().map(|(c, v, _)| (c, v)).collect();
+        for ((var, def), (mono, scheme)) in vars.iter().zip(defs).zip(monos.iter().zip(schemes)) {
+            let (scheme, bound) = match scheme {
+                Some(scheme)
+                    if self.methods.contains_key(&name(var)) && !locate(var).is_known() =>
+                {
+                    (scheme, Bound::Method)
+                }
+                Some(scheme) => (scheme, Bound::Plain),
+                None => {
+                    self.params.insert(Rc::as_ptr(var), names.clone());
+                    let scheme = Scheme {
+                        vars: generalized.clone(),
+                        preds: preds.clone(),
+                        typ: self.apply(mono),
+                    };
+                    (scheme, Bound::Plain)
+                }
+            };
+            self.bind(var, def, &scheme);
+            self.env.push((name(var), scheme, bound));
+        }
+    }
+
+    // the placeholder definition of a method, made in classes::add_class_definitions
+    fn placeholder(&self, var: &Rc<Expr>, def: &Rc<Expr>) -> Option<Scheme> {
+        match &**def {
+            Expr::Bottom if !locate(var).is_known() => self.methods.get(&name(var)).cloned(),
+            _ => None,
+        }
+    }
+
+    // check a definition against its signature, the signature is what gets used from then on
+    // the constraints in the signature are what the definition can use, they become its dictionary parameters
+    // outer is how much of the environment is from outside of the group
+    fn signature(
+        &mut self,
+        var: &Rc<Expr>,
+        def: &Rc<Expr>,
+        info: &TypeInfo,
+        outer: usize,
+    ) -> Scheme {
+        let from = self.wanted.len();
+        let givens = self.givens.len();
+        let mut vars = HashMap::new();
+        let rigid = self.rigid(info, &mut vars);
+        let mut names = Vec::new();
+        if let TypeInfo::TConstrained(context, _) = info {
+            for (class, v) in context {
+                let name = if self.has_dictionary(class) {
+                    self.names += 1;
+                    format!("_{}{}", class, self.names)
+                } else {
+                    String::new()
+                };
+                if !name.is_empty() {
+                    names.push(name.to_string());
+                }
+                if let Some(Type::Rigid(_, r)) = vars.get(v) {
+                    self.givens.push((class.to_string(), *r, name));
+                }
+            }
+        }
+        self.params.insert(Rc::as_ptr(var), names);
+        let t = self.definition(def);
+        let env = self.env_vars();
+        let outer = self.vars_before(outer);
+        let checked = self
+            .unify(&t, &rigid)
+            .and_then(|()| self.escaped(&outer, &vars));
+        if let Err(e) = checked {
+            self.forget(&vars);
+            self.errors.push(Diagnostic::error(
+                locate(var),
+                format!(
+                    "the definition of `{}` doesn't match its signature `{}`: {}",
+                    name(var),
+                    info,
+                    e
+                ),
+            ));
+        }
+        self.solve(from, &[], &env);
+        self.givens.truncate(givens);
+        self.declared(info)
+    }
+
+    // the declared type with its variables made rigid, new ones every time
+    fn rigid(&mut self, info: &TypeInfo, vars: &mut HashMap<String, Type>) -> Type {
+        match info {
+            TypeInfo::TConstructor(s) => Type::Con(s.to_string()),
+            TypeInfo::TVar(s) => {
+                if let Some(t) = vars.get(s) {
+                    t.clone()
+                } else {
+                    self.rigids += 1;
+                    let t = Type::Rigid(s.to_string(), self.rigids);
+                    vars.insert(s.to_string(), t.clone());
+                    t
+                }
+            }
+            TypeInfo::TApp(left, right) => Type::App(
+                Box::new(self.rigid(left, vars)),
+                Box::new(self.rigid(right, vars)),
+            ),
+            TypeInfo::TFun(arg, res) => Type::Fun(
+                Box::new(self.rigid(arg, vars)),
+                Box::new(self.rigid(res, vars)),
+            ),
+            TypeInfo::TConstrained(_, typ) => self.rigid(typ, vars),
+        }
+    }
+
+    // a rigid variable that became the type of one of the outer variables would be more general than it really is
+    fn escaped(&self, outer: &[usize], vars: &HashMap<String, Type>) -> Result<(), String> {
+        for v in outer {
+            let escaped = rigids(&self.apply(&Type::Var(*v)));
+            for (name, t) in vars {
+                if let Type::Rigid(_, r) = t {
+                    if escaped.contains(r) {
+                        return Err(format!(
+                            "`{}` would have to be the type of something from outside, it can't be any type",
+                            name
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // after a signature didn't match, its rigid variables that got into the substitution become unknowns again
+    // otherwise everything that uses them gets an error too
+    fn forget(&mut self, vars: &HashMap<String, Type>) {
+        let mut mapping = HashMap::new();
+        for t in vars.values() {
+            if let Type::Rigid(_, r) = t {
+                let fresh = self.fresh();
+                mapping.insert(*r, fresh);
+            }
+        }
+        for i in 0..self.subst.len() {
+            if let Some(t) = &self.subst[i] {
+                self.subst[i] = Some(unrigid(t, &mapping));
+            }
+        }
+    }
+
+    fn bind(&mut self, var: &Rc<Expr>, def: &Rc<Expr>, scheme: &Scheme) {
+        if let Expr::Data(..) = &**def {
+            return;
+        }
+        self.bindings.push(Binding {
+            name: name(var),
+            span: locate(var),
+            scheme: scheme.clone(),
+            toplevel: self.level == 0,
+        });
+    }
+
+    // put the variables of a pattern in scope, giving back how many there were
+    fn pattern(
+        &mut self,
+        pat: &Pattern,
+        scrutinee: &Type,
+        span: Span,
+    ) -> Result<usize, Diagnostic> {
+        let literal = |name: &str| Type::Con(name.to_string());
+        let at = |e: String| Diagnostic::error(span, e);
+        match pat {
+            Pattern::Wildcard => Ok(0),
+            Pattern::Irrefutable(x) => {
+                self.env
+                    .push((x.to_string(), mono(scrutinee.clone()), Bound::Plain));
+                Ok(1)
+            }
+            Pattern::Int(_) => self
+                .unify(scrutinee, &literal("Int"))
+                .map(|_| 0)
+                .map_err(at),
+            Pattern::Float(_) => self
+                .unify(scrutinee, &literal("Float"))
+                .map(|_| 0)
+                .map_err(at),
+            Pattern::Str(_) => self
+                .unify(scrutinee, &literal("Str"))
+                .map(|_| 0)
+                .map_err(at),
+            Pattern::Record(..) => panic!("Records should be desugared while parsing."),
+            Pattern::Construct(cons, vars) => {
+                let scheme = match self.constructors.get(cons).cloned() {
+                    Some(scheme) => scheme,
+                    None => return Err(at(format!("unknown constructor `{}`", cons))),
+                };
+                let mut t = self.instantiate(&scheme, span).0;
+                let mut args = Vec::new();
+                while let Type::Fun(arg, res) = t {
+                    args.push(*arg);
+                    t = *res;
+                }
+                if args.len() != vars.len() {
+                    return Err(at(format!(
+                        "constructor `{}` has {} fields but the pattern has {}",
+                        cons,
+                        args.len(),
+                        vars.len()
+                    )));
+                }
+                self.unify(scrutinee, &t).map_err(at)?;
+                for (var, arg) in vars.iter().zip(args) {
+                    self.env.push((var.to_string(), mono(arg), Bound::Plain));
+                }
+                Ok(vars.len())
+            }
+        }
+    }
+}
+
+fn mono(typ: Type) -> Scheme {
+    Scheme {
+        vars: Vec::new(),
+        preds: Vec::new(),
+        typ,
+    }
+}
+
+fn param(name: &str) -> Dict {
+    if name.is_empty() {
+        Dict::None
+    } else {
+        Dict::Param(name.to_string())
+    }
+}
+
+fn substitute(t: &Type, mapping: &HashMap<usize, Type>) -> Type {
+    match t {
+        Type::Var(i) => mapping.get(i).cloned().unwrap_or_else(|| t.clone()),
+        Type::App(left, right) => Type::App(
+            Box::new(substitute(left, mapping)),
+            Box::new(substitute(right, mapping)),
+        ),
+        Type::Fun(arg, res) => Type::Fun(
+            Box::new(substitute(arg, mapping)),
+            Box::new(substitute(res, mapping)),
+        ),
+        _ => t.clone(),
+    }
+}
+
+fn free_vars(t: &Type) -> Vec<usize> {
+    match t {
+        Type::Var(i) => vec![*i],
+        Type::App(left, right) | Type::Fun(left, right) => {
+            let mut vars = free_vars(left);
+            vars.extend(free_vars(right));
+            vars
+        }
+        _ => Vec::new(),
+    }
+}
+
+fn unrigid(t: &Type, mapping: &HashMap<usize, Type>) -> Type {
+    match t {
+        Type::Rigid(_, r) => mapping.get(r).cloned().unwrap_or_else(|| t.clone()),
+        Type::App(left, right) => Type::App(
+            Box::new(unrigid(left, mapping)),
+            Box::new(unrigid(right, mapping)),
+        ),
+        Type::Fun(arg, res) => Type::Fun(
+            Box::new(unrigid(arg, mapping)),
+            Box::new(unrigid(res, mapping)),
+        ),
+        _ => t.clone(),
+    }
+}
+
+fn rigids(t: &Type) -> Vec<usize> {
+    match t {
+        Type::Rigid(_, r) => vec![*r],
+        Type::App(left, right) | Type::Fun(left, right) => {
+            let mut found = rigids(left);
+            found.extend(rigids(right));
+            found
+        }
+        _ => Vec::new(),
+    }
+}
+
+fn var_id(t: &Type) -> Option<usize> {
+    match t {
+        Type::Var(i) => Some(*i),
+        _ => None,
+    }
+}
+
+fn mismatch(expr: &Rc<Expr>, error: String) -> Diagnostic {
+    Diagnostic::error(locate(expr), format!("{} in `{}`", error, expr))
+}
+
+// somewhere to point at for an expression, the first variable in it
+fn locate(expr: &Rc<Expr>) -> Span {
+    match &**expr {
+        Expr::Var(_, _, span)
+        | Expr::Builtin(_, _, _, _, span)
+        | Expr::Int(_, span)
+        | Expr::Float(_, span)
+        | Expr::Str(_, span) => *span,
+        Expr::Lam(head, body) => {
+            let span = locate(head);
+            if span.is_known() {
+                span
+            } else {
+                locate(body)
+            }
+        }
+        Expr::App(left, right) => {
+            let span = locate(left);
+            if span.is_known() {
+                span
+            } else {
+                locate(right)
+            }
+        }
+        Expr::If(cond, _, _) | Expr::Case(cond, _, _, _) | Expr::Annot(cond, _) => locate(cond),
+        Expr::Let(_, _, body) | Expr::LetRec(_, _, body) => locate(body),
+        _ => Span::default(),
+    }
+}
+
+fn name(var: &Rc<Expr>) -> String {
+    match &**var {
+        Expr::Var(s, _, _) => s.to_string(),
+        _ => panic!("Can only define variables."),
+    }
+}
+
+// show types together, naming the unknowns a, b, c, ... in the order they show up
+fn show(types: &[&Type]) -> Vec<String> {
+    let mut names = Vec::new();
+    types.iter().map(|t| pretty(t, &mut names, false)).collect()
+}
+
+fn pretty(t: &Type, names: &mut Vec<usize>, nested: bool) -> String {
+    match t {
+        Type::Var(i) => {
+            let n = match names.iter().position(|v| v == i) {
+                Some(n) => n,
+                None => {
+                    names.push(*i);
+                    names.len() - 1
+                }
+            };
+            var_name(n)
+        }
+        Type::Rigid(s, _) | Type::Con(s) => s.to_string(),
+        Type::App(left, right) => {
+            let shown = format!(
+                "{} {}",
+                pretty(left, names, false),
+                pretty(right, names, true)
+            );
+            if nested {
+                format!("({})", shown)
+            } else {
+                shown
+            }
+        }
+        Type::Fun(arg, res) => {
+            let arg = match **arg {
+                Type::Fun(..) => format!("({})", pretty(arg, names, false)),
+                _ => pretty(arg, names, false),
+            };
+            let shown = format!("{} -> {}", arg, pretty(res, names, false));
+            if nested {
+                format!("({})", shown)
+            } else {
+                shown
+            }
+        }
+    }
+}
+
+fn var_name(n: usize) -> String {
+    let letter = (b'a' + (n % 26) as u8) as char;
+    if n < 26 {
+        letter.to_string()
+    } else {
+        format!("{}{}", letter, n / 26)
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", show(&[self])[0])
+    }
+}
+
+impl Display for Scheme {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut names = Vec::new();
+        let typ = pretty(&self.typ, &mut names, false);
+        let preds: Vec<String> = self
+            .preds
+            .iter()
+            .map(|(class, v)| format!("{} {}", class, pretty(&Type::Var(*v), &mut names, true)))
+            .collect();
+        match preds.len() {
+            0 => write!(f, "{}", typ),
+            1 => write!(f, "{} => {}", preds[0], typ),
+            _ => write!(f, "({}) => {}", preds.join(", "), typ),
+        }
+    }
+}
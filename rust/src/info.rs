@@ -30,33 +30,48 @@
 // type definition
 use crate::ast::Definition;
 use crate::ast::Expr;
+use crate::ast::Pattern;
+use crate::ast::Span;
+use crate::records::positions;
 
 use crate::info::TypeInfo::*;
 
 use std::cell::RefCell;
+use std::fmt::Display;
 use std::rc::Rc;
 
+#[derive(Debug, Clone, PartialEq)]
 pub enum TypeInfo {
-    TConstructor(String),               // a type constructor
-    TApp(Box<TypeInfo>, Box<TypeInfo>), // type application
-    TVar(String),                       // or a type variable
+    TConstructor(String),                               // a type constructor
+    TApp(Box<TypeInfo>, Box<TypeInfo>),                 // type application
+    TVar(String),                                       // or a type variable
+    TFun(Box<TypeInfo>, Box<TypeInfo>),                 // function from the first to the second
+    TConstrained(Vec<(String, String)>, Box<TypeInfo>), // class constraints on type variables, only at the top of a signature
 }
 
 // products are the constructor name and the type arguments
+// records also name the arguments, otherwise fields is empty
+#[derive(Debug, Clone)]
 pub struct ProdInfo {
     pub name: String,
     pub args: Vec<TypeInfo>,
+    pub fields: Vec<String>,
+    pub span: Span,
 }
 
 // sum is a collection of products
+#[derive(Debug, Clone)]
 pub struct SumInfo {
     pub alts: Vec<ProdInfo>,
 }
 
 // definition of a data type
+#[derive(Debug, Clone)]
 pub struct DataInfo {
     pub type_info: TypeInfo,
     pub data_info: SumInfo,
+    pub span: Span,                    // where the left hand side is
+    pub deriving: Vec<(String, Span)>, // classes to generate instances for
 }
 
 impl TypeInfo {
@@ -82,7 +97,61 @@ impl TypeInfo {
         }
     }
 
-    fn get_name(&self) -> String {
+    // the arguments of a type constructor, List a b gives [a, b]
+    pub fn args(&self) -> Vec<&TypeInfo> {
+        match self {
+            TApp(left, right) => {
+                let mut args = left.args();
+                args.push(right);
+                args
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    // the type variables in the order they show up
+    pub fn vars(&self) -> Vec<String> {
+        let mut vars = Vec::new();
+        self.collect_vars(&mut vars);
+        vars
+    }
+
+    fn collect_vars(&self, vars: &mut Vec<String>) {
+        match self {
+            TConstructor(_) => (),
+            TVar(v) => {
+                if !vars.contains(v) {
+                    vars.push(v.to_string());
+                }
+            }
+            TApp(left, right) | TFun(left, right) => {
+                left.collect_vars(vars);
+                right.collect_vars(vars);
+            }
+            TConstrained(_, typ) => typ.collect_vars(vars),
+        }
+    }
+
+    // replace a type variable with a type
+    pub fn substitute(&self, var: &str, typ: &TypeInfo) -> TypeInfo {
+        match self {
+            TVar(v) if v == var => typ.clone(),
+            TApp(left, right) => TApp(
+                Box::new(left.substitute(var, typ)),
+                Box::new(right.substitute(var, typ)),
+            ),
+            TFun(arg, res) => TFun(
+                Box::new(arg.substitute(var, typ)),
+                Box::new(res.substitute(var, typ)),
+            ),
+            TConstrained(context, inner) => {
+                TConstrained(context.clone(), Box::new(inner.substitute(var, typ)))
+            }
+            _ => self.clone(),
+        }
+    }
+
+    pub fn get_name(&self) -> String {
         match self {
             TConstructor(s) => s.to_string(),
             TApp(left, _) => left.get_name(),
@@ -93,9 +162,53 @@ impl TypeInfo {
     }
 }
 
+impl Display for TypeInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TConstructor(s) => write!(f, "{}", s),
+            TVar(s) => write!(f, "{}", s),
+            TApp(left, right) => match **right {
+                TConstructor(_) | TVar(_) => write!(f, "{} {}", left, right),
+                _ => write!(f, "{} ({})", left, right),
+            },
+            TFun(arg, res) => match **arg {
+                TFun(_, _) => write!(f, "({}) -> {}", arg, res),
+                _ => write!(f, "{} -> {}", arg, res),
+            },
+            TConstrained(context, typ) => {
+                let constraints: Vec<String> = context
+                    .iter()
+                    .map(|(c, v)| format!("{} {}", c, v))
+                    .collect();
+                if constraints.len() == 1 {
+                    write!(f, "{} => {}", constraints[0], typ)
+                } else {
+                    write!(f, "({}) => {}", constraints.join(", "), typ)
+                }
+            }
+        }
+    }
+}
+
 impl ProdInfo {
-    fn new(name: String, args: Vec<TypeInfo>) -> ProdInfo {
-        ProdInfo { name, args }
+    pub fn new(name: String, args: Vec<TypeInfo>, span: Span) -> ProdInfo {
+        ProdInfo {
+            name,
+            args,
+            fields: Vec::new(),
+            span,
+        }
+    }
+
+    // a constructor with named fields
+    pub fn record(name: String, fields: Vec<(String, TypeInfo)>, span: Span) -> ProdInfo {
+        let (fields, args) = fields.into_iter().unzip();
+        ProdInfo {
+            name,
+            args,
+            fields,
+            span,
+        }
     }
 }
 
@@ -106,10 +219,12 @@ impl SumInfo {
 }
 
 impl DataInfo {
-    fn new(type_info: TypeInfo, d_info: Vec<ProdInfo>) -> DataInfo {
+    fn new(type_info: TypeInfo, d_info: Vec<ProdInfo>, span: Span) -> DataInfo {
         DataInfo {
             type_info,
             data_info: SumInfo::new(d_info),
+            span,
+            deriving: Vec::new(),
         }
     }
 
@@ -133,7 +248,11 @@ impl DataInfo {
         let type_name = self.type_info.get_name();
         for item in &self.data_info.alts {
             let def = Definition::new(
-                Rc::new(Expr::Var(item.name.to_string(), RefCell::new(0))),
+                Rc::new(Expr::Var(
+                    item.name.to_string(),
+                    RefCell::new((0, 0)),
+                    Span::default(),
+                )),
                 Rc::new(Expr::Data(
                     item.args.len(),
                     type_name.to_string(),
@@ -146,24 +265,120 @@ impl DataInfo {
         }
         defs
     }
+
+    // a function for each record field, constructors without the field are an error
+    pub fn to_accessors(&self) -> Vec<Definition> {
+        let mut names: Vec<&String> = Vec::new();
+        for item in &self.data_info.alts {
+            for field in &item.fields {
+                if !names.contains(&field) {
+                    names.push(field);
+                }
+            }
+        }
+        let var = |name: &str| {
+            Rc::new(Expr::Var(
+                name.to_string(),
+                RefCell::new((0, 0)),
+                Span::default(),
+            ))
+        };
+        let mut defs = Vec::new();
+        for name in names {
+            let mut pats = Vec::new();
+            let mut branches = Vec::new();
+            for item in &self.data_info.alts {
+                if let Some(i) = item.fields.iter().position(|f| f == name) {
+                    let vars = positions(item.args.len());
+                    branches.push(var(&vars[i]));
+                    pats.push(Pattern::Construct(item.name.to_string(), vars));
+                }
+            }
+            if pats.len() < self.data_info.alts.len() {
+                pats.push(Pattern::Wildcard);
+                branches.push(Rc::new(Expr::Error(format!("no field {}", name))));
+            }
+            let spans = vec![Span::default(); pats.len()];
+            let body = Rc::new(Expr::Case(var("_r"), pats, branches, spans));
+            defs.push(Definition::new(
+                var(name),
+                Rc::new(Expr::Lam(var("_r"), body)),
+            ));
+        }
+        defs
+    }
 }
 
 // now do not have to construct in the grammar, just collect the strings
-pub fn create_data_info(lhs: Vec<String>, rhs: Vec<Vec<Vec<String>>>) -> DataInfo {
+// the constructor arguments can have function types, so those are parsed as types already
+pub fn create_data_info(lhs: Vec<String>, rhs: Vec<ProdInfo>, span: Span) -> DataInfo {
     // lhs is the type definition
     // rhs are the constructor definitions
-    // don't really like the nested vectors for rhs, but it should work
 
     let type_info = TypeInfo::new(lhs);
-    let mut data_info = Vec::new();
-    for item in rhs {
-        // first should be constructor name
-        // rest should be type info
-        let mut type_data = Vec::new();
-        for x in &item[1..] {
-            type_data.push(TypeInfo::new(x.to_vec()));
+    DataInfo::new(type_info, rhs, span)
+}
+
+// a constructor as the code generators see it, tag is its place among the alternatives of its type
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConTag {
+    pub name: String,
+    pub typ: String,
+    pub arity: usize,
+    pub type_index: usize,
+    pub tag: usize,
+}
+
+// every constructor gets a number, the alternatives of each data declaration in order
+// constructors that aren't declared, like the dictionaries of classes, get numbers after them as a type of their own
+pub struct Constructors {
+    pub tags: Vec<ConTag>,
+    types: usize,
+}
+
+impl Constructors {
+    pub fn new(types: &[DataInfo]) -> Constructors {
+        let mut tags = Vec::new();
+        for (type_index, info) in types.iter().enumerate() {
+            let typ = info.type_info.get_name();
+            for (tag, prod) in info.data_info.alts.iter().enumerate() {
+                tags.push(ConTag {
+                    name: prod.name.to_string(),
+                    typ: typ.to_string(),
+                    arity: prod.args.len(),
+                    type_index,
+                    tag,
+                });
+            }
         }
-        data_info.push(ProdInfo::new(item[0][0].to_string(), type_data));
+        Constructors {
+            tags,
+            types: types.len(),
+        }
+    }
+
+    // the number of a constructor, adding it if it isn't there yet
+    pub fn id(&mut self, arity: usize, typ: &str, name: &str) -> usize {
+        if let Some(i) = self
+            .tags
+            .iter()
+            .position(|c| c.name == name && c.typ == typ)
+        {
+            return i;
+        }
+        self.tags.push(ConTag {
+            name: name.to_string(),
+            typ: typ.to_string(),
+            arity,
+            type_index: self.types,
+            tag: 0,
+        });
+        self.types += 1;
+        self.tags.len() - 1
+    }
+
+    // patterns only have the name of the constructor
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.tags.iter().position(|c| c.name == name)
     }
-    DataInfo::new(type_info, data_info)
 }
@@ -0,0 +1,544 @@
+/*
+
+compile a program to JavaScript, an ES module with the runtime (runtime.js) at the top
+    import { sum, Cons, Nil } from "./program.mjs"
+    sum(Cons(1n)(Cons(2n)(Nil())))
+
+this takes the program after elaborate, before the lets are rearranged, so the top level is still one letrec of everything
+every top level definition is exported under its own name
+    functions, constructors, and builtins are curried JavaScript functions, f(x)(y)
+    anything else is exported as a function of nothing that evaluates it the first time and gives back its value
+    the dictionaries of instances are definitions too, Show.Int is exported as Show$Int
+    functions with a class constraint take their dictionaries first
+the body of the letrec is what bagl prints when it runs the program, that is $run, when there is an entry
+
+variables are JavaScript variables, so the scopes and closures are JavaScript's
+    every variable gets a name of its own, a second x is x$1, so the names never shadow each other
+a let's definitions are thunks unless they're already values, evaluating an expression forces what it uses
+    lambdas force their argument unless it's _, the same as eval
+    an argument that isn't simple enough to pass as it is gets a thunk
+the letrecs are declared in order and can refer to their neighbours lazily, so a definition that is just a variable gets a thunk too
+
+a case is a switch on the tag of the constructor, literal patterns are compared one at a time
+constructor tags are from info::Constructors, the dictionaries of classes all have tag 0
+
+*/
+
+use crate::ast::Expr;
+use crate::ast::Pattern;
+use crate::ast::Toplevel;
+use crate::classes::dict_constructor;
+use crate::info::Constructors;
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::rc::Rc;
+
+const RUNTIME: &str = include_str!("runtime.js");
+
+// the builtins, their arities, and the functions in the runtime that do them
+const BUILTINS: [(&str, usize, &str); 11] = [
+    ("+", 2, "$add"),
+    ("-", 2, "$sub"),
+    ("*", 2, "$mult"),
+    ("/", 2, "$div"),
+    ("++", 2, "$concat"),
+    ("==", 2, "$eq"),
+    ("compare", 2, "$compare"),
+    ("show", 1, "$show"),
+    ("parens", 1, "$parens"),
+    ("assert", 1, "$assert"),
+    ("assertEq", 2, "$assertEq"),
+];
+
+const RESERVED: [&str; 47] = [
+    "arguments",
+    "await",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "debugger",
+    "default",
+    "delete",
+    "do",
+    "else",
+    "enum",
+    "eval",
+    "export",
+    "extends",
+    "false",
+    "finally",
+    "for",
+    "function",
+    "if",
+    "implements",
+    "import",
+    "in",
+    "instanceof",
+    "interface",
+    "let",
+    "new",
+    "null",
+    "package",
+    "private",
+    "protected",
+    "public",
+    "return",
+    "static",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typeof",
+    "var",
+    "void",
+    "while",
+    "with",
+];
+
+struct Compiler {
+    cons: Constructors,
+    // every name that has been used in the module
+    used: HashSet<String>,
+    // what the variables in scope are called in JavaScript, the innermost last
+    scope: Vec<(String, String)>,
+}
+
+// the whole module for the elaborated program
+pub fn to_js(expr: &Rc<Expr>, top: &Toplevel) -> String {
+    let (vars, defs, body) = match &**expr {
+        Expr::LetRec(vars, defs, body) => (vars, defs, body),
+        _ => panic!("A program is a letrec."),
+    };
+    let mut compiler = Compiler::new(top);
+    let names: Vec<String> = vars.iter().map(|v| compiler.bind(var_name(v))).collect();
+
+    let mut out = String::from(RUNTIME);
+    out.push_str("\n// the program\n\n");
+    for (var, name) in &[
+        ("$True", "True"),
+        ("$False", "False"),
+        ("$Less", "Less"),
+        ("$Equal", "Equal"),
+        ("$Greater", "Greater"),
+    ] {
+        let tag = compiler
+            .cons
+            .find(name)
+            .map(|i| compiler.cons.tags[i].tag)
+            .unwrap_or(0);
+        writeln!(
+            out,
+            "const {} = new $Data({}, {}, []);",
+            var,
+            tag,
+            js_string(name)
+        )
+        .unwrap();
+    }
+    out.push('\n');
+
+    let mut exports = Vec::new();
+    for (def, name) in defs.iter().zip(&names) {
+        let value = compiler.delay(def, 0, true);
+        writeln!(out, "const {} = {};", name, value).unwrap();
+        if is_function(def) {
+            exports.push(name.to_string());
+        } else {
+            writeln!(
+                out,
+                "function get${}() {{\n  return $force({});\n}}",
+                name, name
+            )
+            .unwrap();
+            exports.push(format!("get${} as {}", name, name));
+        }
+    }
+    // a library has nothing to run
+    if !matches!(&**body, Expr::Bottom) {
+        let mut lines = Vec::new();
+        compiler.block(body, 1, &mut lines);
+        writeln!(out, "\nexport function $run() {{\n{}\n}}", lines.join("\n")).unwrap();
+    }
+    if !exports.is_empty() {
+        writeln!(out, "\nexport {{\n  {},\n}};", exports.join(",\n  ")).unwrap();
+    }
+    out
+}
+
+fn var_name(var: &Rc<Expr>) -> &str {
+    match &**var {
+        Expr::Var(name, _, _) => name,
+        _ => panic!("Only variables can be defined."),
+    }
+}
+
+// functions are exported as they are, everything else is evaluated when it is asked for
+fn is_function(def: &Rc<Expr>) -> bool {
+    match &**def {
+        Expr::Lam(_, _) => true,
+        Expr::Data(arity, _, _, fields) | Expr::Builtin(arity, _, _, fields, _) => {
+            *arity > fields.len()
+        }
+        Expr::Annot(inner, _) => is_function(inner),
+        _ => false,
+    }
+}
+
+impl Compiler {
+    fn new(top: &Toplevel) -> Compiler {
+        let mut cons = Constructors::new(&top.types);
+        for class in top.classes.iter().filter(|c| c.dictionary) {
+            cons.id(
+                class.methods.len(),
+                &class.name,
+                &dict_constructor(&class.name),
+            );
+        }
+        Compiler {
+            cons,
+            used: HashSet::new(),
+            scope: Vec::new(),
+        }
+    }
+
+    // a name that hasn't been used yet
+    fn fresh(&mut self, base: String) -> String {
+        let mut name = base.clone();
+        let mut n = 0;
+        while self.used.contains(&name) {
+            n += 1;
+            name = format!("{}${}", base, n);
+        }
+        self.used.insert(name.clone());
+        name
+    }
+
+    fn bind(&mut self, name: &str) -> String {
+        let js = self.fresh(js_name(name));
+        self.scope.push((name.to_string(), js.clone()));
+        js
+    }
+
+    fn lookup(&self, name: &str) -> String {
+        match self.scope.iter().rev().find(|(n, _)| n == name) {
+            Some((_, js)) => js.to_string(),
+            None => panic!("{} isn't defined.", name),
+        }
+    }
+
+    // the tag of a constructor in a pattern, None if there isn't one with that name
+    fn tag(&self, name: &str) -> Option<usize> {
+        self.cons.find(name).map(|i| self.cons.tags[i].tag)
+    }
+
+    // a JavaScript expression that is the value of expr
+    fn value(&mut self, expr: &Rc<Expr>, indent: usize) -> String {
+        match &**expr {
+            Expr::Var(name, _, _) => format!("$force({})", self.lookup(name)),
+            Expr::Int(n, _) => format!("{}n", n),
+            Expr::Float(n, _) => float(*n),
+            Expr::Str(s, _) => js_string(s),
+            Expr::Lam(head, body) => {
+                let name = var_name(head);
+                let lazy = name == "_";
+                let mark = self.scope.len();
+                let param = self.bind(name);
+                let mut lines = Vec::new();
+                if !lazy {
+                    lines.push(format!("{}{} = $force({});", pad(indent + 1), param, param));
+                }
+                self.block(body, indent + 1, &mut lines);
+                self.scope.truncate(mark);
+                format!(
+                    "$lam({}, ({}) => {{\n{}\n{}}})",
+                    js_string(&expr.to_string()),
+                    param,
+                    lines.join("\n"),
+                    pad(indent)
+                )
+            }
+            Expr::App(_, _) => self.app(expr, indent),
+            Expr::If(cond, b1, b2) => format!(
+                "($isTrue({}) ? {} : {})",
+                self.value(cond, indent),
+                self.value(b1, indent),
+                self.value(b2, indent)
+            ),
+            Expr::Let(_, _, _) | Expr::LetRec(_, _, _) | Expr::Case(_, _, _, _) => {
+                let mut lines = Vec::new();
+                self.block(expr, indent + 1, &mut lines);
+                format!("(() => {{\n{}\n{}}})()", lines.join("\n"), pad(indent))
+            }
+            Expr::Data(arity, typ, name, _) => {
+                let con = self.cons.id(*arity, typ, name);
+                format!(
+                    "$con({}, {}, {})",
+                    self.cons.tags[con].tag,
+                    js_string(name),
+                    arity
+                )
+            }
+            Expr::Builtin(_, name, _, _, _) => {
+                builtin(name);
+                format!("$builtins[{}]", js_string(name))
+            }
+            Expr::Error(s) => format!("$fail({})", js_string(&format!("Error: {}", s))),
+            Expr::Bottom => "$fail(\"Ran into undefined.\")".to_string(),
+            Expr::Annot(inner, _) => self.value(inner, indent),
+            _ => panic!("Can't compile {} to JavaScript.", expr),
+        }
+    }
+
+    // statements that return the value of expr
+    fn block(&mut self, expr: &Rc<Expr>, indent: usize, lines: &mut Vec<String>) {
+        let mark = self.scope.len();
+        match &**expr {
+            Expr::Let(vars, defs, body) => {
+                // the definitions only see what is outside of the let
+                let values: Vec<String> =
+                    defs.iter().map(|d| self.delay(d, indent, false)).collect();
+                for (var, value) in vars.iter().zip(values) {
+                    let name = self.bind(var_name(var));
+                    lines.push(format!("{}const {} = {};", pad(indent), name, value));
+                }
+                self.block(body, indent, lines);
+            }
+            Expr::LetRec(vars, defs, body) => {
+                let names: Vec<String> = vars.iter().map(|v| self.bind(var_name(v))).collect();
+                for (name, def) in names.iter().zip(defs) {
+                    let value = self.delay(def, indent, true);
+                    lines.push(format!("{}const {} = {};", pad(indent), name, value));
+                }
+                self.block(body, indent, lines);
+            }
+            Expr::If(cond, b1, b2) => {
+                let cond = self.value(cond, indent);
+                lines.push(format!("{}if ($isTrue({})) {{", pad(indent), cond));
+                self.block(b1, indent + 1, lines);
+                lines.push(format!("{}}} else {{", pad(indent)));
+                self.block(b2, indent + 1, lines);
+                lines.push(format!("{}}}", pad(indent)));
+            }
+            Expr::Case(cond, pats, branches, _) => self.case(cond, pats, branches, indent, lines),
+            Expr::Annot(inner, _) => self.block(inner, indent, lines),
+            _ => {
+                let value = self.value(expr, indent);
+                lines.push(format!("{}return {};", pad(indent), value));
+            }
+        }
+        self.scope.truncate(mark);
+    }
+
+    fn case(
+        &mut self,
+        cond: &Rc<Expr>,
+        pats: &[Pattern],
+        branches: &[Rc<Expr>],
+        indent: usize,
+        lines: &mut Vec<String>,
+    ) {
+        let value = self.fresh("$v".to_string());
+        let cond = self.value(cond, indent);
+        lines.push(format!("{}const {} = {};", pad(indent), value, cond));
+        let constructors = matches!(pats.first(), Some(Pattern::Construct(_, _)));
+        if constructors {
+            lines.push(format!("{}switch ({}.tag) {{", pad(indent), value));
+        }
+        let inner = if constructors { indent + 1 } else { indent };
+        let mut seen = HashSet::new();
+        for (pat, branch) in pats.iter().zip(branches) {
+            let mark = self.scope.len();
+            // later patterns can't match anything an earlier one didn't
+            let (test, last) = match pat {
+                Pattern::Construct(name, _) => match self.tag(name) {
+                    Some(tag) if seen.insert(tag) => (format!("case {}:", tag), false),
+                    _ => continue,
+                },
+                Pattern::Wildcard | Pattern::Irrefutable(_) if constructors => {
+                    ("default:".to_string(), true)
+                }
+                Pattern::Wildcard | Pattern::Irrefutable(_) => (String::new(), true),
+                Pattern::Int(n) => (format!("if ({} === {}n)", value, n), false),
+                Pattern::Float(n) => (format!("if ({} === {})", value, float(*n)), false),
+                Pattern::Str(s) => (format!("if ({} === {})", value, js_string(s)), false),
+                Pattern::Record(_, _) => panic!("Records should be desugared before compiling."),
+            };
+            let body = if test.is_empty() {
+                inner
+            } else {
+                lines.push(format!("{}{} {{", pad(inner), test));
+                inner + 1
+            };
+            match pat {
+                Pattern::Construct(_, vars) => {
+                    for (i, var) in vars.iter().enumerate() {
+                        let name = self.bind(var);
+                        lines.push(format!(
+                            "{}const {} = {}.fields[{}];",
+                            pad(body),
+                            name,
+                            value,
+                            i
+                        ));
+                    }
+                }
+                Pattern::Irrefutable(var) => {
+                    let name = self.bind(var);
+                    lines.push(format!("{}const {} = {};", pad(body), name, value));
+                }
+                _ => (),
+            }
+            self.block(branch, body, lines);
+            self.scope.truncate(mark);
+            if !test.is_empty() {
+                lines.push(format!("{}}}", pad(inner)));
+            }
+            if last {
+                if constructors {
+                    lines.push(format!("{}}}", pad(indent)));
+                }
+                return;
+            }
+        }
+        if constructors {
+            lines.push(format!("{}}}", pad(indent)));
+        }
+        lines.push(format!(
+            "{}return $fail(\"No pattern matched.\");",
+            pad(indent)
+        ));
+    }
+
+    // a builtin or constructor with all of its arguments is called directly, anything else goes through $apply
+    fn app(&mut self, expr: &Rc<Expr>, indent: usize) -> String {
+        let mut args = Vec::new();
+        let mut head = expr;
+        loop {
+            match &**head {
+                Expr::App(left, right) => {
+                    args.push(right);
+                    head = left;
+                }
+                Expr::Annot(inner, _) => head = inner,
+                _ => break,
+            }
+        }
+        args.reverse();
+        let (call, applied) = match &**head {
+            Expr::Builtin(arity, name, _, fields, _)
+                if fields.is_empty() && args.len() >= *arity =>
+            {
+                let values: Vec<String> = args[..*arity]
+                    .iter()
+                    .map(|a| self.value(a, indent))
+                    .collect();
+                let prim = BUILTINS[builtin(name)].2;
+                (format!("{}({})", prim, values.join(", ")), *arity)
+            }
+            Expr::Data(arity, typ, name, fields)
+                if fields.is_empty() && *arity > 0 && args.len() >= *arity =>
+            {
+                let con = self.cons.id(*arity, typ, name);
+                let values: Vec<String> = args[..*arity]
+                    .iter()
+                    .map(|a| self.value(a, indent))
+                    .collect();
+                (
+                    format!(
+                        "new $Data({}, {}, [{}])",
+                        self.cons.tags[con].tag,
+                        js_string(name),
+                        values.join(", ")
+                    ),
+                    *arity,
+                )
+            }
+            _ => (self.value(head, indent), 0),
+        };
+        if applied == args.len() {
+            return call;
+        }
+        let rest: Vec<String> = args[applied..]
+            .iter()
+            .map(|a| self.delay(a, indent, false))
+            .collect();
+        format!("$apply({}, {})", call, rest.join(", "))
+    }
+
+    // something that gives the value of expr when it is forced
+    // the definitions of a letrec can't use their neighbours yet, they might not be there
+    fn delay(&mut self, expr: &Rc<Expr>, indent: usize, rec: bool) -> String {
+        match &**expr {
+            Expr::Var(name, _, _) if !rec => self.lookup(name),
+            Expr::Int(_, _) | Expr::Float(_, _) | Expr::Str(_, _) | Expr::Lam(_, _) => {
+                self.value(expr, indent)
+            }
+            Expr::Data(_, _, _, fields) | Expr::Builtin(_, _, _, fields, _)
+                if fields.is_empty() =>
+            {
+                self.value(expr, indent)
+            }
+            Expr::Annot(inner, _) => self.delay(inner, indent, rec),
+            _ => format!("$delay(() => {})", self.value(expr, indent)),
+        }
+    }
+}
+
+fn pad(indent: usize) -> String {
+    "  ".repeat(indent)
+}
+
+fn builtin(name: &str) -> usize {
+    match BUILTINS.iter().position(|(b, _, _)| *b == name) {
+        Some(i) => i,
+        None => panic!("The JavaScript runtime doesn't have the builtin {}.", name),
+    }
+}
+
+// names from bagl that aren't JavaScript names, the . in the names of instances becomes $
+fn js_name(name: &str) -> String {
+    let mut out = String::new();
+    for c in name.chars() {
+        match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' => out.push(c),
+            '.' => out.push('$'),
+            _ => write!(out, "$x{:x}", c as u32).unwrap(),
+        }
+    }
+    if RESERVED.contains(&out.as_str()) {
+        out.push('$');
+    }
+    out
+}
+
+fn float(n: f64) -> String {
+    if n.is_nan() {
+        "NaN".to_string()
+    } else if n.is_infinite() {
+        if n > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+    } else {
+        format!("{:e}", n)
+    }
+}
+
+fn js_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 || c == '\u{7f}' || c == '\u{2028}' || c == '\u{2029}' => {
+                write!(out, "\\u{:04x}", c as u32).unwrap()
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -0,0 +1,517 @@
+/*
+
+validating data declarations and signatures before anything else looks at them
+
+every type constructor has a kind, the kind of a type of values is *
+    Int : *
+    List : * -> *
+    Pair : * -> * -> *
+
+the kind of a declared type comes from its parameters, each parameter starts out unknown and gets solved from how it is used in the constructors
+    Fix f = Fix (f (Fix f))
+    f is applied to something of kind *, so Fix : (* -> *) -> *
+anything that is still unknown at the end is just *
+
+every constructor argument has to have kind *, that catches using a type with the wrong number of arguments
+    List a = Cons a (List a a) | Nil
+    List a a applies List to too many arguments
+
+also checked
+    type variables in constructors have to be parameters of the type
+    type constructors have to exist, the builtin ones are Int, Float, Str and Bool (if Bool isn't declared)
+    type names and constructor names can only be declared once
+    record fields become functions, so a field name can only belong to one type
+    constructors of the same type can share a field as long as it has the same type in each
+    a parameter can only show up once on the left hand side
+
+signatures get their kinds checked the same way, except their type variables don't have to be declared anywhere
+
+classes and instances
+    the kind of a class is the kind of its variable, solved from the method types like a parameter
+        class Show a { show :: a -> Str }    a : *
+    an instance is for a declared type applied to distinct variables, with the same kind as the class
+        instance Show (List a)    List a : *
+    class names, method names, and instances can only be declared once
+    the methods of an instance have to be the methods of the class, each defined once
+    the classes in a context have to exist and be about variables of the type
+    Num only has the builtin instances
+
+*/
+
+use crate::ast::Span;
+use crate::ast::Toplevel;
+use crate::classes::ClassInfo;
+use crate::classes::InstanceInfo;
+use crate::diagnostic::Diagnostic;
+use crate::info::DataInfo;
+use crate::info::ProdInfo;
+use crate::info::TypeInfo;
+use crate::names::unbound;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fmt::Display;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Kind {
+    Star,
+    Arrow(Box<Kind>, Box<Kind>),
+    Var(usize), // not known yet
+}
+
+// check the data declarations, classes, instances, and signatures, giving back the kind of every type constructor
+pub fn check_declarations(top: &Toplevel) -> Result<HashMap<String, Kind>, Vec<Diagnostic>> {
+    let types = &top.types;
+    let mut checker = Checker::new();
+    let mut params = Vec::new();
+    for info in types {
+        params.push(checker.declare(info));
+    }
+    if !checker.kinds.contains_key("Bool") {
+        checker.kinds.insert("Bool".to_string(), Kind::Star);
+    }
+    let mut constructors: HashMap<String, String> = HashMap::new();
+    let mut fields: HashMap<String, (String, TypeInfo)> = HashMap::new();
+    for (info, mut params) in types.iter().zip(params) {
+        let name = info.type_info.get_name();
+        for alt in &info.data_info.alts {
+            if let Some(other) = constructors.get(&alt.name) {
+                checker.errors.push(Diagnostic::error(
+                    alt.span,
+                    format!(
+                        "duplicate constructor `{}`, it is already a constructor of `{}`",
+                        alt.name, other
+                    ),
+                ));
+            } else {
+                constructors.insert(alt.name.to_string(), name.to_string());
+            }
+            for arg in &alt.args {
+                checker.field(arg, &mut params, Some(&name), alt.span);
+            }
+            check_fields(alt, &name, &mut fields, &mut checker.errors);
+        }
+    }
+    let classes = checker.classes(&top.classes);
+    checker.instances(&top.instances, &classes);
+    for sig in &top.sigs {
+        let mut params = HashMap::new();
+        checker.field(&sig.typ, &mut params, None, sig.span);
+        if let TypeInfo::TConstrained(context, _) = &sig.typ {
+            checker.context(context, &params, &classes, sig.span);
+        }
+    }
+    if checker.errors.is_empty() {
+        let mut kinds = HashMap::new();
+        for (name, kind) in &checker.kinds {
+            kinds.insert(name.to_string(), checker.default(kind));
+        }
+        Ok(kinds)
+    } else {
+        Err(checker.errors)
+    }
+}
+
+struct Checker {
+    subst: Vec<Option<Kind>>,
+    kinds: HashMap<String, Kind>,
+    errors: Vec<Diagnostic>,
+}
+
+impl Checker {
+    fn new() -> Checker {
+        let mut kinds = HashMap::new();
+        for builtin in &["Int", "Float", "Str"] {
+            kinds.insert(builtin.to_string(), Kind::Star);
+        }
+        Checker {
+            subst: Vec::new(),
+            kinds,
+            errors: Vec::new(),
+        }
+    }
+
+    fn fresh(&mut self) -> Kind {
+        self.subst.push(None);
+        Kind::Var(self.subst.len() - 1)
+    }
+
+    // give the type a kind from its parameters, the parameters are given back for checking the constructors
+    fn declare(&mut self, info: &DataInfo) -> HashMap<String, Kind> {
+        let mut args = Vec::new();
+        let mut head = &info.type_info;
+        while let TypeInfo::TApp(left, right) = head {
+            args.push(&**right);
+            head = left;
+        }
+        args.reverse();
+        let name = head.get_name();
+        let mut params = HashMap::new();
+        let mut kind = Kind::Star;
+        let mut vars = Vec::new();
+        for arg in args {
+            if let TypeInfo::TVar(v) = arg {
+                if params.contains_key(v) {
+                    self.errors.push(Diagnostic::error(
+                        info.span,
+                        format!(
+                            "type parameter `{}` appears more than once in `{}`",
+                            v, info.type_info
+                        ),
+                    ));
+                }
+                let k = self.fresh();
+                params.insert(v.to_string(), k.clone());
+                vars.push(k);
+            }
+        }
+        for k in vars.into_iter().rev() {
+            kind = Kind::Arrow(Box::new(k), Box::new(kind));
+        }
+        if let Entry::Vacant(entry) = self.kinds.entry(name) {
+            entry.insert(kind);
+        } else {
+            self.errors.push(Diagnostic::error(
+                info.span,
+                format!("duplicate type `{}`", head.get_name()),
+            ));
+        }
+        params
+    }
+
+    // a constructor argument or a signature, it has to be a type of values
+    // without a declared type the type variables are added as they show up
+    fn field(
+        &mut self,
+        typ: &TypeInfo,
+        params: &mut HashMap<String, Kind>,
+        declared: Option<&str>,
+        span: Span,
+    ) {
+        let kind = match self.infer(typ, params, declared, span) {
+            Some(kind) => kind,
+            None => return,
+        };
+        if self.unify(&kind, &Kind::Star).is_err() {
+            let kind = self.apply(&kind);
+            self.errors.push(Diagnostic::error(
+                span,
+                format!(
+                    "`{}` is missing type arguments, it has kind `{}` but a type of values has kind `*`",
+                    typ, kind
+                ),
+            ));
+        }
+    }
+
+    fn infer(
+        &mut self,
+        typ: &TypeInfo,
+        params: &mut HashMap<String, Kind>,
+        declared: Option<&str>,
+        span: Span,
+    ) -> Option<Kind> {
+        match typ {
+            TypeInfo::TConstructor(name) => match self.kinds.get(name) {
+                Some(kind) => Some(kind.clone()),
+                None => {
+                    let known: Vec<String> = self.kinds.keys().cloned().collect();
+                    self.errors
+                        .push(unbound("unknown type", name, span, &known));
+                    None
+                }
+            },
+            TypeInfo::TVar(v) => match (params.get(v), declared) {
+                (Some(kind), _) => Some(kind.clone()),
+                (None, Some(name)) => {
+                    self.errors.push(Diagnostic::error(
+                        span,
+                        format!("type variable `{}` is not a parameter of `{}`", v, name),
+                    ));
+                    None
+                }
+                (None, None) => {
+                    let kind = self.fresh();
+                    params.insert(v.to_string(), kind.clone());
+                    Some(kind)
+                }
+            },
+            TypeInfo::TApp(left, right) => {
+                let kl = self.infer(left, params, declared, span)?;
+                let kr = self.infer(right, params, declared, span)?;
+                let res = self.fresh();
+                let expected = Kind::Arrow(Box::new(kr), Box::new(res.clone()));
+                if let Err(message) = self.unify(&kl, &expected) {
+                    let message = match self.apply(&kl) {
+                        Kind::Star => format!("`{}` is applied to too many type arguments", left),
+                        _ => format!("kind mismatch in `{}`, {}", typ, message),
+                    };
+                    self.errors.push(Diagnostic::error(span, message));
+                    return None;
+                }
+                Some(res)
+            }
+            TypeInfo::TFun(arg, res) => {
+                self.field(arg, params, declared, span);
+                self.field(res, params, declared, span);
+                Some(Kind::Star)
+            }
+            TypeInfo::TConstrained(_, typ) => self.infer(typ, params, declared, span),
+        }
+    }
+
+    // the kind of each class, from how the methods use the class variable
+    fn classes<'a>(&mut self, classes: &'a [ClassInfo]) -> HashMap<String, (&'a ClassInfo, Kind)> {
+        let mut checked: HashMap<String, (&ClassInfo, Kind)> = HashMap::new();
+        let mut methods: HashMap<&str, &str> = HashMap::new();
+        for class in classes {
+            if checked.contains_key(&class.name) {
+                self.errors.push(Diagnostic::error(
+                    class.span,
+                    format!("duplicate class `{}`", class.name),
+                ));
+                continue;
+            }
+            let kind = self.fresh();
+            for (method, typ) in &class.methods {
+                if let Some(other) = methods.insert(method, &class.name) {
+                    self.errors.push(Diagnostic::error(
+                        class.span,
+                        format!(
+                            "duplicate method `{}`, it is already a method of `{}`",
+                            method, other
+                        ),
+                    ));
+                }
+                if !typ.vars().contains(&class.var) {
+                    self.errors.push(Diagnostic::error(
+                        class.span,
+                        format!(
+                            "the type of `{}` has to mention `{}`, the variable of `{}`",
+                            method, class.var, class.name
+                        ),
+                    ));
+                }
+                let mut params = HashMap::new();
+                params.insert(class.var.to_string(), kind.clone());
+                self.field(typ, &mut params, None, class.span);
+            }
+            checked.insert(class.name.to_string(), (class, kind));
+        }
+        checked
+    }
+
+    fn instances(
+        &mut self,
+        instances: &[InstanceInfo],
+        classes: &HashMap<String, (&ClassInfo, Kind)>,
+    ) {
+        let mut seen = Vec::new();
+        for instance in instances {
+            let span = instance.span;
+            let (class, kind) = match classes.get(&instance.class) {
+                Some((class, kind)) => (*class, kind.clone()),
+                None => {
+                    let known: Vec<String> = classes.keys().cloned().collect();
+                    self.errors
+                        .push(unbound("unknown class", &instance.class, span, &known));
+                    continue;
+                }
+            };
+            let head = format!("{} {}", instance.class, parenthesized(&instance.typ));
+            if !class.dictionary && span.is_known() {
+                self.errors.push(Diagnostic::error(
+                    span,
+                    format!("`{}` only has the builtin instances", class.name),
+                ));
+                continue;
+            }
+            let key = (instance.class.to_string(), instance.type_name());
+            if seen.contains(&key) {
+                self.errors.push(Diagnostic::error(
+                    span,
+                    format!("duplicate instance `{}`", head),
+                ));
+            }
+            seen.push(key);
+            let mut params = HashMap::new();
+            if let Some(k) = self.infer(&instance.typ, &mut params, None, span) {
+                if let Err(message) = self.unify(&k, &kind) {
+                    self.errors.push(Diagnostic::error(
+                        span,
+                        format!("kind mismatch in the instance `{}`, {}", head, message),
+                    ));
+                }
+            }
+            self.context(&instance.context, &params, classes, span);
+            for (i, (method, span, _)) in instance.methods.iter().enumerate() {
+                if class.method(method).is_none() {
+                    let known: Vec<String> =
+                        class.methods.iter().map(|(m, _)| m.to_string()).collect();
+                    self.errors.push(unbound(
+                        &format!("`{}` has no method", class.name),
+                        method,
+                        *span,
+                        &known,
+                    ));
+                } else if instance.methods[..i].iter().any(|(m, _, _)| m == method) {
+                    self.errors.push(Diagnostic::error(
+                        *span,
+                        format!("method `{}` is defined more than once", method),
+                    ));
+                }
+            }
+            for (method, _) in &class.methods {
+                if !instance.methods.iter().any(|(m, _, _)| m == method) {
+                    self.errors.push(Diagnostic::error(
+                        span,
+                        format!("missing method `{}` in the instance `{}`", method, head),
+                    ));
+                }
+            }
+        }
+    }
+
+    // constraints have to be on the variables that were found, with the kind of the class
+    fn context(
+        &mut self,
+        context: &[(String, String)],
+        params: &HashMap<String, Kind>,
+        classes: &HashMap<String, (&ClassInfo, Kind)>,
+        span: Span,
+    ) {
+        for (class, var) in context {
+            let kind = match classes.get(class) {
+                Some((_, kind)) => kind.clone(),
+                None => {
+                    let known: Vec<String> = classes.keys().cloned().collect();
+                    self.errors
+                        .push(unbound("unknown class", class, span, &known));
+                    continue;
+                }
+            };
+            match params.get(var) {
+                Some(k) => {
+                    if let Err(message) = self.unify(k, &kind) {
+                        self.errors.push(Diagnostic::error(
+                            span,
+                            format!("kind mismatch in `{} {}`, {}", class, var, message),
+                        ));
+                    }
+                }
+                None => self.errors.push(Diagnostic::error(
+                    span,
+                    format!(
+                        "the constraint `{} {}` is on a type variable that doesn't appear in the type",
+                        class, var
+                    ),
+                )),
+            }
+        }
+    }
+
+    fn apply(&self, kind: &Kind) -> Kind {
+        match kind {
+            Kind::Var(i) => match &self.subst[*i] {
+                Some(solved) => self.apply(solved),
+                None => kind.clone(),
+            },
+            Kind::Arrow(arg, res) => {
+                Kind::Arrow(Box::new(self.apply(arg)), Box::new(self.apply(res)))
+            }
+            Kind::Star => Kind::Star,
+        }
+    }
+
+    // whatever is still unknown is *
+    fn default(&self, kind: &Kind) -> Kind {
+        match self.apply(kind) {
+            Kind::Arrow(arg, res) => {
+                Kind::Arrow(Box::new(self.default(&arg)), Box::new(self.default(&res)))
+            }
+            _ => Kind::Star,
+        }
+    }
+
+    fn unify(&mut self, a: &Kind, b: &Kind) -> Result<(), String> {
+        let a = self.apply(a);
+        let b = self.apply(b);
+        match (&a, &b) {
+            (Kind::Var(i), Kind::Var(j)) if i == j => Ok(()),
+            (Kind::Var(i), k) | (k, Kind::Var(i)) => {
+                if occurs(*i, k) {
+                    Err("the kind would have to contain itself".to_string())
+                } else {
+                    self.subst[*i] = Some(k.clone());
+                    Ok(())
+                }
+            }
+            (Kind::Star, Kind::Star) => Ok(()),
+            (Kind::Arrow(a1, r1), Kind::Arrow(a2, r2)) => {
+                self.unify(a1, a2)?;
+                self.unify(r1, r2)
+            }
+            _ => Err(format!(
+                "cannot match `{}` with `{}`",
+                self.default(&a),
+                self.default(&b)
+            )),
+        }
+    }
+}
+
+fn check_fields(
+    alt: &ProdInfo,
+    name: &str,
+    fields: &mut HashMap<String, (String, TypeInfo)>,
+    errors: &mut Vec<Diagnostic>,
+) {
+    for (i, (field, typ)) in alt.fields.iter().zip(&alt.args).enumerate() {
+        let problem = if alt.fields[..i].contains(field) {
+            format!("field `{}` appears more than once in `{}`", field, alt.name)
+        } else {
+            match fields.get(field) {
+                Some((other, _)) if other != name => format!(
+                    "duplicate field `{}`, it is already a field of `{}`",
+                    field, other
+                ),
+                Some((_, other)) if other != typ => format!(
+                    "field `{}` is `{}` in `{}` but `{}` in another constructor of `{}`",
+                    field, typ, alt.name, other, name
+                ),
+                Some(_) => continue,
+                None => {
+                    fields.insert(field.to_string(), (name.to_string(), typ.clone()));
+                    continue;
+                }
+            }
+        };
+        errors.push(Diagnostic::error(alt.span, problem));
+    }
+}
+
+fn parenthesized(typ: &TypeInfo) -> String {
+    match typ {
+        TypeInfo::TApp(..) | TypeInfo::TFun(..) => format!("({})", typ),
+        _ => typ.to_string(),
+    }
+}
+
+fn occurs(i: usize, kind: &Kind) -> bool {
+    match kind {
+        Kind::Var(j) => i == *j,
+        Kind::Arrow(arg, res) => occurs(i, arg) || occurs(i, res),
+        Kind::Star => false,
+    }
+}
+
+impl Display for Kind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Kind::Star | Kind::Var(_) => write!(f, "*"),
+            Kind::Arrow(arg, res) => match **arg {
+                Kind::Arrow(..) => write!(f, "({}) -> {}", arg, res),
+                _ => write!(f, "{} -> {}", arg, res),
+            },
+        }
+    }
+}
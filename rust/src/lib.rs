@@ -0,0 +1,48 @@
+/*
+
+Assume all lets are letrecs and then transform them into the proper lets and letrecs by analyzing the dependency graph
+
+Now onto type checking
+    have everything worked out except cases
+
+*/
+
+pub mod anf;
+pub mod ast;
+pub mod builtins;
+pub mod c;
+pub mod check;
+pub mod classes;
+pub mod debug;
+pub mod diagnostic;
+pub mod elaborate;
+pub mod env;
+pub mod eval;
+pub mod front;
+pub mod gc;
+pub mod gmachine;
+pub mod graph;
+pub mod infer;
+pub mod info;
+pub mod js;
+pub mod json;
+pub mod kinds;
+pub mod lift;
+pub mod lsp;
+pub mod names;
+pub mod profile;
+pub mod rearrange;
+pub mod records;
+pub mod scan;
+pub mod simplify;
+pub mod strict;
+pub mod testing;
+pub mod unused;
+pub mod wasm;
+
+extern crate num;
+
+#[macro_use]
+extern crate lalrpop_util;
+
+lalrpop_mod!(pub gram); // synthesized by LALRPOP
@@ -0,0 +1,477 @@
+/*
+
+passes that make every function a top level definition, for code generators that only want first order code
+
+they work on the program after change_lets, the lets around the body are the top level definitions
+lambdas right inside of each other are one function, \x . \y . e takes two arguments
+
+free variables
+    what a lambda uses from the definitions and lambdas around it
+    top level definitions aren't counted, they're there for every function
+
+closure conversion
+    a lambda with free variables gets them as parameters first and is applied to them where it was
+    \x . + x k becomes (\~k . \x . + x ~k) k, so the lambda doesn't need anything from around it anymore
+    the new parameters start with ~ and are lazy like _, eval doesn't force them, so nothing is evaluated that wasn't before
+    names can't start with ~ in the source, so they never get in the way of the program's own names
+
+lambda lifting
+    after closure conversion every lambda can be moved to the top level, the lambda inside of sum becomes sum$1
+    the lifted lambdas go in the same group as the definition they came from, turning a let into a letrec
+
+*/
+
+use crate::ast::Expr;
+use crate::classes::app;
+use crate::classes::lams;
+use crate::classes::var;
+use std::cell::RefCell;
+use std::fmt::Write;
+use std::rc::Rc;
+
+// the variables a lambda uses from around it
+pub struct Captures {
+    pub definition: String,
+    pub lambda: Rc<Expr>,
+    pub free: Vec<String>,
+}
+
+// parameters that eval doesn't force
+pub fn is_lazy(name: &str) -> bool {
+    name == "_" || name.starts_with('~')
+}
+
+// the variables an expression uses that it doesn't define, in the order they're first used
+pub fn free_vars(expr: &Rc<Expr>) -> Vec<String> {
+    let mut found = Vec::new();
+    collect(expr, &mut Vec::new(), &mut found);
+    found
+}
+
+fn collect(expr: &Rc<Expr>, bound: &mut Vec<String>, found: &mut Vec<String>) {
+    let mark = bound.len();
+    match &**expr {
+        Expr::Var(name, _, _) if !bound.contains(name) && !found.contains(name) => {
+            found.push(name.to_string());
+        }
+        Expr::Lam(head, body) => {
+            bound.push(var_name(head).to_string());
+            collect(body, bound, found);
+        }
+        Expr::App(left, right) => {
+            collect(left, bound, found);
+            collect(right, bound, found);
+        }
+        Expr::Let(vars, defs, body) => {
+            for def in defs {
+                collect(def, bound, found);
+            }
+            bound.extend(vars.iter().map(|v| var_name(v).to_string()));
+            collect(body, bound, found);
+        }
+        Expr::LetRec(vars, defs, body) => {
+            bound.extend(vars.iter().map(|v| var_name(v).to_string()));
+            for def in defs {
+                collect(def, bound, found);
+            }
+            collect(body, bound, found);
+        }
+        Expr::Case(cond, pats, branches, _) => {
+            collect(cond, bound, found);
+            for (pat, branch) in pats.iter().zip(branches) {
+                bound.extend(pat.vars().iter().map(|v| v.to_string()));
+                collect(branch, bound, found);
+                bound.truncate(mark);
+            }
+        }
+        Expr::If(cond, b1, b2) => {
+            collect(cond, bound, found);
+            collect(b1, bound, found);
+            collect(b2, bound, found);
+        }
+        Expr::Annot(inner, _) => collect(inner, bound, found),
+        _ => (),
+    }
+    bound.truncate(mark);
+}
+
+fn var_name(var: &Rc<Expr>) -> &str {
+    match &**var {
+        Expr::Var(name, _, _) => name,
+        _ => panic!("Only variables can be defined."),
+    }
+}
+
+// the parameters and body of the lambdas right inside of each other
+fn params(expr: &Rc<Expr>) -> (Vec<String>, &Rc<Expr>) {
+    let mut params = Vec::new();
+    let mut body = expr;
+    while let Expr::Lam(head, inner) = &**body {
+        params.push(var_name(head).to_string());
+        body = inner;
+    }
+    (params, body)
+}
+
+// the same program with every top level definition and the body changed by f
+fn definitions(expr: &Rc<Expr>, f: &mut dyn FnMut(&str, &Rc<Expr>) -> Rc<Expr>) -> Rc<Expr> {
+    match &**expr {
+        Expr::Let(vars, defs, body) => {
+            let defs = vars
+                .iter()
+                .zip(defs)
+                .map(|(v, d)| f(var_name(v), d))
+                .collect();
+            Rc::new(Expr::Let(vars.to_vec(), defs, definitions(body, f)))
+        }
+        Expr::LetRec(vars, defs, body) => {
+            let defs = vars
+                .iter()
+                .zip(defs)
+                .map(|(v, d)| f(var_name(v), d))
+                .collect();
+            Rc::new(Expr::LetRec(vars.to_vec(), defs, definitions(body, f)))
+        }
+        _ => f("$main", expr),
+    }
+}
+
+// every lambda in the program with what it uses from around it
+pub fn lambda_free_vars(expr: &Rc<Expr>) -> Vec<Captures> {
+    let mut found = Vec::new();
+    definitions(expr, &mut |name, def| {
+        captures(name, def, &mut Vec::new(), &mut found);
+        Rc::clone(def)
+    });
+    found
+}
+
+fn captures(
+    definition: &str,
+    expr: &Rc<Expr>,
+    locals: &mut Vec<String>,
+    found: &mut Vec<Captures>,
+) {
+    let mark = locals.len();
+    match &**expr {
+        Expr::Lam(_, _) => {
+            let (params, body) = params(expr);
+            let mut free = free_vars(expr);
+            free.retain(|v| locals.contains(v));
+            found.push(Captures {
+                definition: definition.to_string(),
+                lambda: Rc::clone(expr),
+                free,
+            });
+            locals.extend(params);
+            captures(definition, body, locals, found);
+        }
+        Expr::App(left, right) => {
+            captures(definition, left, locals, found);
+            captures(definition, right, locals, found);
+        }
+        Expr::Let(vars, defs, body) => {
+            for def in defs {
+                captures(definition, def, locals, found);
+            }
+            locals.extend(vars.iter().map(|v| var_name(v).to_string()));
+            captures(definition, body, locals, found);
+        }
+        Expr::LetRec(vars, defs, body) => {
+            locals.extend(vars.iter().map(|v| var_name(v).to_string()));
+            for def in defs {
+                captures(definition, def, locals, found);
+            }
+            captures(definition, body, locals, found);
+        }
+        Expr::Case(cond, pats, branches, _) => {
+            captures(definition, cond, locals, found);
+            for (pat, branch) in pats.iter().zip(branches) {
+                locals.extend(pat.vars().iter().map(|v| v.to_string()));
+                captures(definition, branch, locals, found);
+                locals.truncate(mark);
+            }
+        }
+        Expr::If(cond, b1, b2) => {
+            captures(definition, cond, locals, found);
+            captures(definition, b1, locals, found);
+            captures(definition, b2, locals, found);
+        }
+        Expr::Annot(inner, _) => captures(definition, inner, locals, found),
+        _ => (),
+    }
+    locals.truncate(mark);
+}
+
+// every lambda gets its free variables as parameters
+pub fn closure_convert(expr: &Rc<Expr>) -> Rc<Expr> {
+    definitions(expr, &mut |_, def| convert(def, &mut Vec::new()))
+}
+
+fn convert(expr: &Rc<Expr>, locals: &mut Vec<String>) -> Rc<Expr> {
+    let mark = locals.len();
+    let converted = match &**expr {
+        Expr::Lam(_, _) => {
+            let (params, body) = params(expr);
+            let mut free = free_vars(expr);
+            free.retain(|v| locals.contains(v));
+            locals.extend(params.iter().cloned());
+            let mut body = convert(body, locals);
+            let mut all = Vec::new();
+            for v in &free {
+                let lazy = format!("~{}", v);
+                body = rename(&body, v, &lazy);
+                all.push(lazy);
+            }
+            all.extend(params);
+            let all: Vec<&str> = all.iter().map(|p| p.as_str()).collect();
+            let closed = lams(&all, body);
+            app(closed, free.iter().map(|v| var(v)).collect())
+        }
+        Expr::App(left, right) => Rc::new(Expr::App(convert(left, locals), convert(right, locals))),
+        Expr::Let(vars, defs, body) => {
+            let defs = defs.iter().map(|d| convert(d, locals)).collect();
+            locals.extend(vars.iter().map(|v| var_name(v).to_string()));
+            Rc::new(Expr::Let(vars.to_vec(), defs, convert(body, locals)))
+        }
+        Expr::LetRec(vars, defs, body) => {
+            locals.extend(vars.iter().map(|v| var_name(v).to_string()));
+            let defs = defs.iter().map(|d| convert(d, locals)).collect();
+            Rc::new(Expr::LetRec(vars.to_vec(), defs, convert(body, locals)))
+        }
+        Expr::Case(cond, pats, branches, spans) => {
+            let cond = convert(cond, locals);
+            let mut new_branches = Vec::new();
+            for (pat, branch) in pats.iter().zip(branches) {
+                locals.extend(pat.vars().iter().map(|v| v.to_string()));
+                new_branches.push(convert(branch, locals));
+                locals.truncate(mark);
+            }
+            Rc::new(Expr::Case(
+                cond,
+                pats.to_vec(),
+                new_branches,
+                spans.to_vec(),
+            ))
+        }
+        Expr::If(cond, b1, b2) => Rc::new(Expr::If(
+            convert(cond, locals),
+            convert(b1, locals),
+            convert(b2, locals),
+        )),
+        Expr::Annot(inner, typ) => Rc::new(Expr::Annot(convert(inner, locals), typ.clone())),
+        _ => Rc::clone(expr),
+    };
+    locals.truncate(mark);
+    converted
+}
+
+// uses of from become uses of to, until something else is called from
+fn rename(expr: &Rc<Expr>, from: &str, to: &str) -> Rc<Expr> {
+    let binds = |vars: &[Rc<Expr>]| vars.iter().any(|v| var_name(v) == from);
+    match &**expr {
+        Expr::Var(name, _, span) if name == from => {
+            Rc::new(Expr::Var(to.to_string(), RefCell::new((0, 0)), *span))
+        }
+        Expr::Lam(head, _) if var_name(head) == from => Rc::clone(expr),
+        Expr::Lam(head, body) => Rc::new(Expr::Lam(Rc::clone(head), rename(body, from, to))),
+        Expr::App(left, right) => {
+            Rc::new(Expr::App(rename(left, from, to), rename(right, from, to)))
+        }
+        Expr::Let(vars, defs, body) => {
+            let defs = defs.iter().map(|d| rename(d, from, to)).collect();
+            let body = if binds(vars) {
+                Rc::clone(body)
+            } else {
+                rename(body, from, to)
+            };
+            Rc::new(Expr::Let(vars.to_vec(), defs, body))
+        }
+        Expr::LetRec(vars, _, _) if binds(vars) => Rc::clone(expr),
+        Expr::LetRec(vars, defs, body) => Rc::new(Expr::LetRec(
+            vars.to_vec(),
+            defs.iter().map(|d| rename(d, from, to)).collect(),
+            rename(body, from, to),
+        )),
+        Expr::Case(cond, pats, branches, spans) => {
+            let branches = pats
+                .iter()
+                .zip(branches)
+                .map(|(pat, branch)| {
+                    if pat.vars().contains(&from) {
+                        Rc::clone(branch)
+                    } else {
+                        rename(branch, from, to)
+                    }
+                })
+                .collect();
+            Rc::new(Expr::Case(
+                rename(cond, from, to),
+                pats.to_vec(),
+                branches,
+                spans.to_vec(),
+            ))
+        }
+        Expr::If(cond, b1, b2) => Rc::new(Expr::If(
+            rename(cond, from, to),
+            rename(b1, from, to),
+            rename(b2, from, to),
+        )),
+        Expr::Annot(inner, typ) => Rc::new(Expr::Annot(rename(inner, from, to), typ.clone())),
+        _ => Rc::clone(expr),
+    }
+}
+
+// closure conversion and then every lambda that isn't a whole top level definition gets a definition of its own
+pub fn lambda_lift(expr: &Rc<Expr>) -> Rc<Expr> {
+    let converted = closure_convert(expr);
+    lift_groups(&converted)
+}
+
+fn lift_groups(expr: &Rc<Expr>) -> Rc<Expr> {
+    let (vars, defs, body, rec) = match &**expr {
+        Expr::Let(vars, defs, body) => (vars, defs, body, false),
+        Expr::LetRec(vars, defs, body) => (vars, defs, body, true),
+        _ => {
+            let mut lifted = Vec::new();
+            let body = lift(expr, "$main", &mut lifted);
+            return group(Vec::new(), Vec::new(), lifted, body, false);
+        }
+    };
+    let mut lifted = Vec::new();
+    let defs = vars
+        .iter()
+        .zip(defs)
+        .map(|(v, d)| {
+            // each definition numbers its own lambdas
+            let mut own = Vec::new();
+            let def = lift_definition(d, var_name(v), &mut own);
+            lifted.extend(own);
+            def
+        })
+        .collect();
+    group(vars.to_vec(), defs, lifted, lift_groups(body), rec)
+}
+
+// a let with the lifted lambdas added, they can refer to each other so then it's a letrec
+fn group(
+    vars: Vec<Rc<Expr>>,
+    defs: Vec<Rc<Expr>>,
+    lifted: Vec<(String, Rc<Expr>)>,
+    body: Rc<Expr>,
+    rec: bool,
+) -> Rc<Expr> {
+    if lifted.is_empty() && vars.is_empty() {
+        return body;
+    }
+    if lifted.is_empty() && !rec {
+        return Rc::new(Expr::Let(vars, defs, body));
+    }
+    let mut vars = vars;
+    let mut defs = defs;
+    for (name, def) in lifted {
+        vars.push(var(&name));
+        defs.push(def);
+    }
+    Rc::new(Expr::LetRec(vars, defs, body))
+}
+
+// a definition that is a lambda stays one, everything inside it is lifted
+fn lift_definition(
+    expr: &Rc<Expr>,
+    parent: &str,
+    lifted: &mut Vec<(String, Rc<Expr>)>,
+) -> Rc<Expr> {
+    match &**expr {
+        Expr::Lam(_, _) => {
+            let (params, body) = params(expr);
+            let params: Vec<&str> = params.iter().map(|p| p.as_str()).collect();
+            lams(&params, lift(body, parent, lifted))
+        }
+        Expr::Annot(inner, typ) => Rc::new(Expr::Annot(
+            lift_definition(inner, parent, lifted),
+            typ.clone(),
+        )),
+        _ => lift(expr, parent, lifted),
+    }
+}
+
+fn lift(expr: &Rc<Expr>, parent: &str, lifted: &mut Vec<(String, Rc<Expr>)>) -> Rc<Expr> {
+    match &**expr {
+        Expr::Lam(_, _) => {
+            let def = lift_definition(expr, parent, lifted);
+            let name = format!("{}${}", parent, lifted.len() + 1);
+            lifted.push((name.to_string(), def));
+            var(&name)
+        }
+        Expr::App(left, right) => Rc::new(Expr::App(
+            lift(left, parent, lifted),
+            lift(right, parent, lifted),
+        )),
+        Expr::Let(vars, defs, body) => Rc::new(Expr::Let(
+            vars.to_vec(),
+            defs.iter().map(|d| lift(d, parent, lifted)).collect(),
+            lift(body, parent, lifted),
+        )),
+        Expr::LetRec(vars, defs, body) => Rc::new(Expr::LetRec(
+            vars.to_vec(),
+            defs.iter().map(|d| lift(d, parent, lifted)).collect(),
+            lift(body, parent, lifted),
+        )),
+        Expr::Case(cond, pats, branches, spans) => Rc::new(Expr::Case(
+            lift(cond, parent, lifted),
+            pats.to_vec(),
+            branches.iter().map(|b| lift(b, parent, lifted)).collect(),
+            spans.to_vec(),
+        )),
+        Expr::If(cond, b1, b2) => Rc::new(Expr::If(
+            lift(cond, parent, lifted),
+            lift(b1, parent, lifted),
+            lift(b2, parent, lifted),
+        )),
+        Expr::Annot(inner, typ) => Rc::new(Expr::Annot(lift(inner, parent, lifted), typ.clone())),
+        _ => Rc::clone(expr),
+    }
+}
+
+// what --dump=free prints, each lambda by its parameters
+pub fn show_free_vars(captures: &[Captures]) -> String {
+    let mut out = String::new();
+    for c in captures {
+        let (params, _) = params(&c.lambda);
+        let free = if c.free.is_empty() {
+            "nothing".to_string()
+        } else {
+            c.free.join(", ")
+        };
+        writeln!(
+            out,
+            "{}: \\ {} . uses {}",
+            c.definition,
+            params.join(" "),
+            free
+        )
+        .unwrap();
+    }
+    out
+}
+
+// what --dump=closures and --dump=lift print, a line for each top level definition and then the body
+pub fn listing(expr: &Rc<Expr>) -> String {
+    let mut out = String::new();
+    let mut expr = expr;
+    loop {
+        let (keyword, vars, defs, body) = match &**expr {
+            Expr::Let(vars, defs, body) => ("let", vars, defs, body),
+            Expr::LetRec(vars, defs, body) => ("letrec", vars, defs, body),
+            _ => break,
+        };
+        for (i, (v, d)) in vars.iter().zip(defs).enumerate() {
+            let keyword = if i == 0 { keyword } else { "and" };
+            writeln!(out, "{} {} = {}", keyword, v, d).unwrap();
+        }
+        expr = body;
+    }
+    writeln!(out, "in {}", expr).unwrap();
+    out
+}
@@ -0,0 +1,964 @@
+/*
+
+a language server, bagl lsp talks the language server protocol over stdin and stdout
+
+messages are json with a header in front
+    Content-Length: 52\r\n
+    \r\n
+    {"jsonrpc":"2.0","id":1,"method":"initialize",...}
+
+what it does
+    diagnostics         the same errors and warnings as running the program, sent whenever a document changes
+    definition          where a variable, constructor, method, or field is defined
+    hover               the inferred type, or the source of the definition when there is no type
+    document symbols    the toplevel definitions, data types with their constructors, and classes
+    completion          the names in scope, toplevel ones and the ones bound earlier in the same definition
+
+documents are sent whole on every change, they're small enough that checking everything again is fine
+the last document that parsed is kept, so going to a definition still works while the current text is broken
+    its spans are for the text it came from, so positions get converted with that text
+
+the variables are matched up with what defines them by walking the program with the same scoping as resolve
+    resolve can't be used itself since it panics on a name that isn't defined, which is normal while typing
+patterns only have a span for the whole pattern, the names in them are found in the text of the pattern
+
+positions in the protocol are lines and utf-16 columns, spans are byte offsets, everything goes through position and offset
+
+*/
+
+use crate::ast::Expr;
+use crate::ast::Pattern;
+use crate::ast::Span;
+use crate::ast::Toplevel;
+use crate::diagnostic::Diagnostic;
+use crate::diagnostic::Severity;
+use crate::gram;
+use crate::infer::check_types;
+use crate::json::Json;
+use crate::kinds::check_declarations;
+use crate::names::check_names;
+use crate::rearrange::change_lets;
+use crate::unused::unused_warnings;
+use lalrpop_util::ParseError;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::io;
+use std::io::BufRead;
+use std::io::Write;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::rc::Rc;
+
+const KEYWORDS: [&str; 14] = [
+    "let",
+    "in",
+    "case",
+    "if",
+    "then",
+    "else",
+    "error",
+    "undefined",
+    "class",
+    "instance",
+    "deriving",
+    "eq",
+    "assert",
+    "assertEq",
+];
+
+// what the protocol numbers things as
+const SYNC_FULL: usize = 1;
+const ERROR: usize = 1;
+const WARNING: usize = 2;
+const SYMBOL_CLASS: usize = 5;
+const SYMBOL_METHOD: usize = 6;
+const SYMBOL_FIELD: usize = 8;
+const SYMBOL_ENUM: usize = 10;
+const SYMBOL_INTERFACE: usize = 11;
+const SYMBOL_FUNCTION: usize = 12;
+const SYMBOL_VARIABLE: usize = 13;
+const SYMBOL_MEMBER: usize = 22;
+const COMPLETION_METHOD: usize = 2;
+const COMPLETION_FUNCTION: usize = 3;
+const COMPLETION_CONSTRUCTOR: usize = 4;
+const COMPLETION_FIELD: usize = 5;
+const COMPLETION_VARIABLE: usize = 6;
+const COMPLETION_KEYWORD: usize = 14;
+const METHOD_NOT_FOUND: f64 = -32601.0;
+const PARSE_ERROR: f64 = -32700.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Definition, // let, letrec, or the toplevel
+    Parameter,  // lambda
+    Pattern,    // case arm
+    Constructor,
+    Method,
+    Field,
+}
+
+// something that gives a name a meaning
+#[derive(Debug, Clone)]
+struct Binder {
+    name: String,
+    span: Span, // not known for what is generated, constructors, methods, and fields
+    kind: Kind,
+    toplevel: bool,
+}
+
+// a variable or constructor in the source and the binder it refers to, None when it isn't defined
+#[derive(Debug, Clone)]
+struct Use {
+    span: Span,
+    binder: Option<usize>,
+}
+
+// what is known about a document that parsed
+struct Analysis {
+    text: String,
+    top: Toplevel,
+    binders: Vec<Binder>,
+    uses: Vec<Use>,
+    types: Vec<(Span, String)>, // the inferred schemes of the let and letrec definitions
+}
+
+struct Document {
+    analysis: Option<Analysis>,
+}
+
+struct Server {
+    documents: HashMap<String, Document>,
+    shutdown: bool,
+}
+
+// run until the client says to exit, the result is whether it asked for a shutdown before that
+pub fn serve(input: impl BufRead, output: impl Write) -> io::Result<bool> {
+    let mut server = Server {
+        documents: HashMap::new(),
+        shutdown: false,
+    };
+    let mut input = input;
+    let mut output = output;
+    while let Some(body) = read_message(&mut input)? {
+        let message = match Json::parse(&body) {
+            Ok(message) => message,
+            Err(error) => {
+                let response = failure(Json::Null, PARSE_ERROR, &error);
+                write_message(&mut output, &response)?;
+                continue;
+            }
+        };
+        if message.get("method").as_str() == Some("exit") {
+            return Ok(server.shutdown);
+        }
+        for reply in server.handle(&message) {
+            write_message(&mut output, &reply)?;
+        }
+    }
+    Ok(server.shutdown)
+}
+
+// None once the input is closed
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let length = match length {
+        Some(length) => length,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "a message without a Content-Length",
+            ))
+        }
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    Ok(Some(String::from_utf8_lossy(&body).to_string()))
+}
+
+fn write_message(output: &mut impl Write, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+fn success(id: Json, result: Json) -> Json {
+    Json::object(vec![
+        ("jsonrpc", Json::str("2.0")),
+        ("id", id),
+        ("result", result),
+    ])
+}
+
+fn failure(id: Json, code: f64, message: &str) -> Json {
+    Json::object(vec![
+        ("jsonrpc", Json::str("2.0")),
+        ("id", id),
+        (
+            "error",
+            Json::object(vec![
+                ("code", Json::Number(code)),
+                ("message", Json::str(message)),
+            ]),
+        ),
+    ])
+}
+
+fn notification(method: &str, params: Json) -> Json {
+    Json::object(vec![
+        ("jsonrpc", Json::str("2.0")),
+        ("method", Json::str(method)),
+        ("params", params),
+    ])
+}
+
+impl Server {
+    // the messages to send back, a response for a request and any notifications
+    fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let method = message.get("method").as_str().unwrap_or("");
+        let params = message.get("params");
+        let id = message.get("id").clone();
+        let result = match method {
+            "initialize" => capabilities(),
+            "shutdown" => {
+                self.shutdown = true;
+                Json::Null
+            }
+            "textDocument/didOpen" => {
+                let document = params.get("textDocument");
+                return self.update(document.get("uri"), document.get("text"));
+            }
+            "textDocument/didChange" => {
+                // the whole text is sent every time, so only the last change matters
+                let changes = params.get("contentChanges").as_array().unwrap_or(&[]);
+                let text = match changes.last() {
+                    Some(change) => change.get("text"),
+                    None => &Json::Null,
+                };
+                return self.update(params.get("textDocument").get("uri"), text);
+            }
+            "textDocument/didClose" => {
+                let uri = params.get("textDocument").get("uri");
+                self.documents.remove(uri.as_str().unwrap_or(""));
+                return vec![publish(uri, Vec::new())];
+            }
+            "textDocument/definition" => self.at(params, definition),
+            "textDocument/hover" => self.at(params, hover),
+            "textDocument/documentSymbol" => self.with(params, symbols),
+            "textDocument/completion" => self.at(params, completion),
+            _ if id == Json::Null => return Vec::new(), // notifications that don't matter here
+            _ => {
+                let error = format!("`{}` isn't supported", method);
+                return vec![failure(id, METHOD_NOT_FOUND, &error)];
+            }
+        };
+        if id == Json::Null {
+            return Vec::new();
+        }
+        vec![success(id, result)]
+    }
+
+    fn update(&mut self, uri: &Json, text: &Json) -> Vec<Json> {
+        let (key, text) = match (uri.as_str(), text.as_str()) {
+            (Some(key), Some(text)) => (key, text),
+            _ => return Vec::new(),
+        };
+        let (diagnostics, analysis) = analyze(text);
+        let diagnostics = diagnostics.iter().map(|d| diagnostic(text, d)).collect();
+        let document = self
+            .documents
+            .entry(key.to_string())
+            .or_insert(Document { analysis: None });
+        if analysis.is_some() {
+            document.analysis = analysis;
+        }
+        vec![publish(uri, diagnostics)]
+    }
+
+    // the analysis of the document a request is about
+    fn with(&self, params: &Json, f: fn(&Analysis) -> Json) -> Json {
+        let uri = params.get("textDocument").get("uri").as_str().unwrap_or("");
+        match self.documents.get(uri).and_then(|d| d.analysis.as_ref()) {
+            Some(analysis) => f(analysis),
+            None => Json::Null,
+        }
+    }
+
+    // same as with, for requests about a position, f also gets the uri of the document
+    fn at(&self, params: &Json, f: fn(&Analysis, &str, usize) -> Json) -> Json {
+        let uri = params.get("textDocument").get("uri").as_str().unwrap_or("");
+        let analysis = match self.documents.get(uri).and_then(|d| d.analysis.as_ref()) {
+            Some(analysis) => analysis,
+            None => return Json::Null,
+        };
+        let position = params.get("position");
+        match (
+            position.get("line").as_usize(),
+            position.get("character").as_usize(),
+        ) {
+            (Some(line), Some(character)) => {
+                f(analysis, uri, offset(&analysis.text, line, character))
+            }
+            _ => Json::Null,
+        }
+    }
+}
+
+fn capabilities() -> Json {
+    Json::object(vec![
+        (
+            "capabilities",
+            Json::object(vec![
+                ("textDocumentSync", Json::number(SYNC_FULL)),
+                ("definitionProvider", Json::Bool(true)),
+                ("hoverProvider", Json::Bool(true)),
+                ("documentSymbolProvider", Json::Bool(true)),
+                ("completionProvider", Json::object(Vec::new())),
+            ]),
+        ),
+        (
+            "serverInfo",
+            Json::object(vec![
+                ("name", Json::str("bagl")),
+                ("version", Json::str(env!("CARGO_PKG_VERSION"))),
+            ]),
+        ),
+    ])
+}
+
+fn publish(uri: &Json, diagnostics: Vec<Json>) -> Json {
+    notification(
+        "textDocument/publishDiagnostics",
+        Json::object(vec![
+            ("uri", uri.clone()),
+            ("diagnostics", Json::Array(diagnostics)),
+        ]),
+    )
+}
+
+fn diagnostic(text: &str, d: &Diagnostic) -> Json {
+    let severity = match d.severity {
+        Severity::Error => ERROR,
+        Severity::Warning => WARNING,
+    };
+    let message = match &d.help {
+        Some(help) => format!("{}\nhelp: {}", d.message, help),
+        None => d.message.to_string(),
+    };
+    Json::object(vec![
+        ("range", range(text, d.span)),
+        ("severity", Json::number(severity)),
+        ("source", Json::str("bagl")),
+        ("message", Json::Str(message)),
+    ])
+}
+
+// parse and check the text like running it would, the analysis is there whenever it parsed
+fn analyze(text: &str) -> (Vec<Diagnostic>, Option<Analysis>) {
+    // a bug in a pass shouldn't take down the server, the panic is reported like any other error
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let top = match gram::TopParser::new().parse(text) {
+            Ok(top) => top,
+            Err(error) => return (vec![parse_error(error)], None),
+        };
+        let mut analysis = Analysis::new(text, top);
+        let diagnostics = analysis.check();
+        (diagnostics, Some(analysis))
+    }));
+    match result {
+        Ok(result) => result,
+        Err(payload) => {
+            let message = if let Some(s) = payload.downcast_ref::<String>() {
+                s.to_string()
+            } else if let Some(s) = payload.downcast_ref::<&str>() {
+                s.to_string()
+            } else {
+                "panicked".to_string()
+            };
+            let error = format!("internal error while checking, {}", message);
+            (vec![Diagnostic::error(Span::default(), error)], None)
+        }
+    }
+}
+
+fn parse_error<T: Display>(error: ParseError<usize, T, Diagnostic>) -> Diagnostic {
+    let expected = |expected: Vec<String>| {
+        let mut names: Vec<String> = expected.iter().map(|t| token_name(t)).collect();
+        names.dedup();
+        format!("expected one of {}", names.join(", "))
+    };
+    match error {
+        ParseError::InvalidToken { location } => Diagnostic::error(
+            Span::new(location, location + 1),
+            "invalid token".to_string(),
+        ),
+        ParseError::UnrecognizedEOF {
+            location,
+            expected: tokens,
+        } => Diagnostic::error(
+            Span::new(location, location),
+            "unexpected end of file".to_string(),
+        )
+        .with_help(expected(tokens)),
+        ParseError::UnrecognizedToken {
+            token: (l, token, r),
+            expected: tokens,
+        } => Diagnostic::error(Span::new(l, r), format!("unexpected `{}`", token))
+            .with_help(expected(tokens)),
+        ParseError::ExtraToken {
+            token: (l, token, r),
+        } => Diagnostic::error(Span::new(l, r), format!("extra `{}`", token)),
+        ParseError::User { error } => error,
+    }
+}
+
+// lalrpop names the tokens by what matches them, the regular expressions get a description instead
+fn token_name(token: &str) -> String {
+    if !token.starts_with("r#") {
+        return format!("`{}`", token.trim_matches('"').replace("\\\\", "\\"));
+    }
+    let name = if token.contains('\'') || token.contains("\\\"") {
+        "text"
+    } else if token.contains("[a-z_]") {
+        "a variable"
+    } else if token.contains("[A-Z]") {
+        "a constructor"
+    } else if token.contains("\\.") {
+        "a float"
+    } else {
+        "an integer"
+    };
+    name.to_string()
+}
+
+impl Analysis {
+    fn new(text: &str, top: Toplevel) -> Analysis {
+        let mut analysis = Analysis {
+            text: text.to_string(),
+            top,
+            binders: Vec::new(),
+            uses: Vec::new(),
+            types: Vec::new(),
+        };
+        let expr = analysis.top.to_let();
+        analysis.walk(&expr, &mut Vec::new());
+        analysis
+    }
+
+    // the passes main runs before evaluating, stopping at the first one with errors, the types are kept
+    fn check(&mut self) -> Vec<Diagnostic> {
+        if let Err(errors) = check_declarations(&self.top) {
+            return errors;
+        }
+        let expr = self.top.to_let();
+        let errors = check_names(&expr);
+        if !errors.is_empty() {
+            return errors;
+        }
+        let mut diagnostics = unused_warnings(&expr);
+        match check_types(&change_lets(expr), &self.top) {
+            Ok(checked) => {
+                for binding in checked.bindings {
+                    if binding.span.is_known() {
+                        self.types.push((binding.span, binding.scheme.to_string()));
+                    }
+                }
+            }
+            Err(errors) => diagnostics.extend(errors),
+        }
+        diagnostics
+    }
+
+    fn bind(&mut self, name: &str, span: Span, kind: Kind, toplevel: bool) -> usize {
+        self.binders.push(Binder {
+            name: name.to_string(),
+            span,
+            kind,
+            toplevel,
+        });
+        self.binders.len() - 1
+    }
+
+    // what a toplevel definition is, the generated ones are told apart by their names
+    fn toplevel_kind(&self, name: &str) -> Kind {
+        let alts = || self.top.types.iter().flat_map(|t| &t.data_info.alts);
+        if alts().any(|alt| alt.name == name) {
+            Kind::Constructor
+        } else if alts().any(|alt| alt.fields.iter().any(|f| f == name)) {
+            Kind::Field
+        } else if self
+            .top
+            .classes
+            .iter()
+            .any(|c| c.methods.iter().any(|(m, _)| m == name))
+        {
+            Kind::Method
+        } else {
+            Kind::Definition
+        }
+    }
+
+    fn lookup(frames: &[Vec<usize>], binders: &[Binder], name: &str) -> Option<usize> {
+        for frame in frames.iter().rev() {
+            if let Some(&b) = frame.iter().rev().find(|&&b| binders[b].name == name) {
+                return Some(b);
+            }
+        }
+        None
+    }
+
+    // frames hold the binders of each frame, innermost last
+    fn walk(&mut self, expr: &Rc<Expr>, frames: &mut Vec<Vec<usize>>) {
+        match &**expr {
+            Expr::Var(s, _, span) if span.is_known() => {
+                let binder = Analysis::lookup(frames, &self.binders, s);
+                self.uses.push(Use {
+                    span: *span,
+                    binder,
+                });
+            }
+            Expr::App(left, right) => {
+                self.walk(left, frames);
+                self.walk(right, frames);
+            }
+            Expr::Let(vars, defs, body) => {
+                for def in defs {
+                    self.walk(def, frames);
+                }
+                let frame = self.definitions(vars, false);
+                frames.push(frame);
+                self.walk(body, frames);
+                frames.pop();
+            }
+            Expr::LetRec(vars, defs, body) => {
+                let frame = self.definitions(vars, frames.is_empty());
+                frames.push(frame);
+                for def in defs {
+                    self.walk(def, frames);
+                }
+                self.walk(body, frames);
+                frames.pop();
+            }
+            Expr::If(cond, b1, b2) => {
+                self.walk(cond, frames);
+                self.walk(b1, frames);
+                self.walk(b2, frames);
+            }
+            Expr::Lam(head, body) => {
+                let b = match &**head {
+                    Expr::Var(s, _, span) => self.bind(s, *span, Kind::Parameter, false),
+                    _ => panic!("Can only define variables."),
+                };
+                frames.push(vec![b]);
+                self.walk(body, frames);
+                frames.pop();
+            }
+            Expr::Case(cond, pats, branches, spans) => {
+                self.walk(cond, frames);
+                for ((pat, branch), span) in pats.iter().zip(branches).zip(spans) {
+                    let frame = self.pattern(pat, *span, frames);
+                    frames.push(frame);
+                    self.walk(branch, frames);
+                    frames.pop();
+                }
+            }
+            Expr::Annot(inner, _) => self.walk(inner, frames),
+            _ => (),
+        }
+    }
+
+    fn definitions(&mut self, vars: &[Rc<Expr>], toplevel: bool) -> Vec<usize> {
+        vars.iter()
+            .map(|v| match &**v {
+                Expr::Var(s, _, span) => {
+                    let kind = if toplevel {
+                        self.toplevel_kind(s)
+                    } else {
+                        Kind::Definition
+                    };
+                    self.bind(s, *span, kind, toplevel)
+                }
+                _ => panic!("Can only define variables."),
+            })
+            .collect()
+    }
+
+    // the variables a pattern binds, and its constructor as a use
+    fn pattern(&mut self, pat: &Pattern, span: Span, frames: &[Vec<usize>]) -> Vec<usize> {
+        let mut words = words(&self.text, span).into_iter();
+        if let Pattern::Construct(cons, _) | Pattern::Record(cons, _) = pat {
+            if let Some((_, cons_span)) = words.by_ref().find(|(w, _)| w == cons) {
+                let binder = Analysis::lookup(frames, &self.binders, cons);
+                self.uses.push(Use {
+                    span: cons_span,
+                    binder,
+                });
+            }
+        }
+        let mut frame = Vec::new();
+        for var in pat.vars() {
+            // in the order they're written, a record's field names come before the variables they bind
+            let var_span = match words.by_ref().find(|(w, _)| w == var) {
+                Some((_, var_span)) => var_span,
+                None => span,
+            };
+            frame.push(self.bind(var, var_span, Kind::Pattern, false));
+        }
+        frame
+    }
+
+    // the use or binder under the offset
+    fn binder_at(&self, offset: usize) -> Option<usize> {
+        let contains = |span: &Span| span.is_known() && span.start <= offset && offset <= span.end;
+        if let Some(u) = self.uses.iter().find(|u| contains(&u.span)) {
+            return u.binder;
+        }
+        self.binders.iter().position(|b| contains(&b.span))
+    }
+
+    // where the binder is in the source, constructors, fields, and methods are found in their declarations
+    fn site(&self, b: usize) -> Option<Span> {
+        let binder = &self.binders[b];
+        let alts = || self.top.types.iter().flat_map(|t| &t.data_info.alts);
+        let span = match binder.kind {
+            _ if binder.span.is_known() => binder.span,
+            // just the name, not the whole alternative
+            Kind::Constructor => {
+                let alt = alts().find(|alt| alt.name == binder.name)?;
+                words(&self.text, alt.span)
+                    .into_iter()
+                    .find(|(word, _)| *word == binder.name)
+                    .map_or(alt.span, |(_, span)| span)
+            }
+            Kind::Field => alts().find(|alt| alt.fields.contains(&binder.name))?.span,
+            Kind::Method => {
+                let class = self
+                    .top
+                    .classes
+                    .iter()
+                    .find(|c| c.methods.iter().any(|(m, _)| *m == binder.name))?;
+                class.span
+            }
+            _ => return None,
+        };
+        if span.is_known() {
+            Some(span)
+        } else {
+            None
+        }
+    }
+
+    fn typ(&self, b: usize) -> Option<&str> {
+        let span = self.binders[b].span;
+        if !span.is_known() {
+            return None;
+        }
+        self.types
+            .iter()
+            .find(|(s, _)| *s == span)
+            .map(|(_, t)| t.as_str())
+    }
+
+    // the source of what defines the binder, a whole data declaration for its constructors and fields
+    fn source(&self, b: usize) -> Option<String> {
+        let binder = &self.binders[b];
+        if let Kind::Constructor | Kind::Field = binder.kind {
+            let info = self.top.types.iter().find(|t| {
+                t.data_info
+                    .alts
+                    .iter()
+                    .any(|alt| alt.name == binder.name || alt.fields.contains(&binder.name))
+            })?;
+            let end = info.data_info.alts.last()?.span.end;
+            if info.span.is_known() && end > info.span.start {
+                return Some(self.text[info.span.start..end].to_string());
+            }
+            return None;
+        }
+        let span = self.site(b)?;
+        let start = self.text[..span.start].rfind('\n').map_or(0, |i| i + 1);
+        let end = self.text[span.start..]
+            .find('\n')
+            .map_or(self.text.len(), |i| span.start + i);
+        Some(self.text[start..end].trim().to_string())
+    }
+
+    // where each toplevel item starts, an item goes until the next one
+    fn starts(&self) -> Vec<usize> {
+        let top = &self.top;
+        let mut starts: Vec<usize> = top
+            .defs
+            .iter()
+            .map(|d| d.span())
+            .chain(top.types.iter().map(|t| t.span))
+            .chain(top.sigs.iter().map(|s| s.span))
+            .chain(top.classes.iter().map(|c| c.span))
+            .chain(top.instances.iter().map(|i| i.span))
+            .chain(top.tests.iter().map(|(_, span)| *span))
+            .filter(|span| span.is_known())
+            .map(|span| span.start)
+            .collect();
+        starts.sort_unstable();
+        starts.dedup();
+        starts
+    }
+
+    // from the start of an item to the end of its text, without the ; and spaces before the next one
+    fn extent(&self, starts: &[usize], start: usize) -> Span {
+        let next = starts
+            .iter()
+            .find(|&&s| s > start)
+            .copied()
+            .unwrap_or(self.text.len());
+        let item = self.text[start..next].trim_end_matches(|c: char| c.is_whitespace() || c == ';');
+        Span::new(start, start + item.len())
+    }
+}
+
+// the names in a span of the text along with where they are
+fn words(text: &str, span: Span) -> Vec<(String, Span)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (i, c) in text[span.start..span.end].char_indices() {
+        let i = span.start + i;
+        match (c.is_ascii_alphanumeric() || c == '_', start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                words.push((text[s..i].to_string(), Span::new(s, i)));
+                start = None;
+            }
+            _ => (),
+        }
+    }
+    if let Some(s) = start {
+        words.push((text[s..span.end].to_string(), Span::new(s, span.end)));
+    }
+    words
+}
+
+// a Location, in the same document since a program is one file
+fn definition(analysis: &Analysis, uri: &str, offset: usize) -> Json {
+    match analysis.binder_at(offset).and_then(|b| analysis.site(b)) {
+        Some(span) => Json::object(vec![
+            ("uri", Json::str(uri)),
+            ("range", range(&analysis.text, span)),
+        ]),
+        None => Json::Null,
+    }
+}
+
+fn hover(analysis: &Analysis, _uri: &str, offset: usize) -> Json {
+    let b = match analysis.binder_at(offset) {
+        Some(b) => b,
+        None => return Json::Null,
+    };
+    let value = match (analysis.typ(b), analysis.source(b)) {
+        (Some(typ), _) => format!("{} :: {}", analysis.binders[b].name, typ),
+        (None, Some(source)) => source,
+        (None, None) => return Json::Null,
+    };
+    Json::object(vec![(
+        "contents",
+        Json::object(vec![
+            ("kind", Json::str("markdown")),
+            ("value", Json::Str(format!("```bagl\n{}\n```", value))),
+        ]),
+    )])
+}
+
+fn symbols(analysis: &Analysis) -> Json {
+    let text = &analysis.text;
+    let top = &analysis.top;
+    let starts = analysis.starts();
+    let symbol = |name: &str, detail: Option<&str>, kind: usize, span: Span, whole: Span| {
+        let mut fields = vec![("name", Json::str(name))];
+        if let Some(detail) = detail {
+            fields.push(("detail", Json::str(detail)));
+        }
+        fields.push(("kind", Json::number(kind)));
+        fields.push(("range", range(text, whole)));
+        fields.push(("selectionRange", range(text, span)));
+        fields
+    };
+    let mut items: Vec<(usize, Json)> = Vec::new();
+    for d in &top.defs {
+        let span = d.span();
+        if !span.is_known() {
+            continue;
+        }
+        let mut def = d.def();
+        while let Expr::Annot(inner, _) = &**def {
+            def = inner;
+        }
+        let kind = match &**def {
+            Expr::Lam(_, _) => SYMBOL_FUNCTION,
+            _ => SYMBOL_VARIABLE,
+        };
+        let detail = analysis
+            .types
+            .iter()
+            .find(|(s, _)| *s == span)
+            .map(|(_, t)| t.as_str());
+        let whole = analysis.extent(&starts, span.start);
+        items.push((
+            span.start,
+            Json::object(symbol(d.name(), detail, kind, span, whole)),
+        ));
+    }
+    for info in &top.types {
+        if !info.span.is_known() {
+            continue;
+        }
+        let mut children = Vec::new();
+        for alt in &info.data_info.alts {
+            let fields: Vec<Json> = alt
+                .fields
+                .iter()
+                .map(|f| Json::object(symbol(f, None, SYMBOL_FIELD, alt.span, alt.span)))
+                .collect();
+            let mut member = symbol(&alt.name, None, SYMBOL_MEMBER, alt.span, alt.span);
+            if !fields.is_empty() {
+                member.push(("children", Json::Array(fields)));
+            }
+            children.push(Json::object(member));
+        }
+        let whole = analysis.extent(&starts, info.span.start);
+        let name = info.type_info.get_name();
+        let mut data = symbol(&name, None, SYMBOL_ENUM, info.span, whole);
+        data.push(("children", Json::Array(children)));
+        items.push((info.span.start, Json::object(data)));
+    }
+    for class in &top.classes {
+        if !class.span.is_known() {
+            continue;
+        }
+        let whole = analysis.extent(&starts, class.span.start);
+        let methods = class
+            .methods
+            .iter()
+            .map(|(m, _)| Json::object(symbol(m, None, SYMBOL_METHOD, class.span, class.span)))
+            .collect();
+        let kind = if class.methods.is_empty() {
+            SYMBOL_CLASS
+        } else {
+            SYMBOL_INTERFACE
+        };
+        let mut fields = symbol(&class.name, None, kind, class.span, whole);
+        fields.push(("children", Json::Array(methods)));
+        items.push((class.span.start, Json::object(fields)));
+    }
+    items.sort_by_key(|(start, _)| *start);
+    Json::Array(items.into_iter().map(|(_, item)| item).collect())
+}
+
+// the toplevel names and the ones bound before the cursor in the same toplevel item
+fn completion(analysis: &Analysis, _uri: &str, offset: usize) -> Json {
+    let starts = analysis.starts();
+    let item = match starts.iter().rev().find(|&&s| s <= offset) {
+        Some(&start) => analysis.extent(&starts, start),
+        None => Span::default(),
+    };
+    let mut seen: Vec<&str> = Vec::new();
+    let mut items = Vec::new();
+    // the innermost binders first so their types are the ones shown
+    for (b, binder) in analysis.binders.iter().enumerate().rev() {
+        let visible = if binder.toplevel {
+            binder.span.is_known() || binder.kind != Kind::Definition
+        } else {
+            binder.span.start >= item.start && binder.span.end <= offset
+        };
+        if !visible || binder.name == "_" || seen.contains(&binder.name.as_str()) {
+            continue;
+        }
+        seen.push(&binder.name);
+        let kind = match binder.kind {
+            Kind::Constructor => COMPLETION_CONSTRUCTOR,
+            Kind::Method => COMPLETION_METHOD,
+            Kind::Field => COMPLETION_FIELD,
+            Kind::Definition if binder.toplevel => COMPLETION_FUNCTION,
+            _ => COMPLETION_VARIABLE,
+        };
+        let mut fields = vec![
+            ("label", Json::str(&binder.name)),
+            ("kind", Json::number(kind)),
+        ];
+        if let Some(typ) = analysis.typ(b) {
+            fields.push(("detail", Json::str(typ)));
+        }
+        items.push(Json::object(fields));
+    }
+    for keyword in KEYWORDS.iter() {
+        if !seen.contains(keyword) {
+            items.push(Json::object(vec![
+                ("label", Json::str(keyword)),
+                ("kind", Json::number(COMPLETION_KEYWORD)),
+            ]));
+        }
+    }
+    Json::Array(items)
+}
+
+fn range(text: &str, span: Span) -> Json {
+    let (start, end) = if span.is_known() {
+        (span.start, span.end)
+    } else {
+        (0, 0)
+    };
+    Json::object(vec![
+        ("start", position(text, start)),
+        ("end", position(text, end)),
+    ])
+}
+
+// the line and utf-16 column of a byte offset, both from 0
+fn position(text: &str, offset: usize) -> Json {
+    let mut line = 0;
+    let mut character = 0;
+    for (i, c) in text.char_indices() {
+        if i >= offset {
+            break;
+        }
+        if c == '\n' {
+            line += 1;
+            character = 0;
+        } else {
+            character += c.len_utf16();
+        }
+    }
+    Json::object(vec![
+        ("line", Json::number(line)),
+        ("character", Json::number(character)),
+    ])
+}
+
+// the byte offset of a line and utf-16 column, past the end of a line is the end of the line
+fn offset(text: &str, line: usize, character: usize) -> usize {
+    let mut current = 0;
+    let mut column = 0;
+    for (i, c) in text.char_indices() {
+        if current == line && (column >= character || c == '\n') {
+            return i;
+        }
+        if c == '\n' {
+            current += 1;
+            column = 0;
+        } else if current == line {
+            column += c.len_utf16();
+        }
+    }
+    text.len()
+}
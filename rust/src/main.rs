@@ -1,56 +1,804 @@
-/*
-
-Assume all lets are letrecs and then transform them into the proper lets and letrecs by analyzing the dependency graph
-
-Now onto type checking
-    have everything worked out except cases
-
-*/
-
-pub mod ast;
-pub mod builtins;
-pub mod check;
-pub mod env;
-pub mod eval;
-pub mod info;
-pub mod rearrange;
-pub mod scan;
-
-extern crate num;
-
-// use crate::ast::Expr;
-use crate::env::Env;
-use crate::rearrange::change_lets;
+use bagl::anf;
+use bagl::ast::Expr;
+use bagl::ast::Toplevel;
+use bagl::c::to_c;
+use bagl::classes::app;
+use bagl::classes::var;
+use bagl::gram;
 // use std::cell::RefCell;
 // use std::collections::HashMap;
+use lalrpop_util::ParseError;
 use std::env as other_env;
 use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process;
 use std::rc::Rc;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
 
-use crate::eval::eval;
-use crate::scan::resolve;
-
-#[macro_use]
-extern crate lalrpop_util;
+use bagl::debug::Debugger;
+use bagl::debug::Tracer;
+use bagl::elaborate::elaborate;
+use bagl::env::Env;
+use bagl::eval::Budget;
+use bagl::eval::Hook;
+use bagl::eval::Machine;
+use bagl::front::check;
+use bagl::front::finish;
+use bagl::gmachine;
+use bagl::gmachine::Machine as GMachine;
+use bagl::graph::call_graph;
+use bagl::infer::Checked;
+use bagl::infer::Type;
+use bagl::js::to_js;
+use bagl::kinds::check_declarations;
+use bagl::lift;
+use bagl::lsp;
+use bagl::names::check_names;
+use bagl::profile::Profiler;
+use bagl::scan::resolve;
+use bagl::simplify::simplify;
+use bagl::strict::analyze;
+use bagl::testing;
+use bagl::testing::compile;
+use bagl::testing::discover;
+use bagl::testing::Outcome;
+use bagl::unused::unused_warnings;
+use bagl::wasm::to_wat;
+use bagl::wasm::validate;
 
-lalrpop_mod!(pub gram); // synthesized by LALRPOP
+// eval recurses for everything but tail calls, so it gets a bigger stack than the main thread has
+const STACK_SIZE: usize = 512 << 20;
+// the most stack one level of eval was measured to use (loops, arithmetic, --strict) with some room to spare
+// a debug build doesn't reuse the space of locals so it needs a lot more
+const FRAME_SIZE: usize = if cfg!(debug_assertions) {
+    16 << 10
+} else {
+    1 << 10
+};
+// as deep as eval can go without running out of STACK_SIZE, past it is an error instead of an abort
+const DEFAULT_DEPTH: usize = STACK_SIZE / FRAME_SIZE;
+// each test gets this many steps unless told otherwise
+const TEST_STEPS: u64 = 10_000_000;
+// exit code for running out of the budget, so it can be told apart from other errors
+const EXHAUSTED: i32 = 2;
 
 fn main() {
     let args: Vec<String> = other_env::args().collect();
-    let filename = &args[1];
+    let worker = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || match args.get(1).map(|s| s.as_str()) {
+            Some("graph") => graph_command(&args[2..]),
+            Some("test") => test_command(&args[2..]),
+            Some("lsp") => lsp_command(),
+            Some("c") => c_command(&args[2..]),
+            Some("wat") => wat_command(&args[2..]),
+            Some("js") => js_command(&args[2..]),
+            Some("gm") => gm_command(&args[2..]),
+            Some(_) => run(&args[1..]),
+            None => usage(),
+        })
+        .expect("Couldn't start the evaluation thread.");
+    if worker.join().is_err() {
+        process::exit(101);
+    }
+}
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: bagl [--entry <name>] [budget] [--trace | --debug [--break <name>]... | --profile [--folded <file>]] <file>"
+    );
+    eprintln!("       bagl graph [--format dot|json] [--nested] <file>");
+    eprintln!("       bagl test [--max-steps <n>] [--max-depth <n>] [<file or directory>...]");
+    eprintln!("       bagl lsp             a language server over stdin and stdout");
+    eprintln!(
+        "       bagl c [--entry <name>] [-o <file>] <file>   compile to C, to stdout without -o"
+    );
+    eprintln!(
+        "       bagl js [--entry <name>] [-o <file>] <file>   compile to an ES module, to stdout without -o"
+    );
+    eprintln!(
+        "       bagl gm [--entry <name>] [--code] <file>   run on the G-machine, --code prints the supercombinators instead"
+    );
+    eprintln!(
+        "       bagl wat [--entry <name>] [-o <file>] [--check] <file>   compile to WebAssembly text, --check only validates it"
+    );
+    eprintln!();
+    eprintln!(
+        "budget, evaluation stops with exit code {} when it runs out:",
+        EXHAUSTED
+    );
+    eprintln!("       --max-steps <n>      reduction steps");
+    eprintln!("       --timeout <seconds>  wall clock time");
+    eprintln!("       --max-heap <bytes>   approximate allocation, can end in k, m, or g");
+    eprintln!(
+        "       --max-depth <n>      nesting of eval, {} by default",
+        DEFAULT_DEPTH
+    );
+    eprintln!();
+    eprintln!("watching evaluation:");
+    eprintln!("       --trace              print every reduction step to stderr");
+    eprintln!("       --debug              step through the reductions, help lists the commands");
+    eprintln!("       --break <name>       stop the debugger when the definition is looked up");
+    eprintln!(
+        "       --profile            report the steps, allocations, and time of each definition"
+    );
+    eprintln!("       --folded <file>      where the profile's folded stacks go, <file>.folded by default");
+    eprintln!();
+    eprintln!("passes before evaluation:");
+    eprintln!("       --opt                simplify the program first, folding constants and inlining small definitions");
+    eprintln!("       --strict             evaluate lets and arguments that are always forced right away instead of making thunks");
+    eprintln!("       --dump=<pass>        print the program after free, closures, lift, anf, opt, or strict to stderr and run it");
+    process::exit(1);
+}
+
+// the value after a flag
+fn flag_value<T: FromStr>(args: &[String], i: usize) -> T {
+    match args.get(i).and_then(|s| s.parse().ok()) {
+        Some(value) => value,
+        None => usage(),
+    }
+}
+
+// a number of bytes with an optional k, m, or g suffix
+fn bytes(arg: &str) -> Option<usize> {
+    let lower = arg.to_lowercase();
+    let (digits, scale) = match lower.chars().last()? {
+        'k' => (&lower[..lower.len() - 1], 1 << 10),
+        'm' => (&lower[..lower.len() - 1], 1 << 20),
+        'g' => (&lower[..lower.len() - 1], 1 << 30),
+        _ => (&lower[..], 1),
+    };
+    digits.parse::<usize>().ok()?.checked_mul(scale)
+}
+
+fn run(args: &[String]) {
+    let mut entry = "main";
+    let mut filename = None;
+    let mut budget = Budget {
+        depth: Some(DEFAULT_DEPTH),
+        ..Budget::default()
+    };
+    let mut trace = false;
+    let mut debug = false;
+    let mut breakpoints = Vec::new();
+    let mut profile = false;
+    let mut folded = None;
+    let mut dump = None;
+    let mut opt = false;
+    let mut strict = false;
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--dump=free" | "--dump=closures" | "--dump=lift" | "--dump=anf" | "--dump=opt"
+            | "--dump=strict" => dump = Some(args[i]["--dump=".len()..].to_string()),
+            "--opt" => opt = true,
+            "--strict" => strict = true,
+            "--entry" => {
+                i += 1;
+                match args.get(i) {
+                    Some(name) => entry = name,
+                    None => usage(),
+                }
+            }
+            "--max-steps" => {
+                i += 1;
+                budget.steps = Some(flag_value(args, i));
+            }
+            "--timeout" => {
+                i += 1;
+                let seconds: f64 = flag_value(args, i);
+                match Duration::try_from_secs_f64(seconds) {
+                    Ok(timeout) => budget.timeout = Some(timeout),
+                    Err(_) => usage(),
+                }
+            }
+            "--max-heap" => {
+                i += 1;
+                match args.get(i).and_then(|arg| bytes(arg)) {
+                    Some(heap) => budget.heap = Some(heap),
+                    None => usage(),
+                }
+            }
+            "--max-depth" => {
+                i += 1;
+                budget.depth = Some(flag_value(args, i));
+            }
+            "--trace" => trace = true,
+            "--debug" => debug = true,
+            "--break" => {
+                i += 1;
+                match args.get(i) {
+                    Some(name) => breakpoints.push(name.to_string()),
+                    None => usage(),
+                }
+                debug = true;
+            }
+            "--profile" => profile = true,
+            "--folded" => {
+                i += 1;
+                match args.get(i) {
+                    Some(name) => folded = Some(name.to_string()),
+                    None => usage(),
+                }
+                profile = true;
+            }
+            name => filename = Some(name),
+        }
+        i += 1;
+    }
+    let filename = match filename {
+        Some(name) => name,
+        None => usage(),
+    };
+    // only one of them can watch the evaluation
+    if [trace, debug, profile].iter().filter(|&&b| b).count() > 1 {
+        usage();
+    }
     let source = fs::read_to_string(filename).expect("Couldn't read file.");
 
-    // let str = "Bool = True | False; Maybe a = Some a | None; List a = Cons a (List a) | Nil; head = (\\ x . case x {Cons a as -> Some a; Nil -> None}); not = (\\x . case x {True -> False; False -> True}); main = (head (Nil))";
-    let parse = gram::TopParser::new().parse(&source).unwrap();
-    let expr = parse.to_let();
-    resolve(Rc::clone(&expr), 0);
-    let expr = change_lets(expr);
+    let (_, expr) = front_end(filename, &source, entry);
+    // the passes print what they did and the program runs the way they left it
+    let expr = match dump.as_deref() {
+        Some("free") => {
+            eprint!("{}", lift::show_free_vars(&lift::lambda_free_vars(&expr)));
+            expr
+        }
+        Some("closures") => {
+            let expr = lift::closure_convert(&expr);
+            dumped(lift::listing(&expr), expr)
+        }
+        Some("lift") => {
+            let expr = lift::lambda_lift(&expr);
+            dumped(lift::listing(&expr), expr)
+        }
+        Some("anf") => {
+            let term = anf::lower(&expr);
+            dumped(term.to_string(), term.to_expr())
+        }
+        Some("opt") => {
+            let (term, report) = simplify(&anf::lower(&expr));
+            dumped(format!("{}{}", report, term), term.to_expr())
+        }
+        _ if opt => {
+            let (term, _) = simplify(&anf::lower(&expr));
+            let expr = term.to_expr();
+            resolve(Rc::clone(&expr));
+            expr
+        }
+        _ => expr,
+    };
     // println!("{}", expr);
     // println!("{}", expr);
     // let env = Rc::new(parse.to_env());
     // let expr = Rc::new(Expr::Var("main".to_string(), RefCell::new(1)));
     // println!("environment:\n\t{}\nexpr:\n\t{}", env, expr);
     // println!("{}", eval(expr, env));
-    println!("{}", eval(expr, Rc::new(Env::Empty), Vec::new()));
+    let mut profiler = if profile {
+        Some(Profiler::new(&expr))
+    } else {
+        None
+    };
+    let mut watcher: Option<Box<dyn Hook>> = if trace {
+        Some(Box::new(Tracer::new(io::stderr())))
+    } else if debug {
+        let debugger = Debugger::new(io::stdin().lock(), io::stderr(), breakpoints);
+        Some(Box::new(debugger))
+    } else {
+        None
+    };
+    let strictness = if strict || dump.as_deref() == Some("strict") {
+        let strictness = analyze(&expr);
+        if dump.as_deref() == Some("strict") {
+            eprint!("{}", strictness);
+        }
+        Some(strictness)
+    } else {
+        None
+    };
+    let mut machine = match (&mut profiler, &mut watcher) {
+        (Some(profiler), _) => Machine::with_hook(budget, profiler),
+        (_, Some(watcher)) => Machine::with_hook(budget, watcher.as_mut()),
+        _ => Machine::new(budget),
+    };
+    if let Some(strictness) = &strictness {
+        machine = machine.with_strictness(strictness);
+    }
+    let result = machine.eval(expr, Rc::new(Env::new()), Vec::new());
+    let stats = machine.stats();
+    if let Some(profiler) = &mut profiler {
+        // a program that ran out of its budget still gets its profile
+        profiler.finish(&stats);
+        let folded = folded.unwrap_or_else(|| format!("{}.folded", filename));
+        eprint!("{}", profiler.report());
+        match fs::write(&folded, profiler.folded()) {
+            Ok(()) => eprintln!("folded stacks written to {}", folded),
+            Err(error) => eprintln!("{}: error: couldn't write {}, {}", filename, folded, error),
+        }
+    }
+    match result {
+        Ok(value) => println!("{}", value),
+        Err(exhausted) => {
+            eprintln!("{}: error: {}", filename, exhausted);
+            process::exit(EXHAUSTED);
+        }
+    }
     // println!("{}", expr);
 }
+
+// a transformed program has to be resolved again before it runs
+fn dumped(listing: String, expr: Rc<Expr>) -> Rc<Expr> {
+    eprint!("{}", listing);
+    resolve(Rc::clone(&expr));
+    expr
+}
+
+// everything before evaluation, errors are printed and end the program
+// gives back the declarations and the program with every variable resolved
+fn front_end(filename: &str, source: &str, entry: &str) -> (Toplevel, Rc<Expr>) {
+    let (parse, expr, checked) = checked(filename, source, entry, false);
+    let expr = finish(&parse, &expr, &checked);
+    (parse, expr)
+}
+
+// the checked program with its dictionaries, still one letrec of every definition
+fn elaborated(filename: &str, source: &str, entry: &str, library: bool) -> (Toplevel, Rc<Expr>) {
+    let (parse, expr, checked) = checked(filename, source, entry, library);
+    let expr = elaborate(&expr, &checked.elaboration, &parse);
+    (parse, expr)
+}
+
+// the program and its types, main is wrapped in show when it isn't a string
+// a library keeps all of its definitions and doesn't need the entry, without it the body is undefined
+fn checked(
+    filename: &str,
+    source: &str,
+    entry: &str,
+    library: bool,
+) -> (Toplevel, Rc<Expr>, Checked) {
+    // let str = "Bool = True | False; Maybe a = Some a | None; List a = Cons a (List a) | Nil; head = (\\ x . case x {Cons a as -> Some a; Nil -> None}); not = (\\x . case x {True -> False; False -> True}); main = (head (Nil))";
+    let parse = parse(filename, source);
+    if let Err(errors) = check_declarations(&parse) {
+        for error in &errors {
+            eprintln!("{}", error.render(filename, source));
+        }
+        process::exit(1);
+    }
+    let defined = parse.defs.iter().any(|d| d.name() == entry);
+    let mut expr = parse.to_let_entry(entry);
+    if library && !defined {
+        if let Expr::LetRec(vars, defs, _) = &*expr {
+            expr = Rc::new(Expr::LetRec(
+                vars.to_vec(),
+                defs.to_vec(),
+                Rc::new(Expr::Bottom),
+            ));
+        }
+    }
+    // everything in a library is used by whoever imports it, the warnings are only worth it once the names are right
+    if !library && check_names(&expr).is_empty() {
+        for warning in unused_warnings(&expr) {
+            eprintln!("{}", warning.render(filename, source));
+        }
+    }
+    let checked = match check(&parse, &expr) {
+        Ok(checked) => checked,
+        Err(errors) => {
+            for error in &errors {
+                eprintln!("{}", error.render(filename, source));
+            }
+            process::exit(1);
+        }
+    };
+    let mut checked = checked;
+    let expr = shown(&expr, &mut checked, entry).unwrap_or(expr);
+    (parse, expr, checked)
+}
+
+// print the result with show if there is an instance for it, a string is printed as it is
+// the dictionary comes from the instances the program was checked with, the new show is elaborated with it
+fn shown(expr: &Rc<Expr>, checked: &mut Checked, entry: &str) -> Option<Rc<Expr>> {
+    let typ = checked
+        .bindings
+        .iter()
+        .find(|b| b.toplevel && b.name == entry)?
+        .scheme
+        .typ
+        .clone();
+    if typ == Type::Con("Str".to_string()) {
+        return None;
+    }
+    let dict = checked.dictionary("Show", &typ)?;
+    match &**expr {
+        Expr::LetRec(vars, defs, body) => {
+            let show = var("show");
+            checked.elaboration.methods.insert(Rc::as_ptr(&show), dict);
+            let body = app(show, vec![Rc::clone(body)]);
+            Some(Rc::new(Expr::LetRec(vars.to_vec(), defs.to_vec(), body)))
+        }
+        _ => None,
+    }
+}
+
+fn parse(filename: &str, source: &str) -> Toplevel {
+    match try_parse(filename, source) {
+        Ok(parse) => parse,
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(1);
+        }
+    }
+}
+
+// errors from the grammar actions already have a location, the rest are from lalrpop
+fn try_parse(filename: &str, source: &str) -> Result<Toplevel, String> {
+    match gram::TopParser::new().parse(source) {
+        Ok(parse) => Ok(parse),
+        Err(ParseError::User { error }) => Err(error.render(filename, source)),
+        Err(error) => Err(format!("{}: error: {}", filename, error)),
+    }
+}
+
+// print the dependency graph of the definitions instead of running the program
+fn graph_command(args: &[String]) {
+    let mut format = "dot";
+    let mut nested = false;
+    let mut filename = None;
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--format" => {
+                i += 1;
+                match args.get(i).map(|s| s.as_str()) {
+                    Some("dot") => format = "dot",
+                    Some("json") => format = "json",
+                    _ => usage(),
+                }
+            }
+            "--nested" => nested = true,
+            name => filename = Some(name),
+        }
+        i += 1;
+    }
+    let filename = match filename {
+        Some(name) => name,
+        None => usage(),
+    };
+    let source = fs::read_to_string(filename).expect("Couldn't read file.");
+    let parse = parse(filename, &source);
+    let graph = call_graph(&parse.to_let(), nested);
+    if format == "json" {
+        print!("{}", graph.to_json());
+    } else {
+        print!("{}", graph.to_dot());
+    }
+}
+
+// run the tests in the files, or in the .bagl files under the directories
+fn test_command(args: &[String]) {
+    let mut budget = Budget {
+        steps: Some(TEST_STEPS),
+        depth: Some(DEFAULT_DEPTH),
+        ..Budget::default()
+    };
+    let mut paths = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--max-steps" => {
+                i += 1;
+                budget.steps = Some(flag_value(args, i));
+            }
+            "--max-depth" => {
+                i += 1;
+                budget.depth = Some(flag_value(args, i));
+            }
+            path => paths.push(PathBuf::from(path)),
+        }
+        i += 1;
+    }
+    if paths.is_empty() {
+        paths.push(PathBuf::from("."));
+    }
+    let mut files = Vec::new();
+    for path in &paths {
+        if let Err(error) = bagl_files(path, &mut files) {
+            eprintln!("{}: error: {}", path.display(), error);
+            process::exit(1);
+        }
+    }
+
+    let mut passed = 0;
+    let mut failed = 0;
+    for file in &files {
+        let filename = file.display().to_string();
+        let source = match fs::read_to_string(file) {
+            Ok(source) => source,
+            Err(error) => {
+                eprintln!("{}: error: {}", filename, error);
+                failed += 1;
+                continue;
+            }
+        };
+        let parse = match try_parse(&filename, &source) {
+            Ok(parse) => parse,
+            Err(error) => {
+                eprintln!("{}", error);
+                failed += 1;
+                continue;
+            }
+        };
+        let tests = match discover(&parse) {
+            Ok(tests) => tests,
+            Err(errors) => {
+                for error in &errors {
+                    eprintln!("{}", error.render(&filename, &source));
+                }
+                failed += 1;
+                continue;
+            }
+        };
+        if tests.is_empty() {
+            continue;
+        }
+        let plural = if tests.len() == 1 { "" } else { "s" };
+        println!("running {} test{} in {}", tests.len(), plural, filename);
+        let mut failures = Vec::new();
+        for test in &tests {
+            let outcome = match compile(&parse, test) {
+                Ok(expr) => testing::run(expr, budget),
+                Err(errors) => Outcome::Failed(
+                    errors
+                        .iter()
+                        .map(|e| e.render(&filename, &source))
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                ),
+            };
+            match outcome {
+                Outcome::Passed => {
+                    println!("test {} ... ok", test.name);
+                    passed += 1;
+                }
+                Outcome::Failed(message) => {
+                    println!("test {} ... FAILED", test.name);
+                    let (line, col) = test.span.line_col(&source);
+                    failures.push((format!("{}:{}:{}", filename, line, col), test, message));
+                    failed += 1;
+                }
+            }
+        }
+        println!();
+        // the failures are printed with their file so they don't get lost among the other files
+        if !failures.is_empty() {
+            println!("failures:");
+            for (location, test, message) in failures {
+                println!();
+                println!("---- {} ({}) ----", test.name, location);
+                println!("{}", message);
+            }
+            println!();
+        }
+    }
+    let result = if failed == 0 { "ok" } else { "FAILED" };
+    println!(
+        "test result: {}. {} passed; {} failed",
+        result, passed, failed
+    );
+    if failed > 0 {
+        process::exit(1);
+    }
+}
+
+// a file is taken as it is, directories are searched for .bagl files in sorted order
+fn bagl_files(path: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+    let mut entries: Vec<PathBuf> = fs::read_dir(path)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<io::Result<_>>()?;
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() {
+            bagl_files(&entry, files)?;
+        } else if entry.extension().is_some_and(|ext| ext == "bagl") {
+            files.push(entry);
+        }
+    }
+    Ok(())
+}
+
+// serve the language server protocol until the client says to exit
+fn lsp_command() {
+    match lsp::serve(io::stdin().lock(), io::stdout()) {
+        Ok(true) => process::exit(0),
+        // exiting without a shutdown first is an error by the protocol
+        Ok(false) => process::exit(1),
+        Err(error) => {
+            eprintln!("bagl lsp: error: {}", error);
+            process::exit(1);
+        }
+    }
+}
+
+// write the program as C instead of running it
+fn c_command(args: &[String]) {
+    let mut entry = "main";
+    let mut output = None;
+    let mut filename = None;
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--entry" => {
+                i += 1;
+                match args.get(i) {
+                    Some(name) => entry = name,
+                    None => usage(),
+                }
+            }
+            "-o" => {
+                i += 1;
+                match args.get(i) {
+                    Some(name) => output = Some(name),
+                    None => usage(),
+                }
+            }
+            name => filename = Some(name),
+        }
+        i += 1;
+    }
+    let filename = match filename {
+        Some(name) => name,
+        None => usage(),
+    };
+    let source = fs::read_to_string(filename).expect("Couldn't read file.");
+    let (parse, expr) = front_end(filename, &source, entry);
+    let program = to_c(&expr, &parse);
+    match output {
+        Some(output) => {
+            if let Err(error) = fs::write(output, program) {
+                eprintln!("{}: error: couldn't write {}, {}", filename, output, error);
+                process::exit(1);
+            }
+        }
+        None => print!("{}", program),
+    }
+}
+
+// every definition is exported, the entry is optional
+fn js_command(args: &[String]) {
+    let mut entry = "main";
+    let mut output = None;
+    let mut filename = None;
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--entry" => {
+                i += 1;
+                match args.get(i) {
+                    Some(name) => entry = name,
+                    None => usage(),
+                }
+            }
+            "-o" => {
+                i += 1;
+                match args.get(i) {
+                    Some(name) => output = Some(name),
+                    None => usage(),
+                }
+            }
+            name => filename = Some(name),
+        }
+        i += 1;
+    }
+    let filename = match filename {
+        Some(name) => name,
+        None => usage(),
+    };
+    let source = fs::read_to_string(filename).expect("Couldn't read file.");
+    let (parse, expr) = elaborated(filename, &source, entry, true);
+    let module = to_js(&expr, &parse);
+    match output {
+        Some(output) => {
+            if let Err(error) = fs::write(output, module) {
+                eprintln!("{}: error: couldn't write {}, {}", filename, output, error);
+                process::exit(1);
+            }
+        }
+        None => print!("{}", module),
+    }
+}
+
+fn wat_command(args: &[String]) {
+    let mut entry = "main";
+    let mut output = None;
+    let mut filename = None;
+    let mut check = false;
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--entry" => {
+                i += 1;
+                match args.get(i) {
+                    Some(name) => entry = name,
+                    None => usage(),
+                }
+            }
+            "-o" => {
+                i += 1;
+                match args.get(i) {
+                    Some(name) => output = Some(name),
+                    None => usage(),
+                }
+            }
+            "--check" => check = true,
+            name => filename = Some(name),
+        }
+        i += 1;
+    }
+    let filename = match filename {
+        Some(name) => name,
+        None => usage(),
+    };
+    let source = fs::read_to_string(filename).expect("Couldn't read file.");
+    let (parse, expr) = front_end(filename, &source, entry);
+    let module = to_wat(&expr, &parse);
+    if check {
+        if let Err(error) = validate(&module) {
+            eprintln!("{}: error: the module isn't valid, {}", filename, error);
+            process::exit(1);
+        }
+        if output.is_none() {
+            println!("{}: the module is valid", filename);
+            return;
+        }
+    }
+    match output {
+        Some(output) => {
+            if let Err(error) = fs::write(output, module) {
+                eprintln!("{}: error: couldn't write {}, {}", filename, output, error);
+                process::exit(1);
+            }
+        }
+        None => print!("{}", module),
+    }
+}
+
+// the same program as running it with eval, lambda lifted and reduced on the G-machine
+fn gm_command(args: &[String]) {
+    let mut entry = "main";
+    let mut filename = None;
+    let mut code = false;
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--entry" => {
+                i += 1;
+                match args.get(i) {
+                    Some(name) => entry = name,
+                    None => usage(),
+                }
+            }
+            "--code" => code = true,
+            name => filename = Some(name),
+        }
+        i += 1;
+    }
+    let filename = match filename {
+        Some(name) => name,
+        None => usage(),
+    };
+    let source = fs::read_to_string(filename).expect("Couldn't read file.");
+    let (parse, expr) = front_end(filename, &source, entry);
+    let program = gmachine::compile(&expr, &parse);
+    if code {
+        print!("{}", program);
+        return;
+    }
+    println!("{}", GMachine::new(&program).run());
+}
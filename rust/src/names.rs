@@ -0,0 +1,169 @@
+/*
+
+make sure every name refers to something before evaluating
+
+without this a typo is only found when eval fails to look up the variable, and only if that branch actually runs
+
+walk the letrec from Toplevel::to_let keeping track of what is in scope
+    lambdas add their parameter
+    lets and letrecs add their variables, for a let only the body can see them
+    case branches add the variables from their pattern
+
+constructors are the toplevel variables defined as data, patterns can only use those
+anything starting with an uppercase letter is treated as a constructor
+
+for every unbound name look for close names in scope to suggest, using edit distance
+
+*/
+
+use crate::ast::Expr;
+use crate::ast::Pattern;
+use crate::ast::Span;
+use crate::diagnostic::Diagnostic;
+use std::rc::Rc;
+
+// all of the unbound variables and unknown constructors
+pub fn check_names(expr: &Rc<Expr>) -> Vec<Diagnostic> {
+    let mut errors = Vec::new();
+    let mut constructors = Vec::new();
+    if let Expr::LetRec(vars, defs, _) = &**expr {
+        for (var, def) in vars.iter().zip(defs) {
+            if let (Expr::Var(s, _, _), Expr::Data(..)) = (&**var, &**def) {
+                constructors.push(s.to_string());
+            }
+        }
+    }
+    let mut scope = Vec::new();
+    walk(expr, &mut scope, &constructors, &mut errors);
+    errors
+}
+
+fn walk(
+    expr: &Rc<Expr>,
+    scope: &mut Vec<String>,
+    constructors: &[String],
+    errors: &mut Vec<Diagnostic>,
+) {
+    match &**expr {
+        Expr::Var(s, _, span) if !scope.contains(s) => {
+            if is_constructor(s) {
+                errors.push(unbound("unknown constructor", s, *span, constructors));
+            } else {
+                errors.push(unbound("unbound variable", s, *span, scope));
+            }
+        }
+        Expr::Lam(head, body) => {
+            scope.push(name(head));
+            walk(body, scope, constructors, errors);
+            scope.pop();
+        }
+        Expr::App(left, right) => {
+            walk(left, scope, constructors, errors);
+            walk(right, scope, constructors, errors);
+        }
+        Expr::If(cond, b1, b2) => {
+            walk(cond, scope, constructors, errors);
+            walk(b1, scope, constructors, errors);
+            walk(b2, scope, constructors, errors);
+        }
+        Expr::Let(vars, defs, body) => {
+            for def in defs {
+                walk(def, scope, constructors, errors);
+            }
+            scope.extend(vars.iter().map(name));
+            walk(body, scope, constructors, errors);
+            scope.truncate(scope.len() - vars.len());
+        }
+        Expr::LetRec(vars, defs, body) => {
+            scope.extend(vars.iter().map(name));
+            for def in defs {
+                walk(def, scope, constructors, errors);
+            }
+            walk(body, scope, constructors, errors);
+            scope.truncate(scope.len() - vars.len());
+        }
+        Expr::Case(cond, pats, branches, spans) => {
+            walk(cond, scope, constructors, errors);
+            for i in 0..pats.len() {
+                if let Pattern::Construct(cons, _) = &pats[i] {
+                    if !constructors.contains(cons) {
+                        let span = spans.get(i).copied().unwrap_or_default();
+                        errors.push(unbound("unknown constructor", cons, span, constructors));
+                    }
+                }
+                let vars = pats[i].vars();
+                scope.extend(vars.iter().map(|v| v.to_string()));
+                walk(&branches[i], scope, constructors, errors);
+                scope.truncate(scope.len() - vars.len());
+            }
+        }
+        Expr::Annot(inner, _) => walk(inner, scope, constructors, errors),
+        _ => (),
+    }
+}
+
+// an error for a name that isn't defined, suggesting close names that are
+pub fn unbound(problem: &str, name: &str, span: Span, candidates: &[String]) -> Diagnostic {
+    let error = Diagnostic::error(span, format!("{} `{}`", problem, name));
+    let suggestions = suggest(name, candidates);
+    if suggestions.is_empty() {
+        error
+    } else {
+        let quoted: Vec<String> = suggestions.iter().map(|s| format!("`{}`", s)).collect();
+        error.with_help(format!("did you mean {}?", quoted.join(" or ")))
+    }
+}
+
+// the closest few names, only counting ones that are close enough to be a typo
+fn suggest(name: &str, candidates: &[String]) -> Vec<String> {
+    let limit = std::cmp::max(1, name.chars().count() / 3);
+    let mut close = Vec::new();
+    for candidate in candidates {
+        if is_constructor(candidate) != is_constructor(name) || candidate.starts_with('_') {
+            continue;
+        }
+        let distance = edit_distance(name, candidate);
+        if distance <= limit && !close.iter().any(|(_, c)| c == candidate) {
+            close.push((distance, candidate.to_string()));
+        }
+    }
+    close.sort();
+    close.into_iter().take(3).map(|(_, c)| c).collect()
+}
+
+// edit distance counting swapping two neighbouring characters as one edit, since that is a common typo
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut table = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in table.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in table[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            let mut best = (table[i - 1][j - 1] + cost)
+                .min(table[i - 1][j] + 1)
+                .min(table[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(table[i - 2][j - 2] + 1);
+            }
+            table[i][j] = best;
+        }
+    }
+    table[a.len()][b.len()]
+}
+
+fn is_constructor(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| c.is_uppercase())
+}
+
+fn name(var: &Rc<Expr>) -> String {
+    match &**var {
+        Expr::Var(s, _, _) => s.to_string(),
+        _ => panic!("Can only define variables."),
+    }
+}
@@ -51,42 +51,14 @@ pub fn change_lets(expr: Rc<Expr>) -> Rc<Expr> {
             }
             let defs = defs_new;
 
-            let mut names = Vec::new();
-            let mut assoc = HashMap::new();
-            let mut self_rec = Vec::new();
-
-            for var in vars {
-                if let Expr::Var(s, _) = Rc::deref(&var) {
-                    names.push(s.to_string());
-                    assoc.insert(s, names.len() - 1);
-                }
-            }
-
-            let mut graph = HashMap::new();
-
-            for i in 0..defs.len() {
-                let depends = dependencies(&names, Rc::clone(&defs[i]), Vec::new());
-                if depends.contains(&names[i]) {
-                    self_rec.push(i);
-                }
-                let mut depends_index = Vec::new();
-                for d in depends {
-                    if let Some(v) = assoc.get(&d) {
-                        depends_index.push(*v);
-                    }
-                }
-                if let Some(v) = assoc.get(&names[i]) {
-                    graph.insert(*v, depends_index);
-                }
-            }
-            let mut organized = Vec::new();
-            tarjan(&graph, &mut organized);
+            let graph = dependency_graph(vars, &defs);
+            let mut organized = graph.components();
             organized.reverse(); // order to build let ast out of
             let mut expr = change_lets(Rc::clone(body));
             for cycle in organized {
                 if cycle.len() == 1 {
                     let index = cycle[0];
-                    if self_rec.contains(&index) {
+                    if graph.self_rec.contains(&index) {
                         expr = Rc::new(Expr::LetRec(
                             vec![Rc::clone(&vars[index])],
                             vec![Rc::clone(&defs[index])],
@@ -124,7 +96,7 @@ pub fn change_lets(expr: Rc<Expr>) -> Rc<Expr> {
             change_lets(Rc::clone(b1)),
             change_lets(Rc::clone(b2)),
         )),
-        Expr::Case(cond, pats, defs) => {
+        Expr::Case(cond, pats, defs, spans) => {
             let mut new_defs = Vec::new();
             for def in defs {
                 new_defs.push(change_lets(Rc::clone(def)));
@@ -133,18 +105,87 @@ pub fn change_lets(expr: Rc<Expr>) -> Rc<Expr> {
                 change_lets(Rc::clone(cond)),
                 pats.to_vec(),
                 new_defs,
+                spans.to_vec(),
             ))
         }
+        Expr::Annot(inner, typ) => Rc::new(Expr::Annot(change_lets(Rc::clone(inner)), typ.clone())),
         _ => expr,
     }
 }
 
-fn dependencies(names: &Vec<String>, def: Rc<Expr>, acc: Vec<String>) -> Vec<String> {
+// the dependency graph of the definitions in a single letrec
+// nodes are the positions of the variables and an edge points from a definition to what it uses
+pub struct DepGraph {
+    pub names: Vec<String>,
+    pub edges: HashMap<usize, Vec<usize>>,
+    pub self_rec: Vec<usize>,
+}
+
+impl DepGraph {
+    // strongly connected components, a component comes after the ones it depends on
+    pub fn components(&self) -> Vec<Vec<usize>> {
+        let mut organized = Vec::new();
+        tarjan(&self.edges, &mut organized);
+        organized
+    }
+}
+
+pub fn dependency_graph(vars: &[Rc<Expr>], defs: &[Rc<Expr>]) -> DepGraph {
+    let mut names = Vec::new();
+    let mut assoc = HashMap::new();
+    let mut self_rec = Vec::new();
+
+    for var in vars {
+        if let Expr::Var(s, _, _) = Rc::deref(var) {
+            names.push(s.to_string());
+            assoc.insert(s.to_string(), names.len() - 1);
+        }
+    }
+
+    let mut edges = HashMap::new();
+
+    for i in 0..defs.len() {
+        let depends = dependencies(&names, Rc::clone(&defs[i]), Vec::new());
+        if depends.contains(&names[i]) {
+            self_rec.push(i);
+        }
+        let mut depends_index = Vec::new();
+        for d in depends {
+            if let Some(v) = assoc.get(&d) {
+                if !depends_index.contains(v) {
+                    depends_index.push(*v);
+                }
+            }
+        }
+        if let Some(v) = assoc.get(&names[i]) {
+            edges.insert(*v, depends_index);
+        }
+    }
+
+    DepGraph {
+        names,
+        edges,
+        self_rec,
+    }
+}
+
+pub fn dependencies(names: &[String], def: Rc<Expr>, acc: Vec<String>) -> Vec<String> {
     // look through def and see if any variables match a name
-    // probably has issues with shadowing, will ignore for now (probably want to make shadowing an error anyways)
+    let mut bound = Vec::new();
+    free_dependencies(names, def, &mut bound, acc)
+}
+
+// only a variable that isn't bound between the definition and its use is a dependency
+// bound is used like a stack, binders are pushed when entering their scope and popped when leaving
+fn free_dependencies(
+    names: &[String],
+    def: Rc<Expr>,
+    bound: &mut Vec<String>,
+    acc: Vec<String>,
+) -> Vec<String> {
     match &*def {
-        Expr::Var(s, _) => {
-            if names.contains(s) {
+        Expr::Var(s, _, _) => {
+            if names.contains(s) && !bound.contains(s) {
                 let mut acc = acc;
                 acc.push(s.to_string());
                 acc
@@ -152,41 +193,64 @@ fn dependencies(names: &Vec<String>, def: Rc<Expr>, acc: Vec<String>) -> Vec<Str
                 acc
             }
         }
-        Expr::Lam(_, body) => dependencies(names, Rc::clone(body), acc),
+        Expr::Lam(head, body) => {
+            bound.push(var_name(head));
+            let acc = free_dependencies(names, Rc::clone(body), bound, acc);
+            bound.pop();
+            acc
+        }
         Expr::App(left, right) => {
-            let left_acc = dependencies(names, Rc::clone(left), acc);
-            dependencies(names, Rc::clone(right), left_acc)
+            let left_acc = free_dependencies(names, Rc::clone(left), bound, acc);
+            free_dependencies(names, Rc::clone(right), bound, left_acc)
         }
         Expr::If(cond, b1, b2) => {
-            let cond_acc = dependencies(names, Rc::clone(cond), acc);
-            let b1_acc = dependencies(names, Rc::clone(b1), cond_acc);
-            dependencies(names, Rc::clone(b2), b1_acc)
+            let cond_acc = free_dependencies(names, Rc::clone(cond), bound, acc);
+            let b1_acc = free_dependencies(names, Rc::clone(b1), bound, cond_acc);
+            free_dependencies(names, Rc::clone(b2), bound, b1_acc)
         }
-        Expr::Case(cond, _, branches) => {
-            let mut acc = acc;
-            for branch in branches {
-                acc = dependencies(names, Rc::clone(branch), acc);
+        Expr::Case(cond, pats, branches, _) => {
+            let mut acc = free_dependencies(names, Rc::clone(cond), bound, acc);
+            for (pat, branch) in pats.iter().zip(branches) {
+                let vars = pat.vars();
+                bound.extend(vars.iter().map(|v| v.to_string()));
+                acc = free_dependencies(names, Rc::clone(branch), bound, acc);
+                bound.truncate(bound.len() - vars.len());
             }
-            dependencies(names, Rc::clone(cond), acc)
+            acc
         }
-        Expr::Let(_, defs, body) => {
+        Expr::Let(vars, defs, body) => {
+            // the definitions can't see the variables being defined
             let mut acc = acc;
             for def in defs {
-                acc = dependencies(names, Rc::clone(def), acc);
+                acc = free_dependencies(names, Rc::clone(def), bound, acc);
             }
-            dependencies(names, Rc::clone(body), acc)
+            bound.extend(vars.iter().map(var_name));
+            acc = free_dependencies(names, Rc::clone(body), bound, acc);
+            bound.truncate(bound.len() - vars.len());
+            acc
         }
-        Expr::LetRec(_, defs, body) => {
+        Expr::LetRec(vars, defs, body) => {
             let mut acc = acc;
+            bound.extend(vars.iter().map(var_name));
             for def in defs {
-                acc = dependencies(names, Rc::clone(def), acc);
+                acc = free_dependencies(names, Rc::clone(def), bound, acc);
             }
-            dependencies(names, Rc::clone(body), acc)
+            acc = free_dependencies(names, Rc::clone(body), bound, acc);
+            bound.truncate(bound.len() - vars.len());
+            acc
         }
+        Expr::Annot(inner, _) => free_dependencies(names, Rc::clone(inner), bound, acc),
         _ => acc,
     }
 }
 
+fn var_name(var: &Rc<Expr>) -> String {
+    match Rc::deref(var) {
+        Expr::Var(s, _, _) => s.to_string(),
+        _ => panic!("Can only define variables."),
+    }
+}
+
 fn min(a: usize, b: usize) -> usize {
     if a <= b {
         a
@@ -201,7 +265,10 @@ fn tarjan(graph: &HashMap<usize, Vec<usize>>, result: &mut Vec<Vec<usize>>) {
     let mut lowlinks = HashMap::new();
     let mut index = HashMap::new();
 
-    for node in graph.keys() {
+    // in order so the same program always gets split up the same way
+    let mut nodes: Vec<&usize> = graph.keys().collect();
+    nodes.sort();
+    for node in nodes {
         if !lowlinks.contains_key(node) {
             strongconnect(
                 graph,
@@ -0,0 +1,243 @@
+/*
+
+records are just constructors whose arguments have names
+
+    Person = Person { name : Str, age : Int }
+
+is the same as Person = Person Str Int, except there is also an accessor for each field
+    name = \r . case r {Person _0 _1 -> _0}
+    age = \r . case r {Person _0 _1 -> _1}
+the accessors are made with the constructor definitions (DataInfo::to_accessors)
+
+everything else is turned into the positional version once the whole file is parsed, since a declaration can come after it is used
+
+construction by field name puts the fields in the declared order
+    Person { age = 30, name = "bob" }
+    Person "bob" 30
+
+update matches on the record and rebuilds it with the new fields
+    p { age = 31 }
+    case p {Person _0 _1 -> Person _0 31}
+if only some constructors of the type have the fields then the others are an error at runtime
+
+patterns can pun the fields, or give the variable a different name
+    case p {Person { name, age = a } -> ...}
+    case p {Person name a -> ...}
+fields that aren't mentioned are wildcards
+
+the generated variables start with _ and a digit so they can't clash with anything written in the source
+
+*/
+
+use crate::ast::Expr;
+use crate::ast::Pattern;
+use crate::ast::Span;
+use crate::diagnostic::Diagnostic;
+use crate::info::DataInfo;
+use crate::info::ProdInfo;
+use crate::names::unbound;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// replace every record construction, update, and pattern
+pub fn desugar(expr: &Rc<Expr>, types: &[DataInfo]) -> Result<Rc<Expr>, Diagnostic> {
+    let desugared = match &**expr {
+        Expr::Lam(head, body) => Expr::Lam(Rc::clone(head), desugar(body, types)?),
+        Expr::App(left, right) => Expr::App(desugar(left, types)?, desugar(right, types)?),
+        Expr::Let(vars, defs, body) => Expr::Let(
+            vars.clone(),
+            desugar_all(defs, types)?,
+            desugar(body, types)?,
+        ),
+        Expr::LetRec(vars, defs, body) => Expr::LetRec(
+            vars.clone(),
+            desugar_all(defs, types)?,
+            desugar(body, types)?,
+        ),
+        Expr::Case(cond, pats, branches, spans) => {
+            let mut new_pats = Vec::new();
+            for (i, pat) in pats.iter().enumerate() {
+                let span = spans.get(i).copied().unwrap_or_default();
+                new_pats.push(pattern(pat, span, types)?);
+            }
+            Expr::Case(
+                desugar(cond, types)?,
+                new_pats,
+                desugar_all(branches, types)?,
+                spans.clone(),
+            )
+        }
+        Expr::If(cond, b1, b2) => Expr::If(
+            desugar(cond, types)?,
+            desugar(b1, types)?,
+            desugar(b2, types)?,
+        ),
+        Expr::Annot(inner, typ) => Expr::Annot(desugar(inner, types)?, typ.clone()),
+        Expr::Record(cons, fields, span) => return construct(cons, fields, *span, types),
+        Expr::Update(record, fields, span) => return update(record, fields, *span, types),
+        _ => return Ok(Rc::clone(expr)),
+    };
+    Ok(Rc::new(desugared))
+}
+
+fn desugar_all(exprs: &[Rc<Expr>], types: &[DataInfo]) -> Result<Vec<Rc<Expr>>, Diagnostic> {
+    exprs.iter().map(|e| desugar(e, types)).collect()
+}
+
+// the constructor applied to the fields in the declared order
+fn construct(
+    cons: &str,
+    fields: &[(String, Rc<Expr>)],
+    span: Span,
+    types: &[DataInfo],
+) -> Result<Rc<Expr>, Diagnostic> {
+    let prod = record(cons, span, types)?;
+    for (i, (name, _)) in fields.iter().enumerate() {
+        if !prod.fields.contains(name) {
+            return Err(no_field(prod, name, span));
+        }
+        if fields[..i].iter().any(|(n, _)| n == name) {
+            return Err(Diagnostic::error(
+                span,
+                format!("field `{}` is given more than once", name),
+            ));
+        }
+    }
+    let mut expr = Rc::new(Expr::Var(cons.to_string(), RefCell::new((0, 0)), span));
+    for field in &prod.fields {
+        match fields.iter().find(|(n, _)| n == field) {
+            Some((_, value)) => expr = Rc::new(Expr::App(expr, desugar(value, types)?)),
+            None => {
+                return Err(Diagnostic::error(
+                    span,
+                    format!("missing field `{}` in `{}`", field, cons),
+                ))
+            }
+        }
+    }
+    Ok(expr)
+}
+
+// match on every constructor that has all of the fields and rebuild it
+fn update(
+    record: &Rc<Expr>,
+    fields: &[(String, Rc<Expr>)],
+    span: Span,
+    types: &[DataInfo],
+) -> Result<Rc<Expr>, Diagnostic> {
+    let first = &fields[0].0;
+    let info = match types
+        .iter()
+        .find(|t| t.data_info.alts.iter().any(|p| p.fields.contains(first)))
+    {
+        Some(info) => info,
+        None => {
+            let known: Vec<String> = types
+                .iter()
+                .flat_map(|t| t.data_info.alts.iter().flat_map(|p| p.fields.clone()))
+                .collect();
+            return Err(unbound("unknown field", first, span, &known));
+        }
+    };
+    let mut pats = Vec::new();
+    let mut branches = Vec::new();
+    for prod in &info.data_info.alts {
+        if !fields.iter().all(|(n, _)| prod.fields.contains(n)) {
+            continue;
+        }
+        let vars = positions(prod.args.len());
+        let mut expr = Rc::new(Expr::Var(prod.name.to_string(), RefCell::new((0, 0)), span));
+        for (field, var) in prod.fields.iter().zip(&vars) {
+            let value = match fields.iter().find(|(n, _)| n == field) {
+                Some((_, value)) => desugar(value, types)?,
+                None => Rc::new(Expr::Var(var.to_string(), RefCell::new((0, 0)), span)),
+            };
+            expr = Rc::new(Expr::App(expr, value));
+        }
+        pats.push(Pattern::Construct(prod.name.to_string(), vars));
+        branches.push(expr);
+    }
+    if pats.is_empty() {
+        let names: Vec<&str> = fields.iter().map(|(n, _)| n.as_str()).collect();
+        return Err(Diagnostic::error(
+            span,
+            format!(
+                "no constructor of `{}` has all of the fields {}",
+                info.type_info.get_name(),
+                names.join(", ")
+            ),
+        ));
+    }
+    if pats.len() < info.data_info.alts.len() {
+        pats.push(Pattern::Wildcard);
+        branches.push(Rc::new(Expr::Error(
+            "record update on a constructor without the field".to_string(),
+        )));
+    }
+    let spans = vec![span; pats.len()];
+    Ok(Rc::new(Expr::Case(
+        desugar(record, types)?,
+        pats,
+        branches,
+        spans,
+    )))
+}
+
+// named fields become positional, the ones that aren't mentioned are wildcards
+fn pattern(pat: &Pattern, span: Span, types: &[DataInfo]) -> Result<Pattern, Diagnostic> {
+    if let Pattern::Record(cons, named) = pat {
+        let prod = record(cons, span, types)?;
+        for (name, _) in named {
+            if !prod.fields.contains(name) {
+                return Err(no_field(prod, name, span));
+            }
+        }
+        let vars = prod
+            .fields
+            .iter()
+            .map(|field| match named.iter().find(|(n, _)| n == field) {
+                Some((_, var)) => var.to_string(),
+                None => "_".to_string(),
+            })
+            .collect();
+        Ok(Pattern::Construct(cons.to_string(), vars))
+    } else {
+        Ok(pat.clone())
+    }
+}
+
+// the constructor, as long as it was declared with named fields
+fn record<'a>(cons: &str, span: Span, types: &'a [DataInfo]) -> Result<&'a ProdInfo, Diagnostic> {
+    let prod = types
+        .iter()
+        .flat_map(|t| t.data_info.alts.iter())
+        .find(|p| p.name == cons);
+    match prod {
+        Some(prod) if !prod.fields.is_empty() => Ok(prod),
+        Some(_) => Err(Diagnostic::error(
+            span,
+            format!("constructor `{}` doesn't have named fields", cons),
+        )),
+        None => {
+            let known: Vec<String> = types
+                .iter()
+                .flat_map(|t| t.data_info.alts.iter().map(|p| p.name.to_string()))
+                .collect();
+            Err(unbound("unknown constructor", cons, span, &known))
+        }
+    }
+}
+
+fn no_field(prod: &ProdInfo, name: &str, span: Span) -> Diagnostic {
+    unbound(
+        &format!("`{}` has no field", prod.name),
+        name,
+        span,
+        &prod.fields,
+    )
+}
+
+// variables for each argument of a constructor
+pub fn positions(n: usize) -> Vec<String> {
+    (0..n).map(|i| format!("_{}", i)).collect()
+}
@@ -0,0 +1,1006 @@
+/*
+
+the runtime for bagl programs compiled to C (c.rs), the compiled program is put after it in the same file
+
+it does the same thing as eval
+    environments are frames of slots, a variable is found by how many frames up and which slot, the same as resolve works out
+    a lambda's argument is forced when it is applied, unless the parameter is _
+    let definitions are thunks that get replaced by their value the first time they're used
+    constructors and builtins force their arguments, so a constructed value is completely evaluated
+
+values live on a heap that is collected by copying, when it fills up everything that is still reachable is copied to a new one
+    the roots are a stack of slots, the compiled code keeps everything it is working on there instead of in C variables
+    so anything that can allocate has to be given its values through the stack, or push them itself, and read them back after
+
+integers are 64 bits, instead of the arbitrary size eval has, going past that stops the program with an error
+
+errors stop the program with exit code 101 and the message on stderr, like a panic in the interpreter does
+
+*/
+
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <math.h>
+#if defined(__unix__) || defined(__APPLE__)
+#include <pthread.h>
+#endif
+
+typedef struct Obj Obj;
+typedef Obj *(*Code)(Obj *env);
+
+enum {
+    INT,
+    FLOAT,
+    STR,         /* the bytes come after the header */
+    DATA,        /* a constructor with all of its fields */
+    FUN,         /* a lambda and the frame it was made in */
+    PAP_CON,     /* a constructor still waiting for fields */
+    PAP_BUILTIN, /* a builtin still waiting for arguments */
+    THUNK,       /* code and the frame to run it in */
+    BLACKHOLE,   /* a thunk that is being evaluated */
+    IND,         /* a thunk that has been evaluated, points at the value */
+    FRAME,       /* the frame above and then the slots */
+    FORWARD      /* already copied by the collector */
+};
+
+struct Obj {
+    uint32_t tag;
+    uint32_t n;   /* how many pointers are in f */
+    uint32_t aux; /* which constructor, lambda, or builtin */
+    uint32_t len; /* bytes in a string */
+    union {
+        int64_t i;
+        double d;
+        Code code;
+        Obj *forward;
+    } u;
+    Obj *f[];
+};
+
+#define BYTES(o) ((char *)(o)->f)
+
+typedef struct {
+    const char *name;
+    uint32_t arity;
+    uint32_t type; /* which data declaration */
+    uint32_t tag;  /* which alternative of it */
+} Con;
+
+typedef struct {
+    Code code;
+    int lazy; /* the parameter is _ so the argument isn't forced */
+    const char *shown;
+} Lam;
+
+typedef Obj *(*Prim)(Obj **args);
+
+typedef struct {
+    const char *name;
+    uint32_t arity;
+    Prim prim;
+} Builtin;
+
+/* filled in by the compiled program */
+static const Con *cons;
+static const Lam *lams;
+static uint32_t con_true, con_false, con_less, con_equal, con_greater;
+
+static void rt_panic(const char *message) {
+    fflush(stdout);
+    fprintf(stderr, "%s\n", message);
+    exit(101);
+}
+
+/* for errors where a value is expected */
+static Obj *rt_error(const char *message) {
+    rt_panic(message);
+    return NULL;
+}
+
+/* the roots */
+
+#define STACK_SLOTS (1 << 24)
+static Obj **stack_base, **sp, **stack_end;
+
+#define PUSH(x) (*sp++ = (x))
+#define POP() (*--sp)
+
+static Obj **rt_enter(size_t k, Obj *env) {
+    Obj **fp = sp;
+    size_t i;
+    if (sp + k > stack_end) {
+        rt_panic("Ran out of stack.");
+    }
+    fp[0] = env;
+    for (i = 1; i < k; i++) {
+        fp[i] = NULL;
+    }
+    sp += k;
+    return fp;
+}
+
+static Obj *rt_leave(Obj **fp, Obj *value) {
+    sp = fp;
+    return value;
+}
+
+/* the heap */
+
+static char *heap, *hp, *heap_end;
+static size_t semi = 1 << 20;
+
+static size_t obj_size(Obj *o) {
+    size_t size = sizeof(Obj) + o->n * sizeof(Obj *);
+    if (o->tag == STR) {
+        size += o->len + 1;
+    }
+    return (size + 7) & ~(size_t)7;
+}
+
+static char *from_lo, *from_hi;
+
+static Obj *copy(Obj *o) {
+    Obj *c;
+    size_t size;
+    if (o == NULL || (char *)o < from_lo || (char *)o >= from_hi) {
+        return o;
+    }
+    if (o->tag == FORWARD) {
+        return o->u.forward;
+    }
+    if (o->tag == IND) {
+        /* the value is all anyone needs */
+        c = copy(o->f[0]);
+        o->tag = FORWARD;
+        o->u.forward = c;
+        return c;
+    }
+    size = obj_size(o);
+    c = (Obj *)hp;
+    memcpy(c, o, size);
+    hp += size;
+    o->tag = FORWARD;
+    o->u.forward = c;
+    return c;
+}
+
+static void collect(size_t need) {
+    size_t used = hp - heap;
+    size_t size = semi;
+    char *to, *scan;
+    Obj **root;
+    while (size < used + need) {
+        size *= 2;
+    }
+    to = malloc(size);
+    if (to == NULL) {
+        rt_panic("Ran out of memory.");
+    }
+    from_lo = heap;
+    from_hi = hp;
+    hp = to;
+    for (root = stack_base; root < sp; root++) {
+        *root = copy(*root);
+    }
+    for (scan = to; scan < hp; scan += obj_size((Obj *)scan)) {
+        Obj *o = (Obj *)scan;
+        uint32_t i;
+        for (i = 0; i < o->n; i++) {
+            o->f[i] = copy(o->f[i]);
+        }
+    }
+    free(heap);
+    heap = to;
+    heap_end = to + size;
+    /* keep at least half of the heap free so collections don't happen back to back */
+    semi = size;
+    if ((size_t)(hp - heap) + need > size / 2) {
+        semi = size * 2;
+    }
+}
+
+static Obj *alloc(uint32_t tag, uint32_t n, size_t extra) {
+    size_t size = (sizeof(Obj) + n * sizeof(Obj *) + extra + 7) & ~(size_t)7;
+    Obj *o;
+    uint32_t i;
+    if (hp + size > heap_end) {
+        collect(size);
+    }
+    o = (Obj *)hp;
+    hp += size;
+    o->tag = tag;
+    o->n = n;
+    o->aux = 0;
+    o->len = 0;
+    o->u.i = 0;
+    for (i = 0; i < n; i++) {
+        o->f[i] = NULL;
+    }
+    return o;
+}
+
+/* making values */
+
+static Obj *rt_int(int64_t i) {
+    Obj *o = alloc(INT, 0, 0);
+    o->u.i = i;
+    return o;
+}
+
+static Obj *rt_float(double d) {
+    Obj *o = alloc(FLOAT, 0, 0);
+    o->u.d = d;
+    return o;
+}
+
+/* s can't be on the heap, it could move */
+static Obj *rt_str(const char *s, size_t len) {
+    Obj *o = alloc(STR, 0, len + 1);
+    o->len = (uint32_t)len;
+    memcpy(BYTES(o), s, len);
+    BYTES(o)[len] = 0;
+    return o;
+}
+
+static Obj *rt_bool(int b) {
+    Obj *o = alloc(DATA, 0, 0);
+    o->aux = b ? con_true : con_false;
+    return o;
+}
+
+static Obj *rt_con(uint32_t con) {
+    Obj *o = alloc(cons[con].arity == 0 ? DATA : PAP_CON, 0, 0);
+    o->aux = con;
+    return o;
+}
+
+static Obj *rt_builtin(uint32_t id) {
+    Obj *o = alloc(PAP_BUILTIN, 0, 0);
+    o->aux = id;
+    return o;
+}
+
+static Obj *rt_fun(uint32_t lam, Obj *env) {
+    Obj *o;
+    PUSH(env);
+    o = alloc(FUN, 1, 0);
+    o->aux = lam;
+    o->f[0] = POP();
+    return o;
+}
+
+static Obj *rt_thunk(Code code, Obj *env) {
+    Obj *o;
+    PUSH(env);
+    o = alloc(THUNK, 1, 0);
+    o->u.code = code;
+    o->f[0] = POP();
+    return o;
+}
+
+/* a frame of k slots on top of env, the values have to be on the stack, or NULL for empty slots */
+static Obj *rt_frame(Obj *env, uint32_t k, Obj **values) {
+    Obj *o;
+    uint32_t i;
+    PUSH(env);
+    o = alloc(FRAME, k + 1, 0);
+    o->f[0] = POP();
+    for (i = 0; i < k; i++) {
+        o->f[i + 1] = values == NULL ? NULL : values[i];
+    }
+    return o;
+}
+
+/* a frame holding the fields of a constructor, for a case branch */
+static Obj *rt_frame_fields(Obj *env, Obj *data) {
+    Obj **r = sp;
+    Obj *o;
+    uint32_t i;
+    PUSH(env);
+    PUSH(data);
+    o = alloc(FRAME, data->n + 1, 0);
+    o->f[0] = r[0];
+    for (i = 0; i < r[1]->n; i++) {
+        o->f[i + 1] = r[1]->f[i];
+    }
+    sp = r;
+    return o;
+}
+
+static void rt_set(Obj *frame, uint32_t slot, Obj *value) {
+    frame->f[slot + 1] = value;
+}
+
+static Obj *rt_up(Obj *env, uint32_t depth) {
+    while (depth-- > 0) {
+        env = env->f[0];
+    }
+    return env;
+}
+
+/* evaluating */
+
+static Obj *rt_force(Obj *v) {
+    Obj *r;
+    while (v->tag == IND) {
+        v = v->f[0];
+    }
+    if (v->tag == BLACKHOLE) {
+        rt_panic("A definition depends on its own value.");
+    }
+    if (v->tag != THUNK) {
+        return v;
+    }
+    v->tag = BLACKHOLE;
+    PUSH(v);
+    r = v->u.code(v->f[0]);
+    v = POP();
+    v->tag = IND;
+    v->f[0] = r;
+    return r;
+}
+
+/* what is in a slot, without evaluating it */
+static Obj *rt_lookup(Obj *env, uint32_t depth, uint32_t slot) {
+    return rt_up(env, depth)->f[slot + 1];
+}
+
+/* the value of a variable, the slot gets the value once it has been evaluated */
+static Obj *rt_var(Obj *env, uint32_t depth, uint32_t slot) {
+    Obj *frame = rt_up(env, depth);
+    Obj *v = frame->f[slot + 1];
+    if (v->tag == THUNK || v->tag == IND || v->tag == BLACKHOLE) {
+        PUSH(frame);
+        v = rt_force(v);
+        frame = POP();
+        frame->f[slot + 1] = v;
+    }
+    return v;
+}
+
+static const Builtin *builtin(uint32_t id);
+
+static Obj *rt_apply(Obj *f, Obj *arg) {
+    Obj **r = sp;
+    Obj *v;
+    uint32_t i, arity;
+    PUSH(f);
+    PUSH(arg);
+    v = rt_force(r[0]);
+    r[0] = v;
+    switch (v->tag) {
+    case FUN: {
+        Obj *frame;
+        const Lam *lam = &lams[v->aux];
+        if (!lam->lazy) {
+            v = rt_force(r[1]);
+            r[1] = v;
+        }
+        frame = rt_frame(r[0]->f[0], 1, &r[1]);
+        sp = r;
+        return lam->code(frame);
+    }
+    case PAP_CON:
+    case PAP_BUILTIN:
+        v = rt_force(r[1]);
+        r[1] = v;
+        v = r[0];
+        arity = v->tag == PAP_CON ? cons[v->aux].arity : builtin(v->aux)->arity;
+        if (v->tag == PAP_BUILTIN && v->n + 1 == arity) {
+            /* the arguments go on the stack so the builtin can allocate */
+            Obj **args = sp;
+            for (i = 0; i < r[0]->n; i++) {
+                PUSH(r[0]->f[i]);
+            }
+            PUSH(r[1]);
+            v = builtin(r[0]->aux)->prim(args);
+            sp = r;
+            return v;
+        }
+        v = alloc(v->tag == PAP_CON && v->n + 1 == arity ? DATA : v->tag, v->n + 1, 0);
+        v->aux = r[0]->aux;
+        for (i = 0; i < r[0]->n; i++) {
+            v->f[i] = r[0]->f[i];
+        }
+        v->f[r[0]->n] = r[1];
+        sp = r;
+        return v;
+    default:
+        /* anything else ignores its arguments, like eval does */
+        sp = r;
+        return v;
+    }
+}
+
+/* patterns */
+
+static int rt_is_con(Obj *v, uint32_t con, uint32_t n) {
+    return v->tag == DATA && v->aux == con && v->n == n;
+}
+
+static int rt_is_int(Obj *v, int64_t i) {
+    return v->tag == INT && v->u.i == i;
+}
+
+static int rt_is_float(Obj *v, double d) {
+    return v->tag == FLOAT && v->u.d == d;
+}
+
+static int rt_is_str(Obj *v, const char *s, size_t len) {
+    return v->tag == STR && v->len == len && memcmp(BYTES(v), s, len) == 0;
+}
+
+static int rt_is_true(Obj *v) {
+    if (v->tag == DATA && v->aux == con_true) {
+        return 1;
+    }
+    if (v->tag == DATA && v->aux == con_false) {
+        return 0;
+    }
+    rt_panic("If expression needs condition to be a boolean.");
+    return 0;
+}
+
+/* writing values out the same way Display does for Expr */
+
+typedef struct {
+    char *s;
+    size_t len, cap;
+} Buf;
+
+static void put(Buf *b, const char *s, size_t n) {
+    if (b->len + n + 1 > b->cap) {
+        b->cap = (b->len + n + 1) * 2;
+        b->s = realloc(b->s, b->cap);
+        if (b->s == NULL) {
+            rt_panic("Ran out of memory.");
+        }
+    }
+    memcpy(b->s + b->len, s, n);
+    b->len += n;
+    b->s[b->len] = 0;
+}
+
+static void puts_(Buf *b, const char *s) {
+    put(b, s, strlen(s));
+}
+
+static void put_int(Buf *b, int64_t i) {
+    char digits[32];
+    snprintf(digits, sizeof digits, "%lld", (long long)i);
+    puts_(b, digits);
+}
+
+/* the shortest digits that read back as the same number, written out without an exponent like rust does */
+static void put_float(Buf *b, double d) {
+    char text[40], digits[20];
+    int precision, exponent, n = 0, i;
+    char *p;
+    if (isnan(d)) {
+        puts_(b, "NaN");
+        return;
+    }
+    if (signbit(d)) {
+        puts_(b, "-");
+        d = -d;
+    }
+    if (isinf(d)) {
+        puts_(b, "inf");
+        return;
+    }
+    if (d == 0) {
+        puts_(b, "0");
+        return;
+    }
+    for (precision = 1; precision <= 17; precision++) {
+        snprintf(text, sizeof text, "%.*e", precision - 1, d);
+        if (strtod(text, NULL) == d) {
+            break;
+        }
+    }
+    for (p = text; *p != 'e'; p++) {
+        if (*p >= '0' && *p <= '9') {
+            digits[n++] = *p;
+        }
+    }
+    exponent = atoi(p + 1);
+    while (n > 1 && digits[n - 1] == '0') {
+        n--;
+    }
+    if (exponent < 0) {
+        puts_(b, "0.");
+        for (i = 0; i < -exponent - 1; i++) {
+            puts_(b, "0");
+        }
+        put(b, digits, n);
+        return;
+    }
+    for (i = 0; i <= exponent; i++) {
+        put(b, i < n ? &digits[i] : "0", 1);
+    }
+    if (n > exponent + 1) {
+        puts_(b, ".");
+        put(b, &digits[exponent + 1], n - exponent - 1);
+    }
+}
+
+/* quoted and escaped like {:?} */
+static void put_debug(Buf *b, const char *s, size_t len) {
+    size_t i;
+    char escape[16];
+    puts_(b, "\"");
+    for (i = 0; i < len; i++) {
+        unsigned char c = (unsigned char)s[i];
+        switch (c) {
+        case '"':
+            puts_(b, "\\\"");
+            break;
+        case '\\':
+            puts_(b, "\\\\");
+            break;
+        case '\n':
+            puts_(b, "\\n");
+            break;
+        case '\r':
+            puts_(b, "\\r");
+            break;
+        case '\t':
+            puts_(b, "\\t");
+            break;
+        case 0:
+            puts_(b, "\\0");
+            break;
+        default:
+            if (c < 0x20 || c == 0x7f) {
+                snprintf(escape, sizeof escape, "\\u{%x}", c);
+                puts_(b, escape);
+            } else {
+                put(b, (const char *)&s[i], 1);
+            }
+        }
+    }
+    puts_(b, "\"");
+}
+
+static void display(Buf *b, Obj *v);
+
+/* a value as the field of a constructor */
+static void put_field(Buf *b, Obj *v) {
+    if ((v->tag == DATA && v->n > 0) || (v->tag == INT && v->u.i < 0) ||
+        (v->tag == FLOAT && v->u.d < 0)) {
+        puts_(b, "(");
+        display(b, v);
+        puts_(b, ")");
+    } else if (v->tag == STR) {
+        put_debug(b, BYTES(v), v->len);
+    } else {
+        display(b, v);
+    }
+}
+
+static void display(Buf *b, Obj *v) {
+    uint32_t i;
+    while (v->tag == IND) {
+        v = v->f[0];
+    }
+    switch (v->tag) {
+    case INT:
+        put_int(b, v->u.i);
+        break;
+    case FLOAT:
+        put_float(b, v->u.d);
+        break;
+    case STR:
+        put(b, BYTES(v), v->len);
+        break;
+    case DATA:
+        puts_(b, cons[v->aux].name);
+        for (i = 0; i < v->n; i++) {
+            puts_(b, " ");
+            put_field(b, v->f[i]);
+        }
+        break;
+    case PAP_CON:
+        puts_(b, "<");
+        puts_(b, cons[v->aux].name);
+        for (i = 0; i < v->n; i++) {
+            puts_(b, " ");
+            put_field(b, v->f[i]);
+        }
+        for (i = v->n; i < cons[v->aux].arity; i++) {
+            puts_(b, " _");
+        }
+        puts_(b, ">");
+        break;
+    case PAP_BUILTIN:
+        puts_(b, "(");
+        puts_(b, builtin(v->aux)->name);
+        for (i = 0; i < v->n; i++) {
+            puts_(b, " ");
+            display(b, v->f[i]);
+        }
+        puts_(b, ")");
+        break;
+    case FUN:
+        puts_(b, lams[v->aux].shown);
+        break;
+    default:
+        puts_(b, "_");
+    }
+}
+
+/* builtins, the arguments are on the stack and have been forced */
+
+static Obj *overflow(void) {
+    return rt_error("Integer overflow, compiled programs only have 64 bit integers.");
+}
+
+static Obj *b_add(Obj **a) {
+    if (a[0]->tag == INT && a[1]->tag == INT) {
+        int64_t x = a[0]->u.i, y = a[1]->u.i;
+        if ((y > 0 && x > INT64_MAX - y) || (y < 0 && x < INT64_MIN - y)) {
+            return overflow();
+        }
+        return rt_int(x + y);
+    }
+    if (a[0]->tag == FLOAT && a[1]->tag == FLOAT) {
+        return rt_float(a[0]->u.d + a[1]->u.d);
+    }
+    return rt_error("Can only add numbers.");
+}
+
+static Obj *b_sub(Obj **a) {
+    if (a[0]->tag == INT && a[1]->tag == INT) {
+        int64_t x = a[0]->u.i, y = a[1]->u.i;
+        if ((y < 0 && x > INT64_MAX + y) || (y > 0 && x < INT64_MIN + y)) {
+            return overflow();
+        }
+        return rt_int(x - y);
+    }
+    if (a[0]->tag == FLOAT && a[1]->tag == FLOAT) {
+        return rt_float(a[0]->u.d - a[1]->u.d);
+    }
+    return rt_error("Can only subtract numbers.");
+}
+
+static Obj *b_mult(Obj **a) {
+    if (a[0]->tag == INT && a[1]->tag == INT) {
+        int64_t x = a[0]->u.i, y = a[1]->u.i;
+        if (x != 0 && y != 0) {
+            if ((x == -1 && y == INT64_MIN) || (y == -1 && x == INT64_MIN)) {
+                return overflow();
+            }
+            if ((x > 0 && y > 0 && x > INT64_MAX / y) || (x < 0 && y < 0 && x < INT64_MAX / y) ||
+                (x > 0 && y < 0 && y < INT64_MIN / x) || (x < 0 && y > 0 && x < INT64_MIN / y)) {
+                return overflow();
+            }
+        }
+        return rt_int(x * y);
+    }
+    if (a[0]->tag == FLOAT && a[1]->tag == FLOAT) {
+        return rt_float(a[0]->u.d * a[1]->u.d);
+    }
+    return rt_error("Can only multiply numbers.");
+}
+
+static Obj *b_div(Obj **a) {
+    if (a[0]->tag == INT && a[1]->tag == INT) {
+        if (a[1]->u.i == 0) {
+            return rt_error("attempt to divide by zero");
+        }
+        if (a[0]->u.i == INT64_MIN && a[1]->u.i == -1) {
+            return overflow();
+        }
+        return rt_int(a[0]->u.i / a[1]->u.i);
+    }
+    if (a[0]->tag == FLOAT && a[1]->tag == FLOAT) {
+        return rt_float(a[0]->u.d / a[1]->u.d);
+    }
+    return rt_error("Can only divide numbers.");
+}
+
+static Obj *b_eq(Obj **a) {
+    if (a[0]->tag == INT && a[1]->tag == INT) {
+        return rt_bool(a[0]->u.i == a[1]->u.i);
+    }
+    if (a[0]->tag == FLOAT && a[1]->tag == FLOAT) {
+        return rt_bool(a[0]->u.d == a[1]->u.d);
+    }
+    if (a[0]->tag == STR && a[1]->tag == STR) {
+        return rt_bool(rt_is_str(a[0], BYTES(a[1]), a[1]->len));
+    }
+    return rt_error("Can only equate numbers and strings.");
+}
+
+static Obj *b_compare(Obj **a) {
+    int order = 0;
+    Obj *o;
+    if (a[0]->tag == INT && a[1]->tag == INT) {
+        order = (a[0]->u.i > a[1]->u.i) - (a[0]->u.i < a[1]->u.i);
+    } else if (a[0]->tag == FLOAT && a[1]->tag == FLOAT) {
+        if (isnan(a[0]->u.d) || isnan(a[1]->u.d)) {
+            return rt_error("Can't compare NaN.");
+        }
+        order = (a[0]->u.d > a[1]->u.d) - (a[0]->u.d < a[1]->u.d);
+    } else if (a[0]->tag == STR && a[1]->tag == STR) {
+        size_t n = a[0]->len < a[1]->len ? a[0]->len : a[1]->len;
+        order = memcmp(BYTES(a[0]), BYTES(a[1]), n);
+        if (order == 0) {
+            order = (a[0]->len > a[1]->len) - (a[0]->len < a[1]->len);
+        }
+        order = (order > 0) - (order < 0);
+    } else {
+        return rt_error("Can only compare numbers or strings of the same type.");
+    }
+    o = alloc(DATA, 0, 0);
+    o->aux = order < 0 ? con_less : order == 0 ? con_equal : con_greater;
+    return o;
+}
+
+/* a string made from a buffer, which is freed */
+static Obj *from_buf(Buf *b) {
+    Obj *o = rt_str(b->s == NULL ? "" : b->s, b->len);
+    free(b->s);
+    return o;
+}
+
+static Obj *b_show(Obj **a) {
+    Buf b = {NULL, 0, 0};
+    if (a[0]->tag == INT) {
+        put_int(&b, a[0]->u.i);
+    } else if (a[0]->tag == FLOAT) {
+        put_float(&b, a[0]->u.d);
+    } else if (a[0]->tag == STR) {
+        put_debug(&b, BYTES(a[0]), a[0]->len);
+    } else {
+        return rt_error("Can only show numbers and strings.");
+    }
+    return from_buf(&b);
+}
+
+/* spaces inside of brackets or strings don't count */
+static int needs_parens(const char *s, size_t len) {
+    int depth = 0, quoted = 0, escaped = 0;
+    size_t i;
+    for (i = 0; i < len; i++) {
+        char c = s[i];
+        if (quoted) {
+            if (escaped) {
+                escaped = 0;
+            } else if (c == '\\') {
+                escaped = 1;
+            } else if (c == '"') {
+                quoted = 0;
+            }
+            continue;
+        }
+        if (c == '"') {
+            quoted = 1;
+        } else if (c == '(' || c == '[' || c == '{') {
+            depth++;
+        } else if (c == ')' || c == ']' || c == '}') {
+            depth--;
+        } else if (c == ' ' && depth == 0) {
+            return 1;
+        }
+    }
+    return len > 0 && s[0] == '-';
+}
+
+static Obj *b_parens(Obj **a) {
+    Buf b = {NULL, 0, 0};
+    if (a[0]->tag != STR) {
+        return rt_error("Can only put parentheses around strings.");
+    }
+    if (!needs_parens(BYTES(a[0]), a[0]->len)) {
+        return a[0];
+    }
+    puts_(&b, "(");
+    put(&b, BYTES(a[0]), a[0]->len);
+    puts_(&b, ")");
+    return from_buf(&b);
+}
+
+static Obj *b_concat(Obj **a) {
+    Obj *o;
+    if (a[0]->tag != STR || a[1]->tag != STR) {
+        return rt_error("Can only concatenate strings.");
+    }
+    o = alloc(STR, 0, a[0]->len + a[1]->len + 1);
+    o->len = a[0]->len + a[1]->len;
+    memcpy(BYTES(o), BYTES(a[0]), a[0]->len);
+    memcpy(BYTES(o) + a[0]->len, BYTES(a[1]), a[1]->len);
+    BYTES(o)[o->len] = 0;
+    return o;
+}
+
+static Obj *b_assert(Obj **a) {
+    if (a[0]->tag == DATA && a[0]->aux == con_true) {
+        return a[0];
+    }
+    if (a[0]->tag == DATA && a[0]->aux == con_false) {
+        rt_panic("Error: assert failed");
+    }
+    return rt_error("Can only assert booleans.");
+}
+
+/* strings are quoted so "1" and 1 don't look the same */
+static void put_shown(Buf *b, Obj *v) {
+    if (v->tag == STR) {
+        put_debug(b, BYTES(v), v->len);
+    } else {
+        display(b, v);
+    }
+}
+
+static void put_marked(Buf *b, Obj *v) {
+    puts_(b, "[");
+    put_shown(b, v);
+    puts_(b, "]");
+}
+
+/* 0 when they're the same, 1 with the first difference marked in left and right, -1 with a message in left */
+static int difference(Obj *x, Obj *y, Buf *left, Buf *right) {
+    uint32_t i, j;
+    int same_kind = x->tag == y->tag && (x->tag == INT || x->tag == FLOAT || x->tag == STR);
+    if (same_kind) {
+        int same = x->tag == INT ? x->u.i == y->u.i
+                 : x->tag == FLOAT ? x->u.d == y->u.d
+                 : rt_is_str(x, BYTES(y), y->len);
+        if (same) {
+            return 0;
+        }
+        put_marked(left, x);
+        put_marked(right, y);
+        return 1;
+    }
+    if (x->tag == DATA && y->tag == DATA) {
+        if (x->aux != y->aux) {
+            put_marked(left, x);
+            put_marked(right, y);
+            return 1;
+        }
+        for (i = 0; i < x->n; i++) {
+            Buf l = {NULL, 0, 0}, r = {NULL, 0, 0};
+            int found = difference(x->f[i], y->f[i], &l, &r);
+            if (found < 0) {
+                put(left, l.s, l.len);
+                free(l.s);
+                free(r.s);
+                return found;
+            }
+            if (found > 0) {
+                Buf *sides[2] = {left, right}, *inner[2] = {&l, &r};
+                int s;
+                for (s = 0; s < 2; s++) {
+                    int wrap = strchr(inner[s]->s, ' ') != NULL && inner[s]->s[0] != '[';
+                    puts_(sides[s], cons[x->aux].name);
+                    for (j = 0; j < x->n; j++) {
+                        puts_(sides[s], " ");
+                        if (j != i) {
+                            puts_(sides[s], "_");
+                            continue;
+                        }
+                        puts_(sides[s], wrap ? "(" : "");
+                        put(sides[s], inner[s]->s, inner[s]->len);
+                        puts_(sides[s], wrap ? ")" : "");
+                    }
+                }
+                free(l.s);
+                free(r.s);
+                return 1;
+            }
+        }
+        return 0;
+    }
+    puts_(left, "assertEq can only compare values, not ");
+    put_shown(left, x);
+    puts_(left, " and ");
+    put_shown(left, y);
+    return -1;
+}
+
+static Obj *b_assert_eq(Obj **a) {
+    Buf left = {NULL, 0, 0}, right = {NULL, 0, 0}, message = {NULL, 0, 0};
+    int found = difference(a[0], a[1], &left, &right);
+    if (found == 0) {
+        return rt_bool(1);
+    }
+    puts_(&message, "Error: ");
+    if (found < 0) {
+        put(&message, left.s, left.len);
+    } else {
+        puts_(&message, "assertEq failed\n    left:  ");
+        put_shown(&message, a[0]);
+        puts_(&message, "\n    right: ");
+        put_shown(&message, a[1]);
+        puts_(&message, "\n    first difference: ");
+        put(&message, left.s, left.len);
+        puts_(&message, " vs ");
+        put(&message, right.s, right.len);
+    }
+    rt_panic(message.s);
+    return NULL;
+}
+
+/* the order has to match the builtin numbers the compiler uses */
+static const Builtin builtins[] = {
+    {"+", 2, b_add},
+    {"-", 2, b_sub},
+    {"*", 2, b_mult},
+    {"/", 2, b_div},
+    {"++", 2, b_concat},
+    {"==", 2, b_eq},
+    {"compare", 2, b_compare},
+    {"show", 1, b_show},
+    {"parens", 1, b_parens},
+    {"assert", 1, b_assert},
+    {"assertEq", 2, b_assert_eq},
+};
+
+static const Builtin *builtin(uint32_t id) {
+    return &builtins[id];
+}
+
+/* a builtin with all of its arguments, they're on the stack and have been forced */
+static Obj *rt_prim(uint32_t id, Obj **args) {
+    return builtins[id].prim(args);
+}
+
+/* a constructor with all of its fields, they're on the stack and have been forced */
+static Obj *rt_data(uint32_t con, Obj **fields) {
+    uint32_t i, n = cons[con].arity;
+    Obj *o = alloc(DATA, n, 0);
+    o->aux = con;
+    for (i = 0; i < n; i++) {
+        o->f[i] = fields[i];
+    }
+    return o;
+}
+
+/* running the program */
+
+static Code entry;
+
+static void *run(void *unused) {
+    Buf b = {NULL, 0, 0};
+    Obj *value;
+    (void)unused;
+    stack_base = malloc(STACK_SLOTS * sizeof(Obj *));
+    heap = malloc(semi);
+    if (stack_base == NULL || heap == NULL) {
+        rt_panic("Ran out of memory.");
+    }
+    sp = stack_base;
+    stack_end = stack_base + STACK_SLOTS;
+    hp = heap;
+    heap_end = heap + semi;
+    value = rt_force(entry(NULL));
+    display(&b, value);
+    puts_(&b, "\n");
+    fwrite(b.s, 1, b.len, stdout);
+    fflush(stdout);
+    return NULL;
+}
+
+/* every step of evaluation is a C call, so it gets a big stack like the interpreter does */
+static int rt_main(Code program) {
+    entry = program;
+#if defined(__unix__) || defined(__APPLE__)
+    {
+        pthread_attr_t attr;
+        pthread_t thread;
+        size_t size = sizeof(size_t) > 4 ? (size_t)1 << 32 : (size_t)1 << 28;
+        pthread_attr_init(&attr);
+        if (pthread_attr_setstacksize(&attr, size) == 0 &&
+            pthread_create(&thread, &attr, run, NULL) == 0) {
+            pthread_join(thread, NULL);
+            return 0;
+        }
+    }
+#endif
+    run(NULL);
+    return 0;
+}
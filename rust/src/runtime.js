@@ -0,0 +1,414 @@
+// the runtime for bagl programs compiled to JavaScript (js.rs), the compiled program goes in the same module after it
+//
+// values are JavaScript values
+//     Int is a BigInt, Float is a number, Str is a string
+//     a constructor with all of its fields is a $Data, the tag is its place among the alternatives of its type
+//     functions are functions of one argument, lambdas have the source they were written as for printing
+//     constructors and builtins without all of their arguments are functions that remember what they have
+// a definition that hasn't been evaluated yet is a $Thunk, $force gives back its value and keeps it
+// everything that isn't from the program starts with $, names in bagl can't have a $ in them
+//
+// the program sets $True, $False, $Less, $Equal, and $Greater, the tags depend on the declarations
+
+export class $BaglError extends Error {}
+
+export class $Data {
+  constructor(tag, name, fields) {
+    this.tag = tag;
+    this.name = name;
+    this.fields = fields;
+  }
+}
+
+export class $Thunk {
+  constructor(code) {
+    this.code = code;
+    this.value = undefined;
+  }
+}
+
+function $fail(message) {
+  throw new $BaglError(message);
+}
+
+// the thunk is being evaluated
+function $blackhole() {
+  $fail("A definition depends on its own value.");
+}
+
+export function $force(v) {
+  if (!(v instanceof $Thunk)) {
+    return v;
+  }
+  if (v.code === null) {
+    return v.value;
+  }
+  const code = v.code;
+  v.code = $blackhole;
+  const value = $force(code());
+  v.code = null;
+  v.value = value;
+  return value;
+}
+
+function $delay(code) {
+  return new $Thunk(code);
+}
+
+function $lam(shown, f) {
+  f.shown = shown;
+  return f;
+}
+
+// anything that isn't a function ignores its arguments, like eval does
+function $apply(f, ...args) {
+  for (const a of args) {
+    f = $force(f);
+    if (typeof f !== "function") {
+      return f;
+    }
+    f = f(a);
+  }
+  return $force(f);
+}
+
+function $con(tag, name, arity, fields = []) {
+  if (fields.length === arity) {
+    return new $Data(tag, name, fields);
+  }
+  const f = (a) => $con(tag, name, arity, [...fields, $force(a)]);
+  f.con = { name, arity, fields };
+  return f;
+}
+
+function $builtin(name, arity, prim, args = []) {
+  const f = (a) => {
+    const all = [...args, $force(a)];
+    return all.length === arity ? prim(...all) : $builtin(name, arity, prim, all);
+  };
+  f.builtin = { name, args };
+  return f;
+}
+
+function $isTrue(v) {
+  if (v instanceof $Data && v.name === $True.name) {
+    return true;
+  }
+  if (v instanceof $Data && v.name === $False.name) {
+    return false;
+  }
+  return $fail("If expression needs condition to be a boolean.");
+}
+
+function $bool(b) {
+  return b ? $True : $False;
+}
+
+function $order(a, b) {
+  return a < b ? $Less : a > b ? $Greater : $Equal;
+}
+
+// writing values out the same way Display does for Expr
+
+// the shortest digits that read back as the same number, without an exponent
+function $showFloat(d) {
+  if (Number.isNaN(d)) {
+    return "NaN";
+  }
+  if (d === Infinity) {
+    return "inf";
+  }
+  if (d === -Infinity) {
+    return "-inf";
+  }
+  if (Object.is(d, -0)) {
+    return "-0";
+  }
+  const s = String(d);
+  if (!s.includes("e")) {
+    return s;
+  }
+  let [mantissa, exponent] = s.split("e");
+  const sign = mantissa.startsWith("-") ? "-" : "";
+  mantissa = mantissa.replace("-", "");
+  const [whole, fraction = ""] = mantissa.split(".");
+  const digits = whole + fraction;
+  const point = Number(exponent) + whole.length;
+  if (point <= 0) {
+    return sign + "0." + "0".repeat(-point) + digits;
+  }
+  if (point >= digits.length) {
+    return sign + digits + "0".repeat(point - digits.length);
+  }
+  return sign + digits.slice(0, point) + "." + digits.slice(point);
+}
+
+// quoted and escaped like {:?}
+function $debug(s) {
+  let out = '"';
+  for (const c of s) {
+    const code = c.codePointAt(0);
+    if (c === '"' || c === "\\") {
+      out += "\\" + c;
+    } else if (c === "\n") {
+      out += "\\n";
+    } else if (c === "\r") {
+      out += "\\r";
+    } else if (c === "\t") {
+      out += "\\t";
+    } else if (code === 0) {
+      out += "\\0";
+    } else if (code < 32 || code === 127) {
+      out += "\\u{" + code.toString(16) + "}";
+    } else {
+      out += c;
+    }
+  }
+  return out + '"';
+}
+
+// a value as the field of a constructor
+function $field(v) {
+  if (
+    (v instanceof $Data && v.fields.length > 0) ||
+    (typeof v === "bigint" && v < 0n) ||
+    (typeof v === "number" && v < 0)
+  ) {
+    return "(" + $display(v) + ")";
+  }
+  if (typeof v === "string") {
+    return $debug(v);
+  }
+  return $display(v);
+}
+
+// the text of a value the way bagl prints it
+export function $display(v) {
+  v = $force(v);
+  switch (typeof v) {
+    case "bigint":
+      return v.toString();
+    case "number":
+      return $showFloat(v);
+    case "string":
+      return v;
+    case "function":
+      if (v.con) {
+        const holes = " _".repeat(v.con.arity - v.con.fields.length);
+        return "<" + [v.con.name, ...v.con.fields.map($field)].join(" ") + holes + ">";
+      }
+      if (v.builtin) {
+        return "(" + [v.builtin.name, ...v.builtin.args.map($display)].join(" ") + ")";
+      }
+      if (v.shown) {
+        return v.shown;
+      }
+      return "_";
+    default:
+      if (v instanceof $Data) {
+        return [v.name, ...v.fields.map($field)].join(" ");
+      }
+      return "_";
+  }
+}
+
+// builtins, their arguments have been forced
+
+function $ints(a, b) {
+  return typeof a === "bigint" && typeof b === "bigint";
+}
+
+function $floats(a, b) {
+  return typeof a === "number" && typeof b === "number";
+}
+
+function $strs(a, b) {
+  return typeof a === "string" && typeof b === "string";
+}
+
+function $add(a, b) {
+  return $ints(a, b) || $floats(a, b) ? a + b : $fail("Can only add numbers.");
+}
+
+function $sub(a, b) {
+  return $ints(a, b) || $floats(a, b) ? a - b : $fail("Can only subtract numbers.");
+}
+
+function $mult(a, b) {
+  return $ints(a, b) || $floats(a, b) ? a * b : $fail("Can only multiply numbers.");
+}
+
+function $div(a, b) {
+  if ($ints(a, b) && b === 0n) {
+    return $fail("attempt to divide by zero");
+  }
+  return $ints(a, b) || $floats(a, b) ? a / b : $fail("Can only divide numbers.");
+}
+
+function $concat(a, b) {
+  return $strs(a, b) ? a + b : $fail("Can only concatenate strings.");
+}
+
+function $eq(a, b) {
+  return $ints(a, b) || $floats(a, b) || $strs(a, b)
+    ? $bool(a === b)
+    : $fail("Can only equate numbers and strings.");
+}
+
+// strings are compared by code point, the same order as the bytes of their UTF-8
+function $compareStrs(a, b) {
+  const x = Array.from(a, (c) => c.codePointAt(0));
+  const y = Array.from(b, (c) => c.codePointAt(0));
+  for (let i = 0; i < x.length && i < y.length; i++) {
+    if (x[i] !== y[i]) {
+      return $order(x[i], y[i]);
+    }
+  }
+  return $order(x.length, y.length);
+}
+
+function $compare(a, b) {
+  if ($ints(a, b)) {
+    return $order(a, b);
+  }
+  if ($floats(a, b)) {
+    return Number.isNaN(a) || Number.isNaN(b) ? $fail("Can't compare NaN.") : $order(a, b);
+  }
+  if ($strs(a, b)) {
+    return $compareStrs(a, b);
+  }
+  return $fail("Can only compare numbers or strings of the same type.");
+}
+
+function $show(a) {
+  switch (typeof a) {
+    case "bigint":
+      return a.toString();
+    case "number":
+      return $showFloat(a);
+    case "string":
+      return $debug(a);
+    default:
+      return $fail("Can only show numbers and strings.");
+  }
+}
+
+// spaces inside of brackets or strings don't count
+function $needsParens(s) {
+  let depth = 0;
+  let quoted = false;
+  let escaped = false;
+  for (const c of s) {
+    if (quoted) {
+      if (escaped) {
+        escaped = false;
+      } else if (c === "\\") {
+        escaped = true;
+      } else if (c === '"') {
+        quoted = false;
+      }
+    } else if (c === '"') {
+      quoted = true;
+    } else if ("([{".includes(c)) {
+      depth += 1;
+    } else if (")]}".includes(c)) {
+      depth -= 1;
+    } else if (c === " " && depth === 0) {
+      return true;
+    }
+  }
+  return s.startsWith("-");
+}
+
+function $parens(a) {
+  if (typeof a !== "string") {
+    return $fail("Can only put parentheses around strings.");
+  }
+  return $needsParens(a) ? "(" + a + ")" : a;
+}
+
+function $assert(a) {
+  if (a instanceof $Data && a.name === $True.name) {
+    return a;
+  }
+  if (a instanceof $Data && a.name === $False.name) {
+    return $fail("Error: assert failed");
+  }
+  return $fail("Can only assert booleans.");
+}
+
+// strings are quoted so "1" and 1 don't look the same
+function $shown(v) {
+  return typeof v === "string" ? $debug(v) : $display(v);
+}
+
+function $marked(v) {
+  return "[" + $shown(v) + "]";
+}
+
+// the constructor with _ for every field but the one that differs
+function $around(data, at, inner) {
+  const wrap = inner.includes(" ") && !inner.startsWith("[");
+  const fields = data.fields.map((_, i) => (i !== at ? "_" : wrap ? "(" + inner + ")" : inner));
+  return [data.name, ...fields].join(" ");
+}
+
+// null when they're the same, the first difference marked in both, or a message when they can't be compared
+function $difference(x, y) {
+  const same = typeof x === typeof y && ["bigint", "number", "string"].includes(typeof x);
+  if (same) {
+    return x === y ? null : { left: $marked(x), right: $marked(y) };
+  }
+  if (x instanceof $Data && y instanceof $Data) {
+    if (x.name !== y.name) {
+      return { left: $marked(x), right: $marked(y) };
+    }
+    for (let i = 0; i < x.fields.length; i++) {
+      const found = $difference(x.fields[i], y.fields[i]);
+      if (found !== null && found.message !== undefined) {
+        return found;
+      }
+      if (found !== null) {
+        return { left: $around(x, i, found.left), right: $around(x, i, found.right) };
+      }
+    }
+    return null;
+  }
+  return {
+    message: "assertEq can only compare values, not " + $shown(x) + " and " + $shown(y),
+  };
+}
+
+function $assertEq(x, y) {
+  const found = $difference(x, y);
+  if (found === null) {
+    return $True;
+  }
+  if (found.message !== undefined) {
+    return $fail("Error: " + found.message);
+  }
+  return $fail(
+    "Error: assertEq failed\n    left:  " +
+      $shown(x) +
+      "\n    right: " +
+      $shown(y) +
+      "\n    first difference: " +
+      found.left +
+      " vs " +
+      found.right
+  );
+}
+
+const $builtins = {
+  "+": $builtin("+", 2, $add),
+  "-": $builtin("-", 2, $sub),
+  "*": $builtin("*", 2, $mult),
+  "/": $builtin("/", 2, $div),
+  "++": $builtin("++", 2, $concat),
+  "==": $builtin("==", 2, $eq),
+  compare: $builtin("compare", 2, $compare),
+  show: $builtin("show", 1, $show),
+  parens: $builtin("parens", 1, $parens),
+  assert: $builtin("assert", 1, $assert),
+  assertEq: $builtin("assertEq", 2, $assertEq),
+};
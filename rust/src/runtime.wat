@@ -0,0 +1,917 @@
+;; the runtime for bagl programs compiled to WebAssembly text (wasm.rs), the compiled program goes in the same module after it
+;;
+;; it works like the C runtime (runtime.c) does, the same frames, thunks, closures, and constructor numbers
+;; every value is an object in linear memory, pointers are i32
+;;     0 tag, 4 how many pointers, 8 which constructor, lambda, or builtin, 12 bytes in a string
+;;     16 the number, or the code of a thunk as an index into the table
+;;     24 the pointers, or the bytes of a string
+;; memory is only ever allocated, there is no collector, the module grows its memory when it needs more
+;;     nothing is ever freed, not even garbage, so memory only goes back when the host drops the instance
+;;
+;; (string "text") isn't WebAssembly, wasm.rs replaces it with the address and length of the text in the data
+;;
+;; errors put the message in error_ptr and error_len and trap
+;; floats are written out by the host, format_float gets the number and where to write it
+;; it has to write what rust's Display does (shortest digits that read back the same, no exponent, inf, NaN) and give back the length
+
+(import "bagl" "format_float" (func $format_float (param f64 i32) (result i32)))
+
+(type $code (func (param i32) (result i32)))
+
+(global $error_ptr (export "error_ptr") (mut i32) (i32.const 0))
+(global $error_len (export "error_len") (mut i32) (i32.const 0))
+
+(func $fail (param $ptr i32) (param $len i32)
+  (global.set $error_ptr (local.get $ptr))
+  (global.set $error_len (local.get $len))
+  (unreachable))
+
+;; for errors where a value is expected
+(func $error (param $ptr i32) (param $len i32) (result i32)
+  (call $fail (local.get $ptr) (local.get $len))
+  (unreachable))
+
+;; the heap
+
+(func $alloc (param $tag i32) (param $n i32) (param $extra i32) (result i32)
+  (local $p i32)
+  (local $end i32)
+  (local.set $p (global.get $hp))
+  (local.set $end
+    (i32.add (local.get $p)
+      (i32.and
+        (i32.add (i32.add (i32.const 31) (i32.shl (local.get $n) (i32.const 2))) (local.get $extra))
+        (i32.const -8))))
+  (if (i32.gt_u (local.get $end) (i32.shl (memory.size) (i32.const 16)))
+    (then
+      (if (i32.eq
+            (memory.grow
+              (i32.shr_u
+                (i32.add (i32.sub (local.get $end) (i32.shl (memory.size) (i32.const 16))) (i32.const 65535))
+                (i32.const 16)))
+            (i32.const -1))
+        (then (call $fail (string "Ran out of memory."))))))
+  (global.set $hp (local.get $end))
+  ;; new memory is zero, and nothing is used twice, so only the tag and size need to be written
+  (i32.store (local.get $p) (local.get $tag))
+  (i32.store offset=4 (local.get $p) (local.get $n))
+  (local.get $p))
+
+(func $tag (param $v i32) (result i32) (i32.load (local.get $v)))
+(func $size (param $v i32) (result i32) (i32.load offset=4 (local.get $v)))
+(func $aux (param $v i32) (result i32) (i32.load offset=8 (local.get $v)))
+(func $len (param $v i32) (result i32) (i32.load offset=12 (local.get $v)))
+(func $bytes (param $v i32) (result i32) (i32.add (local.get $v) (i32.const 24)))
+(func $get (param $v i32) (param $i i32) (result i32)
+  (i32.load offset=24 (i32.add (local.get $v) (i32.shl (local.get $i) (i32.const 2)))))
+(func $put_field_at (param $v i32) (param $i i32) (param $x i32)
+  (i32.store offset=24 (i32.add (local.get $v) (i32.shl (local.get $i) (i32.const 2))) (local.get $x)))
+
+;; the tables from the compiled program
+
+(func $con_name (param $con i32) (result i32)
+  (i32.load (i32.add (global.get $cons) (i32.mul (local.get $con) (i32.const 20)))))
+(func $con_name_len (param $con i32) (result i32)
+  (i32.load offset=4 (i32.add (global.get $cons) (i32.mul (local.get $con) (i32.const 20)))))
+(func $con_arity (param $con i32) (result i32)
+  (i32.load offset=8 (i32.add (global.get $cons) (i32.mul (local.get $con) (i32.const 20)))))
+(func $lam (param $lam i32) (result i32)
+  (i32.add (global.get $lams) (i32.shl (local.get $lam) (i32.const 4))))
+(func $builtin_entry (param $id i32) (result i32)
+  (i32.add (global.get $builtins) (i32.mul (local.get $id) (i32.const 12))))
+
+;; making values
+
+(func $int (param $i i64) (result i32)
+  (local $p i32)
+  (local.set $p (call $alloc (i32.const 0) (i32.const 0) (i32.const 0)))
+  (i64.store offset=16 (local.get $p) (local.get $i))
+  (local.get $p))
+
+(func $float (param $d f64) (result i32)
+  (local $p i32)
+  (local.set $p (call $alloc (i32.const 1) (i32.const 0) (i32.const 0)))
+  (f64.store offset=16 (local.get $p) (local.get $d))
+  (local.get $p))
+
+(func $str (param $ptr i32) (param $len i32) (result i32)
+  (local $p i32)
+  (local.set $p (call $alloc (i32.const 2) (i32.const 0) (local.get $len)))
+  (i32.store offset=12 (local.get $p) (local.get $len))
+  (memory.copy (call $bytes (local.get $p)) (local.get $ptr) (local.get $len))
+  (local.get $p))
+
+(func $bool (param $b i32) (result i32)
+  (local $p i32)
+  (local.set $p (call $alloc (i32.const 3) (i32.const 0) (i32.const 0)))
+  (i32.store offset=8 (local.get $p)
+    (select (global.get $con_true) (global.get $con_false) (local.get $b)))
+  (local.get $p))
+
+(func $con (param $con i32) (result i32)
+  (local $p i32)
+  (local.set $p
+    (call $alloc
+      (select (i32.const 3) (i32.const 5) (i32.eqz (call $con_arity (local.get $con))))
+      (i32.const 0)
+      (i32.const 0)))
+  (i32.store offset=8 (local.get $p) (local.get $con))
+  (local.get $p))
+
+;; a constructor with all of its fields, they're set with $put_field_at
+(func $data (param $con i32) (result i32)
+  (local $p i32)
+  (local.set $p (call $alloc (i32.const 3) (call $con_arity (local.get $con)) (i32.const 0)))
+  (i32.store offset=8 (local.get $p) (local.get $con))
+  (local.get $p))
+
+(func $builtin (param $id i32) (result i32)
+  (local $p i32)
+  (local.set $p (call $alloc (i32.const 6) (i32.const 0) (i32.const 0)))
+  (i32.store offset=8 (local.get $p) (local.get $id))
+  (local.get $p))
+
+(func $fun (param $lam i32) (param $env i32) (result i32)
+  (local $p i32)
+  (local.set $p (call $alloc (i32.const 4) (i32.const 1) (i32.const 0)))
+  (i32.store offset=8 (local.get $p) (local.get $lam))
+  (call $put_field_at (local.get $p) (i32.const 0) (local.get $env))
+  (local.get $p))
+
+(func $thunk (param $code i32) (param $env i32) (result i32)
+  (local $p i32)
+  (local.set $p (call $alloc (i32.const 7) (i32.const 1) (i32.const 0)))
+  (i32.store offset=16 (local.get $p) (local.get $code))
+  (call $put_field_at (local.get $p) (i32.const 0) (local.get $env))
+  (local.get $p))
+
+;; a frame of k empty slots on top of env, filled in with $set
+(func $frame (param $env i32) (param $k i32) (result i32)
+  (local $p i32)
+  (local.set $p (call $alloc (i32.const 10) (i32.add (local.get $k) (i32.const 1)) (i32.const 0)))
+  (call $put_field_at (local.get $p) (i32.const 0) (local.get $env))
+  (local.get $p))
+
+(func $set (param $frame i32) (param $slot i32) (param $v i32)
+  (call $put_field_at (local.get $frame) (i32.add (local.get $slot) (i32.const 1)) (local.get $v)))
+
+;; a frame holding the fields of a constructor, for a case branch
+(func $frame_fields (param $env i32) (param $data i32) (result i32)
+  (local $p i32)
+  (local.set $p (call $frame (local.get $env) (call $size (local.get $data))))
+  (memory.copy
+    (i32.add (local.get $p) (i32.const 28))
+    (call $bytes (local.get $data))
+    (i32.shl (call $size (local.get $data)) (i32.const 2)))
+  (local.get $p))
+
+;; a frame with one slot holding the value
+(func $frame1 (param $env i32) (param $v i32) (result i32)
+  (local $p i32)
+  (local.set $p (call $frame (local.get $env) (i32.const 1)))
+  (call $set (local.get $p) (i32.const 0) (local.get $v))
+  (local.get $p))
+
+(func $up (param $env i32) (param $depth i32) (result i32)
+  (block $done
+    (loop $next
+      (br_if $done (i32.eqz (local.get $depth)))
+      (local.set $env (call $get (local.get $env) (i32.const 0)))
+      (local.set $depth (i32.sub (local.get $depth) (i32.const 1)))
+      (br $next)))
+  (local.get $env))
+
+;; evaluating
+
+(func $force (param $v i32) (result i32)
+  (local $r i32)
+  (block $found
+    (loop $follow
+      (br_if $found (i32.ne (call $tag (local.get $v)) (i32.const 9)))
+      (local.set $v (call $get (local.get $v) (i32.const 0)))
+      (br $follow)))
+  (if (i32.eq (call $tag (local.get $v)) (i32.const 8))
+    (then (call $fail (string "A definition depends on its own value."))))
+  (if (i32.ne (call $tag (local.get $v)) (i32.const 7))
+    (then (return (local.get $v))))
+  (i32.store (local.get $v) (i32.const 8))
+  (local.set $r
+    (call_indirect (type $code)
+      (call $get (local.get $v) (i32.const 0))
+      (i32.load offset=16 (local.get $v))))
+  (i32.store (local.get $v) (i32.const 9))
+  (call $put_field_at (local.get $v) (i32.const 0) (local.get $r))
+  (local.get $r))
+
+;; what is in a slot, without evaluating it
+(func $lookup (param $env i32) (param $depth i32) (param $slot i32) (result i32)
+  (call $get (call $up (local.get $env) (local.get $depth)) (i32.add (local.get $slot) (i32.const 1))))
+
+;; the value of a variable, the slot gets the value once it has been evaluated
+(func $var (param $env i32) (param $depth i32) (param $slot i32) (result i32)
+  (local $frame i32)
+  (local $v i32)
+  (local.set $frame (call $up (local.get $env) (local.get $depth)))
+  (local.set $v (call $get (local.get $frame) (i32.add (local.get $slot) (i32.const 1))))
+  (if (i32.ge_u (call $tag (local.get $v)) (i32.const 7))
+    (then
+      (local.set $v (call $force (local.get $v)))
+      (call $set (local.get $frame) (local.get $slot) (local.get $v))))
+  (local.get $v))
+
+(func $apply (param $f i32) (param $a i32) (result i32)
+  (local $t i32)
+  (local $n i32)
+  (local $arity i32)
+  (local $lam i32)
+  (local $p i32)
+  (local.set $f (call $force (local.get $f)))
+  (local.set $t (call $tag (local.get $f)))
+  (if (i32.eq (local.get $t) (i32.const 4))
+    (then
+      (local.set $lam (call $lam (call $aux (local.get $f))))
+      (if (i32.eqz (i32.load offset=4 (local.get $lam)))
+        (then (local.set $a (call $force (local.get $a)))))
+      (return
+        (call_indirect (type $code)
+          (call $frame1 (call $get (local.get $f) (i32.const 0)) (local.get $a))
+          (i32.load (local.get $lam))))))
+  ;; anything else ignores its arguments, like eval does
+  (if (i32.and (i32.ne (local.get $t) (i32.const 5)) (i32.ne (local.get $t) (i32.const 6)))
+    (then (return (local.get $f))))
+  (local.set $a (call $force (local.get $a)))
+  (local.set $n (call $size (local.get $f)))
+  (local.set $arity
+    (if (result i32) (i32.eq (local.get $t) (i32.const 5))
+      (then (call $con_arity (call $aux (local.get $f))))
+      (else (i32.load offset=8 (call $builtin_entry (call $aux (local.get $f)))))))
+  (if (i32.and (i32.eq (local.get $t) (i32.const 6))
+               (i32.eq (i32.add (local.get $n) (i32.const 1)) (local.get $arity)))
+    (then
+      (return
+        (call $prim
+          (call $aux (local.get $f))
+          (if (result i32) (i32.eqz (local.get $n))
+            (then (local.get $a))
+            (else (call $get (local.get $f) (i32.const 0))))
+          (local.get $a)))))
+  (local.set $p
+    (call $alloc
+      (select
+        (i32.const 3)
+        (local.get $t)
+        (i32.and (i32.eq (local.get $t) (i32.const 5))
+                 (i32.eq (i32.add (local.get $n) (i32.const 1)) (local.get $arity))))
+      (i32.add (local.get $n) (i32.const 1))
+      (i32.const 0)))
+  (i32.store offset=8 (local.get $p) (call $aux (local.get $f)))
+  (memory.copy (call $bytes (local.get $p)) (call $bytes (local.get $f)) (i32.shl (local.get $n) (i32.const 2)))
+  (call $put_field_at (local.get $p) (local.get $n) (local.get $a))
+  (local.get $p))
+
+;; patterns
+
+(func $is_con (param $v i32) (param $con i32) (param $n i32) (result i32)
+  (i32.and
+    (i32.eq (call $tag (local.get $v)) (i32.const 3))
+    (i32.and
+      (i32.eq (call $aux (local.get $v)) (local.get $con))
+      (i32.eq (call $size (local.get $v)) (local.get $n)))))
+
+(func $is_int (param $v i32) (param $i i64) (result i32)
+  (if (result i32) (i32.eqz (call $tag (local.get $v)))
+    (then (i64.eq (i64.load offset=16 (local.get $v)) (local.get $i)))
+    (else (i32.const 0))))
+
+(func $is_float (param $v i32) (param $d f64) (result i32)
+  (if (result i32) (i32.eq (call $tag (local.get $v)) (i32.const 1))
+    (then (f64.eq (f64.load offset=16 (local.get $v)) (local.get $d)))
+    (else (i32.const 0))))
+
+(func $same_bytes (param $x i32) (param $y i32) (param $len i32) (result i32)
+  (local $i i32)
+  (block $differ
+    (loop $next
+      (if (i32.eq (local.get $i) (local.get $len)) (then (return (i32.const 1))))
+      (br_if $differ
+        (i32.ne
+          (i32.load8_u (i32.add (local.get $x) (local.get $i)))
+          (i32.load8_u (i32.add (local.get $y) (local.get $i)))))
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (br $next)))
+  (i32.const 0))
+
+(func $is_str (param $v i32) (param $ptr i32) (param $len i32) (result i32)
+  (if (result i32)
+      (i32.and (i32.eq (call $tag (local.get $v)) (i32.const 2))
+               (i32.eq (call $len (local.get $v)) (local.get $len)))
+    (then (call $same_bytes (call $bytes (local.get $v)) (local.get $ptr) (local.get $len)))
+    (else (i32.const 0))))
+
+(func $is_true (param $v i32) (result i32)
+  (if (i32.eq (call $tag (local.get $v)) (i32.const 3))
+    (then
+      (if (i32.eq (call $aux (local.get $v)) (global.get $con_true)) (then (return (i32.const 1))))
+      (if (i32.eq (call $aux (local.get $v)) (global.get $con_false)) (then (return (i32.const 0))))))
+  (call $fail (string "If expression needs condition to be a boolean."))
+  (unreachable))
+
+;; buffers for building strings, 24 where the bytes are, 28 how many, 32 how many fit
+
+(func $buf_new (result i32)
+  (local $b i32)
+  (local.set $b (call $alloc (i32.const 11) (i32.const 0) (i32.const 12)))
+  (i32.store offset=24 (local.get $b)
+    (call $bytes (call $alloc (i32.const 11) (i32.const 0) (i32.const 64))))
+  (i32.store offset=32 (local.get $b) (i32.const 64))
+  (local.get $b))
+
+(func $buf_data (param $b i32) (result i32) (i32.load offset=24 (local.get $b)))
+(func $buf_len (param $b i32) (result i32) (i32.load offset=28 (local.get $b)))
+
+;; where n more bytes can be written
+(func $ensure (param $b i32) (param $n i32) (result i32)
+  (local $want i32)
+  (local $data i32)
+  (local.set $want (i32.add (call $buf_len (local.get $b)) (local.get $n)))
+  (if (i32.gt_u (local.get $want) (i32.load offset=32 (local.get $b)))
+    (then
+      (local.set $want (i32.shl (local.get $want) (i32.const 1)))
+      (local.set $data (call $bytes (call $alloc (i32.const 11) (i32.const 0) (local.get $want))))
+      (memory.copy (local.get $data) (call $buf_data (local.get $b)) (call $buf_len (local.get $b)))
+      (i32.store offset=24 (local.get $b) (local.get $data))
+      (i32.store offset=32 (local.get $b) (local.get $want))))
+  (i32.add (call $buf_data (local.get $b)) (call $buf_len (local.get $b))))
+
+(func $advance (param $b i32) (param $n i32)
+  (i32.store offset=28 (local.get $b) (i32.add (call $buf_len (local.get $b)) (local.get $n))))
+
+(func $put (param $b i32) (param $ptr i32) (param $len i32)
+  (memory.copy (call $ensure (local.get $b) (local.get $len)) (local.get $ptr) (local.get $len))
+  (call $advance (local.get $b) (local.get $len)))
+
+(func $put_byte (param $b i32) (param $c i32)
+  (i32.store8 (call $ensure (local.get $b) (i32.const 1)) (local.get $c))
+  (call $advance (local.get $b) (i32.const 1)))
+
+(func $put_str (param $b i32) (param $s i32)
+  (call $put (local.get $b) (call $bytes (local.get $s)) (call $len (local.get $s))))
+
+(func $put_buf (param $b i32) (param $other i32)
+  (call $put (local.get $b) (call $buf_data (local.get $other)) (call $buf_len (local.get $other))))
+
+(func $buf_str (param $b i32) (result i32)
+  (call $str (call $buf_data (local.get $b)) (call $buf_len (local.get $b))))
+
+;; writing values out the same way Display does for Expr
+
+(func $put_int (param $b i32) (param $i i64)
+  (local $u i64)
+  (local $t i64)
+  (local $digits i32)
+  (local $p i32)
+  (local.set $u (local.get $i))
+  (if (i64.lt_s (local.get $i) (i64.const 0))
+    (then
+      (call $put_byte (local.get $b) (i32.const 45))
+      (local.set $u (i64.sub (i64.const 0) (local.get $i)))))
+  (local.set $digits (i32.const 1))
+  (local.set $t (local.get $u))
+  (block $counted
+    (loop $count
+      (br_if $counted (i64.lt_u (local.get $t) (i64.const 10)))
+      (local.set $t (i64.div_u (local.get $t) (i64.const 10)))
+      (local.set $digits (i32.add (local.get $digits) (i32.const 1)))
+      (br $count)))
+  (local.set $p (call $ensure (local.get $b) (local.get $digits)))
+  (call $advance (local.get $b) (local.get $digits))
+  (loop $write
+    (local.set $digits (i32.sub (local.get $digits) (i32.const 1)))
+    (i32.store8 (i32.add (local.get $p) (local.get $digits))
+      (i32.add (i32.const 48) (i32.wrap_i64 (i64.rem_u (local.get $u) (i64.const 10)))))
+    (local.set $u (i64.div_u (local.get $u) (i64.const 10)))
+    (br_if $write (local.get $digits))))
+
+(func $put_float (param $b i32) (param $d f64)
+  ;; the longest is a tiny number written out without an exponent
+  (call $advance (local.get $b)
+    (call $format_float (local.get $d) (call $ensure (local.get $b) (i32.const 400)))))
+
+(func $put_hex (param $b i32) (param $c i32)
+  (call $put_byte (local.get $b)
+    (i32.add (local.get $c) (select (i32.const 48) (i32.const 87) (i32.lt_u (local.get $c) (i32.const 10))))))
+
+;; quoted and escaped like {:?}
+(func $put_debug (param $b i32) (param $ptr i32) (param $len i32)
+  (local $i i32)
+  (local $c i32)
+  (call $put_byte (local.get $b) (i32.const 34))
+  (block $done
+    (loop $next
+      (br_if $done (i32.eq (local.get $i) (local.get $len)))
+      (local.set $c (i32.load8_u (i32.add (local.get $ptr) (local.get $i))))
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (if (i32.or (i32.eq (local.get $c) (i32.const 34)) (i32.eq (local.get $c) (i32.const 92)))
+        (then
+          (call $put_byte (local.get $b) (i32.const 92))
+          (call $put_byte (local.get $b) (local.get $c))
+          (br $next)))
+      (if (i32.eq (local.get $c) (i32.const 10))
+        (then (call $put (local.get $b) (string "\\n")) (br $next)))
+      (if (i32.eq (local.get $c) (i32.const 13))
+        (then (call $put (local.get $b) (string "\\r")) (br $next)))
+      (if (i32.eq (local.get $c) (i32.const 9))
+        (then (call $put (local.get $b) (string "\\t")) (br $next)))
+      (if (i32.eqz (local.get $c))
+        (then (call $put (local.get $b) (string "\\0")) (br $next)))
+      (if (i32.or (i32.lt_u (local.get $c) (i32.const 32)) (i32.eq (local.get $c) (i32.const 127)))
+        (then
+          (call $put (local.get $b) (string "\\u{"))
+          (if (i32.ge_u (local.get $c) (i32.const 16))
+            (then (call $put_hex (local.get $b) (i32.shr_u (local.get $c) (i32.const 4)))))
+          (call $put_hex (local.get $b) (i32.and (local.get $c) (i32.const 15)))
+          (call $put_byte (local.get $b) (i32.const 125))
+          (br $next)))
+      (call $put_byte (local.get $b) (local.get $c))
+      (br $next)))
+  (call $put_byte (local.get $b) (i32.const 34)))
+
+(func $put_con_name (param $b i32) (param $con i32)
+  (call $put (local.get $b) (call $con_name (local.get $con)) (call $con_name_len (local.get $con))))
+
+;; a value as the field of a constructor
+(func $put_field (param $b i32) (param $v i32)
+  (local $t i32)
+  (local.set $t (call $tag (local.get $v)))
+  (if (i32.or
+        (i32.and (i32.eq (local.get $t) (i32.const 3)) (i32.ne (call $size (local.get $v)) (i32.const 0)))
+        (i32.or
+          (i32.and (i32.eqz (local.get $t)) (i64.lt_s (i64.load offset=16 (local.get $v)) (i64.const 0)))
+          (i32.and (i32.eq (local.get $t) (i32.const 1)) (f64.lt (f64.load offset=16 (local.get $v)) (f64.const 0)))))
+    (then
+      (call $put_byte (local.get $b) (i32.const 40))
+      (call $display (local.get $b) (local.get $v))
+      (call $put_byte (local.get $b) (i32.const 41))
+      (return)))
+  (if (i32.eq (local.get $t) (i32.const 2))
+    (then
+      (call $put_debug (local.get $b) (call $bytes (local.get $v)) (call $len (local.get $v)))
+      (return)))
+  (call $display (local.get $b) (local.get $v)))
+
+(func $display (param $b i32) (param $v i32)
+  (local $t i32)
+  (local $i i32)
+  (local $lam i32)
+  (local $entry i32)
+  (block $found
+    (loop $follow
+      (br_if $found (i32.ne (call $tag (local.get $v)) (i32.const 9)))
+      (local.set $v (call $get (local.get $v) (i32.const 0)))
+      (br $follow)))
+  (local.set $t (call $tag (local.get $v)))
+  (if (i32.eqz (local.get $t))
+    (then (call $put_int (local.get $b) (i64.load offset=16 (local.get $v))) (return)))
+  (if (i32.eq (local.get $t) (i32.const 1))
+    (then (call $put_float (local.get $b) (f64.load offset=16 (local.get $v))) (return)))
+  (if (i32.eq (local.get $t) (i32.const 2))
+    (then (call $put_str (local.get $b) (local.get $v)) (return)))
+  (if (i32.or (i32.eq (local.get $t) (i32.const 3)) (i32.eq (local.get $t) (i32.const 5)))
+    (then
+      (if (i32.eq (local.get $t) (i32.const 5)) (then (call $put_byte (local.get $b) (i32.const 60))))
+      (call $put_con_name (local.get $b) (call $aux (local.get $v)))
+      (block $fields
+        (loop $field
+          (br_if $fields (i32.eq (local.get $i) (call $size (local.get $v))))
+          (call $put_byte (local.get $b) (i32.const 32))
+          (call $put_field (local.get $b) (call $get (local.get $v) (local.get $i)))
+          (local.set $i (i32.add (local.get $i) (i32.const 1)))
+          (br $field)))
+      (if (i32.eq (local.get $t) (i32.const 5))
+        (then
+          (block $holes
+            (loop $hole
+              (br_if $holes (i32.ge_u (local.get $i) (call $con_arity (call $aux (local.get $v)))))
+              (call $put (local.get $b) (string " _"))
+              (local.set $i (i32.add (local.get $i) (i32.const 1)))
+              (br $hole)))
+          (call $put_byte (local.get $b) (i32.const 62))))
+      (return)))
+  (if (i32.eq (local.get $t) (i32.const 6))
+    (then
+      (local.set $entry (call $builtin_entry (call $aux (local.get $v))))
+      (call $put_byte (local.get $b) (i32.const 40))
+      (call $put (local.get $b) (i32.load (local.get $entry)) (i32.load offset=4 (local.get $entry)))
+      (block $args
+        (loop $arg
+          (br_if $args (i32.eq (local.get $i) (call $size (local.get $v))))
+          (call $put_byte (local.get $b) (i32.const 32))
+          (call $display (local.get $b) (call $get (local.get $v) (local.get $i)))
+          (local.set $i (i32.add (local.get $i) (i32.const 1)))
+          (br $arg)))
+      (call $put_byte (local.get $b) (i32.const 41))
+      (return)))
+  (if (i32.eq (local.get $t) (i32.const 4))
+    (then
+      (local.set $lam (call $lam (call $aux (local.get $v))))
+      (call $put (local.get $b) (i32.load offset=8 (local.get $lam)) (i32.load offset=12 (local.get $lam)))
+      (return)))
+  (call $put_byte (local.get $b) (i32.const 95)))
+
+;; the text of a value the way bagl prints it, as a string object
+(func (export "display") (param $v i32) (result i32)
+  (local $b i32)
+  (local.set $b (call $buf_new))
+  (call $display (local.get $b) (call $force (local.get $v)))
+  (call $buf_str (local.get $b)))
+
+;; builtins, the arguments have been forced, the second is the same as the first for the ones that take one
+
+(func $ints (param $x i32) (param $y i32) (result i32)
+  (i32.and (i32.eqz (call $tag (local.get $x))) (i32.eqz (call $tag (local.get $y)))))
+
+(func $floats (param $x i32) (param $y i32) (result i32)
+  (i32.and (i32.eq (call $tag (local.get $x)) (i32.const 1)) (i32.eq (call $tag (local.get $y)) (i32.const 1))))
+
+(func $strs (param $x i32) (param $y i32) (result i32)
+  (i32.and (i32.eq (call $tag (local.get $x)) (i32.const 2)) (i32.eq (call $tag (local.get $y)) (i32.const 2))))
+
+(func $i (param $v i32) (result i64) (i64.load offset=16 (local.get $v)))
+(func $d (param $v i32) (result f64) (f64.load offset=16 (local.get $v)))
+
+(func $overflow (result i32)
+  (call $error (string "Integer overflow, compiled programs only have 64 bit integers.")))
+
+(func $add (param $x i32) (param $y i32) (result i32)
+  (local $a i64)
+  (local $b i64)
+  (local $r i64)
+  (if (call $ints (local.get $x) (local.get $y))
+    (then
+      (local.set $a (call $i (local.get $x)))
+      (local.set $b (call $i (local.get $y)))
+      (local.set $r (i64.add (local.get $a) (local.get $b)))
+      (if (i64.lt_s
+            (i64.and (i64.xor (local.get $a) (local.get $r)) (i64.xor (local.get $b) (local.get $r)))
+            (i64.const 0))
+        (then (return (call $overflow))))
+      (return (call $int (local.get $r)))))
+  (if (call $floats (local.get $x) (local.get $y))
+    (then (return (call $float (f64.add (call $d (local.get $x)) (call $d (local.get $y)))))))
+  (call $error (string "Can only add numbers.")))
+
+(func $sub (param $x i32) (param $y i32) (result i32)
+  (local $a i64)
+  (local $b i64)
+  (local $r i64)
+  (if (call $ints (local.get $x) (local.get $y))
+    (then
+      (local.set $a (call $i (local.get $x)))
+      (local.set $b (call $i (local.get $y)))
+      (local.set $r (i64.sub (local.get $a) (local.get $b)))
+      (if (i64.lt_s
+            (i64.and (i64.xor (local.get $a) (local.get $b)) (i64.xor (local.get $a) (local.get $r)))
+            (i64.const 0))
+        (then (return (call $overflow))))
+      (return (call $int (local.get $r)))))
+  (if (call $floats (local.get $x) (local.get $y))
+    (then (return (call $float (f64.sub (call $d (local.get $x)) (call $d (local.get $y)))))))
+  (call $error (string "Can only subtract numbers.")))
+
+(func $mult (param $x i32) (param $y i32) (result i32)
+  (local $a i64)
+  (local $b i64)
+  (local $r i64)
+  (if (call $ints (local.get $x) (local.get $y))
+    (then
+      (local.set $a (call $i (local.get $x)))
+      (local.set $b (call $i (local.get $y)))
+      (local.set $r (i64.mul (local.get $a) (local.get $b)))
+      ;; -1 times the smallest integer is the only product that the division check would trap on
+      (if (i64.eq (local.get $a) (i64.const -1))
+        (then
+          (if (i64.eq (local.get $b) (i64.const 0x8000000000000000))
+            (then (return (call $overflow))))))
+      (if (i32.and
+            (i64.ne (local.get $a) (i64.const 0))
+            (i64.ne (local.get $a) (i64.const -1)))
+        (then
+          (if (i64.ne (i64.div_s (local.get $r) (local.get $a)) (local.get $b))
+            (then (return (call $overflow))))))
+      (return (call $int (local.get $r)))))
+  (if (call $floats (local.get $x) (local.get $y))
+    (then (return (call $float (f64.mul (call $d (local.get $x)) (call $d (local.get $y)))))))
+  (call $error (string "Can only multiply numbers.")))
+
+(func $div (param $x i32) (param $y i32) (result i32)
+  (if (call $ints (local.get $x) (local.get $y))
+    (then
+      (if (i64.eqz (call $i (local.get $y)))
+        (then (return (call $error (string "attempt to divide by zero")))))
+      (if (i32.and
+            (i64.eq (call $i (local.get $x)) (i64.const 0x8000000000000000))
+            (i64.eq (call $i (local.get $y)) (i64.const -1)))
+        (then (return (call $overflow))))
+      (return (call $int (i64.div_s (call $i (local.get $x)) (call $i (local.get $y)))))))
+  (if (call $floats (local.get $x) (local.get $y))
+    (then (return (call $float (f64.div (call $d (local.get $x)) (call $d (local.get $y)))))))
+  (call $error (string "Can only divide numbers.")))
+
+(func $same_str (param $x i32) (param $y i32) (result i32)
+  (call $is_str (local.get $x) (call $bytes (local.get $y)) (call $len (local.get $y))))
+
+(func $eq (param $x i32) (param $y i32) (result i32)
+  (if (call $ints (local.get $x) (local.get $y))
+    (then (return (call $bool (i64.eq (call $i (local.get $x)) (call $i (local.get $y)))))))
+  (if (call $floats (local.get $x) (local.get $y))
+    (then (return (call $bool (f64.eq (call $d (local.get $x)) (call $d (local.get $y)))))))
+  (if (call $strs (local.get $x) (local.get $y))
+    (then (return (call $bool (call $same_str (local.get $x) (local.get $y))))))
+  (call $error (string "Can only equate numbers and strings.")))
+
+(func $order (param $less i32) (param $greater i32) (result i32)
+  (local $p i32)
+  (local.set $p (call $alloc (i32.const 3) (i32.const 0) (i32.const 0)))
+  (i32.store offset=8 (local.get $p)
+    (if (result i32) (local.get $less)
+      (then (global.get $con_less))
+      (else (select (global.get $con_greater) (global.get $con_equal) (local.get $greater)))))
+  (local.get $p))
+
+(func $compare (param $x i32) (param $y i32) (result i32)
+  (local $i i32)
+  (local $n i32)
+  (local $a i32)
+  (local $b i32)
+  (if (call $ints (local.get $x) (local.get $y))
+    (then
+      (return
+        (call $order
+          (i64.lt_s (call $i (local.get $x)) (call $i (local.get $y)))
+          (i64.gt_s (call $i (local.get $x)) (call $i (local.get $y)))))))
+  (if (call $floats (local.get $x) (local.get $y))
+    (then
+      (if (i32.or
+            (f64.ne (call $d (local.get $x)) (call $d (local.get $x)))
+            (f64.ne (call $d (local.get $y)) (call $d (local.get $y))))
+        (then (return (call $error (string "Can't compare NaN.")))))
+      (return
+        (call $order
+          (f64.lt (call $d (local.get $x)) (call $d (local.get $y)))
+          (f64.gt (call $d (local.get $x)) (call $d (local.get $y)))))))
+  (if (call $strs (local.get $x) (local.get $y))
+    (then
+      (local.set $n
+        (select (call $len (local.get $x)) (call $len (local.get $y))
+          (i32.lt_u (call $len (local.get $x)) (call $len (local.get $y)))))
+      (block $done
+        (loop $next
+          (br_if $done (i32.eq (local.get $i) (local.get $n)))
+          (local.set $a (i32.load8_u (i32.add (call $bytes (local.get $x)) (local.get $i))))
+          (local.set $b (i32.load8_u (i32.add (call $bytes (local.get $y)) (local.get $i))))
+          (if (i32.ne (local.get $a) (local.get $b))
+            (then
+              (return
+                (call $order
+                  (i32.lt_u (local.get $a) (local.get $b))
+                  (i32.gt_u (local.get $a) (local.get $b))))))
+          (local.set $i (i32.add (local.get $i) (i32.const 1)))
+          (br $next)))
+      (return
+        (call $order
+          (i32.lt_u (call $len (local.get $x)) (call $len (local.get $y)))
+          (i32.gt_u (call $len (local.get $x)) (call $len (local.get $y)))))))
+  (call $error (string "Can only compare numbers or strings of the same type.")))
+
+(func $show (param $x i32) (result i32)
+  (local $b i32)
+  (local.set $b (call $buf_new))
+  (block $shown
+    (if (i32.eqz (call $tag (local.get $x)))
+      (then (call $put_int (local.get $b) (call $i (local.get $x))) (br $shown)))
+    (if (i32.eq (call $tag (local.get $x)) (i32.const 1))
+      (then (call $put_float (local.get $b) (call $d (local.get $x))) (br $shown)))
+    (if (i32.eq (call $tag (local.get $x)) (i32.const 2))
+      (then
+        (call $put_debug (local.get $b) (call $bytes (local.get $x)) (call $len (local.get $x)))
+        (br $shown)))
+    (return (call $error (string "Can only show numbers and strings."))))
+  (call $buf_str (local.get $b)))
+
+;; spaces inside of brackets or strings don't count
+(func $needs_parens (param $s i32) (result i32)
+  (local $i i32)
+  (local $c i32)
+  (local $depth i32)
+  (local $quoted i32)
+  (local $escaped i32)
+  (block $done
+    (loop $next
+      (br_if $done (i32.eq (local.get $i) (call $len (local.get $s))))
+      (local.set $c (i32.load8_u (i32.add (call $bytes (local.get $s)) (local.get $i))))
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (if (local.get $quoted)
+        (then
+          (if (local.get $escaped)
+            (then (local.set $escaped (i32.const 0)))
+            (else
+              (if (i32.eq (local.get $c) (i32.const 92))
+                (then (local.set $escaped (i32.const 1)))
+                (else
+                  (if (i32.eq (local.get $c) (i32.const 34))
+                    (then (local.set $quoted (i32.const 0))))))))
+          (br $next)))
+      (if (i32.eq (local.get $c) (i32.const 34))
+        (then (local.set $quoted (i32.const 1)) (br $next)))
+      (if (i32.or (i32.eq (local.get $c) (i32.const 40))
+            (i32.or (i32.eq (local.get $c) (i32.const 91)) (i32.eq (local.get $c) (i32.const 123))))
+        (then (local.set $depth (i32.add (local.get $depth) (i32.const 1))) (br $next)))
+      (if (i32.or (i32.eq (local.get $c) (i32.const 41))
+            (i32.or (i32.eq (local.get $c) (i32.const 93)) (i32.eq (local.get $c) (i32.const 125))))
+        (then (local.set $depth (i32.sub (local.get $depth) (i32.const 1))) (br $next)))
+      (if (i32.and (i32.eq (local.get $c) (i32.const 32)) (i32.eqz (local.get $depth)))
+        (then (return (i32.const 1))))
+      (br $next)))
+  (i32.and
+    (i32.ne (call $len (local.get $s)) (i32.const 0))
+    (i32.eq (i32.load8_u (call $bytes (local.get $s))) (i32.const 45))))
+
+(func $parens (param $x i32) (result i32)
+  (local $b i32)
+  (if (i32.ne (call $tag (local.get $x)) (i32.const 2))
+    (then (return (call $error (string "Can only put parentheses around strings.")))))
+  (if (i32.eqz (call $needs_parens (local.get $x)))
+    (then (return (local.get $x))))
+  (local.set $b (call $buf_new))
+  (call $put_byte (local.get $b) (i32.const 40))
+  (call $put_str (local.get $b) (local.get $x))
+  (call $put_byte (local.get $b) (i32.const 41))
+  (call $buf_str (local.get $b)))
+
+(func $concat (param $x i32) (param $y i32) (result i32)
+  (local $b i32)
+  (if (i32.eqz (call $strs (local.get $x) (local.get $y)))
+    (then (return (call $error (string "Can only concatenate strings.")))))
+  (local.set $b (call $buf_new))
+  (call $put_str (local.get $b) (local.get $x))
+  (call $put_str (local.get $b) (local.get $y))
+  (call $buf_str (local.get $b)))
+
+(func $assert (param $x i32) (result i32)
+  (if (i32.eq (call $tag (local.get $x)) (i32.const 3))
+    (then
+      (if (i32.eq (call $aux (local.get $x)) (global.get $con_true))
+        (then (return (local.get $x))))
+      (if (i32.eq (call $aux (local.get $x)) (global.get $con_false))
+        (then (return (call $error (string "Error: assert failed")))))))
+  (call $error (string "Can only assert booleans.")))
+
+;; strings are quoted so "1" and 1 don't look the same
+(func $put_shown (param $b i32) (param $v i32)
+  (if (i32.eq (call $tag (local.get $v)) (i32.const 2))
+    (then (call $put_debug (local.get $b) (call $bytes (local.get $v)) (call $len (local.get $v))))
+    (else (call $display (local.get $b) (local.get $v)))))
+
+(func $put_marked (param $b i32) (param $v i32)
+  (call $put_byte (local.get $b) (i32.const 91))
+  (call $put_shown (local.get $b) (local.get $v))
+  (call $put_byte (local.get $b) (i32.const 93)))
+
+;; the constructor with _ for every field but the one that differs
+(func $around (param $b i32) (param $data i32) (param $at i32) (param $inner i32)
+  (local $i i32)
+  (local $wrap i32)
+  (local $j i32)
+  ;; parentheses when the inner part has a space, unless it is only the marked value
+  (block $checked
+    (loop $next
+      (br_if $checked (i32.eq (local.get $j) (call $buf_len (local.get $inner))))
+      (if (i32.eq (i32.load8_u (i32.add (call $buf_data (local.get $inner)) (local.get $j))) (i32.const 32))
+        (then (local.set $wrap (i32.const 1)) (br $checked)))
+      (local.set $j (i32.add (local.get $j) (i32.const 1)))
+      (br $next)))
+  (if (i32.eq (i32.load8_u (call $buf_data (local.get $inner))) (i32.const 91))
+    (then (local.set $wrap (i32.const 0))))
+  (call $put_con_name (local.get $b) (call $aux (local.get $data)))
+  (block $done
+    (loop $field
+      (br_if $done (i32.eq (local.get $i) (call $size (local.get $data))))
+      (call $put_byte (local.get $b) (i32.const 32))
+      (if (i32.eq (local.get $i) (local.get $at))
+        (then
+          (if (local.get $wrap) (then (call $put_byte (local.get $b) (i32.const 40))))
+          (call $put_buf (local.get $b) (local.get $inner))
+          (if (local.get $wrap) (then (call $put_byte (local.get $b) (i32.const 41)))))
+        (else (call $put_byte (local.get $b) (i32.const 95))))
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (br $field))))
+
+;; 0 when they're the same, 1 with the first difference marked in left and right, -1 with a message in left
+(func $difference (param $x i32) (param $y i32) (param $left i32) (param $right i32) (result i32)
+  (local $i i32)
+  (local $l i32)
+  (local $r i32)
+  (local $found i32)
+  (local $same i32)
+  (if (i32.and (i32.eq (call $tag (local.get $x)) (call $tag (local.get $y)))
+               (i32.le_u (call $tag (local.get $x)) (i32.const 2)))
+    (then
+      (local.set $same
+        (if (result i32) (i32.eqz (call $tag (local.get $x)))
+          (then (i64.eq (call $i (local.get $x)) (call $i (local.get $y))))
+          (else
+            (if (result i32) (i32.eq (call $tag (local.get $x)) (i32.const 1))
+              (then (f64.eq (call $d (local.get $x)) (call $d (local.get $y))))
+              (else (call $same_str (local.get $x) (local.get $y)))))))
+      (if (local.get $same) (then (return (i32.const 0))))
+      (call $put_marked (local.get $left) (local.get $x))
+      (call $put_marked (local.get $right) (local.get $y))
+      (return (i32.const 1))))
+  (if (i32.and (i32.eq (call $tag (local.get $x)) (i32.const 3))
+               (i32.eq (call $tag (local.get $y)) (i32.const 3)))
+    (then
+      (if (i32.ne (call $aux (local.get $x)) (call $aux (local.get $y)))
+        (then
+          (call $put_marked (local.get $left) (local.get $x))
+          (call $put_marked (local.get $right) (local.get $y))
+          (return (i32.const 1))))
+      (block $done
+        (loop $field
+          (br_if $done (i32.eq (local.get $i) (call $size (local.get $x))))
+          (local.set $l (call $buf_new))
+          (local.set $r (call $buf_new))
+          (local.set $found
+            (call $difference
+              (call $get (local.get $x) (local.get $i))
+              (call $get (local.get $y) (local.get $i))
+              (local.get $l)
+              (local.get $r)))
+          (if (i32.lt_s (local.get $found) (i32.const 0))
+            (then
+              (call $put_buf (local.get $left) (local.get $l))
+              (return (local.get $found))))
+          (if (local.get $found)
+            (then
+              (call $around (local.get $left) (local.get $x) (local.get $i) (local.get $l))
+              (call $around (local.get $right) (local.get $x) (local.get $i) (local.get $r))
+              (return (i32.const 1))))
+          (local.set $i (i32.add (local.get $i) (i32.const 1)))
+          (br $field)))
+      (return (i32.const 0))))
+  (call $put (local.get $left) (string "assertEq can only compare values, not "))
+  (call $put_shown (local.get $left) (local.get $x))
+  (call $put (local.get $left) (string " and "))
+  (call $put_shown (local.get $left) (local.get $y))
+  (i32.const -1))
+
+(func $assert_eq (param $x i32) (param $y i32) (result i32)
+  (local $left i32)
+  (local $right i32)
+  (local $message i32)
+  (local $found i32)
+  (local.set $left (call $buf_new))
+  (local.set $right (call $buf_new))
+  (local.set $found (call $difference (local.get $x) (local.get $y) (local.get $left) (local.get $right)))
+  (if (i32.eqz (local.get $found))
+    (then (return (call $bool (i32.const 1)))))
+  (local.set $message (call $buf_new))
+  (call $put (local.get $message) (string "Error: "))
+  (if (i32.lt_s (local.get $found) (i32.const 0))
+    (then (call $put_buf (local.get $message) (local.get $left)))
+    (else
+      (call $put (local.get $message) (string "assertEq failed\n    left:  "))
+      (call $put_shown (local.get $message) (local.get $x))
+      (call $put (local.get $message) (string "\n    right: "))
+      (call $put_shown (local.get $message) (local.get $y))
+      (call $put (local.get $message) (string "\n    first difference: "))
+      (call $put_buf (local.get $message) (local.get $left))
+      (call $put (local.get $message) (string " vs "))
+      (call $put_buf (local.get $message) (local.get $right))))
+  (call $error (call $buf_data (local.get $message)) (call $buf_len (local.get $message))))
+
+;; a builtin with all of its arguments, the numbers are the order of the builtin table wasm.rs makes
+(func $prim (param $id i32) (param $x i32) (param $y i32) (result i32)
+  (block $assert_eq
+    (block $assert
+      (block $parens
+        (block $show
+          (block $compare
+            (block $eq
+              (block $concat
+                (block $div
+                  (block $mult
+                    (block $sub
+                      (block $add
+                        (br_table $add $sub $mult $div $concat $eq $compare $show $parens $assert $assert_eq
+                          (local.get $id)))
+                      (return (call $add (local.get $x) (local.get $y))))
+                    (return (call $sub (local.get $x) (local.get $y))))
+                  (return (call $mult (local.get $x) (local.get $y))))
+                (return (call $div (local.get $x) (local.get $y))))
+              (return (call $concat (local.get $x) (local.get $y))))
+            (return (call $eq (local.get $x) (local.get $y))))
+          (return (call $compare (local.get $x) (local.get $y))))
+        (return (call $show (local.get $x))))
+      (return (call $parens (local.get $x))))
+    (return (call $assert (local.get $x))))
+  (call $assert_eq (local.get $x) (local.get $y)))
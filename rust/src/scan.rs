@@ -103,44 +103,89 @@ seems to now be working, really overcomplicated that at first
     let and letrec now act differently as desired
 
 
+moving to a lexically scoped environment (debruijn style)
+    the depth counting above only works because lookups still search by name
+    want to store exactly where a variable is so a lookup doesn't need to hash the name or search
+
+every binder creates a frame:
+    lambda: one slot for the argument
+    let: a slot per variable, the definitions are resolved outside of the new frame
+    letrec: a slot per variable, definitions and body are resolved inside
+    case branch: a slot per variable in the pattern (possibly none)
+
+a variable gets (depth, slot), depth is how many frames up from the current one and slot is the position in that frame
+this has to run after the lets are rearranged since that changes the frames
+
+evaluation has to create frames in exactly the same way, closures keep the environment they were made in
+
 */
 
 use crate::ast::Expr;
 use std::rc::Rc;
 
-pub fn resolve(expr: Rc<Expr>, depth: usize) -> usize {
+pub fn resolve(expr: Rc<Expr>) {
+    let mut frames = Vec::new();
+    resolve_in(expr, &mut frames);
+}
+
+// frames holds the variable names of each frame, innermost last
+fn resolve_in(expr: Rc<Expr>, frames: &mut Vec<Vec<String>>) {
     match &*expr {
-        Expr::Var(_, d) => {
-            // set depth
-            d.replace(depth);
-            depth
+        Expr::Var(s, coords, _) => {
+            for (depth, frame) in frames.iter().rev().enumerate() {
+                if let Some(slot) = frame.iter().rposition(|name| name == s) {
+                    coords.replace((depth, slot));
+                    return;
+                }
+            }
+            panic!("Variable not found while resolving, {}", s)
         }
         Expr::App(left, right) => {
-            // have to do special treatment as lambdas will modify the environment when defined
-            let d = resolve(Rc::clone(left), depth);
-            resolve(Rc::clone(right), d)
+            resolve_in(Rc::clone(left), frames);
+            resolve_in(Rc::clone(right), frames);
         }
-        Expr::Let(_, defs, body) => {
-            // for each variable create environment and resolve the depth in the variable and the definition
+        Expr::Let(vars, defs, body) => {
             for def in defs {
-                resolve(Rc::clone(def), 1);
+                resolve_in(Rc::clone(def), frames);
             }
-            resolve(Rc::clone(body), 0)
+            frames.push(vars.iter().map(name).collect());
+            resolve_in(Rc::clone(body), frames);
+            frames.pop();
         }
-        Expr::LetRec(_, defs, body) => {
-            // define all variables in a new environment layer and set all of the depths to the new one
+        Expr::LetRec(vars, defs, body) => {
+            frames.push(vars.iter().map(name).collect());
             for def in defs {
-                resolve(Rc::clone(def), 0);
+                resolve_in(Rc::clone(def), frames);
             }
-            resolve(Rc::clone(body), 0)
+            resolve_in(Rc::clone(body), frames);
+            frames.pop();
         }
         Expr::If(cond, b1, b2) => {
-            resolve(Rc::clone(cond), depth);
-            resolve(Rc::clone(b1), depth);
-            resolve(Rc::clone(b2), depth);
-            depth
+            resolve_in(Rc::clone(cond), frames);
+            resolve_in(Rc::clone(b1), frames);
+            resolve_in(Rc::clone(b2), frames);
         }
-        Expr::Lam(_, body) => resolve(Rc::clone(body), 0),
-        _ => depth,
+        Expr::Lam(head, body) => {
+            frames.push(vec![name(head)]);
+            resolve_in(Rc::clone(body), frames);
+            frames.pop();
+        }
+        Expr::Case(cond, pats, branches, _) => {
+            resolve_in(Rc::clone(cond), frames);
+            for (pat, branch) in pats.iter().zip(branches) {
+                frames.push(pat.vars().iter().map(|v| v.to_string()).collect());
+                resolve_in(Rc::clone(branch), frames);
+                frames.pop();
+            }
+        }
+        Expr::Annot(inner, _) => resolve_in(Rc::clone(inner), frames),
+        _ => (),
+    }
+}
+
+fn name(var: &Rc<Expr>) -> String {
+    match &**var {
+        Expr::Var(s, _, _) => s.to_string(),
+        _ => panic!("Can only define variables."),
     }
 }
@@ -0,0 +1,440 @@
+/*
+
+simplify a program in a-normal form before it runs, so work that can be done once isn't done every time
+
+what it does
+    a let of an atom is copied to where the variable is used and the let goes away
+    a small lambda from a let is copied to where it's applied to all of its arguments, that's the beta reduction
+    a builtin applied to all of its arguments, all Int or Float literals, is replaced by its result
+    a case of a variable that's known to be a constructor goes straight to the matching branch
+    an if of True or False goes straight to the branch
+    lets that aren't used anymore are removed, they're lazy so nothing is lost by not evaluating them
+each pass can make more of these possible, so passes are repeated until one doesn't change anything
+copying lambdas makes the program bigger, so it stops copying them once they've added as much as the program was to begin with
+
+it can't change what a program does, and the evaluation order is part of that
+    a lambda forces its arguments, a beta reduction only happens when they're values already
+    otherwise an error in an argument the body never uses would stop being an error
+    parameters of lambdas and fields of constructors are values, they were forced to get there
+    so is anything that's a literal, a lambda, or a constructor applied to values
+    constructors are strict too, so a case is only skipped when the fields are values
+    dividing an Int by 0 is left to happen at runtime
+
+names are scoped, when something is defined again anything known that uses the old one is forgotten in its scope
+the variables that the copy would have captured stay as they are, and their lets stay too
+
+*/
+
+use crate::anf::Atom;
+use crate::anf::Term;
+use crate::ast::Expr;
+use crate::ast::Pattern;
+use crate::lift::is_lazy;
+use std::fmt::Display;
+use std::rc::Rc;
+
+// lambdas with a bigger body than this aren't copied
+const INLINE_SIZE: usize = 20;
+const PASSES: usize = 10;
+
+// what the simplifier did, each change says which top level definition it was in
+pub struct Report {
+    pub passes: usize,
+    pub changes: Vec<String>,
+}
+
+// what's known about a variable where it's used
+#[derive(Clone)]
+enum Known {
+    Unknown,
+    Value,                                   // evaluated, but nothing else is known
+    Atom(Atom),                              // can be replaced by the atom
+    Lam(Vec<String>, Rc<Term>, Vec<String>), // parameters, body, free variables
+    Con(String, Vec<Atom>),                  // a constructor applied to values
+}
+
+pub fn simplify(term: &Term) -> (Term, Report) {
+    // the program can get twice as big
+    let mut growth = size(term) as isize;
+    let mut term = term.clone();
+    let mut report = Report {
+        passes: 0,
+        changes: Vec::new(),
+    };
+    while report.passes < PASSES {
+        let mut pass = Simplifier {
+            known: Vec::new(),
+            top: true,
+            within: "the body".to_string(),
+            growth,
+            changes: Vec::new(),
+        };
+        let next = pass.term(&term);
+        growth = pass.growth;
+        report.passes += 1;
+        if pass.changes.is_empty() {
+            break;
+        }
+        report.changes.extend(pass.changes);
+        term = next;
+    }
+    (term, report)
+}
+
+fn size(term: &Term) -> usize {
+    match term {
+        Term::App(_, args) => 1 + args.len(),
+        Term::Lam(_, body) => 1 + size(body),
+        Term::Let(_, def, body) => 1 + size(def) + size(body),
+        Term::LetRec(defs, body) => {
+            1 + defs.iter().map(|(_, d)| size(d)).sum::<usize>() + size(body)
+        }
+        Term::Case(_, alts) => 1 + alts.iter().map(|(_, b)| size(b)).sum::<usize>(),
+        Term::If(_, b1, b2) => 1 + size(b1) + size(b2),
+        _ => 1,
+    }
+}
+
+struct Simplifier {
+    known: Vec<(String, Known)>, // the innermost definition of a name is the last one
+    top: bool,                   // still in the lets around the body
+    within: String,
+    growth: isize, // how much bigger copying lambdas can still make the program
+    changes: Vec<String>,
+}
+
+impl Known {
+    fn uses(&self, name: &str) -> bool {
+        match self {
+            Known::Atom(Atom::Var(v)) => v == name,
+            Known::Lam(_, _, free) => free.iter().any(|v| v == name),
+            Known::Con(_, args) => args.iter().any(|a| matches!(a, Atom::Var(v) if v == name)),
+            _ => false,
+        }
+    }
+}
+
+impl Simplifier {
+    fn change(&mut self, what: String) {
+        self.changes.push(format!("{}: {}", self.within, what));
+    }
+
+    fn lookup(&self, name: &str) -> Known {
+        match self.known.iter().rev().find(|(n, _)| n == name) {
+            Some((_, known)) => known.clone(),
+            None => Known::Unknown,
+        }
+    }
+
+    // a new definition of name, what was known in terms of the old one is forgotten
+    fn bind(&mut self, name: &str, known: Known) {
+        let mut forgotten = Vec::new();
+        for (n, k) in &self.known {
+            if k.uses(name) && !forgotten.contains(n) {
+                forgotten.push(n.to_string());
+            }
+        }
+        for n in forgotten {
+            if self.lookup(&n).uses(name) {
+                self.known.push((n, Known::Unknown));
+            }
+        }
+        self.known.push((name.to_string(), known));
+    }
+
+    fn atom(&self, atom: &Atom) -> Atom {
+        if let Atom::Var(name) = atom {
+            if let Known::Atom(a) = self.lookup(name) {
+                return a;
+            }
+        }
+        atom.clone()
+    }
+
+    fn is_value(&self, atom: &Atom) -> bool {
+        match atom {
+            Atom::Var(name) => matches!(
+                self.lookup(name),
+                Known::Value | Known::Lam(_, _, _) | Known::Con(_, _)
+            ),
+            _ => true,
+        }
+    }
+
+    // what's known about a let from its definition
+    fn known(&self, def: &Term) -> Known {
+        match def {
+            Term::Atom(a) => Known::Atom(a.clone()),
+            Term::Lam(params, body) => {
+                Known::Lam(params.to_vec(), Rc::clone(body), def.free_vars())
+            }
+            Term::App(Atom::Con(arity, _, name), args)
+                if args.len() == *arity && args.iter().all(|a| self.is_value(a)) =>
+            {
+                Known::Con(name.to_string(), args.to_vec())
+            }
+            _ => Known::Unknown,
+        }
+    }
+
+    fn term(&mut self, term: &Term) -> Term {
+        let top = std::mem::replace(&mut self.top, false);
+        let mark = self.known.len();
+        let simplified = match term {
+            Term::Atom(a) => Term::Atom(self.atom(a)),
+            Term::App(func, args) => {
+                let func = self.atom(func);
+                let args: Vec<Atom> = args.iter().map(|a| self.atom(a)).collect();
+                match self.fold(&func, &args) {
+                    Some(folded) => folded,
+                    None => match self.beta(&func, &args) {
+                        Some(reduced) => self.term(&reduced),
+                        None => Term::App(func, args),
+                    },
+                }
+            }
+            Term::Lam(params, body) => {
+                for param in params {
+                    let known = if is_lazy(param) {
+                        Known::Unknown
+                    } else {
+                        Known::Value
+                    };
+                    self.bind(param, known);
+                }
+                Term::Lam(params.to_vec(), Rc::new(self.term(body)))
+            }
+            Term::Let(name, def, body) => {
+                if top {
+                    self.within = name.to_string();
+                }
+                let def = self.term(def);
+                let known = self.known(&def);
+                if top {
+                    self.within = "the body".to_string();
+                }
+                self.top = top;
+                if matches!(&known, Known::Atom(Atom::Var(v)) if v == name) {
+                    // let x = x of the x from outside doesn't do anything
+                    self.term(body)
+                } else {
+                    self.bind(name, known);
+                    let body = self.term(body);
+                    if body.free_vars().contains(name) {
+                        Term::Let(name.to_string(), Rc::new(def), Rc::new(body))
+                    } else {
+                        match def {
+                            Term::Atom(_) | Term::Lam(_, _) => {
+                                self.change(format!("inlined {}", name))
+                            }
+                            _ => self.change(format!("removed unused {}", name)),
+                        }
+                        body
+                    }
+                }
+            }
+            Term::LetRec(defs, body) => {
+                for (name, def) in defs.iter() {
+                    let known = match &**def {
+                        Term::Lam(_, _) => Known::Value,
+                        _ => Known::Unknown,
+                    };
+                    self.bind(name, known);
+                }
+                let defs: Vec<(String, Rc<Term>)> = defs
+                    .iter()
+                    .map(|(name, def)| {
+                        if top {
+                            self.within = name.to_string();
+                        }
+                        (name.to_string(), Rc::new(self.term(def)))
+                    })
+                    .collect();
+                if top {
+                    self.within = "the body".to_string();
+                }
+                self.top = top;
+                let body = self.term(body);
+                let free = body.free_vars();
+                if defs.iter().any(|(name, _)| free.contains(name)) {
+                    Term::LetRec(defs, Rc::new(body))
+                } else {
+                    let names: Vec<&str> = defs.iter().map(|(name, _)| name.as_str()).collect();
+                    self.change(format!("removed unused {}", names.join(", ")));
+                    body
+                }
+            }
+            Term::Case(name, alts) => {
+                let scrutinee = self.atom(&Atom::Var(name.to_string()));
+                match self.known_case(&scrutinee, alts) {
+                    Some(branch) => self.term(&branch),
+                    None => {
+                        let name = match scrutinee {
+                            Atom::Var(v) => v,
+                            _ => name.to_string(),
+                        };
+                        let alts = alts
+                            .iter()
+                            .map(|(pat, branch)| {
+                                let mark = self.known.len();
+                                for v in pat.vars() {
+                                    self.bind(v, Known::Value);
+                                }
+                                let branch = self.term(branch);
+                                self.known.truncate(mark);
+                                (pat.clone(), Rc::new(branch))
+                            })
+                            .collect();
+                        Term::Case(name, alts)
+                    }
+                }
+            }
+            Term::If(cond, b1, b2) => match self.atom(cond) {
+                Atom::Con(_, _, name) if name == "True" => {
+                    self.change("if True".to_string());
+                    self.term(b1)
+                }
+                Atom::Con(_, _, name) if name == "False" => {
+                    self.change("if False".to_string());
+                    self.term(b2)
+                }
+                cond => Term::If(cond, Rc::new(self.term(b1)), Rc::new(self.term(b2))),
+            },
+            Term::Error(_) | Term::Bottom => term.clone(),
+        };
+        self.known.truncate(mark);
+        simplified
+    }
+
+    // a builtin applied to literals is replaced by what it gives back
+    fn fold(&mut self, func: &Atom, args: &[Atom]) -> Option<Term> {
+        let Atom::Builtin(arity, name, f) = func else {
+            return None;
+        };
+        if args.len() != *arity
+            || !args
+                .iter()
+                .all(|a| matches!(a, Atom::Int(_) | Atom::Float(_)))
+        {
+            return None;
+        }
+        if name == "/" && matches!(&args[1], Atom::Int(n) if n == &0.into()) {
+            return None;
+        }
+        let atom = match &*f(args.iter().map(Atom::to_expr).collect()) {
+            Expr::Int(n, _) => Atom::Int(n.clone()),
+            Expr::Float(n, _) => Atom::Float(*n),
+            Expr::Str(s, _) => Atom::Str(s.to_string()),
+            Expr::Data(0, typ, con, fields) if fields.is_empty() => {
+                Atom::Con(0, typ.to_string(), con.to_string())
+            }
+            _ => return None,
+        };
+        let shown: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        self.change(format!("folded ({}) {} to {}", name, shown.join(" "), atom));
+        Some(Term::Atom(atom))
+    }
+
+    // the body of the lambda with lets of the arguments for the parameters
+    fn beta(&mut self, func: &Atom, args: &[Atom]) -> Option<Term> {
+        let Atom::Var(f) = func else {
+            return None;
+        };
+        let Known::Lam(params, body, _) = self.lookup(f) else {
+            return None;
+        };
+        let size = size(&body) as isize;
+        if params.len() != args.len() || size > INLINE_SIZE as isize || size > self.growth {
+            return None;
+        }
+        // a lambda forces the arguments it gets
+        if !params
+            .iter()
+            .zip(args)
+            .all(|(p, a)| is_lazy(p) || self.is_value(a))
+        {
+            return None;
+        }
+        let reduced = lets(&params, args, &body)?;
+        self.growth -= size;
+        self.change(format!("beta reduced {}", f));
+        Some(reduced)
+    }
+
+    // the branch that a case of a constructor or a literal goes to
+    fn known_case(&mut self, scrutinee: &Atom, alts: &[(Pattern, Rc<Term>)]) -> Option<Term> {
+        let (con, fields) = match scrutinee {
+            Atom::Var(v) => match self.lookup(v) {
+                Known::Con(con, fields) => (Some(con), fields),
+                _ => return None,
+            },
+            Atom::Con(0, _, con) => (Some(con.to_string()), Vec::new()),
+            Atom::Con(_, _, _) | Atom::Builtin(_, _, _) => return None,
+            _ => (None, Vec::new()),
+        };
+        for (pat, branch) in alts {
+            let matched = match (pat, scrutinee) {
+                (Pattern::Wildcard, _) => Some((**branch).clone()),
+                (Pattern::Irrefutable(v), _) => lets(
+                    std::slice::from_ref(v),
+                    std::slice::from_ref(scrutinee),
+                    branch,
+                ),
+                (Pattern::Construct(c, vars), _) if Some(c) == con.as_ref() => {
+                    if vars.len() != fields.len() {
+                        return None;
+                    }
+                    lets(vars, &fields, branch)
+                }
+                (Pattern::Int(i), Atom::Int(n)) if i == n => Some((**branch).clone()),
+                (Pattern::Float(i), Atom::Float(n)) if i == n => Some((**branch).clone()),
+                (Pattern::Str(i), Atom::Str(n)) if i == n => Some((**branch).clone()),
+                _ => continue,
+            };
+            // a match that can't be written as lets is left for runtime
+            let matched = matched?;
+            let what = match &con {
+                Some(con) => con.to_string(),
+                None => scrutinee.to_string(),
+            };
+            self.change(format!("case of known {}", what));
+            return Some(matched);
+        }
+        None
+    }
+}
+
+// let p1 = a1 in let p2 = a2 in ... body
+// an argument can't be one of the other parameters, the lets before it would capture it
+fn lets(params: &[String], args: &[Atom], body: &Term) -> Option<Term> {
+    for (i, arg) in args.iter().enumerate() {
+        if let Atom::Var(v) = arg {
+            if params.iter().enumerate().any(|(j, p)| j != i && p == v) {
+                return None;
+            }
+        }
+    }
+    let mut term = body.clone();
+    for (param, arg) in params.iter().zip(args).rev() {
+        term = Term::Let(
+            param.to_string(),
+            Rc::new(Term::Atom(arg.clone())),
+            Rc::new(term),
+        );
+    }
+    Some(term)
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for change in &self.changes {
+            writeln!(f, "{}", change)?;
+        }
+        writeln!(
+            f,
+            "{} changes in {} passes",
+            self.changes.len(),
+            self.passes
+        )
+    }
+}
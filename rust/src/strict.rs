@@ -0,0 +1,480 @@
+/*
+
+strictness analysis, which definitions and arguments are going to be evaluated anyway so they can be evaluated right away instead of making a thunk
+
+works on the program from change_lets, it's abstract interpretation where the abstract value of an expression is what it forces
+    forced(e) is the variables that are definitely evaluated when e is evaluated
+    a variable forces itself, a literal or a lambda forces nothing
+    a case forces what it looks at and what every branch forces, an if is the same with its two branches
+    error and undefined never finish, so they count as forcing everything
+    let x = d in b forces what b forces, and what d forces too if b forces x
+
+a function's signature says which of its parameters get forced
+    lambdas force their arguments as they take them, so every parameter is strict except _ and the ones closure conversion adds
+    those are strict too if the body forces them, but only once the function has all of its arguments
+    constructors and builtins force all of their arguments
+    an application of something with a signature forces what its strict arguments force
+recursive definitions start out strict in everything and are analyzed again until their signatures stop changing
+
+what eval does with it
+    a let whose variable is forced by the body is evaluated before the body instead of being a thunk
+    the strict arguments of an application of something with a signature are evaluated in order before it's applied
+    that's the order the lambda would have forced them in, so only the thunks go away
+letrec definitions stay thunks, they can refer to each other before they're done
+a program that would stop with one error can stop with a different one, when a strict let is evaluated before the error that used to come first
+
+*/
+
+use crate::ast::Expr;
+use crate::lift::is_lazy;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::hash::BuildHasherDefault;
+use std::hash::Hasher;
+use std::rc::Rc;
+
+// how a parameter is treated
+#[derive(Clone, Copy, PartialEq)]
+enum Param {
+    Forced, // when it's taken off of the spine
+    Body,   // lazy but the body forces it
+    Lazy,
+}
+
+// what eval needs to know, the lets and applications are found by their address
+pub struct Strictness {
+    lets: HashSet<*const Expr, Addresses>,
+    calls: HashMap<*const Expr, Vec<bool>, Addresses>,
+    pub report: Vec<String>,
+}
+
+// eval looks up every application it reduces, hashing the address the default way is slower than the thunk it saves
+pub(crate) type Addresses = BuildHasherDefault<Address>;
+
+#[derive(Default)]
+pub(crate) struct Address(u64);
+
+impl Hasher for Address {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 = (self.0 << 8 | *b as u64).wrapping_mul(0x9e3779b97f4a7c15);
+        }
+    }
+
+    fn write_usize(&mut self, n: usize) {
+        // the low bits of an address are always the same
+        self.0 = (n as u64 >> 4).wrapping_mul(0x9e3779b97f4a7c15);
+    }
+}
+
+impl Strictness {
+    // the definition of a let that can be evaluated before the body
+    pub fn strict_let(&self, def: &Rc<Expr>) -> bool {
+        self.lets.contains(&Rc::as_ptr(def))
+    }
+
+    // which arguments of the application can be evaluated before it's applied
+    pub fn strict_args(&self, app: &Rc<Expr>) -> Option<&[bool]> {
+        self.calls.get(&Rc::as_ptr(app)).map(|v| v.as_slice())
+    }
+}
+
+// the variables that get evaluated, All when it never finishes
+#[derive(Clone, PartialEq)]
+enum Forced {
+    All,
+    Vars(HashSet<String>),
+}
+
+impl Forced {
+    fn none() -> Forced {
+        Forced::Vars(HashSet::new())
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        match self {
+            Forced::All => true,
+            Forced::Vars(vars) => vars.contains(name),
+        }
+    }
+
+    fn union(self, other: Forced) -> Forced {
+        match (self, other) {
+            (Forced::Vars(mut a), Forced::Vars(b)) => {
+                a.extend(b);
+                Forced::Vars(a)
+            }
+            _ => Forced::All,
+        }
+    }
+
+    fn intersect(self, other: Forced) -> Forced {
+        match (self, other) {
+            (Forced::All, x) | (x, Forced::All) => x,
+            (Forced::Vars(a), Forced::Vars(b)) => {
+                Forced::Vars(a.intersection(&b).cloned().collect())
+            }
+        }
+    }
+
+    // leaving the scope of the names
+    fn without(self, names: &[String]) -> Forced {
+        match self {
+            Forced::All => Forced::All,
+            Forced::Vars(mut vars) => {
+                for name in names {
+                    vars.remove(name);
+                }
+                Forced::Vars(vars)
+            }
+        }
+    }
+}
+
+pub fn analyze(expr: &Rc<Expr>) -> Strictness {
+    let mut analysis = Analysis {
+        signatures: Vec::new(),
+        recording: true,
+        within: "the body".to_string(),
+        strictness: Strictness {
+            lets: HashSet::default(),
+            calls: HashMap::default(),
+            report: Vec::new(),
+        },
+    };
+    analysis.top(expr);
+    analysis.strictness
+}
+
+struct Analysis {
+    signatures: Vec<(String, Option<Vec<Param>>)>, // None when the variable isn't something with a signature
+    recording: bool,                               // false while a letrec is still being worked out
+    within: String,
+    strictness: Strictness,
+}
+
+fn var_name(var: &Rc<Expr>) -> String {
+    match &**var {
+        Expr::Var(name, _, _) => name.to_string(),
+        _ => panic!("Only variables can be defined."),
+    }
+}
+
+impl Analysis {
+    fn signature(&self, name: &str) -> Option<Vec<Param>> {
+        self.signatures
+            .iter()
+            .rev()
+            .find(|(n, _)| n == name)
+            .and_then(|(_, s)| s.clone())
+    }
+
+    // the top level definitions are named in the report and their lets aren't reported
+    fn top(&mut self, expr: &Rc<Expr>) -> Forced {
+        match &**expr {
+            Expr::Let(vars, defs, body) => {
+                let names: Vec<String> = vars.iter().map(var_name).collect();
+                let mut found = Vec::new();
+                for (name, def) in names.iter().zip(defs) {
+                    self.within = name.to_string();
+                    found.push(self.definition(def));
+                }
+                self.within = "the body".to_string();
+                let mark = self.signatures.len();
+                for (name, (_, signature)) in names.iter().zip(&found) {
+                    self.signatures.push((name.to_string(), signature.clone()));
+                }
+                let forced = self.top(body);
+                self.signatures.truncate(mark);
+                self.lets(&names, defs, found, forced, false)
+            }
+            Expr::LetRec(vars, defs, body) => {
+                let names: Vec<String> = vars.iter().map(var_name).collect();
+                let mark = self.signatures.len();
+                self.letrec(&names, defs, true);
+                let forced = self.top(body);
+                self.signatures.truncate(mark);
+                forced.without(&names)
+            }
+            _ => self.forced(expr),
+        }
+    }
+
+    // what the body of a let forces decides which definitions are strict
+    fn lets(
+        &mut self,
+        names: &[String],
+        defs: &[Rc<Expr>],
+        found: Vec<(Forced, Option<Vec<Param>>)>,
+        body: Forced,
+        report: bool,
+    ) -> Forced {
+        let mut forced = body.clone().without(names);
+        for ((name, def), (def_forced, _)) in names.iter().zip(defs).zip(found) {
+            let strict = body.contains(name);
+            if self.recording {
+                if strict {
+                    self.strictness.lets.insert(Rc::as_ptr(def));
+                } else {
+                    self.strictness.lets.remove(&Rc::as_ptr(def));
+                }
+                if report {
+                    let what = if strict { "strict" } else { "lazy" };
+                    self.strictness
+                        .report
+                        .push(format!("{}: let {} is {}", self.within, name, what));
+                }
+            }
+            if strict {
+                forced = forced.union(def_forced);
+            }
+        }
+        forced
+    }
+
+    // the signatures of a group of recursive definitions, they're in scope until the caller truncates
+    fn letrec(&mut self, names: &[String], defs: &[Rc<Expr>], top: bool) {
+        let recording = self.recording;
+        let within = self.within.to_string();
+        let mark = self.signatures.len();
+        // everything starts out strict and only gets lazier
+        let mut signatures: Vec<Option<Vec<Param>>> = defs
+            .iter()
+            .map(|def| {
+                lambda(def).map(|(params, _)| params.iter().map(|_| Param::Forced).collect())
+            })
+            .collect();
+        self.recording = false;
+        loop {
+            self.signatures.truncate(mark);
+            for (name, signature) in names.iter().zip(&signatures) {
+                self.signatures.push((name.to_string(), signature.clone()));
+            }
+            let mut next = Vec::new();
+            for (name, def) in names.iter().zip(defs) {
+                if top {
+                    self.within = name.to_string();
+                }
+                next.push(self.definition(def).1);
+            }
+            if next == signatures {
+                break;
+            }
+            signatures = next;
+        }
+        // once more to write down what was found
+        self.recording = recording;
+        if recording {
+            for (name, def) in names.iter().zip(defs) {
+                if top {
+                    self.within = name.to_string();
+                }
+                self.definition(def);
+            }
+        }
+        self.within = within;
+    }
+
+    // what a definition forces and its signature if it has one
+    fn definition(&mut self, def: &Rc<Expr>) -> (Forced, Option<Vec<Param>>) {
+        match &**def {
+            Expr::Data(arity, _, _, fields) | Expr::Builtin(arity, _, _, fields, _)
+                if fields.is_empty() =>
+            {
+                (Forced::none(), Some(vec![Param::Forced; *arity]))
+            }
+            Expr::Annot(inner, _) => self.definition(inner),
+            _ => match lambda(def) {
+                Some((params, body)) => {
+                    let signature = self.lambda(&params, body);
+                    (Forced::none(), Some(signature))
+                }
+                None => (self.forced(def), None),
+            },
+        }
+    }
+
+    fn lambda(&mut self, params: &[String], body: &Rc<Expr>) -> Vec<Param> {
+        let mark = self.signatures.len();
+        for param in params {
+            self.signatures.push((param.to_string(), None));
+        }
+        let forced = self.forced(body);
+        self.signatures.truncate(mark);
+        let signature: Vec<Param> = params
+            .iter()
+            .map(|p| {
+                if !is_lazy(p) {
+                    Param::Forced
+                } else if forced.contains(p) && p != "_" {
+                    Param::Body
+                } else {
+                    Param::Lazy
+                }
+            })
+            .collect();
+        if self.recording {
+            let strict: Vec<&str> = params
+                .iter()
+                .zip(&signature)
+                .filter(|(_, s)| **s != Param::Lazy)
+                .map(|(p, _)| p.as_str())
+                .collect();
+            let strict = if strict.is_empty() {
+                "nothing".to_string()
+            } else {
+                strict.join(" ")
+            };
+            self.strictness.report.push(format!(
+                "{}: \\ {} . strict in {}",
+                self.within,
+                params.join(" "),
+                strict
+            ));
+        }
+        signature
+    }
+
+    fn forced(&mut self, expr: &Rc<Expr>) -> Forced {
+        match &**expr {
+            Expr::Var(name, _, _) => Forced::Vars(HashSet::from([name.to_string()])),
+            Expr::Lam(_, _) => {
+                let (params, body) = lambda(expr).unwrap();
+                self.lambda(&params, body);
+                Forced::none()
+            }
+            Expr::App(_, _) => self.call(expr),
+            Expr::Let(vars, defs, body) => {
+                let names: Vec<String> = vars.iter().map(var_name).collect();
+                let found: Vec<(Forced, Option<Vec<Param>>)> =
+                    defs.iter().map(|def| self.definition(def)).collect();
+                let mark = self.signatures.len();
+                for (name, (_, signature)) in names.iter().zip(&found) {
+                    self.signatures.push((name.to_string(), signature.clone()));
+                }
+                let forced = self.forced(body);
+                self.signatures.truncate(mark);
+                self.lets(&names, defs, found, forced, true)
+            }
+            Expr::LetRec(vars, defs, body) => {
+                let names: Vec<String> = vars.iter().map(var_name).collect();
+                let mark = self.signatures.len();
+                self.letrec(&names, defs, false);
+                let forced = self.forced(body);
+                self.signatures.truncate(mark);
+                forced.without(&names)
+            }
+            Expr::Case(cond, pats, branches, _) => {
+                let mut forced = self.forced(cond);
+                let mut every: Option<Forced> = None;
+                for (pat, branch) in pats.iter().zip(branches) {
+                    let vars: Vec<String> = pat.vars().iter().map(|v| v.to_string()).collect();
+                    let mark = self.signatures.len();
+                    for v in &vars {
+                        self.signatures.push((v.to_string(), None));
+                    }
+                    let branch = self.forced(branch).without(&vars);
+                    self.signatures.truncate(mark);
+                    every = Some(match every {
+                        Some(f) => f.intersect(branch),
+                        None => branch,
+                    });
+                }
+                if let Some(every) = every {
+                    forced = forced.union(every);
+                }
+                forced
+            }
+            Expr::If(cond, b1, b2) => {
+                let forced = self.forced(cond);
+                let b1 = self.forced(b1);
+                let b2 = self.forced(b2);
+                forced.union(b1.intersect(b2))
+            }
+            Expr::Error(_) | Expr::Bottom => Forced::All,
+            Expr::Annot(inner, _) => self.forced(inner),
+            _ => Forced::none(),
+        }
+    }
+
+    // an application forces what's applied and the strict arguments
+    fn call(&mut self, expr: &Rc<Expr>) -> Forced {
+        let mut args = Vec::new();
+        let mut head = expr;
+        while let Expr::App(left, right) = &**head {
+            args.push(right);
+            head = left;
+        }
+        args.reverse();
+        let (mut forced, signature) = match &**head {
+            Expr::Var(name, _, _) => (self.forced(head), self.signature(name)),
+            Expr::Data(_, _, _, fields) | Expr::Builtin(_, _, _, fields, _)
+                if fields.is_empty() =>
+            {
+                self.definition(head)
+            }
+            Expr::Lam(_, _) => {
+                let (params, body) = lambda(head).unwrap();
+                (Forced::none(), Some(self.lambda(&params, body)))
+            }
+            _ => (self.forced(head), None),
+        };
+        let signature = signature.unwrap_or_default();
+        let saturated = args.len() >= signature.len();
+        let mut strict = Vec::new();
+        for (i, arg) in args.iter().enumerate() {
+            let arg_forced = self.forced(arg);
+            let is_strict = match signature.get(i) {
+                Some(Param::Forced) => true,
+                Some(Param::Body) => saturated,
+                _ => false,
+            };
+            if is_strict {
+                forced = forced.union(arg_forced);
+            }
+            strict.push(is_strict);
+        }
+        if self.recording {
+            if strict.iter().any(|s| *s) {
+                self.strictness.calls.insert(Rc::as_ptr(expr), strict);
+            } else {
+                self.strictness.calls.remove(&Rc::as_ptr(expr));
+            }
+        }
+        forced
+    }
+}
+
+// the parameters and body of the lambdas right inside of each other
+fn lambda(expr: &Rc<Expr>) -> Option<(Vec<String>, &Rc<Expr>)> {
+    let mut params = Vec::new();
+    let mut body = expr;
+    while let Expr::Lam(head, inner) = &**body {
+        params.push(var_name(head));
+        body = inner;
+    }
+    if params.is_empty() {
+        None
+    } else {
+        Some((params, body))
+    }
+}
+
+impl Display for Strictness {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for line in &self.report {
+            writeln!(f, "{}", line)?;
+        }
+        writeln!(
+            f,
+            "{} strict lets, {} applications with strict arguments",
+            self.lets.len(),
+            self.calls.len()
+        )
+    }
+}
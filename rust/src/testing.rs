@@ -0,0 +1,129 @@
+/*
+
+tests written in bagl, run by bagl test
+
+a test is a toplevel definition named test_something, or any definition marked with a pragma
+    {-# TEST works #-}
+    works = assertEq (sum 3) 6
+it has to be a Bool, it passes when it is True and fails when it is False or something goes wrong
+    assertEq a b    True when a and b are the same value, otherwise fails showing both
+    assert b        True when b is, otherwise fails
+a failed assertion is an error so it stops the test right there, nothing after it has to be checked
+
+each test is its own program with the test as the entry, so a test only pays for the definitions it uses
+it runs with a budget, a test that loops fails instead of hanging the run
+errors at runtime panic (error "...", no pattern matching, ...), they get caught and the test fails with the message
+
+*/
+
+use crate::ast::Expr;
+use crate::ast::Span;
+use crate::ast::Toplevel;
+use crate::diagnostic::Diagnostic;
+use crate::eval::eval_with_budget;
+use crate::eval::Budget;
+use crate::front::check;
+use crate::front::finish;
+use crate::infer::Type;
+use crate::kinds::check_declarations;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::rc::Rc;
+
+pub const PREFIX: &str = "test_";
+
+#[derive(Debug, Clone)]
+pub struct Test {
+    pub name: String,
+    pub span: Span, // where it is defined
+}
+
+#[derive(Debug, Clone)]
+pub enum Outcome {
+    Passed,
+    Failed(String),
+}
+
+// the tests of a program in the order they're defined, after checking the declarations
+pub fn discover(top: &Toplevel) -> Result<Vec<Test>, Vec<Diagnostic>> {
+    check_declarations(top)?;
+    let mut errors = Vec::new();
+    for (name, span) in &top.tests {
+        if !top.defs.iter().any(|d| d.name() == name) {
+            errors.push(Diagnostic::error(
+                *span,
+                format!("there is no definition `{}` to test", name),
+            ));
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let mut tests = Vec::new();
+    for d in &top.defs {
+        let name = d.name();
+        if name.starts_with(PREFIX) || top.tests.iter().any(|(n, _)| n == name) {
+            tests.push(Test {
+                name: name.to_string(),
+                span: d.span(),
+            });
+        }
+    }
+    Ok(tests)
+}
+
+// the program for a single test, ready to evaluate
+pub fn compile(top: &Toplevel, test: &Test) -> Result<Rc<Expr>, Vec<Diagnostic>> {
+    let expr = top.to_let_entry(&test.name);
+    let checked = check(top, &expr)?;
+    // something that never gives back a value, like error, can be anything so it can be a Bool too
+    let not_bool = |typ: &Type| match typ {
+        Type::Con(name) => name != "Bool",
+        Type::Var(_) | Type::Rigid(..) => false,
+        _ => true,
+    };
+    if let Some(binding) = checked
+        .bindings
+        .iter()
+        .find(|b| b.toplevel && b.name == test.name && not_bool(&b.scheme.typ))
+    {
+        return Err(vec![Diagnostic::error(
+            test.span,
+            format!(
+                "test `{}` is a `{}`, a test has to be a `Bool`",
+                test.name, binding.scheme
+            ),
+        )
+        .with_help("check the result with assertEq or assert".to_string())]);
+    }
+    Ok(finish(top, &expr, &checked))
+}
+
+pub fn run(expr: Rc<Expr>, budget: Budget) -> Outcome {
+    // the message is what the test reports, the usual printing of the panic would get in the way
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let result = panic::catch_unwind(AssertUnwindSafe(|| eval_with_budget(expr, budget)));
+    panic::set_hook(hook);
+    match result {
+        Ok(Ok(value)) => match &*value {
+            Expr::Data(_, _, cons, _) if cons == "True" => Outcome::Passed,
+            _ => Outcome::Failed(format!("evaluated to {}", value)),
+        },
+        Ok(Err(exhausted)) => Outcome::Failed(exhausted.to_string()),
+        Err(payload) => {
+            let message = if let Some(s) = payload.downcast_ref::<String>() {
+                s.to_string()
+            } else if let Some(s) = payload.downcast_ref::<&str>() {
+                s.to_string()
+            } else {
+                "panicked".to_string()
+            };
+            // the assertions fail with an Error, the prefix doesn't add anything
+            Outcome::Failed(match message.strip_prefix("Error: ") {
+                Some(rest) => rest.to_string(),
+                None => message,
+            })
+        }
+    }
+}
@@ -0,0 +1,223 @@
+/*
+
+find definitions and variables that are never used
+
+after rearranging the lets it is known exactly what the entry point depends on
+anything that isn't reachable from the entry can be dropped before evaluating
+    a let is only kept if its variable shows up in the body
+    a letrec group is kept if any of its variables show up in the body
+    work from the inside out so that a binding only used by a dropped binding is also dropped
+
+warnings are generated for
+    toplevel definitions that can't be reached from the entry
+    lambda parameters that are never used
+    pattern variables that are never used
+
+a name starting with an underscore is never warned about, so _ and _x can be used to mark things as unused on purpose
+
+constructors are defined along with the toplevel definitions, they are removed when unused but never warned about
+
+*/
+
+use crate::ast::Expr;
+use crate::ast::Span;
+use crate::diagnostic::Diagnostic;
+use crate::rearrange::dependencies;
+use crate::rearrange::dependency_graph;
+use std::rc::Rc;
+
+// drop the lets and letrecs that are never used, expects the output of change_lets
+pub fn eliminate(expr: Rc<Expr>) -> Rc<Expr> {
+    match &*expr {
+        Expr::Let(vars, defs, body) => {
+            let body = eliminate(Rc::clone(body));
+            if vars.iter().any(|var| occurs(&var_name(var), &body)) {
+                let defs = defs.iter().map(|def| eliminate(Rc::clone(def))).collect();
+                Rc::new(Expr::Let(vars.to_vec(), defs, body))
+            } else {
+                body
+            }
+        }
+        Expr::LetRec(vars, defs, body) => {
+            let body = eliminate(Rc::clone(body));
+            if vars.iter().any(|var| occurs(&var_name(var), &body)) {
+                let defs = defs.iter().map(|def| eliminate(Rc::clone(def))).collect();
+                Rc::new(Expr::LetRec(vars.to_vec(), defs, body))
+            } else {
+                body
+            }
+        }
+        Expr::Lam(head, body) => Rc::new(Expr::Lam(Rc::clone(head), eliminate(Rc::clone(body)))),
+        Expr::App(left, right) => Rc::new(Expr::App(
+            eliminate(Rc::clone(left)),
+            eliminate(Rc::clone(right)),
+        )),
+        Expr::If(cond, b1, b2) => Rc::new(Expr::If(
+            eliminate(Rc::clone(cond)),
+            eliminate(Rc::clone(b1)),
+            eliminate(Rc::clone(b2)),
+        )),
+        Expr::Case(cond, pats, branches, spans) => Rc::new(Expr::Case(
+            eliminate(Rc::clone(cond)),
+            pats.to_vec(),
+            branches.iter().map(|b| eliminate(Rc::clone(b))).collect(),
+            spans.to_vec(),
+        )),
+        Expr::Annot(inner, typ) => Rc::new(Expr::Annot(eliminate(Rc::clone(inner)), typ.clone())),
+        _ => expr,
+    }
+}
+
+// does the variable appear free in the expression
+fn occurs(name: &str, expr: &Rc<Expr>) -> bool {
+    !dependencies(&[name.to_string()], Rc::clone(expr), Vec::new()).is_empty()
+}
+
+// warnings for the letrec created by Toplevel::to_let
+pub fn unused_warnings(expr: &Rc<Expr>) -> Vec<Diagnostic> {
+    let mut warnings = Vec::new();
+    if let Expr::LetRec(vars, defs, body) = &**expr {
+        // walk the dependency graph from whatever the body uses
+        let graph = dependency_graph(vars, defs);
+        let mut reachable = vec![false; graph.names.len()];
+        let mut todo: Vec<usize> = dependencies(&graph.names, Rc::clone(body), Vec::new())
+            .iter()
+            .filter_map(|d| graph.names.iter().position(|n| n == d))
+            .collect();
+        while let Some(i) = todo.pop() {
+            if !reachable[i] {
+                reachable[i] = true;
+                if let Some(next) = graph.edges.get(&i) {
+                    todo.extend(next);
+                }
+            }
+        }
+        for i in 0..graph.names.len() {
+            // constructors and field accessors are generated, nobody wrote them to be used
+            if let Expr::Data(..) = &*defs[i] {
+                continue;
+            }
+            if !var_span(&vars[i]).is_known() {
+                continue;
+            }
+            if !reachable[i] && !graph.names[i].starts_with('_') {
+                warnings.push(Diagnostic::warning(
+                    var_span(&vars[i]),
+                    format!("unused definition `{}`", graph.names[i]),
+                ));
+            }
+        }
+
+        for def in defs {
+            let mut scope = Vec::new();
+            binders(def, &mut scope, &mut warnings);
+        }
+    }
+    warnings
+}
+
+struct Binder {
+    name: String,
+    span: Span,
+    kind: &'static str,
+    used: bool,
+}
+
+// track the parameters and pattern variables in scope and mark them when used
+// definitions made by lets are put in scope so that they shadow properly, but are never warned about
+fn binders(expr: &Rc<Expr>, scope: &mut Vec<Binder>, warnings: &mut Vec<Diagnostic>) {
+    match &**expr {
+        Expr::Var(s, _, _) => {
+            if let Some(binder) = scope.iter_mut().rev().find(|b| &b.name == s) {
+                binder.used = true;
+            }
+        }
+        Expr::Lam(head, body) => {
+            scope.push(Binder {
+                name: var_name(head),
+                span: var_span(head),
+                kind: "parameter",
+                used: false,
+            });
+            binders(body, scope, warnings);
+            close(scope, 1, warnings);
+        }
+        Expr::App(left, right) => {
+            binders(left, scope, warnings);
+            binders(right, scope, warnings);
+        }
+        Expr::If(cond, b1, b2) => {
+            binders(cond, scope, warnings);
+            binders(b1, scope, warnings);
+            binders(b2, scope, warnings);
+        }
+        Expr::Let(vars, defs, body) | Expr::LetRec(vars, defs, body) => {
+            let recursive = matches!(&**expr, Expr::LetRec(..));
+            if !recursive {
+                for def in defs {
+                    binders(def, scope, warnings);
+                }
+            }
+            for var in vars {
+                scope.push(Binder {
+                    name: var_name(var),
+                    span: var_span(var),
+                    kind: "definition",
+                    used: true,
+                });
+            }
+            if recursive {
+                for def in defs {
+                    binders(def, scope, warnings);
+                }
+            }
+            binders(body, scope, warnings);
+            close(scope, vars.len(), warnings);
+        }
+        Expr::Case(cond, pats, branches, spans) => {
+            binders(cond, scope, warnings);
+            for (i, (pat, branch)) in pats.iter().zip(branches).enumerate() {
+                let vars = pat.vars();
+                for var in &vars {
+                    scope.push(Binder {
+                        name: var.to_string(),
+                        span: spans.get(i).copied().unwrap_or_default(),
+                        kind: "pattern variable",
+                        used: false,
+                    });
+                }
+                binders(branch, scope, warnings);
+                close(scope, vars.len(), warnings);
+            }
+        }
+        Expr::Annot(inner, _) => binders(inner, scope, warnings),
+        _ => (),
+    }
+}
+
+// leave the scope of the last n binders
+fn close(scope: &mut Vec<Binder>, n: usize, warnings: &mut Vec<Diagnostic>) {
+    let start = scope.len() - n;
+    for binder in scope.drain(start..) {
+        if !binder.used && !binder.name.starts_with('_') {
+            warnings.push(Diagnostic::warning(
+                binder.span,
+                format!("unused {} `{}`", binder.kind, binder.name),
+            ));
+        }
+    }
+}
+
+fn var_name(var: &Rc<Expr>) -> String {
+    match &**var {
+        Expr::Var(s, _, _) => s.to_string(),
+        _ => panic!("Can only define variables."),
+    }
+}
+
+fn var_span(var: &Rc<Expr>) -> Span {
+    match &**var {
+        Expr::Var(_, _, span) => *span,
+        _ => Span::default(),
+    }
+}
@@ -0,0 +1,1457 @@
+/*
+
+compile a program to WebAssembly text, one module with the runtime (runtime.wat) at the top
+any assembler can turn it into a binary, wat2wasm program.wat
+
+this works the same way c.rs does, on the program after resolve, building the same frames eval does
+every expression becomes a function of its environment giving back the value, they're all in the table so thunks and closures can call them
+the slots of a C function are locals here, $s0 is the environment it was given
+there's no collector, so unlike the C program nothing has to be kept where a collector can find it
+    the runtime never frees anything, every object stays until the instance is thrown away
+    a program that runs for long enough can use up all the memory the host allows, then it stops with "Ran out of memory."
+
+memory starts with the data, the strings and then the tables for constructors, lambdas, and builtins
+    a constructor is 5 i32s, where its name is, how long the name is, its arity, the number of its type, its tag
+    a lambda is 4, the index of its code, whether it is lazy, where the source to print is and how long it is
+    a builtin is 3, where its name is, how long the name is, its arity
+the heap comes after that, $hp is where the next object goes
+constructors are numbered by info::Constructors like the C program, each one gets a comment in the table saying what it is
+
+the module needs one thing from the host, bagl.format_float, see runtime.wat
+it exports memory, main that gives back the value of the program, display that gives back a string object for a value
+    a string object has its length at 12 and its bytes from 24
+when something goes wrong main traps and error_ptr and error_len say where the message is
+
+validate checks what it can of a module without assembling it, so it can be used anywhere without a WebAssembly toolchain
+    the names that are used are defined, and only once
+    calls have as many arguments as the function takes, globals that are set are mutable, branches go to labels around them
+    instructions are ones it knows, imports are before everything else, the data fits in memory, main and memory are exported
+it only understands the folded form, which is all that gets written here
+
+*/
+
+use crate::ast::Expr;
+use crate::ast::Pattern;
+use crate::ast::Toplevel;
+use crate::classes::dict_constructor;
+use crate::info::Constructors;
+use num::ToPrimitive;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::rc::Rc;
+
+const RUNTIME: &str = include_str!("runtime.wat");
+
+// in the same order as $prim in the runtime, with their arities
+const BUILTINS: [(&str, usize); 11] = [
+    ("+", 2),
+    ("-", 2),
+    ("*", 2),
+    ("/", 2),
+    ("++", 2),
+    ("==", 2),
+    ("compare", 2),
+    ("show", 1),
+    ("parens", 1),
+    ("assert", 1),
+    ("assertEq", 2),
+];
+
+// where the data starts, 0 is never a pointer
+const DATA_START: usize = 8;
+
+const PAGE: usize = 65536;
+
+struct Lam {
+    code: usize,
+    lazy: bool,
+    shown: String,
+}
+
+// a function being written, slots counts how many locals it needs
+struct Function {
+    lines: Vec<String>,
+    indent: usize,
+    slots: usize,
+}
+
+impl Function {
+    fn new() -> Function {
+        Function {
+            lines: Vec::new(),
+            indent: 1,
+            slots: 1,
+        }
+    }
+
+    fn emit(&mut self, line: String) {
+        self.lines
+            .push(format!("{}{}", "  ".repeat(self.indent), line));
+    }
+
+    // $s{slot} gets the value
+    fn set(&mut self, slot: usize, value: String) {
+        self.uses(slot);
+        self.emit(format!("(local.set $s{} {})", slot, value));
+    }
+
+    fn uses(&mut self, slot: usize) {
+        self.slots = self.slots.max(slot + 1);
+    }
+
+    fn open(&mut self, line: String) {
+        self.emit(line);
+        self.indent += 1;
+    }
+
+    fn close(&mut self, line: &str) {
+        self.indent -= 1;
+        self.emit(line.to_string());
+    }
+}
+
+fn get(slot: usize) -> String {
+    format!("(local.get $s{})", slot)
+}
+
+// the strings in memory, each one is only there once
+struct Strings {
+    bytes: Vec<u8>,
+    places: HashMap<Vec<u8>, usize>,
+}
+
+impl Strings {
+    fn new() -> Strings {
+        Strings {
+            bytes: Vec::new(),
+            places: HashMap::new(),
+        }
+    }
+
+    // where the string is in memory
+    fn place(&mut self, s: &[u8]) -> usize {
+        if let Some(place) = self.places.get(s) {
+            return *place;
+        }
+        let place = DATA_START + self.bytes.len();
+        self.bytes.extend_from_slice(s);
+        self.places.insert(s.to_vec(), place);
+        place
+    }
+
+    // the address and length as the arguments of a call
+    fn args(&mut self, s: &[u8]) -> String {
+        format!("(i32.const {}) (i32.const {})", self.place(s), s.len())
+    }
+
+    fn align(&mut self, to: usize) {
+        while !(DATA_START + self.bytes.len()).is_multiple_of(to) {
+            self.bytes.push(0);
+        }
+    }
+
+    fn end(&self) -> usize {
+        DATA_START + self.bytes.len()
+    }
+
+    fn int(&mut self, i: usize) {
+        self.bytes.extend_from_slice(&(i as u32).to_le_bytes());
+    }
+}
+
+struct Compiler {
+    cons: Constructors,
+    lams: Vec<Lam>,
+    functions: Vec<String>,
+    strings: Strings,
+}
+
+// the whole module for the resolved expression
+pub fn to_wat(expr: &Rc<Expr>, top: &Toplevel) -> String {
+    let mut compiler = Compiler::new(top);
+    // the runtime's strings go first so the program's can share them
+    let runtime = runtime_strings(RUNTIME, &mut compiler.strings);
+    let entry = compiler.function(expr);
+
+    let mut strings = std::mem::replace(&mut compiler.strings, Strings::new());
+    let mut tables = String::new();
+    let names: Vec<usize> = compiler
+        .cons
+        .tags
+        .iter()
+        .map(|con| strings.place(con.name.as_bytes()))
+        .collect();
+    let shown: Vec<usize> = compiler
+        .lams
+        .iter()
+        .map(|lam| strings.place(lam.shown.as_bytes()))
+        .collect();
+    let builtin_names: Vec<usize> = BUILTINS
+        .iter()
+        .map(|(name, _)| strings.place(name.as_bytes()))
+        .collect();
+
+    strings.align(4);
+    let cons = strings.end();
+    for (i, con) in compiler.cons.tags.iter().enumerate() {
+        writeln!(
+            tables,
+            ";; con {} {}, type {} tag {} arity {}",
+            i, con.name, con.type_index, con.tag, con.arity
+        )
+        .unwrap();
+        for n in &[names[i], con.name.len(), con.arity, con.type_index, con.tag] {
+            strings.int(*n);
+        }
+    }
+    let lams = strings.end();
+    for (i, lam) in compiler.lams.iter().enumerate() {
+        for n in &[lam.code, lam.lazy as usize, shown[i], lam.shown.len()] {
+            strings.int(*n);
+        }
+    }
+    let builtins = strings.end();
+    for (i, (name, arity)) in BUILTINS.iter().enumerate() {
+        for n in &[builtin_names[i], name.len(), *arity] {
+            strings.int(*n);
+        }
+    }
+    strings.align(8);
+    let heap = strings.end();
+    let pages = heap / PAGE + 1;
+
+    let mut out = String::from(&runtime);
+    out.push_str("\n;; the program\n\n");
+    writeln!(out, "(memory (export \"memory\") {})", pages).unwrap();
+    writeln!(out, "(global $hp (mut i32) (i32.const {}))", heap).unwrap();
+    writeln!(out, "(global $cons i32 (i32.const {}))", cons).unwrap();
+    writeln!(out, "(global $lams i32 (i32.const {}))", lams).unwrap();
+    writeln!(out, "(global $builtins i32 (i32.const {}))", builtins).unwrap();
+    let id = |name: &str| compiler.cons.find(name).unwrap_or(0);
+    for (var, name) in &[
+        ("con_true", "True"),
+        ("con_false", "False"),
+        ("con_less", "Less"),
+        ("con_equal", "Equal"),
+        ("con_greater", "Greater"),
+    ] {
+        writeln!(out, "(global ${} i32 (i32.const {}))", var, id(name)).unwrap();
+    }
+    out.push('\n');
+    out.push_str(&tables);
+    writeln!(
+        out,
+        "(data (i32.const {}) {})",
+        DATA_START,
+        wat_string(&strings.bytes)
+    )
+    .unwrap();
+    writeln!(out, "\n(table {} funcref)", compiler.functions.len()).unwrap();
+    out.push_str("(elem (i32.const 0)");
+    for i in 0..compiler.functions.len() {
+        if i % 16 == 0 {
+            out.push_str("\n ");
+        }
+        write!(out, " $code_{}", i).unwrap();
+    }
+    out.push_str(")\n");
+    for (i, function) in compiler.functions.iter().enumerate() {
+        write!(
+            out,
+            "\n(func $code_{} (type $code) (param $s0 i32) (result i32)\n{})\n",
+            i, function
+        )
+        .unwrap();
+    }
+    writeln!(
+        out,
+        "\n(func (export \"main\") (result i32)\n  (call $force (call $code_{} (i32.const 0))))",
+        entry
+    )
+    .unwrap();
+    format!("(module\n\n{})\n", out)
+}
+
+// (string "text") in the runtime becomes where the text is and its length
+fn runtime_strings(runtime: &str, strings: &mut Strings) -> String {
+    let mut out = String::new();
+    let mut rest = runtime;
+    while let Some(start) = rest.find("(string \"") {
+        out.push_str(&rest[..start]);
+        let text = &rest[start + "(string \"".len()..];
+        let end = text
+            .find("\")")
+            .expect("A string in the runtime isn't closed.");
+        out.push_str(&strings.args(&unescape(&text[..end])));
+        rest = &text[end + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+// the runtime's strings only have \\ and \n in them
+fn unescape(s: &str) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut bytes = s.bytes();
+    while let Some(b) = bytes.next() {
+        match (b, b == b'\\') {
+            (_, true) => match bytes.next() {
+                Some(b'n') => out.push(b'\n'),
+                Some(c) => out.push(c),
+                None => (),
+            },
+            (b, false) => out.push(b),
+        }
+    }
+    out
+}
+
+impl Compiler {
+    fn new(top: &Toplevel) -> Compiler {
+        let mut cons = Constructors::new(&top.types);
+        for class in top.classes.iter().filter(|c| c.dictionary) {
+            cons.id(
+                class.methods.len(),
+                &class.name,
+                &dict_constructor(&class.name),
+            );
+        }
+        Compiler {
+            cons,
+            lams: Vec::new(),
+            functions: Vec::new(),
+            strings: Strings::new(),
+        }
+    }
+
+    // a function evaluating the expression in the environment it is given, gives back its number
+    fn function(&mut self, expr: &Rc<Expr>) -> usize {
+        let index = self.functions.len();
+        self.functions.push(String::new());
+        let mut f = Function::new();
+        self.expr(&mut f, expr, 0, 1, 2);
+        let mut body = String::new();
+        for slot in 1..f.slots {
+            writeln!(body, "  (local $s{} i32)", slot).unwrap();
+        }
+        for line in &f.lines {
+            body.push_str(line);
+            body.push('\n');
+        }
+        body.push_str("  (local.get $s1)");
+        self.functions[index] = body;
+        index
+    }
+
+    fn lam(&mut self, expr: &Rc<Expr>, head: &Rc<Expr>, body: &Rc<Expr>) -> usize {
+        let code = self.function(body);
+        let lazy = matches!(&**head, Expr::Var(s, _, _) if s == "_");
+        self.lams.push(Lam {
+            code,
+            lazy,
+            shown: expr.to_string(),
+        });
+        self.lams.len() - 1
+    }
+
+    // put the value of expr in $s{target}, the environment is in $s{env} and everything from free up can be used
+    fn expr(&mut self, f: &mut Function, expr: &Rc<Expr>, env: usize, target: usize, free: usize) {
+        match &**expr {
+            Expr::Var(_, coords, _) => {
+                let (depth, slot) = *coords.borrow();
+                f.set(
+                    target,
+                    format!(
+                        "(call $var {} (i32.const {}) (i32.const {}))",
+                        get(env),
+                        depth,
+                        slot
+                    ),
+                );
+            }
+            Expr::Int(_, _) | Expr::Float(_, _) | Expr::Str(_, _) => {
+                let value = self.literal(expr);
+                f.set(target, value)
+            }
+            Expr::Lam(head, body) => {
+                let lam = self.lam(expr, head, body);
+                f.set(
+                    target,
+                    format!("(call $fun (i32.const {}) {})", lam, get(env)),
+                );
+            }
+            Expr::App(_, _) => self.app(f, expr, env, target, free),
+            Expr::Let(_, defs, body) => {
+                for (i, def) in defs.iter().enumerate() {
+                    self.delay(f, def, env, free + i, false);
+                }
+                let frame = free + defs.len();
+                f.set(
+                    frame,
+                    format!("(call $frame {} (i32.const {}))", get(env), defs.len()),
+                );
+                for i in 0..defs.len() {
+                    f.emit(format!(
+                        "(call $set {} (i32.const {}) {})",
+                        get(frame),
+                        i,
+                        get(free + i)
+                    ));
+                }
+                self.expr(f, body, frame, target, frame + 1);
+            }
+            Expr::LetRec(_, defs, body) => {
+                f.set(
+                    free,
+                    format!("(call $frame {} (i32.const {}))", get(env), defs.len()),
+                );
+                for (i, def) in defs.iter().enumerate() {
+                    self.delay(f, def, free, free + 1, true);
+                    f.emit(format!(
+                        "(call $set {} (i32.const {}) {})",
+                        get(free),
+                        i,
+                        get(free + 1)
+                    ));
+                }
+                self.expr(f, body, free, target, free + 1);
+            }
+            Expr::If(cond, b1, b2) => {
+                self.expr(f, cond, env, free, free + 1);
+                f.open(format!("(if (call $is_true {})", get(free)));
+                f.open("(then".to_string());
+                self.expr(f, b1, env, target, free);
+                f.close(")");
+                f.open("(else".to_string());
+                self.expr(f, b2, env, target, free);
+                f.close(")");
+                f.close(")");
+            }
+            Expr::Case(cond, pats, branches, _) => {
+                self.expr(f, cond, env, free, free + 1);
+                let value = get(free);
+                let frame = free + 1;
+                // each pattern that doesn't match goes on to the next one in the else
+                for (pat, branch) in pats.iter().zip(branches) {
+                    let test = self.test(pat, &value);
+                    f.open(format!("(if {}", test));
+                    f.open("(then".to_string());
+                    let slots = match pat {
+                        Pattern::Construct(_, _) => {
+                            format!("(call $frame_fields {} {})", get(env), value)
+                        }
+                        Pattern::Irrefutable(_) => format!("(call $frame1 {} {})", get(env), value),
+                        _ => format!("(call $frame {} (i32.const 0))", get(env)),
+                    };
+                    f.set(frame, slots);
+                    self.expr(f, branch, frame, target, frame + 1);
+                    f.close(")");
+                    f.open("(else".to_string());
+                }
+                let error = self.error(b"No pattern matched.");
+                f.set(target, error);
+                for _ in pats {
+                    f.close(")");
+                    f.close(")");
+                }
+            }
+            Expr::Data(arity, typ, name, _) => {
+                let con = self.cons.id(*arity, typ, name);
+                f.set(target, format!("(call $con (i32.const {}))", con));
+            }
+            Expr::Builtin(_, name, _, _, _) => f.set(
+                target,
+                format!("(call $builtin (i32.const {}))", builtin(name)),
+            ),
+            Expr::Error(s) => {
+                let error = self.error(format!("Error: {}", s).as_bytes());
+                f.set(target, error)
+            }
+            Expr::Bottom => {
+                let error = self.error(b"Ran into undefined.");
+                f.set(target, error)
+            }
+            Expr::Annot(inner, _) => self.expr(f, inner, env, target, free),
+            _ => panic!("Can't compile {} to WebAssembly.", expr),
+        }
+    }
+
+    fn error(&mut self, message: &[u8]) -> String {
+        format!("(call $error {})", self.strings.args(message))
+    }
+
+    // the condition for a pattern to match the value
+    fn test(&mut self, pat: &Pattern, value: &str) -> String {
+        match pat {
+            Pattern::Wildcard | Pattern::Irrefutable(_) => "(i32.const 1)".to_string(),
+            Pattern::Construct(name, vars) => match self.cons.find(name) {
+                Some(con) => format!(
+                    "(call $is_con {} (i32.const {}) (i32.const {}))",
+                    value,
+                    con,
+                    vars.len()
+                ),
+                None => "(i32.const 0)".to_string(),
+            },
+            // an integer too big for 64 bits can't be a value
+            Pattern::Int(n) => match n.to_i64() {
+                Some(i) => format!("(call $is_int {} (i64.const {}))", value, i),
+                None => "(i32.const 0)".to_string(),
+            },
+            Pattern::Float(n) => format!("(call $is_float {} (f64.const {}))", value, float(*n)),
+            Pattern::Str(s) => format!(
+                "(call $is_str {} {})",
+                value,
+                self.strings.args(s.as_bytes())
+            ),
+            Pattern::Record(_, _) => panic!("Records should be desugared before compiling."),
+        }
+    }
+
+    // applications go through $apply one argument at a time, unless a builtin or constructor gets all of its arguments
+    fn app(&mut self, f: &mut Function, expr: &Rc<Expr>, env: usize, target: usize, free: usize) {
+        let mut args = Vec::new();
+        let mut head = expr;
+        loop {
+            match &**head {
+                Expr::App(left, right) => {
+                    args.push(right);
+                    head = left;
+                }
+                Expr::Annot(inner, _) => head = inner,
+                _ => break,
+            }
+        }
+        args.reverse();
+        for (i, arg) in args.iter().enumerate() {
+            self.delay(f, arg, env, free + i, false);
+        }
+        let applied = match &**head {
+            Expr::Builtin(arity, name, _, fields, _)
+                if fields.is_empty() && args.len() >= *arity =>
+            {
+                force(f, free, *arity);
+                // the ones taking one argument get it twice
+                f.set(
+                    target,
+                    format!(
+                        "(call $prim (i32.const {}) {} {})",
+                        builtin(name),
+                        get(free),
+                        get(free + *arity - 1)
+                    ),
+                );
+                *arity
+            }
+            Expr::Data(arity, typ, name, fields)
+                if fields.is_empty() && *arity > 0 && args.len() >= *arity =>
+            {
+                let con = self.cons.id(*arity, typ, name);
+                force(f, free, *arity);
+                f.set(target, format!("(call $data (i32.const {}))", con));
+                for i in 0..*arity {
+                    f.emit(format!(
+                        "(call $put_field_at {} (i32.const {}) {})",
+                        get(target),
+                        i,
+                        get(free + i)
+                    ));
+                }
+                *arity
+            }
+            _ => {
+                let function = free + args.len();
+                self.expr(f, head, env, function, function + 1);
+                f.set(
+                    target,
+                    format!("(call $apply {} {})", get(function), get(free)),
+                );
+                1
+            }
+        };
+        for i in applied..args.len() {
+            f.set(
+                target,
+                format!("(call $apply {} {})", get(target), get(free + i)),
+            );
+        }
+    }
+
+    // put something in $s{slot} that gives the value of expr when it is forced
+    // the definitions of a letrec can't look up their neighbours yet, they might not be there
+    fn delay(&mut self, f: &mut Function, expr: &Rc<Expr>, env: usize, slot: usize, rec: bool) {
+        match &**expr {
+            Expr::Int(_, _) | Expr::Float(_, _) | Expr::Str(_, _) => {
+                let value = self.literal(expr);
+                f.set(slot, value)
+            }
+            Expr::Var(_, coords, _) if !rec => {
+                let (depth, index) = *coords.borrow();
+                f.set(
+                    slot,
+                    format!(
+                        "(call $lookup {} (i32.const {}) (i32.const {}))",
+                        get(env),
+                        depth,
+                        index
+                    ),
+                );
+            }
+            Expr::Lam(head, body) => {
+                let lam = self.lam(expr, head, body);
+                f.set(
+                    slot,
+                    format!("(call $fun (i32.const {}) {})", lam, get(env)),
+                );
+            }
+            Expr::Data(_, _, _, fields) | Expr::Builtin(_, _, _, fields, _)
+                if fields.is_empty() =>
+            {
+                self.expr(f, expr, env, slot, slot + 1)
+            }
+            Expr::Annot(inner, _) => self.delay(f, inner, env, slot, rec),
+            _ => {
+                let code = self.function(expr);
+                f.set(
+                    slot,
+                    format!("(call $thunk (i32.const {}) {})", code, get(env)),
+                );
+            }
+        }
+    }
+
+    fn literal(&mut self, expr: &Expr) -> String {
+        match expr {
+            Expr::Int(n, _) => match n.to_i64() {
+                Some(i) => format!("(call $int (i64.const {}))", i),
+                None => self.error(format!("The integer {} doesn't fit in 64 bits.", n).as_bytes()),
+            },
+            Expr::Float(n, _) => format!("(call $float (f64.const {}))", float(*n)),
+            Expr::Str(s, _) => format!("(call $str {})", self.strings.args(s.as_bytes())),
+            _ => panic!("{} isn't a literal.", expr),
+        }
+    }
+}
+
+// the arguments of a builtin or constructor are evaluated before it gets them
+fn force(f: &mut Function, free: usize, arity: usize) {
+    for i in free..free + arity {
+        f.set(i, format!("(call $force {})", get(i)));
+    }
+}
+
+fn builtin(name: &str) -> usize {
+    match BUILTINS.iter().position(|(b, _)| *b == name) {
+        Some(i) => i,
+        None => panic!("The WebAssembly runtime doesn't have the builtin {}.", name),
+    }
+}
+
+// rust writes the shortest exponent form that reads back as the same number, which is also how the text format writes floats
+fn float(n: f64) -> String {
+    if n.is_nan() {
+        "nan".to_string()
+    } else if n.is_infinite() {
+        if n > 0.0 { "inf" } else { "-inf" }.to_string()
+    } else {
+        format!("{:e}", n)
+    }
+}
+
+// anything that isn't printable ascii is written as two hex digits
+fn wat_string(bytes: &[u8]) -> String {
+    let mut out = String::from("\"");
+    for b in bytes {
+        match b {
+            b'"' | b'\\' => write!(out, "\\{:02x}", b).unwrap(),
+            0x20..=0x7e => out.push(*b as char),
+            _ => write!(out, "\\{:02x}", b).unwrap(),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/* checking a module */
+
+#[derive(Debug)]
+enum Sexp {
+    Atom(String),
+    Str(Vec<u8>),
+    List(Vec<Sexp>),
+}
+
+impl Sexp {
+    fn atom(&self) -> Option<&str> {
+        match self {
+            Sexp::Atom(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn list(&self) -> Option<&[Sexp]> {
+        match self {
+            Sexp::List(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    // the first word of a list, (func ...) is func
+    fn head(&self) -> Option<&str> {
+        self.list()
+            .and_then(|items| items.first())
+            .and_then(|s| s.atom())
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.atom().filter(|s| s.starts_with('$'))
+    }
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    at: usize,
+    line: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error<T>(&self, message: &str) -> Result<T, String> {
+        Err(format!("line {}: {}", self.line, message))
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.at).copied()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let c = self.peek();
+        if c == Some(b'\n') {
+            self.line += 1;
+        }
+        self.at += 1;
+        c
+    }
+
+    // spaces and comments, block comments can be nested
+    fn skip(&mut self) -> Result<(), String> {
+        loop {
+            match (self.peek(), self.text.get(self.at + 1)) {
+                (Some(c), _) if c.is_ascii_whitespace() => {
+                    self.next();
+                }
+                (Some(b';'), Some(b';')) => while !matches!(self.next(), Some(b'\n') | None) {},
+                (Some(b'('), Some(b';')) => {
+                    let mut depth = 0;
+                    loop {
+                        match (self.next(), self.peek()) {
+                            (Some(b'('), Some(b';')) => {
+                                self.next();
+                                depth += 1;
+                            }
+                            (Some(b';'), Some(b')')) => {
+                                self.next();
+                                depth -= 1;
+                                if depth == 0 {
+                                    break;
+                                }
+                            }
+                            (None, _) => return self.error("a block comment isn't closed"),
+                            _ => (),
+                        }
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn sexp(&mut self) -> Result<Sexp, String> {
+        self.skip()?;
+        match self.peek() {
+            Some(b'(') => {
+                self.next();
+                let mut items = Vec::new();
+                loop {
+                    self.skip()?;
+                    match self.peek() {
+                        Some(b')') => {
+                            self.next();
+                            return Ok(Sexp::List(items));
+                        }
+                        None => return self.error("a list isn't closed"),
+                        _ => items.push(self.sexp()?),
+                    }
+                }
+            }
+            Some(b')') => self.error("there's a ) without a ("),
+            Some(b'"') => {
+                self.next();
+                let mut bytes = Vec::new();
+                loop {
+                    match self.next() {
+                        Some(b'"') => return Ok(Sexp::Str(bytes)),
+                        Some(b'\\') => bytes.extend(self.escape()?),
+                        Some(c) => bytes.push(c),
+                        None => return self.error("a string isn't closed"),
+                    }
+                }
+            }
+            Some(_) => {
+                let start = self.at;
+                while let Some(c) = self.peek() {
+                    if c.is_ascii_whitespace() || c == b'(' || c == b')' || c == b'"' || c == b';' {
+                        break;
+                    }
+                    self.next();
+                }
+                Ok(Sexp::Atom(
+                    String::from_utf8_lossy(&self.text[start..self.at]).to_string(),
+                ))
+            }
+            None => self.error("the text ended early"),
+        }
+    }
+
+    fn escape(&mut self) -> Result<Vec<u8>, String> {
+        let hex = |c: u8| (c as char).to_digit(16);
+        match self.next() {
+            Some(b'n') => Ok(vec![b'\n']),
+            Some(b't') => Ok(vec![b'\t']),
+            Some(b'r') => Ok(vec![b'\r']),
+            Some(b'"') => Ok(vec![b'"']),
+            Some(b'\'') => Ok(vec![b'\'']),
+            Some(b'\\') => Ok(vec![b'\\']),
+            Some(b'u') => {
+                let mut code = 0;
+                if self.next() != Some(b'{') {
+                    return self.error("\\u needs {");
+                }
+                loop {
+                    match self.next() {
+                        Some(b'}') => break,
+                        Some(c) => match hex(c) {
+                            Some(d) => code = code * 16 + d,
+                            None => return self.error("\\u needs hex digits"),
+                        },
+                        None => return self.error("a string isn't closed"),
+                    }
+                }
+                match std::char::from_u32(code) {
+                    Some(c) => Ok(c.to_string().into_bytes()),
+                    None => self.error("\\u isn't a character"),
+                }
+            }
+            Some(c) => match (hex(c), self.next().and_then(hex)) {
+                (Some(high), Some(low)) => Ok(vec![(high * 16 + low) as u8]),
+                _ => self.error("unknown escape in a string"),
+            },
+            None => self.error("a string isn't closed"),
+        }
+    }
+}
+
+// how many parameters and results a function has
+#[derive(Clone, Copy)]
+struct Signature {
+    params: usize,
+    results: usize,
+}
+
+fn signature(items: &[Sexp]) -> Signature {
+    let count = |head: &str| {
+        items
+            .iter()
+            .filter(|s| s.head() == Some(head))
+            .map(|s| {
+                let rest = &s.list().unwrap()[1..];
+                // a named one only has one type
+                if rest.first().and_then(|s| s.name()).is_some() {
+                    1
+                } else {
+                    rest.len()
+                }
+            })
+            .sum()
+    };
+    Signature {
+        params: count("param"),
+        results: count("result"),
+    }
+}
+
+// everything that can be named, and what the checks need to know about it
+#[derive(Default)]
+struct Module {
+    types: HashMap<String, Signature>,
+    funcs: HashMap<String, Signature>,
+    globals: HashMap<String, bool>,
+    tables: usize,
+    table_size: usize,
+    memories: usize,
+    memory_size: usize,
+    exports: HashMap<String, (String, Option<Signature>)>,
+}
+
+fn define<T>(
+    names: &mut HashMap<String, T>,
+    kind: &str,
+    name: Option<&str>,
+    value: T,
+) -> Result<(), String> {
+    if let Some(name) = name {
+        if names.insert(name.to_string(), value).is_some() {
+            return Err(format!("the {} {} is defined twice", kind, name));
+        }
+    }
+    Ok(())
+}
+
+fn number(s: &str) -> Result<usize, String> {
+    let s = s.replace('_', "");
+    let parsed = match s.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.map_err(|_| format!("{} isn't a number", s))
+}
+
+// the value of an (i32.const n) offset
+fn offset(sexp: Option<&Sexp>) -> Result<usize, String> {
+    match sexp.and_then(|s| s.list()) {
+        Some([Sexp::Atom(op), Sexp::Atom(n)]) if op == "i32.const" => number(n),
+        _ => Err("an offset has to be an i32.const".to_string()),
+    }
+}
+
+// the text has to be a module of functions, globals, and so on that refer to each other properly
+pub fn validate(text: &str) -> Result<(), String> {
+    let mut parser = Parser {
+        text: text.as_bytes(),
+        at: 0,
+        line: 1,
+    };
+    let module = parser.sexp()?;
+    parser.skip()?;
+    if parser.peek().is_some() {
+        return parser.error("there's more after the module");
+    }
+    if module.head() != Some("module") {
+        return Err("the text isn't a module".to_string());
+    }
+    let mut fields = &module.list().unwrap()[1..];
+    if fields.first().and_then(|s| s.name()).is_some() {
+        fields = &fields[1..];
+    }
+
+    let mut m = Module::default();
+    let mut defined = false;
+    for field in fields {
+        let items = match field.list() {
+            Some(items) => items,
+            None => return Err(format!("{:?} isn't a module field", field)),
+        };
+        let name = items.get(1).and_then(|s| s.name());
+        match field.head() {
+            Some("type") => {
+                let func = items.iter().find(|s| s.head() == Some("func"));
+                match func {
+                    Some(func) => {
+                        define(&mut m.types, "type", name, signature(func.list().unwrap()))?
+                    }
+                    None => return Err("a type has to be a func".to_string()),
+                }
+            }
+            Some("import") => {
+                if defined {
+                    return Err("imports have to come before everything else".to_string());
+                }
+                let desc = items.get(3).and_then(|s| s.list()).unwrap_or(&[]);
+                let name = desc.get(1).and_then(|s| s.name());
+                match desc.first().and_then(|s| s.atom()) {
+                    Some("func") => {
+                        let sig = func_signature(&m, desc)?;
+                        define(&mut m.funcs, "func", name, sig)?
+                    }
+                    Some("global") => define(
+                        &mut m.globals,
+                        "global",
+                        name,
+                        desc.iter().any(|s| s.head() == Some("mut")),
+                    )?,
+                    Some("memory") => m.memories += 1,
+                    Some("table") => m.tables += 1,
+                    _ => return Err("an import has to say what it imports".to_string()),
+                }
+            }
+            Some("func") => {
+                defined = true;
+                let sig = func_signature(&m, items)?;
+                define(&mut m.funcs, "func", name, sig)?;
+            }
+            Some("global") => {
+                defined = true;
+                define(
+                    &mut m.globals,
+                    "global",
+                    name,
+                    items.iter().any(|s| s.head() == Some("mut")),
+                )?;
+            }
+            Some("memory") => {
+                defined = true;
+                m.memories += 1;
+                let size = items[1..]
+                    .iter()
+                    .find_map(|s| s.atom().filter(|a| !a.starts_with('$')));
+                m.memory_size = match size {
+                    Some(pages) => number(pages)? * PAGE,
+                    None => return Err("a memory needs a size".to_string()),
+                };
+            }
+            Some("table") => {
+                defined = true;
+                m.tables += 1;
+                let size = items[1..]
+                    .iter()
+                    .find_map(|s| s.atom().filter(|a| !a.starts_with('$')));
+                m.table_size = match size {
+                    Some(n) => number(n)?,
+                    None => return Err("a table needs a size".to_string()),
+                };
+            }
+            Some("export") | Some("elem") | Some("data") | Some("start") => (),
+            _ => return Err(format!("{:?} isn't a module field", field)),
+        }
+        // exports can be written inside of what they export
+        for export in items.iter().filter(|s| s.head() == Some("export")) {
+            let kind = field.head().unwrap();
+            if kind == "export" {
+                continue;
+            }
+            let sig = match kind {
+                "func" => Some(func_signature(&m, items)?),
+                _ => None,
+            };
+            export_name(&mut m, export, kind, name, sig)?;
+        }
+    }
+    if m.memories > 1 || m.tables > 1 {
+        return Err("there can only be one memory and one table".to_string());
+    }
+
+    for field in fields {
+        let items = field.list().unwrap();
+        match field.head() {
+            Some("export") => {
+                let desc = items.get(2).and_then(|s| s.list()).unwrap_or(&[]);
+                let kind = desc.first().and_then(|s| s.atom()).unwrap_or("");
+                let name = desc.get(1).and_then(|s| s.name());
+                let sig = name.and_then(|n| m.funcs.get(n)).copied();
+                export_name(&mut m, field, kind, name, sig)?;
+            }
+            Some("func") => check_func(&m, items)?,
+            Some("global") => {
+                let init = items.last().filter(|s| s.list().is_some());
+                if let Some(init) = init {
+                    check_instr(&m, &Locals::default(), &mut Vec::new(), init)?;
+                }
+            }
+            Some("elem") => {
+                let start = offset(items.get(1))?;
+                let funcs: Vec<&Sexp> = items[2..]
+                    .iter()
+                    .filter(|s| s.atom() != Some("func"))
+                    .collect();
+                for func in &funcs {
+                    match func.name() {
+                        Some(name) if m.funcs.contains_key(name) => (),
+                        _ => {
+                            return Err(format!("the table has {:?} which isn't a function", func))
+                        }
+                    }
+                }
+                if start + funcs.len() > m.table_size {
+                    return Err("the elements don't fit in the table".to_string());
+                }
+            }
+            Some("data") => {
+                if m.memories == 0 {
+                    return Err("there's data without a memory".to_string());
+                }
+                let start = offset(items.get(1))?;
+                let mut len = 0;
+                for s in &items[2..] {
+                    match s {
+                        Sexp::Str(bytes) => len += bytes.len(),
+                        _ => return Err("data can only be strings".to_string()),
+                    }
+                }
+                if start + len > m.memory_size {
+                    return Err("the data doesn't fit in memory".to_string());
+                }
+            }
+            Some("start") => match items.get(1).and_then(|s| s.name()) {
+                Some(name) if m.funcs.contains_key(name) => (),
+                _ => return Err("the start function isn't defined".to_string()),
+            },
+            _ => (),
+        }
+    }
+
+    match m.exports.get("main") {
+        Some((kind, sig)) if kind == "func" => {
+            if let Some(sig) = sig {
+                if sig.params != 0 || sig.results != 1 {
+                    return Err("main has to take nothing and give back a value".to_string());
+                }
+            }
+        }
+        _ => return Err("main isn't exported as a function".to_string()),
+    }
+    match m.exports.get("memory") {
+        Some((kind, _)) if kind == "memory" => Ok(()),
+        _ => Err("memory isn't exported".to_string()),
+    }
+}
+
+fn func_signature(m: &Module, items: &[Sexp]) -> Result<Signature, String> {
+    let typ = items.iter().find(|s| s.head() == Some("type"));
+    let inline = signature(items);
+    match typ
+        .and_then(|t| t.list().unwrap().get(1))
+        .and_then(|s| s.atom())
+    {
+        Some(name) => match m.types.get(name) {
+            Some(sig) => Ok(*sig),
+            None => Err(format!("the type {} isn't defined", name)),
+        },
+        None if typ.is_some() => Err("a type use needs a name".to_string()),
+        None => Ok(inline),
+    }
+}
+
+// every export name is only used once and refers to something that is there
+fn export_name(
+    m: &mut Module,
+    export: &Sexp,
+    kind: &str,
+    name: Option<&str>,
+    sig: Option<Signature>,
+) -> Result<(), String> {
+    let items = export.list().unwrap();
+    let export_name = match items.get(1) {
+        Some(Sexp::Str(bytes)) => String::from_utf8_lossy(bytes).to_string(),
+        _ => return Err("an export needs a name".to_string()),
+    };
+    let exists = match (kind, name) {
+        ("func", Some(name)) => m.funcs.contains_key(name),
+        ("global", Some(name)) => m.globals.contains_key(name),
+        ("memory", _) => m.memories > 0,
+        ("table", _) => m.tables > 0,
+        ("func", None) | ("global", None) => items.len() == 2,
+        _ => false,
+    };
+    if !exists {
+        return Err(format!("the export {} isn't defined", export_name));
+    }
+    let value = (kind.to_string(), sig.filter(|_| kind == "func"));
+    if m.exports.insert(export_name.clone(), value).is_some() {
+        return Err(format!("{} is exported twice", export_name));
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct Locals {
+    names: HashSet<String>,
+    count: usize,
+}
+
+fn check_func(m: &Module, items: &[Sexp]) -> Result<(), String> {
+    let mut locals = Locals::default();
+    let name = items.get(1).and_then(|s| s.name()).unwrap_or("func");
+    let mut body = Vec::new();
+    for item in &items[1..] {
+        match item.head() {
+            Some("param") | Some("local") => {
+                let rest = &item.list().unwrap()[1..];
+                match rest.first().and_then(|s| s.name()) {
+                    Some(local) => {
+                        if !locals.names.insert(local.to_string()) {
+                            return Err(format!("{} has two locals called {}", name, local));
+                        }
+                        locals.count += 1;
+                    }
+                    None => locals.count += rest.len(),
+                }
+            }
+            Some("export") | Some("type") | Some("result") => (),
+            _ if item.name().is_some() && body.is_empty() => (),
+            _ => body.push(item),
+        }
+    }
+    // a type use without inline params still has the parameters
+    if let Ok(sig) = func_signature(m, items) {
+        locals.count = locals.count.max(sig.params);
+    }
+    for instr in body {
+        check_instr(m, &locals, &mut Vec::new(), instr)
+            .map_err(|e| format!("in {}: {}", name, e))?;
+    }
+    Ok(())
+}
+
+fn check_local(locals: &Locals, local: Option<&Sexp>) -> Result<(), String> {
+    match local.and_then(|s| s.atom()) {
+        Some(name) if name.starts_with('$') && locals.names.contains(name) => Ok(()),
+        Some(n) if !n.starts_with('$') && number(n)? < locals.count => Ok(()),
+        _ => Err(format!("the local {:?} isn't defined", local)),
+    }
+}
+
+fn check_label(labels: &[Option<String>], label: &Sexp) -> Result<(), String> {
+    match label.atom() {
+        Some(name) if name.starts_with('$') => {
+            if labels.iter().any(|l| l.as_deref() == Some(name)) {
+                Ok(())
+            } else {
+                Err(format!("there's no {} to branch to", name))
+            }
+        }
+        Some(n) if number(n)? < labels.len() => Ok(()),
+        _ => Err(format!("{:?} isn't a label", label)),
+    }
+}
+
+fn check_instr(
+    m: &Module,
+    locals: &Locals,
+    labels: &mut Vec<Option<String>>,
+    instr: &Sexp,
+) -> Result<(), String> {
+    let items = match instr {
+        Sexp::List(items) => items,
+        Sexp::Atom(op) if known(op) => return Ok(()),
+        _ => return Err(format!("{:?} isn't an instruction", instr)),
+    };
+    let op = match items.first().and_then(|s| s.atom()) {
+        Some(op) => op,
+        None => return Err(format!("{:?} isn't an instruction", instr)),
+    };
+    let mut rest = &items[1..];
+    match op {
+        "block" | "loop" | "if" => {
+            let label = rest.first().and_then(|s| s.name()).map(|s| s.to_string());
+            if label.is_some() {
+                rest = &rest[1..];
+            }
+            while matches!(
+                rest.first().and_then(|s| s.head()),
+                Some("result") | Some("param")
+            ) {
+                rest = &rest[1..];
+            }
+            if op == "if" {
+                let then = rest.iter().position(|s| s.head() == Some("then"));
+                let then = match then {
+                    Some(then) => then,
+                    None => return Err("an if needs a then".to_string()),
+                };
+                // the condition is outside of the if's label
+                for cond in &rest[..then] {
+                    check_instr(m, locals, labels, cond)?;
+                }
+                rest = &rest[then..];
+            }
+            labels.push(label);
+            for item in rest {
+                match item.head() {
+                    Some("then") | Some("else") if op == "if" => {
+                        for i in &item.list().unwrap()[1..] {
+                            check_instr(m, locals, labels, i)?;
+                        }
+                    }
+                    _ if op == "if" => return Err("an if can only have then and else".to_string()),
+                    _ => check_instr(m, locals, labels, item)?,
+                }
+            }
+            labels.pop();
+            return Ok(());
+        }
+        "br" | "br_if" => {
+            match rest.first() {
+                Some(label) => check_label(labels, label)?,
+                None => return Err(format!("{} needs a label", op)),
+            }
+            rest = &rest[1..];
+        }
+        "br_table" => {
+            let count = rest.iter().take_while(|s| s.atom().is_some()).count();
+            if count == 0 {
+                return Err("br_table needs labels".to_string());
+            }
+            for label in &rest[..count] {
+                check_label(labels, label)?;
+            }
+            rest = &rest[count..];
+        }
+        "local.get" | "local.set" | "local.tee" => {
+            check_local(locals, rest.first())?;
+            rest = &rest[1..];
+        }
+        "global.get" | "global.set" => {
+            let name = rest.first().and_then(|s| s.atom()).unwrap_or("");
+            match m.globals.get(name) {
+                Some(false) if op == "global.set" => {
+                    return Err(format!("the global {} can't be set", name))
+                }
+                Some(_) => (),
+                None => return Err(format!("the global {} isn't defined", name)),
+            }
+            rest = &rest[1..];
+        }
+        "call" => {
+            let name = rest.first().and_then(|s| s.atom()).unwrap_or("");
+            let sig = match m.funcs.get(name) {
+                Some(sig) => sig,
+                None => return Err(format!("the function {} isn't defined", name)),
+            };
+            rest = &rest[1..];
+            // only the folded arguments can be counted
+            if rest.iter().all(|s| s.list().is_some())
+                && !rest.is_empty()
+                && rest.len() != sig.params
+            {
+                return Err(format!(
+                    "{} takes {} arguments, not {}",
+                    name,
+                    sig.params,
+                    rest.len()
+                ));
+            }
+        }
+        "call_indirect" => {
+            if m.tables == 0 {
+                return Err("call_indirect needs a table".to_string());
+            }
+            let typ = rest.iter().find(|s| s.head() == Some("type"));
+            let name = typ
+                .and_then(|t| t.list().unwrap().get(1))
+                .and_then(|s| s.atom())
+                .unwrap_or("");
+            if !m.types.contains_key(name) {
+                return Err(format!("the type {} isn't defined", name));
+            }
+            rest = &rest[1..];
+        }
+        "i32.const" | "i64.const" | "f32.const" | "f64.const" => {
+            if rest.first().and_then(|s| s.atom()).is_none() {
+                return Err(format!("{} needs a number", op));
+            }
+            rest = &rest[1..];
+        }
+        _ if known(op) => {
+            if op.starts_with("memory.") || op.contains(".load") || op.contains(".store") {
+                if m.memories == 0 {
+                    return Err(format!("{} needs a memory", op));
+                }
+                let immediates = rest
+                    .iter()
+                    .take_while(|s| {
+                        s.atom()
+                            .is_some_and(|a| a.starts_with("offset=") || a.starts_with("align="))
+                    })
+                    .count();
+                rest = &rest[immediates..];
+            }
+        }
+        _ => return Err(format!("{} isn't an instruction", op)),
+    }
+    for operand in rest {
+        if operand.list().is_none() {
+            return Err(format!("{:?} isn't an operand of {}", operand, op));
+        }
+        check_instr(m, locals, labels, operand)?;
+    }
+    Ok(())
+}
+
+// the instructions that aren't checked any more than their operands are
+fn known(op: &str) -> bool {
+    const PLAIN: [&str; 11] = [
+        "unreachable",
+        "nop",
+        "return",
+        "drop",
+        "select",
+        "memory.size",
+        "memory.grow",
+        "memory.copy",
+        "memory.fill",
+        "i32.wrap_i64",
+        "i64.extend_i32_s",
+    ];
+    const INT: [&str; 40] = [
+        "clz",
+        "ctz",
+        "popcnt",
+        "add",
+        "sub",
+        "mul",
+        "div_s",
+        "div_u",
+        "rem_s",
+        "rem_u",
+        "and",
+        "or",
+        "xor",
+        "shl",
+        "shr_s",
+        "shr_u",
+        "rotl",
+        "rotr",
+        "eqz",
+        "eq",
+        "ne",
+        "lt_s",
+        "lt_u",
+        "gt_s",
+        "gt_u",
+        "le_s",
+        "le_u",
+        "ge_s",
+        "ge_u",
+        "load",
+        "store",
+        "load8_s",
+        "load8_u",
+        "load16_s",
+        "load16_u",
+        "store8",
+        "store16",
+        "trunc_f64_s",
+        "trunc_f64_u",
+        "reinterpret_f64",
+    ];
+    const FLOAT: [&str; 22] = [
+        "abs", "neg", "ceil", "floor", "trunc", "nearest", "sqrt", "add", "sub", "mul", "div",
+        "min", "max", "copysign", "eq", "ne", "lt", "gt", "le", "ge", "load", "store",
+    ];
+    const CONVERT: [&str; 6] = [
+        "i64.extend_i32_u",
+        "f64.convert_i64_s",
+        "f64.convert_i32_s",
+        "f64.promote_f32",
+        "f32.demote_f64",
+        "f64.reinterpret_i64",
+    ];
+    if PLAIN.contains(&op) || CONVERT.contains(&op) {
+        return true;
+    }
+    match op.split_once('.') {
+        Some(("i32", rest)) | Some(("i64", rest)) => INT.contains(&rest),
+        Some(("f32", rest)) | Some(("f64", rest)) => FLOAT.contains(&rest),
+        _ => false,
+    }
+}
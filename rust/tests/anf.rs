@@ -0,0 +1,118 @@
+/*
+
+lowering to a-normal form can't change what a program does
+
+the programs are lowered, turned back into an Expr, and have to print the same as before with eval
+what --dump=anf prints is the anf fixture
+
+*/
+
+use bagl::anf::lower;
+use bagl::anf::Atom;
+use bagl::anf::Term;
+use bagl::ast::Expr;
+use bagl::env::Env;
+use bagl::eval::eval;
+use bagl::front::compile;
+use bagl::gram;
+use bagl::scan::resolve;
+use std::rc::Rc;
+
+fn program(source: &str) -> Rc<Expr> {
+    compile(&gram::TopParser::new().parse(source).unwrap(), "main").unwrap()
+}
+
+fn run(expr: Rc<Expr>) -> String {
+    resolve(Rc::clone(&expr));
+    eval(expr, Rc::new(Env::new()), Vec::new()).to_string()
+}
+
+// the names the lowering made up, each let of one has to be the only one
+fn fresh(term: &Term, found: &mut Vec<String>) {
+    match term {
+        Term::Let(name, def, body) => {
+            if name.starts_with('$') {
+                assert!(!found.contains(name), "{} is bound twice", name);
+                found.push(name.to_string());
+            }
+            fresh(def, found);
+            fresh(body, found);
+        }
+        Term::LetRec(defs, body) => {
+            for (_, def) in defs {
+                fresh(def, found);
+            }
+            fresh(body, found);
+        }
+        Term::Lam(_, body) => fresh(body, found),
+        Term::Case(_, alts) => {
+            for (_, branch) in alts {
+                fresh(branch, found);
+            }
+        }
+        Term::If(_, b1, b2) => {
+            fresh(b1, found);
+            fresh(b2, found);
+        }
+        _ => (),
+    }
+}
+
+#[test]
+fn lowering_keeps_values() {
+    let programs = [
+        "main = + (* 2 3) (- 10 4)",
+        "main = (\\x . \\y . + x y) (+ 1 2) 4",
+        "main = (\\x . \\_ . x) 5 (error \"never\")",
+        "Maybe a = Just a | None;
+main = case (Just (+ 1 2)) { Just x -> x; None -> 0 }",
+        "main = if (eq (+ 1 1) 2) then \"yes\" else \"no\"",
+        "main = let evn = \\n . if (eq n 0) then True else od (- n 1); od = \\n . if (eq n 0) then False else evn (- n 1) in evn 11",
+        "List a = Cons a (List a) | Nil;
+map f xs = case xs { Cons y ys -> Cons (f y) (map f ys); Nil -> Nil };
+range a b = if (eq a b) then Nil else Cons a (range (+ a 1) b);
+main = let k = 10 in map (\\x . * x k) (range 0 5)",
+        "main = let xs = ++ \"a\" (show 1.5) in ++ xs xs",
+        "Pair a b = Pair a b; main = Pair 1",
+    ];
+    for source in programs {
+        let expr = program(source);
+        let term = lower(&expr);
+        fresh(&term, &mut Vec::new());
+        assert_eq!(run(expr), run(term.to_expr()), "in {}", source);
+    }
+}
+
+#[test]
+fn results_in_between_are_named() {
+    let term = lower(&program("main = + (* 2 3) 1"));
+    let mut main = None;
+    let mut rest = &term;
+    while let Term::Let(name, def, body) = rest {
+        if name == "main" {
+            main = Some(def);
+        }
+        rest = body;
+    }
+    let printed = term.to_string();
+    // the product gets a name and the sum only takes atoms
+    match &**main.expect("main isn't defined") {
+        Term::Let(name, def, body) => {
+            assert_eq!(name, "$1");
+            assert!(matches!(&**def, Term::App(Atom::Builtin(2, _, _), _)));
+            match &**body {
+                Term::App(Atom::Builtin(2, op, _), args) => {
+                    assert_eq!(op, "+");
+                    assert!(matches!(&args[..], [Atom::Var(v), Atom::Int(_)] if v == "$1"));
+                }
+                _ => panic!("{}", printed),
+            }
+        }
+        _ => panic!("{}", printed),
+    }
+    assert!(
+        printed.contains("    let $1 = (*) 2 3 in\n    (+) $1 1\n"),
+        "{}",
+        printed
+    );
+}
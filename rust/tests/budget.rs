@@ -0,0 +1,64 @@
+/*
+
+a machine that ran out of its budget can still be used
+
+the depth goes back to where it was after a program that nested too deeply, so the next one gets the whole budget
+a loop made of tail calls doesn't nest at all, it runs until the time is up
+
+*/
+
+use bagl::ast::Expr;
+use bagl::env::Env;
+use bagl::eval::Budget;
+use bagl::eval::Limit;
+use bagl::eval::Machine;
+use bagl::front::compile;
+use bagl::gram;
+use std::rc::Rc;
+use std::time::Duration;
+
+fn program(source: &str) -> Rc<Expr> {
+    compile(&gram::TopParser::new().parse(source).unwrap(), "main").unwrap()
+}
+
+#[test]
+fn depth_is_restored_after_running_out() {
+    let shallow = program("sum n = if (eq n 0) then 0 else + n (sum (- n 1)); main = sum 5");
+    let deep = program("deep n = + 1 (deep n); main = deep 1");
+    let mut machine = Machine::new(Budget::default());
+    machine
+        .eval(Rc::clone(&shallow), Rc::new(Env::new()), Vec::new())
+        .unwrap();
+    let needed = machine.stats().max_depth;
+
+    let mut machine = Machine::new(Budget {
+        depth: Some(needed),
+        ..Budget::default()
+    });
+    for _ in 0..10 {
+        let exhausted = machine
+            .eval(Rc::clone(&deep), Rc::new(Env::new()), Vec::new())
+            .unwrap_err();
+        assert_eq!(exhausted.limit, Limit::Depth);
+    }
+    let value = machine
+        .eval(shallow, Rc::new(Env::new()), Vec::new())
+        .unwrap();
+    assert_eq!(value.to_string(), "15");
+}
+
+#[test]
+fn loops_run_until_the_time_is_up() {
+    let looping = program("loop x = loop x; main = loop 1");
+    // a tail call doesn't nest, so the loop stays well under the depth it's given
+    let mut machine = Machine::new(Budget {
+        timeout: Some(Duration::from_millis(100)),
+        depth: Some(1000),
+        ..Budget::default()
+    });
+    let exhausted = machine
+        .eval(looping, Rc::new(Env::new()), Vec::new())
+        .unwrap_err();
+    assert_eq!(exhausted.limit, Limit::Timeout);
+    assert!(exhausted.stats.max_depth < 10);
+}
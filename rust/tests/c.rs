@@ -0,0 +1,127 @@
+/*
+
+the programs in tests/fixtures compiled to C have to do the same thing eval does
+
+each fixture that runs, or stops with an error at runtime, is compiled with bagl c and then with the C compiler
+the exit code and stdout have to be what the .expected file says (tests/common)
+
+the C compiler is $CC or cc, when there isn't one the test says so and passes
+
+integers are 64 bits in C, going past that has to be an error even when the optimizer is looking for undefined behavior
+
+*/
+
+mod common;
+
+use common::agree;
+use common::fixtures;
+use common::tool;
+use std::env;
+use std::fs;
+use std::process::Command;
+
+fn compiler() -> Option<String> {
+    tool("CC", "cc")
+}
+
+// compile and run a program with the optimizations on, the exit code and stdout
+fn run(cc: &str, name: &str, program: &str) -> (Option<i32>, String) {
+    let out = env::temp_dir().join(format!("bagl-c-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&out).unwrap();
+    let bagl = out.join(name).with_extension("bagl");
+    let source = out.join(name).with_extension("c");
+    let binary = out.join(name);
+    fs::write(&bagl, program).unwrap();
+    let status = Command::new(env!("CARGO_BIN_EXE_bagl"))
+        .arg("c")
+        .arg("-o")
+        .arg(&source)
+        .arg(&bagl)
+        .output()
+        .expect("Couldn't run bagl.");
+    assert!(status.status.success(), "{} didn't compile to C", name);
+    let build = Command::new(cc)
+        .arg("-O2")
+        .arg("-o")
+        .arg(&binary)
+        .arg(&source)
+        .arg("-lm")
+        .arg("-lpthread")
+        .output()
+        .expect("Couldn't run the C compiler.");
+    assert!(build.status.success(), "{} didn't build", name);
+    let run = Command::new(&binary).output().unwrap();
+    fs::remove_dir_all(&out).ok();
+    (
+        run.status.code(),
+        String::from_utf8_lossy(&run.stdout).to_string(),
+    )
+}
+
+#[test]
+fn multiplying_past_64_bits_is_an_error() {
+    let cc = match compiler() {
+        Some(cc) => cc,
+        None => {
+            eprintln!("no C compiler, skipping");
+            return;
+        }
+    };
+    let (code, stdout) = run(&cc, "fits", "main = * 3037000499 (- 0 3037000499)");
+    assert_eq!((code, stdout.trim()), (Some(0), "-9223372030926249001"));
+    for (name, program) in [
+        ("positive", "main = * 3037000500 3037000500"),
+        ("negative", "main = * 3037000500 (- 0 3037000500)"),
+        (
+            "smallest",
+            "main = * (- (- 0 9223372036854775807) 1) (- 0 1)",
+        ),
+    ] {
+        let (code, stdout) = run(&cc, name, program);
+        assert_ne!(code, Some(0), "{} gave {}", program, stdout);
+    }
+}
+
+#[test]
+fn compiled_fixtures_match_eval() {
+    let cc = match compiler() {
+        Some(cc) => cc,
+        None => {
+            eprintln!("no C compiler, skipping");
+            return;
+        }
+    };
+    let dir = fixtures();
+    let out = env::temp_dir().join(format!("bagl-c-{}", std::process::id()));
+    fs::create_dir_all(&out).unwrap();
+    agree(|name| {
+        let source = out.join(name).with_extension("c");
+        let binary = out.join(name);
+        let status = Command::new(env!("CARGO_BIN_EXE_bagl"))
+            .arg("c")
+            .arg("-o")
+            .arg(&source)
+            .arg(format!("{}.bagl", name))
+            .current_dir(&dir)
+            .output()
+            .expect("Couldn't run bagl.");
+        assert!(status.status.success(), "{} didn't compile to C", name);
+        let build = Command::new(&cc)
+            .arg("-O1")
+            .arg("-o")
+            .arg(&binary)
+            .arg(&source)
+            .arg("-lm")
+            .arg("-lpthread")
+            .output()
+            .expect("Couldn't run the C compiler.");
+        assert!(
+            build.status.success(),
+            "{} didn't build:\n{}",
+            name,
+            String::from_utf8_lossy(&build.stderr)
+        );
+        Command::new(&binary).output().unwrap()
+    });
+    fs::remove_dir_all(&out).ok();
+}
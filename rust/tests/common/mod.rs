@@ -0,0 +1,126 @@
+/*
+
+what the tests that run the fixtures share, the golden test and the backends that have to agree with it
+
+a .expected file is written by the golden test and read by the rest
+    exit: 0
+    --- stdout
+    ...
+    --- stderr
+    ...
+the backends compare the exit code and stdout, stderr isn't compared since a panic prints more
+
+*/
+
+// each test file only uses some of this
+#![allow(dead_code)]
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::ExitStatus;
+use std::process::Output;
+
+pub fn fixtures() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+}
+
+// the names of the fixtures without the .bagl, sorted
+pub fn names() -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(fixtures())
+        .expect("Couldn't read the fixtures.")
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|p| p.extension().is_some_and(|e| e == "bagl"))
+        .map(|p| p.file_stem().unwrap().to_string_lossy().to_string())
+        .collect();
+    names.sort();
+    names
+}
+
+// a program that answers --version, the name from the variable or the default
+pub fn tool(variable: &str, default: &str) -> Option<String> {
+    let tool = env::var(variable).unwrap_or_else(|_| default.to_string());
+    match Command::new(&tool).arg("--version").output() {
+        Ok(output) if output.status.success() => Some(tool),
+        _ => None,
+    }
+}
+
+pub fn exit_code(status: &ExitStatus) -> String {
+    match status.code() {
+        Some(code) => code.to_string(),
+        None => "killed".to_string(),
+    }
+}
+
+pub fn render(code: &str, stdout: &str, stderr: &str) -> String {
+    format!(
+        "exit: {}\n--- stdout\n{}--- stderr\n{}",
+        code, stdout, stderr
+    )
+}
+
+// the exit code and stdout from the .expected file
+pub fn expected(text: &str) -> (String, String) {
+    let code = text
+        .lines()
+        .next()
+        .and_then(|l| l.strip_prefix("exit: "))
+        .unwrap_or("")
+        .to_string();
+    let start = text.find("--- stdout\n").map(|i| i + "--- stdout\n".len());
+    let end = text.find("--- stderr\n");
+    let stdout = match (start, end) {
+        (Some(start), Some(end)) => text[start..end].to_string(),
+        _ => String::new(),
+    };
+    (code, stdout)
+}
+
+// the fixtures another backend has to agree with eval on, the ones that run or stop with an error at runtime
+// fixtures with a .args file are left out, they test flags of the interpreter
+pub fn runnable() -> Vec<String> {
+    let dir = fixtures();
+    names()
+        .into_iter()
+        .filter(|name| !dir.join(name).with_extension("args").exists())
+        .filter(|name| {
+            let text = fs::read_to_string(dir.join(name).with_extension("expected")).unwrap();
+            let (code, _) = expected(&text);
+            code == "0" || code == "101"
+        })
+        .collect()
+}
+
+// run each of the runnable fixtures some other way and check it does what the .expected file says
+pub fn agree(mut run: impl FnMut(&str) -> Output) {
+    let dir = fixtures();
+    let mut failures = Vec::new();
+    let mut ran = 0;
+    for name in runnable() {
+        let text = fs::read_to_string(dir.join(&name).with_extension("expected")).unwrap();
+        let (code, stdout) = expected(&text);
+        let output = run(&name);
+        let actual_code = exit_code(&output.status);
+        let actual = String::from_utf8_lossy(&output.stdout).to_string();
+        if actual_code != code || actual != stdout {
+            failures.push(format!(
+                "{}: expected exit {} with\n{}got exit {} with\n{}{}",
+                name,
+                code,
+                stdout,
+                actual_code,
+                actual,
+                String::from_utf8_lossy(&output.stderr)
+            ));
+        }
+        ran += 1;
+    }
+    assert!(ran > 0);
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
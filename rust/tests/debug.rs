@@ -0,0 +1,50 @@
+/*
+
+the debugger shows the environment by the names the slots were bound to
+
+*/
+
+use bagl::ast::Expr;
+use bagl::debug::Debugger;
+use bagl::env::Env;
+use bagl::eval::Budget;
+use bagl::eval::Machine;
+use bagl::front::compile;
+use bagl::gram;
+use std::io::Cursor;
+use std::rc::Rc;
+
+fn program(source: &str) -> Rc<Expr> {
+    compile(&gram::TopParser::new().parse(source).unwrap(), "main").unwrap()
+}
+
+// what the debugger writes when given the commands
+fn session(source: &str, breakpoints: &[&str], commands: &str) -> String {
+    let mut output = Vec::new();
+    let mut debugger = Debugger::new(
+        Cursor::new(commands.to_string()),
+        &mut output,
+        breakpoints.iter().map(|b| b.to_string()).collect(),
+    );
+    let mut machine = Machine::with_hook(Budget::default(), &mut debugger);
+    let _ = machine.eval(program(source), Rc::new(Env::new()), Vec::new());
+    drop(machine);
+    drop(debugger);
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn slots_are_shown_by_name() {
+    let output = session(
+        "Maybe a = Just a | Nothing;
+double x = + x x;
+main = case Just 3 { Just n -> double n; Nothing -> 0 }",
+        &["double"],
+        "env 2\nstep\nstep\nstep\nenv 1\nquit\n",
+    );
+    // the branch, then the letrec of the program
+    assert!(output.contains("frame 0\n    n: 3\nframe 1\n    double: (\\ x . "));
+    // the lambda
+    assert!(output.contains("frame 0\n    x: 3\n"));
+    assert!(!output.contains("    0: "));
+}
@@ -0,0 +1,78 @@
+/*
+
+frames in cycles have to be freed by the collector in gc.rs, and nothing that is still used can be
+
+a machine is used the way an embedding would, the heap stats say what was collected
+a value held on to after evaluation has to keep working after a collection
+
+*/
+
+use bagl::ast::Expr;
+use bagl::ast::Span;
+use bagl::env::Env;
+use bagl::eval::Budget;
+use bagl::eval::Machine;
+use bagl::front::compile;
+use bagl::gram;
+use num::bigint::BigInt;
+use std::rc::Rc;
+use std::thread;
+
+fn program(source: &str) -> Rc<Expr> {
+    compile(&gram::TopParser::new().parse(source).unwrap(), "main").unwrap()
+}
+
+const COUNT: &str = "count n = let go = \\i . if (eq i 0) then 0 else + 1 (go (- i 1)) in go n;
+loop k acc = if (eq k 0) then acc else loop (- k 1) (+ acc (count 3));";
+
+#[test]
+fn letrec_frames_are_collected() {
+    let expr = program(&format!("{} main = loop 10 0", COUNT));
+    let mut machine = Machine::new(Budget::default());
+    let value = machine.eval(expr, Rc::new(Env::new()), Vec::new()).unwrap();
+    assert_eq!(value.to_string(), "30");
+    drop(value);
+    let collected = machine.collect();
+    let heap = machine.heap();
+    assert!(collected > 0);
+    assert_eq!(heap.frames, 0, "{:?}", heap);
+    assert_eq!(heap.collected, collected as u64);
+}
+
+#[test]
+fn values_keep_their_frames() {
+    let expr = program("main = let go = \\i . if (eq i 0) then 0 else + 2 (go (- i 1)) in go");
+    let mut machine = Machine::new(Budget::default());
+    let go = machine.eval(expr, Rc::new(Env::new()), Vec::new()).unwrap();
+    machine.collect();
+    assert!(machine.heap().frames > 0);
+    let arg = Rc::new(Expr::Int(BigInt::from(5), Span::default()));
+    let value = machine
+        .eval(Rc::clone(&go), Rc::new(Env::new()), vec![arg])
+        .unwrap();
+    assert_eq!(value.to_string(), "10");
+    drop(go);
+    machine.collect();
+    assert_eq!(machine.heap().frames, 0);
+}
+
+// long enough for collections to happen during evaluation, which needs more stack than a test gets
+#[test]
+fn collecting_while_running() {
+    thread::Builder::new()
+        .stack_size(1 << 28)
+        .spawn(|| {
+            let expr = program(&format!("{} main = loop 2000 0", COUNT));
+            let mut machine = Machine::new(Budget::default());
+            let value = machine.eval(expr, Rc::new(Env::new()), Vec::new()).unwrap();
+            assert_eq!(value.to_string(), "6000");
+            let heap = machine.heap();
+            assert!(heap.collections > 0, "{:?}", heap);
+            assert!(heap.collected > 0, "{:?}", heap);
+            // without collecting every frame made would still be alive
+            assert!(heap.peak < heap.made as usize / 2, "{:?}", heap);
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+}
@@ -0,0 +1,173 @@
+/*
+
+the G-machine has to do the same thing eval does
+
+the fixtures that run, or stop with an error at runtime, are run with bagl gm and have to match their .expected exit code and stdout
+smaller programs are run both ways and the values have to print the same
+the rest checks the parts eval doesn't have, the lifting and the sharing
+
+*/
+
+use bagl::ast::Expr;
+use bagl::env::Env;
+use bagl::eval::eval;
+use bagl::front;
+use bagl::gmachine::compile;
+use bagl::gmachine::Machine;
+use bagl::gram;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::rc::Rc;
+
+// the program the way bagl runs it, without showing the result
+fn program(source: &str) -> (bagl::ast::Toplevel, Rc<Expr>) {
+    let parse = gram::TopParser::new().parse(source).unwrap();
+    let expr = front::compile(&parse, "main").unwrap();
+    (parse, expr)
+}
+
+fn both(source: &str) -> (String, String) {
+    let (parse, expr) = program(source);
+    let evaluated = eval(Rc::clone(&expr), Rc::new(Env::new()), Vec::new());
+    let compiled = compile(&expr, &parse);
+    let reduced = Machine::new(&compiled).run();
+    (evaluated.to_string(), reduced.to_string())
+}
+
+fn steps(source: &str) -> u64 {
+    let (parse, expr) = program(source);
+    let compiled = compile(&expr, &parse);
+    let mut machine = Machine::new(&compiled);
+    machine.run();
+    machine.steps()
+}
+
+#[test]
+fn fixtures_match_eval() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures");
+    let mut failures = Vec::new();
+    let mut ran = 0;
+    for entry in fs::read_dir(&dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_none_or(|e| e != "bagl") || path.with_extension("args").exists() {
+            continue;
+        }
+        let expected = fs::read_to_string(path.with_extension("expected")).unwrap();
+        let code = expected.lines().next().unwrap_or("").to_string();
+        if code != "exit: 0" && code != "exit: 101" {
+            continue;
+        }
+        let start = expected.find("--- stdout\n").unwrap() + "--- stdout\n".len();
+        let end = expected.find("--- stderr\n").unwrap();
+        let output = Command::new(env!("CARGO_BIN_EXE_bagl"))
+            .arg("gm")
+            .arg(path.file_name().unwrap())
+            .current_dir(&dir)
+            .env("RUST_BACKTRACE", "0")
+            .output()
+            .expect("Couldn't run bagl.");
+        let actual_code = format!("exit: {}", output.status.code().unwrap_or(-1));
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if actual_code != code || stdout != expected[start..end] {
+            failures.push(format!(
+                "{}: expected {} with\n{}got {} with\n{}{}",
+                path.display(),
+                code,
+                &expected[start..end],
+                actual_code,
+                stdout,
+                String::from_utf8_lossy(&output.stderr)
+            ));
+        }
+        ran += 1;
+    }
+    assert!(ran > 0);
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+fn values_print_like_eval() {
+    let programs = [
+        "main = + 1",
+        "main = (\\x . + x 1.5)",
+        "add x y = + x y; main = add 1",
+        "compose f g = \\x . f (g x); main = compose (\\y . * y 2) (\\z . + z 1)",
+        "Pair a b = Pair a b; main = Pair (- 0.0 2.0)",
+        "Pair a b = Pair a b; main = Pair \"a\" (Pair 1 (- 0 3))",
+        "main = (\\x . \\_ . x) 5 (error \"never\")",
+        "main = let evn = \\n . if (eq n 0) then True else od (- n 1); od = \\n . if (eq n 0) then False else evn (- n 1) in evn 11",
+        "f x = let go = \\n . if (eq n 0) then x else go (- n 1) in go 20; main = f \"done\"",
+        "List a = Cons a (List a) | Nil;
+map f xs = case xs { Cons y ys -> Cons (f y) (map f ys); Nil -> Nil };
+range a b = if (eq a b) then Nil else Cons a (range (+ a 1) b);
+main = let k = 10 in map (\\x . * x k) (range 0 5)",
+        "Maybe a = Just a | None;
+name m = case m { Just x -> case x { 3 -> \"three\"; _ -> show x }; None -> \"none\" };
+main = ++ (name (Just 4)) (name (Just 3))",
+        "main = compare \"b\" \"a\"",
+    ];
+    for source in programs {
+        let (evaluated, reduced) = both(source);
+        assert_eq!(evaluated, reduced, "in {}", source);
+    }
+}
+
+#[test]
+fn lambdas_become_supercombinators() {
+    let (parse, expr) = program(
+        "adder n = let k = + n 1 in \\x . + x k;
+main = adder 1 2",
+    );
+    let compiled = compile(&expr, &parse);
+    // the lambda inside adder takes k first and then its own x
+    let lifted = compiled
+        .globals
+        .iter()
+        .find(|g| g.name == "adder$1")
+        .expect("the lambda wasn't lifted");
+    assert_eq!(lifted.arity, 2);
+    let adder = compiled.globals.iter().find(|g| g.name == "adder").unwrap();
+    assert_eq!(adder.arity, 1);
+    assert_eq!(Machine::new(&compiled).run().to_string(), "4");
+}
+
+#[test]
+fn the_code_uses_the_instructions() {
+    let (parse, expr) = program(
+        "List a = Cons a (List a) | Nil;
+sum xs = case xs { Cons y ys -> + y (sum ys); Nil -> 0 };
+main = let ones = Cons 1 (Cons 1 Nil) in sum ones",
+    );
+    let code = compile(&expr, &parse).to_string();
+    for instr in [
+        "PUSH ",
+        "MKAP",
+        "UPDATE ",
+        "EVAL",
+        "CASEJUMP",
+        "PACK Cons 2",
+        "SPLIT 2",
+        "UNWIND",
+    ] {
+        assert!(code.contains(instr), "no {} in\n{}", instr, code);
+    }
+}
+
+#[test]
+fn shared_values_are_evaluated_once() {
+    let fib =
+        "fib n = if (eq n 0) then 0 else if (eq n 1) then 1 else + (fib (- n 1)) (fib (- n 2));";
+    let once = steps(&format!("{} main = fib 15", fib));
+    let shared = steps(&format!("{} main = let x = fib 15 in + x x", fib));
+    let caf = steps(&format!("{} x = fib 15; main = + x x", fib));
+    assert!(
+        shared < once + once / 10,
+        "{} steps against {}",
+        shared,
+        once
+    );
+    assert!(caf < once + once / 10, "{} steps against {}", caf, once);
+}
@@ -0,0 +1,127 @@
+/*
+
+run every program in tests/fixtures and compare what happens with what is expected, run with cargo test
+
+each fixture is a .bagl file with a .expected file next to it
+    exit: 0
+    --- stdout
+    ...
+    --- stderr
+    ...
+the program goes through the bagl binary so it gets the whole pipeline, the diagnostics, and the exit code
+a .args file holds the arguments to use instead of just the file, one per line, the file goes last
+    --entry
+    other
+or for a subcommand
+    test
+
+things that change from run to run are replaced
+    durations become <time>, the location of a panic is dropped
+
+to accept the new output as the expected output
+    BLESS=1 cargo test --test golden
+
+*/
+
+mod common;
+
+use common::exit_code;
+use common::fixtures;
+use common::names;
+use common::render;
+use regex::Regex;
+use std::env;
+use std::fs;
+use std::process::Command;
+
+// the program is run from the fixtures directory so the file names in messages don't depend on where the repo is
+fn run(name: &str) -> String {
+    let dir = fixtures();
+    let mut args: Vec<String> = match fs::read_to_string(dir.join(name).with_extension("args")) {
+        Ok(args) => args.lines().map(|l| l.trim().to_string()).collect(),
+        Err(_) => Vec::new(),
+    };
+    args.retain(|a| !a.is_empty());
+    args.push(name.to_string());
+    let output = Command::new(env!("CARGO_BIN_EXE_bagl"))
+        .args(&args)
+        .current_dir(&dir)
+        .env("RUST_BACKTRACE", "0")
+        .output()
+        .expect("Couldn't run bagl.");
+    render(
+        &exit_code(&output.status),
+        &normalize(&String::from_utf8_lossy(&output.stdout)),
+        &normalize(&String::from_utf8_lossy(&output.stderr)),
+    )
+}
+
+fn normalize(text: &str) -> String {
+    let time = Regex::new(r"\d+(\.\d+)?(ns|µs|ms|s)\b").unwrap();
+    let mut out = String::new();
+    for line in text.lines() {
+        if line.starts_with("note: run with `RUST_BACKTRACE=1`") {
+            continue;
+        }
+        if line.starts_with("thread '") && line.contains("panicked at") {
+            out.push_str("thread panicked:\n");
+            continue;
+        }
+        out.push_str(&time.replace_all(line, "<time>"));
+        out.push('\n');
+    }
+    out
+}
+
+// the lines that are different, by line number
+fn diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    let mut out = String::new();
+    for i in 0..expected.len().max(actual.len()) {
+        let e = expected.get(i);
+        let a = actual.get(i);
+        if e != a {
+            if let Some(e) = e {
+                out.push_str(&format!("    {:>4} - {}\n", i + 1, e));
+            }
+            if let Some(a) = a {
+                out.push_str(&format!("    {:>4} + {}\n", i + 1, a));
+            }
+        }
+    }
+    out
+}
+
+#[test]
+fn golden() {
+    let bless = env::var_os("BLESS").is_some();
+    let names: Vec<String> = names().iter().map(|n| format!("{}.bagl", n)).collect();
+    assert!(!names.is_empty(), "there are no fixtures");
+
+    let mut failures = Vec::new();
+    for name in &names {
+        let actual = run(name);
+        let path = fixtures().join(name).with_extension("expected");
+        if bless {
+            fs::write(&path, &actual).expect("Couldn't write the expected output.");
+            continue;
+        }
+        match fs::read_to_string(&path) {
+            Ok(expected) if expected == actual => (),
+            Ok(expected) => failures.push(format!("{}\n{}", name, diff(&expected, &actual))),
+            Err(_) => failures.push(format!(
+                "{}\n    no expected output, run with BLESS=1\n",
+                name
+            )),
+        }
+    }
+    if !failures.is_empty() {
+        panic!(
+            "{} of {} fixtures didn't match:\n\n{}",
+            failures.len(),
+            names.len(),
+            failures.join("\n")
+        );
+    }
+}
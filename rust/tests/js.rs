@@ -0,0 +1,111 @@
+/*
+
+the programs in tests/fixtures compiled to JavaScript have to do the same thing eval does
+
+each fixture that runs, or stops with an error at runtime, is compiled with bagl js and run with node
+a small script imports the module, prints $display($run()), and exits with 101 when bagl stops with an error
+the exit code and stdout have to be what the .expected file says (tests/common)
+
+node is $NODE or node, when there isn't one the test says so and passes
+the exports of a module are checked on their own, that doesn't need node
+
+*/
+
+mod common;
+
+use common::agree;
+use common::fixtures;
+use common::tool;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+const RUN: &str = "import * as program from \"./program.mjs\";
+try {
+  process.stdout.write(program.$display(program.$run()) + \"\\n\");
+} catch (error) {
+  if (!(error instanceof program.$BaglError)) {
+    throw error;
+  }
+  process.stderr.write(error.message + \"\\n\");
+  process.exitCode = 101;
+}
+";
+
+fn compile(dir: &Path, file: &Path, out: &Path) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_bagl"))
+        .arg("js")
+        .arg("-o")
+        .arg(out)
+        .arg(file)
+        .current_dir(dir)
+        .output()
+        .expect("Couldn't run bagl.");
+    assert!(
+        output.status.success(),
+        "{} didn't compile to JavaScript:\n{}",
+        file.display(),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    fs::read_to_string(out).unwrap()
+}
+
+#[test]
+fn compiled_fixtures_match_eval() {
+    let node = match tool("NODE", "node") {
+        Some(node) => node,
+        None => {
+            eprintln!("no node, skipping");
+            return;
+        }
+    };
+    let dir = fixtures();
+    agree(|name| {
+        let out = env::temp_dir().join(format!("bagl-js-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&out).unwrap();
+        compile(
+            &dir,
+            Path::new(&format!("{}.bagl", name)),
+            &out.join("program.mjs"),
+        );
+        fs::write(out.join("run.mjs"), RUN).unwrap();
+        // evaluation is as deep as eval's
+        let run = Command::new(&node)
+            .arg("--stack-size=4000")
+            .arg("run.mjs")
+            .current_dir(&out)
+            .output()
+            .expect("Couldn't run node.");
+        fs::remove_dir_all(&out).ok();
+        run
+    });
+}
+
+#[test]
+fn every_definition_is_exported() {
+    let out = env::temp_dir().join(format!("bagl-js-exports-{}", std::process::id()));
+    fs::create_dir_all(&out).unwrap();
+    let source = out.join("library.bagl");
+    fs::write(
+        &source,
+        "List a = Cons a (List a) | Nil;
+sum xs = case xs { Cons y ys -> + y (sum ys); Nil -> 0 };
+new = sum (Cons 1 Nil);
+showAll xs = case xs { Cons y ys -> ++ (show y) (showAll ys); Nil -> \"\" }",
+    )
+    .unwrap();
+    let module = compile(&out, &source, &out.join("library.mjs"));
+    fs::remove_dir_all(&out).ok();
+    let exports = &module[module.rfind("export {").expect("nothing is exported")..];
+    // functions and constructors as they are, values through a function
+    for export in &["  sum,", "  Cons,", "  get$Nil as Nil,", "  showAll,"] {
+        assert!(exports.contains(export), "{} isn't exported", export);
+    }
+    // a reserved word gets a $ so it can be a JavaScript name
+    assert!(exports.contains("  get$new$ as new$,"));
+    // dictionaries are exported with the . as $
+    assert!(exports.contains("  Show$Int,") || exports.contains("  get$Show$Int as Show$Int,"));
+    // there's no main, so nothing to run
+    assert!(!module.contains("export function $run"));
+}
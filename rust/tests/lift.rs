@@ -0,0 +1,117 @@
+/*
+
+the passes in lift.rs can't change what a program does
+
+the programs are run with eval before and after each pass and have to print the same
+the rest checks what the passes leave behind, nothing captured after closure conversion and no lambdas inside of definitions after lifting
+what --dump=lift prints is the lift fixture
+
+*/
+
+use bagl::ast::Expr;
+use bagl::env::Env;
+use bagl::eval::eval;
+use bagl::front::compile;
+use bagl::gram;
+use bagl::lift::closure_convert;
+use bagl::lift::lambda_free_vars;
+use bagl::lift::lambda_lift;
+use bagl::scan::resolve;
+use std::rc::Rc;
+
+fn program(source: &str) -> Rc<Expr> {
+    compile(&gram::TopParser::new().parse(source).unwrap(), "main").unwrap()
+}
+
+fn run(expr: Rc<Expr>) -> String {
+    resolve(Rc::clone(&expr));
+    eval(expr, Rc::new(Env::new()), Vec::new()).to_string()
+}
+
+// the definitions of the top level lets and the body
+fn definitions(expr: &Rc<Expr>) -> Vec<Rc<Expr>> {
+    match &**expr {
+        Expr::Let(_, defs, body) | Expr::LetRec(_, defs, body) => {
+            let mut found = defs.to_vec();
+            found.extend(definitions(body));
+            found
+        }
+        _ => vec![Rc::clone(expr)],
+    }
+}
+
+fn has_lambda(expr: &Rc<Expr>) -> bool {
+    match &**expr {
+        Expr::Lam(_, _) => true,
+        Expr::App(left, right) => has_lambda(left) || has_lambda(right),
+        Expr::Let(_, defs, body) | Expr::LetRec(_, defs, body) => {
+            defs.iter().any(has_lambda) || has_lambda(body)
+        }
+        Expr::Case(cond, _, branches, _) => has_lambda(cond) || branches.iter().any(has_lambda),
+        Expr::If(cond, b1, b2) => has_lambda(cond) || has_lambda(b1) || has_lambda(b2),
+        Expr::Annot(inner, _) => has_lambda(inner),
+        _ => false,
+    }
+}
+
+const PROGRAMS: [&str; 6] = [
+    "adder n = let k = + n 1 in \\x . + x k; main = adder 1 2",
+    "twice f = let g = \\x . f (f x) in g; main = twice (\\y . * y 3) 2",
+    "x = 100; f x = let g = \\y . + x y in g 1; main = f 5",
+    "Maybe a = Just a | None;
+pick m = case m { Just v -> (\\d . + v d); None -> (\\d . d) };
+main = + (pick (Just 1) 10) (pick None 20)",
+    "main = let y = error \"no\" in (\\x . if (x) then 1 else y) True",
+    "List a = Cons a (List a) | Nil;
+map f xs = case xs { Cons y ys -> Cons (f y) (map f ys); Nil -> Nil };
+range a b = if (eq a b) then Nil else Cons a (range (+ a 1) b);
+main = let k = 10 in map (\\x . let j = + x k in (\\z . * z j) 2) (range 0 4)",
+];
+
+#[test]
+fn free_variables_are_what_lambdas_capture() {
+    let expr = program("adder n = let k = + n 1 in \\x . + x k; main = adder 1 2");
+    let captures = lambda_free_vars(&expr);
+    let inner = captures
+        .iter()
+        .find(|c| c.definition == "adder" && !c.free.is_empty())
+        .expect("the lambda inside adder wasn't found");
+    assert_eq!(inner.free, vec!["k"]);
+    // adder itself is a top level definition and doesn't capture anything
+    assert!(captures
+        .iter()
+        .filter(|c| c.definition == "adder")
+        .any(|c| c.free.is_empty()));
+}
+
+#[test]
+fn closure_conversion_keeps_values() {
+    for source in PROGRAMS {
+        let expr = program(source);
+        let converted = closure_convert(&expr);
+        assert!(
+            lambda_free_vars(&converted)
+                .iter()
+                .all(|c| c.free.is_empty()),
+            "something is still captured in {}",
+            source
+        );
+        assert_eq!(run(expr), run(converted), "in {}", source);
+    }
+}
+
+#[test]
+fn lifting_leaves_lambdas_at_the_top() {
+    for source in PROGRAMS {
+        let expr = program(source);
+        let lifted = lambda_lift(&expr);
+        for def in definitions(&lifted) {
+            let mut inner = &def;
+            while let Expr::Lam(_, body) = &**inner {
+                inner = body;
+            }
+            assert!(!has_lambda(inner), "{} wasn't lifted in {}", def, source);
+        }
+        assert_eq!(run(expr), run(lifted), "in {}", source);
+    }
+}
@@ -0,0 +1,274 @@
+/*
+
+the language server, driven with the messages an editor would send
+
+*/
+
+use bagl::json::Json;
+use bagl::lsp::serve;
+
+const URI: &str = "file:///test.bagl";
+
+fn frame(message: &Json) -> String {
+    let body = message.to_string();
+    format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+}
+
+fn request(id: usize, method: &str, params: Json) -> Json {
+    Json::object(vec![
+        ("jsonrpc", Json::str("2.0")),
+        ("id", Json::number(id)),
+        ("method", Json::str(method)),
+        ("params", params),
+    ])
+}
+
+fn notification(method: &str, params: Json) -> Json {
+    Json::object(vec![
+        ("jsonrpc", Json::str("2.0")),
+        ("method", Json::str(method)),
+        ("params", params),
+    ])
+}
+
+fn open(text: &str) -> Json {
+    notification(
+        "textDocument/didOpen",
+        Json::object(vec![(
+            "textDocument",
+            Json::object(vec![
+                ("uri", Json::str(URI)),
+                ("languageId", Json::str("bagl")),
+                ("version", Json::number(1)),
+                ("text", Json::str(text)),
+            ]),
+        )]),
+    )
+}
+
+fn at(line: usize, character: usize) -> Json {
+    Json::object(vec![
+        ("textDocument", Json::object(vec![("uri", Json::str(URI))])),
+        (
+            "position",
+            Json::object(vec![
+                ("line", Json::number(line)),
+                ("character", Json::number(character)),
+            ]),
+        ),
+    ])
+}
+
+// everything the server sends back for the messages, and whether it exited after a shutdown
+fn session(messages: Vec<Json>) -> (Vec<Json>, bool) {
+    let mut input = String::new();
+    for message in &messages {
+        input.push_str(&frame(message));
+    }
+    let mut output = Vec::new();
+    let clean = serve(input.as_bytes(), &mut output).unwrap();
+    let mut output = String::from_utf8(output).unwrap();
+    let mut replies = Vec::new();
+    while let Some(end) = output.find("\r\n\r\n") {
+        let length: usize = output["Content-Length: ".len()..end].parse().unwrap();
+        let body = output[end + 4..end + 4 + length].to_string();
+        replies.push(Json::parse(&body).unwrap());
+        output = output[end + 4 + length..].to_string();
+    }
+    (replies, clean)
+}
+
+// the result of the request with the id
+fn result(replies: &[Json], id: usize) -> &Json {
+    replies
+        .iter()
+        .find(|r| r.get("id").as_usize() == Some(id))
+        .map(|r| r.get("result"))
+        .expect("no response")
+}
+
+fn diagnostics(replies: &[Json]) -> Vec<&Json> {
+    replies
+        .iter()
+        .filter(|r| r.get("method").as_str() == Some("textDocument/publishDiagnostics"))
+        .map(|r| r.get("params").get("diagnostics"))
+        .collect()
+}
+
+fn start(range: &Json) -> (usize, usize) {
+    let start = range.get("start");
+    (
+        start.get("line").as_usize().unwrap(),
+        start.get("character").as_usize().unwrap(),
+    )
+}
+
+fn end(range: &Json) -> (usize, usize) {
+    let end = range.get("end");
+    (
+        end.get("line").as_usize().unwrap(),
+        end.get("character").as_usize().unwrap(),
+    )
+}
+
+const PROGRAM: &str = "List a = Cons a (List a) | Nil;
+length xs = case xs { Cons y ys -> + 1 (length ys); Nil -> 0 };
+main = let twice = \\n . * 2 n in twice (length (Cons 1 Nil))";
+
+#[test]
+fn initialize_shutdown_and_exit() {
+    let (replies, clean) = session(vec![
+        request(1, "initialize", Json::object(Vec::new())),
+        request(2, "shutdown", Json::Null),
+        notification("exit", Json::Null),
+    ]);
+    let capabilities = result(&replies, 1).get("capabilities");
+    assert_eq!(capabilities.get("definitionProvider"), &Json::Bool(true));
+    assert_eq!(capabilities.get("textDocumentSync").as_usize(), Some(1));
+    assert_eq!(result(&replies, 2), &Json::Null);
+    assert!(clean);
+
+    let (_, clean) = session(vec![notification("exit", Json::Null)]);
+    assert!(!clean);
+}
+
+#[test]
+fn errors_become_diagnostics() {
+    let (replies, _) = session(vec![open("main = + 1 x")]);
+    let published = diagnostics(&replies);
+    let first = &published[0].as_array().unwrap()[0];
+    assert_eq!(first.get("severity").as_usize(), Some(1));
+    assert_eq!(first.get("message").as_str(), Some("unbound variable `x`"));
+    assert_eq!(start(first.get("range")), (0, 11));
+
+    let (replies, _) = session(vec![open("main = + 1 (")]);
+    let first = &diagnostics(&replies)[0].as_array().unwrap()[0];
+    assert!(first
+        .get("message")
+        .as_str()
+        .unwrap()
+        .starts_with("unexpected end of file"));
+
+    // y isn't used, that is only a warning
+    let (replies, _) = session(vec![open(PROGRAM)]);
+    let published = diagnostics(&replies)[0].as_array().unwrap();
+    assert_eq!(published.len(), 1);
+    assert_eq!(published[0].get("severity").as_usize(), Some(2));
+}
+
+#[test]
+fn definitions_of_variables_and_constructors() {
+    let (replies, _) = session(vec![
+        open(PROGRAM),
+        // length in its own body
+        request(1, "textDocument/definition", at(1, 40)),
+        // n in the lambda
+        request(2, "textDocument/definition", at(2, 28)),
+        // the constructor in the pattern
+        request(3, "textDocument/definition", at(1, 23)),
+        // the constructor in main
+        request(4, "textDocument/definition", at(2, 50)),
+    ]);
+    assert_eq!(start(result(&replies, 1).get("range")), (1, 0));
+    assert_eq!(start(result(&replies, 2).get("range")), (2, 20));
+    assert_eq!(start(result(&replies, 3).get("range")), (0, 9));
+    assert_eq!(start(result(&replies, 4).get("range")), (0, 9));
+    // a Location has the document it's in
+    for id in 1..=4 {
+        assert_eq!(result(&replies, id).get("uri").as_str(), Some(URI));
+    }
+    // only the name of the constructor, not its fields
+    assert_eq!(end(result(&replies, 3).get("range")), (0, 13));
+}
+
+#[test]
+fn hover_shows_types_or_source() {
+    let (replies, _) = session(vec![
+        open(PROGRAM),
+        request(1, "textDocument/hover", at(1, 2)),
+        request(2, "textDocument/hover", at(2, 28)),
+        request(3, "textDocument/hover", at(2, 7)),
+    ]);
+    let value = |id| {
+        result(&replies, id)
+            .get("contents")
+            .get("value")
+            .as_str()
+            .unwrap()
+            .to_string()
+    };
+    assert!(value(1).contains("length :: List a -> Int"));
+    // a lambda's parameter doesn't get a type, its line is shown instead
+    assert!(value(2).contains("main = let twice"));
+    assert_eq!(result(&replies, 3), &Json::Null);
+}
+
+#[test]
+fn symbols_and_completion() {
+    let (replies, _) = session(vec![
+        open(PROGRAM),
+        request(1, "textDocument/documentSymbol", at(0, 0)),
+        request(2, "textDocument/completion", at(2, 29)),
+        request(3, "textDocument/completion", at(1, 20)),
+    ]);
+    let symbols = result(&replies, 1).as_array().unwrap();
+    let names: Vec<&str> = symbols
+        .iter()
+        .map(|s| s.get("name").as_str().unwrap())
+        .collect();
+    assert_eq!(names, vec!["List", "length", "main"]);
+    let constructors = symbols[0].get("children").as_array().unwrap();
+    assert_eq!(constructors.len(), 2);
+
+    let labels = |id| -> Vec<String> {
+        result(&replies, id)
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| c.get("label").as_str().unwrap().to_string())
+            .collect()
+    };
+    let in_main = labels(2);
+    for name in &["n", "twice", "length", "Cons", "case"] {
+        assert!(in_main.contains(&name.to_string()), "{} is missing", name);
+    }
+    // the parameter of length isn't in scope in main
+    assert!(!in_main.contains(&"xs".to_string()));
+    assert!(labels(3).contains(&"xs".to_string()));
+}
+
+#[test]
+fn the_last_parse_is_kept() {
+    let change = notification(
+        "textDocument/didChange",
+        Json::object(vec![
+            (
+                "textDocument",
+                Json::object(vec![("uri", Json::str(URI)), ("version", Json::number(2))]),
+            ),
+            (
+                "contentChanges",
+                Json::Array(vec![Json::object(vec![(
+                    "text",
+                    Json::str(&format!("{} (", PROGRAM)),
+                )])]),
+            ),
+        ]),
+    );
+    let (replies, _) = session(vec![
+        open(PROGRAM),
+        change,
+        request(1, "textDocument/definition", at(1, 40)),
+    ]);
+    assert_eq!(diagnostics(&replies).len(), 2);
+    assert_eq!(start(result(&replies, 1).get("range")), (1, 0));
+}
+
+#[test]
+fn json_round_trips() {
+    let text = r#"{"a":[1,-2.5,true,null],"b":"x\"y\\z\né😀"}"#;
+    let value = Json::parse(text).unwrap();
+    assert_eq!(value.get("b").as_str(), Some("x\"y\\z\né😀"));
+    assert_eq!(Json::parse(&value.to_string()).unwrap(), value);
+    assert!(Json::parse("{\"a\": }").is_err());
+}
@@ -0,0 +1,148 @@
+/*
+
+the passes on their own, the golden tests only see what comes out at the end
+
+*/
+
+use bagl::ast::Expr;
+use bagl::check::shadowing;
+use bagl::env::Env;
+use bagl::eval::eval;
+use bagl::gram;
+use bagl::rearrange::change_lets;
+use bagl::rearrange::dependency_graph;
+use bagl::scan::resolve;
+use bagl::unused::eliminate;
+use std::rc::Rc;
+
+fn program(source: &str) -> Rc<Expr> {
+    gram::TopParser::new().parse(source).unwrap().to_let()
+}
+
+// the definition of main in a program
+fn main_def(source: &str) -> Rc<Expr> {
+    match &*program(source) {
+        Expr::LetRec(vars, defs, _) => {
+            let i = vars
+                .iter()
+                .position(|v| matches!(&**v, Expr::Var(s, _, _) if s == "main"))
+                .unwrap();
+            Rc::clone(&defs[i])
+        }
+        _ => panic!("a program is a letrec"),
+    }
+}
+
+fn names(vars: &[Rc<Expr>]) -> Vec<String> {
+    vars.iter()
+        .map(|v| match &**v {
+            Expr::Var(s, _, _) => s.to_string(),
+            _ => panic!("only variables are defined"),
+        })
+        .collect()
+}
+
+#[test]
+fn components_come_after_what_they_use() {
+    let expr = program("a = + b c; b = 1; c = d; d = c; e = e; main = a");
+    let (vars, defs) = match &*expr {
+        Expr::LetRec(vars, defs, _) => (vars, defs),
+        _ => panic!("a program is a letrec"),
+    };
+    let graph = dependency_graph(vars, defs);
+    let components = graph.components();
+    let position = |name: &str| {
+        let node = graph.names.iter().position(|n| n == name).unwrap();
+        components.iter().position(|c| c.contains(&node)).unwrap()
+    };
+    assert!(position("b") < position("a"));
+    assert!(position("c") < position("a"));
+    assert!(position("a") < position("main"));
+    assert_eq!(position("c"), position("d"));
+    assert_ne!(position("b"), position("c"));
+    assert!(graph
+        .self_rec
+        .contains(&graph.names.iter().position(|n| n == "e").unwrap()));
+}
+
+#[test]
+fn lets_are_split_by_dependencies() {
+    let expr = change_lets(program("a = 1; b = + a 1; main = b"));
+    // nothing is recursive, so every definition gets its own let, the prelude's constructors too
+    let mut groups = Vec::new();
+    let mut next = expr;
+    loop {
+        next = match &*next {
+            Expr::Let(vars, _, body) => {
+                groups.push(names(vars));
+                Rc::clone(body)
+            }
+            Expr::LetRec(vars, _, _) => panic!("{:?} aren't recursive", names(vars)),
+            _ => break,
+        };
+    }
+    groups.retain(|g| ["a", "b", "main"].contains(&g[0].as_str()));
+    assert_eq!(groups, vec![vec!["a"], vec!["b"], vec!["main"]]);
+}
+
+#[test]
+fn mutual_recursion_stays_together() {
+    let expr = change_lets(program(
+        "even n = if (eq n 0) then True else odd (- n 1); odd n = if (eq n 0) then False else even (- n 1); main = even 2",
+    ));
+    let mut next = expr;
+    let mut found = false;
+    while let Expr::Let(_, _, body) | Expr::LetRec(_, _, body) = &*next {
+        if let Expr::LetRec(vars, _, _) = &*next {
+            let mut group = names(vars);
+            group.sort();
+            found |= group == vec!["even", "odd"];
+        }
+        next = Rc::clone(body);
+    }
+    assert!(found);
+}
+
+#[test]
+fn variables_point_at_their_frame() {
+    let def = main_def("main = \\x . \\y . x");
+    resolve(Rc::clone(&def));
+    match &*def {
+        Expr::Lam(_, inner) => match &**inner {
+            Expr::Lam(_, body) => match &**body {
+                Expr::Var(_, coords, _) => assert_eq!(*coords.borrow(), (1, 0)),
+                _ => panic!("the body is x"),
+            },
+            _ => panic!("two lambdas"),
+        },
+        _ => panic!("two lambdas"),
+    }
+}
+
+#[test]
+fn shadowed_names_are_found() {
+    assert!(shadowing(main_def("main = \\x . \\x . x"), Vec::new()));
+    assert!(!shadowing(main_def("main = \\x . \\y . x"), Vec::new()));
+}
+
+#[test]
+fn unused_definitions_are_never_evaluated() {
+    // evaluating either of these would stop the program, one never finishes and the other is an error
+    let source = "spin x = spin x; forever = spin 0; broken = error \"never\"; main = + 1 2";
+    let expr = change_lets(program(source));
+    let text = expr.to_string();
+    assert!(text.contains("forever") && text.contains("broken"));
+    let expr = eliminate(expr);
+    let text = expr.to_string();
+    for name in ["spin", "forever", "broken"] {
+        assert!(
+            !text.contains(name),
+            "{} wasn't dropped from {}",
+            name,
+            text
+        );
+    }
+    resolve(Rc::clone(&expr));
+    let value = eval(expr, Rc::new(Env::new()), Vec::new());
+    assert_eq!(value.to_string(), "3");
+}
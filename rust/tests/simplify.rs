@@ -0,0 +1,137 @@
+/*
+
+the simplifier can't change what a program does
+
+the programs are run with eval before and after simplifying and have to print the same
+the ones that stop with an error have to stop with the same error
+the rest checks that the things it's supposed to do get done
+
+*/
+
+use bagl::anf::lower;
+use bagl::anf::Atom;
+use bagl::anf::Term;
+use bagl::ast::Expr;
+use bagl::env::Env;
+use bagl::eval::eval;
+use bagl::front::compile;
+use bagl::gram;
+use bagl::scan::resolve;
+use bagl::simplify::simplify;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::rc::Rc;
+
+fn program(source: &str) -> Rc<Expr> {
+    compile(&gram::TopParser::new().parse(source).unwrap(), "main").unwrap()
+}
+
+// errors stop eval with a panic, what it says is the outcome
+fn run(expr: Rc<Expr>) -> Result<String, String> {
+    resolve(Rc::clone(&expr));
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        eval(expr, Rc::new(Env::new()), Vec::new()).to_string()
+    }));
+    result.map_err(|error| match error.downcast::<String>() {
+        Ok(message) => *message,
+        Err(error) => error.downcast::<&str>().unwrap().to_string(),
+    })
+}
+
+// the body of main after simplifying
+fn main_of(source: &str) -> Term {
+    let (term, _) = simplify(&lower(&program(source)));
+    let mut rest = &term;
+    loop {
+        match rest {
+            Term::Let(name, def, _) if name == "main" => return (**def).clone(),
+            Term::Let(_, _, body) | Term::LetRec(_, body) => rest = body,
+            // main was simple enough to be put in the body
+            _ => return rest.clone(),
+        }
+    }
+}
+
+#[test]
+fn simplifying_keeps_values() {
+    let programs = [
+        "main = + 1 2",
+        "main = (\\x . + x 1) 2",
+        "main = (\\x . \\_ . x) 5 (error \"never\")",
+        "f x y = x; main = f 1 (error \"still forced\")",
+        "main = / 1 0",
+        "main = / 1.0 0.0",
+        "Maybe a = Just a | None;
+main = case (Just (error \"field\")) { Just x -> 1; None -> 0 }",
+        "Maybe a = Just a | None;
+main = case (Just (* 2 3)) { Just x -> + x 1; None -> 0 }",
+        "main = if (eq (+ 1 1) 2) then \"yes\" else \"no\"",
+        "main = case (+ 1 2) { 3 -> \"three\"; _ -> \"other\" }",
+        "swap f a b = f b a; main = swap (\\x . \\y . - x y) 1 10",
+        "Pair a b = Pair a b;
+flip p = case p { Pair a b -> Pair b a };
+main = flip (flip (Pair 1 \"x\"))",
+        "fact n = if (eq n 0) then 1 else * n (fact (- n 1)); main = fact 10",
+        "List a = Cons a (List a) | Nil;
+map f xs = case xs { Cons y ys -> Cons (f y) (map f ys); Nil -> Nil };
+range a b = if (eq a b) then Nil else Cons a (range (+ a 1) b);
+adder n = let k = + n 1 in \\x . + x k;
+main = let k = 10 in map (adder k) (range 0 5)",
+        "x = 100; f x = let g = \\y . + x y in g 1; main = f 5",
+        "main = let x = 1 in let f = \\y . + x y in let x = 2 in f x",
+    ];
+    for source in programs {
+        let expr = program(source);
+        let (term, report) = simplify(&lower(&expr));
+        assert!(report.passes <= 10);
+        assert_eq!(run(expr), run(term.to_expr()), "in {}", source);
+    }
+}
+
+#[test]
+fn constants_are_folded() {
+    assert!(matches!(main_of("main = + 1 2"), Term::Atom(Atom::Int(n)) if n == 3.into()));
+    assert!(
+        matches!(main_of("main = (\\x . * x 2.5) 2.0"), Term::Atom(Atom::Float(n)) if n == 5.0)
+    );
+    assert!(matches!(
+        main_of("main = if (eq 1 1) then 10 else 20"),
+        Term::Atom(Atom::Int(n)) if n == 10.into()
+    ));
+    assert!(matches!(
+        main_of("Maybe a = Just a | None; main = case (Just 4) { Just x -> + x 1; None -> 0 }"),
+        Term::Atom(Atom::Int(n)) if n == 5.into()
+    ));
+    // dividing by zero has to happen when the program runs
+    assert!(matches!(
+        main_of("main = / 1 0"),
+        Term::App(Atom::Builtin(_, _, _), _)
+    ));
+}
+
+#[test]
+fn arguments_that_arent_values_stay_forced() {
+    let source = "f x y = x; main = \\z . f 1 z";
+    match main_of(source) {
+        Term::Lam(_, body) => assert!(
+            matches!(&*body, Term::Atom(Atom::Int(_))),
+            "z is a value, it was forced by the lambda"
+        ),
+        _ => panic!("main isn't a lambda anymore"),
+    }
+    let (term, report) = simplify(&lower(&program("f x y = x; main = f 1 (error \"e\")")));
+    assert!(
+        !report.changes.iter().any(|c| c.contains("beta reduced f")),
+        "{}\n{}",
+        report,
+        term
+    );
+}
+
+#[test]
+fn recursive_definitions_stay() {
+    let (term, _) = simplify(&lower(&program(
+        "fact n = if (eq n 0) then 1 else * n (fact (- n 1)); main = fact 5",
+    )));
+    assert!(term.to_string().contains("letrec fact"), "{}", term);
+}
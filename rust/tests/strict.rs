@@ -0,0 +1,107 @@
+/*
+
+the strictness analysis can only take away thunks, what a program gives back has to stay the same
+
+the programs are run with and without it and have to print the same
+the rest checks what the analysis says about parameters and lets, and that it does save allocations
+
+*/
+
+use bagl::ast::Expr;
+use bagl::env::Env;
+use bagl::eval::Budget;
+use bagl::eval::Machine;
+use bagl::front::compile;
+use bagl::gram;
+use bagl::lift::lambda_lift;
+use bagl::scan::resolve;
+use bagl::strict::analyze;
+use std::rc::Rc;
+
+fn program(source: &str) -> Rc<Expr> {
+    compile(&gram::TopParser::new().parse(source).unwrap(), "main").unwrap()
+}
+
+// the value and how many allocations it took
+fn run(expr: &Rc<Expr>, strict: bool) -> (String, u64) {
+    let strictness = analyze(expr);
+    let mut machine = Machine::new(Budget::default());
+    if strict {
+        machine = machine.with_strictness(&strictness);
+    }
+    let value = machine
+        .eval(Rc::clone(expr), Rc::new(Env::new()), Vec::new())
+        .unwrap();
+    (value.to_string(), machine.stats().allocations)
+}
+
+const PROGRAMS: [&str; 7] = [
+    "fact n = if (eq n 0) then 1 else * n (fact (- n 1)); main = fact 10",
+    "main = let e = error \"never\" in if (eq 1 1) then 1 else e",
+    "main = let x = + 1 2; y = error \"never\" in (\\a . \\_ . a) x y",
+    "Maybe a = Just a | None;
+main = let m = Just (+ 1 2) in case m { Just v -> v; None -> 0 }",
+    "List a = Cons a (List a) | Nil;
+take n xs = if (eq n 0) then Nil else case xs { Cons y ys -> Cons y (take (- n 1) ys); Nil -> Nil };
+range a b = if (eq a b) then Nil else Cons a (range (+ a 1) b);
+main = take 3 (range 0 10)",
+    "main = let evn = \\n . if (eq n 0) then True else od (- n 1); od = \\n . if (eq n 0) then False else evn (- n 1) in evn 11",
+    "adder n = let k = + n 1 in \\x . + x k; main = adder 1 2",
+];
+
+#[test]
+fn strictness_keeps_values() {
+    for source in PROGRAMS {
+        let expr = program(source);
+        assert_eq!(run(&expr, false).0, run(&expr, true).0, "in {}", source);
+    }
+}
+
+#[test]
+fn lifted_programs_keep_values() {
+    for source in PROGRAMS {
+        let expr = lambda_lift(&program(source));
+        resolve(Rc::clone(&expr));
+        assert_eq!(run(&expr, false).0, run(&expr, true).0, "in {}", source);
+    }
+}
+
+#[test]
+fn parameters_and_lets() {
+    let strictness = analyze(&program(
+        "const x = \\_ . x;
+main = let n = 10; unused = error \"never\" in + n (const n unused)",
+    ));
+    let report = strictness.report.join("\n");
+    assert!(
+        report.contains("const: \\ x _ . strict in x\n"),
+        "{}",
+        report
+    );
+    assert!(report.contains("main: let n is strict"), "{}", report);
+    assert!(report.contains("main: let unused is lazy"), "{}", report);
+    // the parameter closure conversion adds is lazy, but the body uses it
+    let strictness = analyze(&lambda_lift(&program(
+        "adder n = let k = + n 1 in \\x . + x k; main = adder 1 2",
+    )));
+    let report = strictness.report.join("\n");
+    assert!(
+        report.contains("adder$1: \\ ~k x . strict in ~k x"),
+        "{}",
+        report
+    );
+}
+
+#[test]
+fn fewer_thunks_are_made() {
+    let expr = program("sum n = if (eq n 0) then 0 else + n (sum (- n 1)); main = sum 10");
+    let (lazy, lazy_allocations) = run(&expr, false);
+    let (strict, strict_allocations) = run(&expr, true);
+    assert_eq!(lazy, strict);
+    assert!(
+        strict_allocations < lazy_allocations / 2,
+        "{} allocations against {}",
+        strict_allocations,
+        lazy_allocations
+    );
+}
@@ -0,0 +1,253 @@
+/*
+
+the modules bagl wat writes, checked with wasm::validate and, when there's a toolchain, by running them
+
+every fixture that runs, or stops with an error at runtime, has to compile to a module that validates
+validate has to catch the mistakes a change to the code generator or the runtime is likely to make
+the constructors have to be numbered the way info::Constructors numbers the declarations
+
+the same fixtures are assembled with $WAT2WASM or wat2wasm and run with $NODE or node
+    a small script is the host, it gives the module format_float, prints the display of what main gives back
+    and exits with 101 with the message from error_ptr and error_len when main traps
+    the exit code and stdout have to be what the .expected file says (tests/common)
+when there's no assembler or no node the test says so and passes
+
+*/
+
+mod common;
+
+use bagl::gram;
+use bagl::info::Constructors;
+use bagl::wasm::validate;
+use common::agree;
+use common::fixtures;
+use common::runnable;
+use common::tool;
+use std::env;
+use std::fs;
+use std::process::Command;
+
+// the host, format_float writes what rust's Display does, toString's exponent is written out
+const RUN: &str = "import { readFileSync } from \"node:fs\";
+function format(x) {
+  if (Number.isNaN(x)) return \"NaN\";
+  if (x === Infinity) return \"inf\";
+  if (x === -Infinity) return \"-inf\";
+  const sign = x < 0 || Object.is(x, -0) ? \"-\" : \"\";
+  const text = Math.abs(x).toString();
+  const m = text.match(/^(\\d)(?:\\.(\\d+))?e([+-]\\d+)$/);
+  if (!m) return sign + text;
+  const digits = m[1] + (m[2] || \"\");
+  const point = 1 + Number(m[3]);
+  if (point <= 0) return sign + \"0.\" + \"0\".repeat(-point) + digits;
+  if (point >= digits.length) return sign + digits + \"0\".repeat(point - digits.length);
+  return sign + digits.slice(0, point) + \".\" + digits.slice(point);
+}
+let memory;
+const bytes = (ptr, len) => new Uint8Array(memory.buffer, ptr, len);
+const host = {
+  bagl: {
+    format_float(x, ptr) {
+      const text = new TextEncoder().encode(format(x));
+      bytes(ptr, text.length).set(text);
+      return text.length;
+    },
+  },
+};
+const { instance } = await WebAssembly.instantiate(readFileSync(\"program.wasm\"), host);
+const exports = instance.exports;
+memory = exports.memory;
+try {
+  const str = exports.display(exports.main());
+  const view = new DataView(memory.buffer);
+  const text = bytes(str + 24, view.getUint32(str + 12, true));
+  process.stdout.write(new TextDecoder().decode(text) + \"\\n\");
+} catch (error) {
+  if (!(error instanceof WebAssembly.RuntimeError) || exports.error_len.value === 0) {
+    throw error;
+  }
+  const message = bytes(exports.error_ptr.value, exports.error_len.value);
+  process.stderr.write(new TextDecoder().decode(message) + \"\\n\");
+  process.exitCode = 101;
+}
+";
+
+// the module for a program, written to a file first since bagl reads programs from files
+fn module(name: &str, source: &str) -> String {
+    let path = env::temp_dir().join(format!("bagl-wasm-{}-{}.bagl", name, std::process::id()));
+    fs::write(&path, source).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_bagl"))
+        .arg("wat")
+        .arg(&path)
+        .output()
+        .expect("Couldn't run bagl.");
+    fs::remove_file(&path).ok();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+const PROGRAM: &str = "List a = Cons a (List a) | Nil;
+sum xs = case xs { Cons y ys -> + y (sum ys); Nil -> 0 };
+main = sum (Cons 1 (Cons 2 Nil))";
+
+#[test]
+fn fixtures_compile_to_valid_modules() {
+    let dir = fixtures();
+    let names = runnable();
+    assert!(!names.is_empty());
+    for name in names {
+        let output = Command::new(env!("CARGO_BIN_EXE_bagl"))
+            .arg("wat")
+            .arg("--check")
+            .arg(format!("{}.bagl", name))
+            .current_dir(&dir)
+            .output()
+            .expect("Couldn't run bagl.");
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+}
+
+#[test]
+fn broken_modules_are_rejected() {
+    let wat = module("broken", PROGRAM);
+    assert_eq!(validate(&wat), Ok(()));
+    let broken = [
+        // a function that isn't there
+        wat.replacen("(call $force", "(call $forced", 1),
+        // too few arguments
+        wat.replacen(
+            "(call $set (local.get $frame) (local.get $slot) (local.get $v))",
+            "(call $set (local.get $frame) (local.get $v))",
+            1,
+        ),
+        // a local that isn't there
+        wat.replacen("(local.get $depth)", "(local.get $height)", 1),
+        // a label that isn't around the branch
+        wat.replacen("(br $follow)", "(br $elsewhere)", 1),
+        // setting a global that can't be set
+        wat.replacen("(global.set $hp", "(global.set $cons", 1),
+        // an instruction that doesn't exist
+        wat.replacen("i32.add", "i32.plus", 1),
+        // two functions with the same name
+        wat.replacen("(func $size", "(func $tag", 1),
+        // nothing to run
+        wat.replacen("(export \"main\")", "(export \"start\")", 1),
+        // no memory for the host to read
+        wat.replacen("(memory (export \"memory\")", "(memory", 1),
+        // unbalanced
+        wat.replacen("(unreachable)", "(unreachable", 1),
+        // an import after something has been defined
+        wat.replacen(
+            "(import \"bagl\" \"format_float\" (func $format_float (param f64 i32) (result i32)))",
+            "",
+            1,
+        )
+        .replacen(
+            ";; the program",
+            "(import \"bagl\" \"format_float\" (func $format_float (param f64 i32) (result i32)))",
+            1,
+        ),
+    ];
+    for (i, text) in broken.iter().enumerate() {
+        assert_ne!(text, &wat, "mutation {} didn't change anything", i);
+        assert!(validate(text).is_err(), "mutation {} wasn't caught", i);
+    }
+}
+
+#[test]
+fn the_data_has_to_fit_in_memory() {
+    let module = "(module
+  (memory (export \"memory\") 1)
+  (func (export \"main\") (result i32) (i32.const 0))
+  (data (i32.const 65530) \"too long\"))";
+    assert!(validate(module).is_err());
+    assert_eq!(validate(&module.replace("65530", "65528")), Ok(()));
+}
+
+#[test]
+fn constructors_are_numbered_like_the_declarations() {
+    let source = "Color = Red | Green | Blue;
+Shape = Circle Float | Rect Float Float;
+main = Rect 1.0 2.0";
+    let top = gram::TopParser::new().parse(source).unwrap();
+    let cons = Constructors::new(&top.types);
+    let wat = module("tags", source);
+    for (i, con) in cons.tags.iter().enumerate() {
+        let line = format!(
+            ";; con {} {}, type {} tag {} arity {}",
+            i, con.name, con.type_index, con.tag, con.arity
+        );
+        assert!(wat.contains(&line), "missing {}", line);
+    }
+    let find = |name: &str| cons.tags.iter().find(|c| c.name == name).unwrap();
+    assert_eq!(find("Blue").tag, 2);
+    assert_eq!(find("Rect").tag, 1);
+    assert_eq!(find("Rect").arity, 2);
+    assert_eq!(find("Circle").type_index, find("Rect").type_index);
+    assert_ne!(find("Red").type_index, find("Rect").type_index);
+}
+
+#[test]
+fn compiled_fixtures_run() {
+    let (wat2wasm, node) = match (tool("WAT2WASM", "wat2wasm"), tool("NODE", "node")) {
+        (Some(wat2wasm), Some(node)) => (wat2wasm, node),
+        (None, _) => {
+            eprintln!("no wat2wasm, skipping");
+            return;
+        }
+        (_, None) => {
+            eprintln!("no node, skipping");
+            return;
+        }
+    };
+    let dir = fixtures();
+    agree(|name| {
+        let out = env::temp_dir().join(format!("bagl-wasm-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&out).unwrap();
+        let output = Command::new(env!("CARGO_BIN_EXE_bagl"))
+            .arg("wat")
+            .arg("-o")
+            .arg(out.join("program.wat"))
+            .arg(format!("{}.bagl", name))
+            .current_dir(&dir)
+            .output()
+            .expect("Couldn't run bagl.");
+        assert!(
+            output.status.success(),
+            "{} didn't compile to WebAssembly:\n{}",
+            name,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let output = Command::new(&wat2wasm)
+            .arg("program.wat")
+            .arg("-o")
+            .arg("program.wasm")
+            .current_dir(&out)
+            .output()
+            .expect("Couldn't run wat2wasm.");
+        assert!(
+            output.status.success(),
+            "{} didn't assemble:\n{}",
+            name,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        fs::write(out.join("run.mjs"), RUN).unwrap();
+        // evaluation is as deep as eval's
+        let run = Command::new(&node)
+            .arg("--stack-size=4000")
+            .arg("run.mjs")
+            .current_dir(&out)
+            .output()
+            .expect("Couldn't run node.");
+        fs::remove_dir_all(&out).ok();
+        run
+    });
+}